@@ -0,0 +1,72 @@
+//! Compares the time taken by `ThorArchive::extract_all` and
+//! `ThorArchive::extract_all_parallel` on a generated archive.
+//!
+//! Usage:
+//!
+//! ```text
+//! cargo run --release --example extract_parallel --features parallel,test-support -- \
+//!     [file_count] [file_size]
+//! ```
+
+use std::env;
+use std::fs::{self, File};
+use std::time::{Duration, Instant};
+
+use gruf::thor::test_support::write_generated_thor_archive;
+use gruf::thor::ThorArchive;
+use tempfile::tempdir;
+
+const DEFAULT_FILE_COUNT: usize = 5000;
+const DEFAULT_FILE_SIZE: usize = 16 * 1024;
+const RUN_COUNT: usize = 5;
+
+fn main() -> gruf::Result<()> {
+    let mut args = env::args()
+        .skip(1)
+        .map(|arg| arg.parse::<usize>().expect("invalid number"));
+    let file_count = args.next().unwrap_or(DEFAULT_FILE_COUNT);
+    let file_size = args.next().unwrap_or(DEFAULT_FILE_SIZE);
+
+    let temp_dir = tempdir()?;
+    let thor_archive_path = temp_dir.path().join("bench.thor");
+    write_generated_thor_archive(File::create(&thor_archive_path)?, file_count, file_size)?;
+    println!(
+        "{} files of {} bytes, best of {} runs",
+        file_count, file_size, RUN_COUNT
+    );
+
+    let serial_time = best_time(|| {
+        let destination_path = temp_dir.path().join("serial");
+        let mut thor_archive = ThorArchive::open(&thor_archive_path)?;
+        let start = Instant::now();
+        thor_archive.extract_all(&destination_path)?;
+        let elapsed = start.elapsed();
+        fs::remove_dir_all(&destination_path)?;
+        Ok(elapsed)
+    })?;
+    println!("extract_all:          {:?}", serial_time);
+
+    let parallel_time = best_time(|| {
+        let destination_path = temp_dir.path().join("parallel");
+        let thor_archive = ThorArchive::open(&thor_archive_path)?;
+        let start = Instant::now();
+        thor_archive.extract_all_parallel(&destination_path, || File::open(&thor_archive_path))?;
+        let elapsed = start.elapsed();
+        fs::remove_dir_all(&destination_path)?;
+        Ok(elapsed)
+    })?;
+    println!("extract_all_parallel: {:?}", parallel_time);
+    Ok(())
+}
+
+/// Runs `f` `RUN_COUNT` times and returns the shortest time it reported.
+fn best_time<F>(mut f: F) -> gruf::Result<Duration>
+where
+    F: FnMut() -> gruf::Result<Duration>,
+{
+    let mut best = Duration::from_secs(u64::MAX);
+    for _ in 0..RUN_COUNT {
+        best = best.min(f()?);
+    }
+    Ok(best)
+}
@@ -0,0 +1,64 @@
+//! Compares the time taken by `ThorArchive::read_file_content` on an archive
+//! opened with `ThorArchive::open` and with `ThorArchive::open_mmap`, on a
+//! large generated archive.
+//!
+//! Usage:
+//!
+//! ```text
+//! cargo run --release --example mmap_reads --features mmap,test-support -- \
+//!     [file_count] [file_size]
+//! ```
+
+use std::env;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::time::{Duration, Instant};
+
+use gruf::thor::test_support::write_generated_thor_archive;
+use gruf::thor::ThorArchive;
+use tempfile::tempdir;
+
+const DEFAULT_FILE_COUNT: usize = 2000;
+const DEFAULT_FILE_SIZE: usize = 64 * 1024;
+const RUN_COUNT: usize = 5;
+
+fn main() -> gruf::Result<()> {
+    let mut args = env::args()
+        .skip(1)
+        .map(|arg| arg.parse::<usize>().expect("invalid number"));
+    let file_count = args.next().unwrap_or(DEFAULT_FILE_COUNT);
+    let file_size = args.next().unwrap_or(DEFAULT_FILE_SIZE);
+
+    let temp_dir = tempdir()?;
+    let thor_archive_path = temp_dir.path().join("bench.thor");
+    write_generated_thor_archive(File::create(&thor_archive_path)?, file_count, file_size)?;
+    println!(
+        "{} files of {} bytes, best of {} runs",
+        file_count, file_size, RUN_COUNT
+    );
+
+    let mut thor_archive = ThorArchive::open(&thor_archive_path)?;
+    println!("open:      {:?}", best_time(&mut thor_archive)?);
+    // Safety: the archive is a temporary file that isn't modified anymore
+    let mut mapped_archive = unsafe { ThorArchive::open_mmap(&thor_archive_path)? };
+    println!("open_mmap: {:?}", best_time(&mut mapped_archive)?);
+    Ok(())
+}
+
+/// Reads the content of all the files of `thor_archive` `RUN_COUNT` times and
+/// returns the shortest time it took.
+fn best_time<R: Read + Seek>(thor_archive: &mut ThorArchive<R>) -> gruf::Result<Duration> {
+    let file_paths: Vec<String> = thor_archive
+        .present_entries()
+        .map(|e| e.relative_path.clone())
+        .collect();
+    let mut best = Duration::from_secs(u64::MAX);
+    for _ in 0..RUN_COUNT {
+        let start = Instant::now();
+        for file_path in &file_paths {
+            thor_archive.read_file_content(file_path)?;
+        }
+        best = best.min(start.elapsed());
+    }
+    Ok(best)
+}
@@ -0,0 +1,71 @@
+//! Compares the time taken by `ThorArchive::read_file_content` and
+//! `ThorArchive::read_file_content_into` when reading all the files of the
+//! `small.thor` fixture many times.
+//!
+//! Usage: `cargo run --release --example read_into [iteration_count]`
+
+use std::env;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use gruf::thor::ThorArchive;
+
+const DEFAULT_ITERATION_COUNT: usize = 200;
+
+fn main() -> gruf::Result<()> {
+    let iteration_count = env::args()
+        .nth(1)
+        .map(|arg| arg.parse::<usize>().expect("invalid number"))
+        .unwrap_or(DEFAULT_ITERATION_COUNT);
+    let thor_archive_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("resources")
+        .join("tests")
+        .join("thor")
+        .join("small.thor");
+    let mut thor_archive = ThorArchive::open(&thor_archive_path)?;
+    let file_paths: Vec<String> = thor_archive
+        .present_entries()
+        .filter(|e| !e.is_directory())
+        .map(|e| e.relative_path.clone())
+        .collect();
+    println!("{} files read {} times", file_paths.len(), iteration_count);
+
+    let (elapsed, total_size) = time_reads(iteration_count, || {
+        let mut total_size = 0;
+        for file_path in &file_paths {
+            total_size += thor_archive.read_file_content(file_path)?.len();
+        }
+        Ok(total_size)
+    })?;
+    report("read_file_content:     ", elapsed, total_size);
+
+    let mut buf = Vec::new();
+    let (elapsed, total_size) = time_reads(iteration_count, || {
+        let mut total_size = 0;
+        for file_path in &file_paths {
+            total_size += thor_archive.read_file_content_into(file_path, &mut buf)?;
+        }
+        Ok(total_size)
+    })?;
+    report("read_file_content_into:", elapsed, total_size);
+    Ok(())
+}
+
+/// Calls `read_all` `iteration_count` times, returns the time taken and the
+/// number of bytes read.
+fn time_reads<F>(iteration_count: usize, mut read_all: F) -> gruf::Result<(Duration, usize)>
+where
+    F: FnMut() -> gruf::Result<usize>,
+{
+    let start = Instant::now();
+    let mut total_size = 0;
+    for _ in 0..iteration_count {
+        total_size += read_all()?;
+    }
+    Ok((start.elapsed(), total_size))
+}
+
+fn report(label: &str, elapsed: Duration, total_size: usize) {
+    let throughput = total_size as f64 / elapsed.as_secs_f64() / (1024.0 * 1024.0);
+    println!("{} {:?} ({:.1} MiB/s)", label, elapsed, throughput);
+}
@@ -1,8 +1,8 @@
-use std::io::Write;
+use std::io::{Read, Write};
 
 use crate::{GrufError, Result};
-use encoding::label::encoding_from_whatwg_label;
-use encoding::EncoderTrap;
+use encoding::all::{WINDOWS_1252, WINDOWS_949};
+use encoding::{DecoderTrap, EncoderTrap, EncodingRef};
 
 pub struct GenericFileEntry {
     pub offset: u64,
@@ -11,30 +11,65 @@ pub struct GenericFileEntry {
     pub size_compressed: u32,
 }
 
-/// Serializes string into a NULL-terminated list of win1252 chars and write it
+/// Default encoding of the paths stored in archives. Korean clients use CP949
+/// (i.e., what the "euc-kr" label refers to).
+pub const ANSI_PATH_ENCODING: EncodingRef = WINDOWS_949;
+
+/// Decodes a path stored in an archive.
+pub fn decode_ansi_path(v: &[u8]) -> Result<String> {
+    decode_path(v, ANSI_PATH_ENCODING)
+}
+
+/// Decodes a path stored in an archive as a windows-1252 string, the
+/// encoding paths used to be decoded with.
+pub fn string_from_win_1252(v: &[u8]) -> Result<String> {
+    decode_path(v, WINDOWS_1252)
+}
+
+fn decode_path(v: &[u8], encoding: EncodingRef) -> Result<String> {
+    encoding
+        .decode(v, DecoderTrap::Strict)
+        .map_err(GrufError::parsing_error)
+}
+
+/// Serializes string into a NULL-terminated list of ANSI chars and write it
 /// into writer.
 ///
 /// Used in GRF archives
-pub fn serialize_as_win1252_cstr_into<W: Write>(mut writer: W, string: &str) -> Result<()> {
-    let mut vec = serialize_to_win1252(string)?;
+pub fn serialize_as_ansi_cstr_into<W: Write>(mut writer: W, string: &str) -> Result<()> {
+    let mut vec = serialize_to_ansi(string)?;
     vec.push(0); // NUL char terminator
     writer.write_all(vec.as_slice())?;
     Ok(())
 }
 
-/// Serializes string into a list of win1252 chars and write it into writer.
+/// Serializes string into a list of ANSI chars and write it into writer.
 ///
 // Used in THOR archives
-pub fn serialize_as_win1252_str_into<W: Write>(mut writer: W, string: &str) -> Result<()> {
-    let vec = serialize_to_win1252(string)?;
+pub fn serialize_as_ansi_str_into<W: Write>(mut writer: W, string: &str) -> Result<()> {
+    let vec = serialize_to_ansi(string)?;
     writer.write_all(vec.as_slice())?;
     Ok(())
 }
 
-pub fn serialize_to_win1252(string: &str) -> Result<Vec<u8>> {
-    let decoder = encoding_from_whatwg_label("windows-1252")
-        .ok_or_else(|| GrufError::serialization_error("Encoder unavailable"))?;
-    decoder
+/// Serializes string into a list of ANSI chars, encoded the same way paths
+/// are (see `decode_ansi_path`).
+pub fn serialize_to_ansi(string: &str) -> Result<Vec<u8>> {
+    ANSI_PATH_ENCODING
         .encode(string, EncoderTrap::Strict)
         .map_err(|_| GrufError::serialization_error("Encoding failed"))
 }
+
+/// Reads the decompressed data from `reader` until EOF, appending it to
+/// `into`, and returns its length.
+///
+/// Fails with `GrufError::DecompressionTooLarge` as soon as more than `max`
+/// bytes are decompressed, in which case `into` holds the first `max + 1`
+/// bytes. On I/O errors, `into` holds the bytes decompressed so far.
+pub fn decompress_bounded<R: Read>(reader: R, max: usize, into: &mut Vec<u8>) -> Result<usize> {
+    let read_size = reader.take(max as u64 + 1).read_to_end(into)?;
+    if read_size > max {
+        return Err(GrufError::DecompressionTooLarge(max));
+    }
+    Ok(read_size)
+}
@@ -15,8 +15,85 @@ pub enum GrufError {
     TryFromIntError(#[from] num::TryFromIntError),
     #[error("failed to parse archive: {0}")]
     ParsingError(String),
+    #[error("invalid archive header magic (found: {})", to_hex_string(.found))]
+    InvalidMagic {
+        /// Leading bytes of the archive, where the magic was expected
+        found: Vec<u8>,
+    },
+    #[error("unsupported GRF version: 0x{0:04x}")]
+    UnsupportedGrfVersion(u32),
+    #[error("invalid THOR archive mode: {0}")]
+    InvalidThorMode(i16),
+    #[error("file table is truncated")]
+    TruncatedTable,
+    #[error("file table is corrupted after {parsed_count} entries")]
+    CorruptedTable {
+        /// Number of entries parsed successfully
+        parsed_count: usize,
+    },
+    #[error("offset out of bounds")]
+    OffsetOutOfBounds,
+    #[error(
+        "content of '{relative_path}' is truncated ({available} of {expected} bytes available)"
+    )]
+    TruncatedPayload {
+        relative_path: String,
+        /// Size of the compressed content, according to the file table
+        expected: usize,
+        /// Number of bytes available in the archive
+        available: usize,
+    },
+    #[error("failed to decode entry path")]
+    PathDecodingError,
+    #[error("failed to decompress file table at offset {offset}: {message}")]
+    TableDecompressionError {
+        /// Offset of the compressed table in the archive
+        offset: u64,
+        message: String,
+    },
+    #[error("failed to decrypt content: {0}")]
+    DesDecryptionError(String),
+    #[error("archive contains too many entries (limit: {0})")]
+    TooManyEntries(usize),
+    #[error("unexpected number of entries (header: {expected}, parsed: {parsed})")]
+    EntryCountMismatch { expected: usize, parsed: usize },
     #[error("failed to find file entry")]
     EntryNotFound,
+    #[error("file entry is marked for removal")]
+    EntryRemoved,
+    #[error("failed to decompress '{relative_path}' at offset {offset}: {message}")]
+    DecompressionError {
+        relative_path: String,
+        /// Offset of the compressed content in the archive
+        offset: u64,
+        message: String,
+    },
+    #[error("content of '{relative_path}' at offset {offset} isn't zlib data")]
+    NotZlibData {
+        relative_path: String,
+        /// Offset of the content in the archive
+        offset: u64,
+    },
+    #[error("decompressed data exceeds the size limit ({0} bytes)")]
+    DecompressionTooLarge(usize),
+    #[error("invalid entry size: {0}")]
+    InvalidSize(i32),
+    #[error("unexpected decompressed size (expected: {expected}, actual: {actual})")]
+    SizeMismatch { expected: usize, actual: usize },
+    #[error("operation was cancelled")]
+    Cancelled,
+    #[error("insufficient disk space (required: {required} bytes, available: {available} bytes)")]
+    InsufficientSpace { required: u64, available: u64 },
+    #[error("archive targets '{expected}', not '{actual}'")]
+    TargetMismatch { expected: String, actual: String },
+    #[error("archive targets the client's directory, not '{0}'")]
+    NotGrfPatch(String),
+    #[error("file name already used by another entry: {0}")]
+    NameCollision(String),
+    #[error("unsafe entry path: {0}")]
+    UnsafePath(String),
+    #[error("invalid glob pattern: {0}")]
+    InvalidGlob(String),
     #[error("failed to read content: {0}")]
     InvalidContent(String),
     #[error("failed to serialize data: {0}")]
@@ -26,6 +103,14 @@ pub enum GrufError {
 }
 
 impl GrufError {
+    /// Returns an `InvalidMagic` error, with (up to) the first `magic_len`
+    /// bytes of `header` as the bytes found.
+    pub(crate) fn invalid_magic(header: &[u8], magic_len: usize) -> Self {
+        Self::InvalidMagic {
+            found: header[..std::cmp::min(header.len(), magic_len)].to_vec(),
+        }
+    }
+
     pub fn parsing_error(msg: impl Into<String>) -> Self {
         Self::ParsingError(msg.into())
     }
@@ -38,3 +123,7 @@ impl GrufError {
         Self::SerializationError(msg.into())
     }
 }
+
+fn to_hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -1,13 +1,13 @@
 use std::boxed::Box;
 use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::fs::{File, OpenOptions};
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 
-use crate::archive::{serialize_as_win1252_cstr_into, GenericFileEntry};
+use crate::archive::{serialize_as_ansi_cstr_into, GenericFileEntry};
 use crate::grf::dyn_alloc::{self, AvailableChunkList};
-use crate::grf::{GrfArchive, GRF_HEADER_MAGIC, GRF_HEADER_SIZE};
+use crate::grf::{GrfArchive, GrfVersion, GRF_HEADER_MAGIC, GRF_HEADER_SIZE};
 use crate::thor::ThorArchive;
 use crate::{GrufError, Result};
 use flate2::write::ZlibEncoder;
@@ -24,6 +24,8 @@ pub struct GrfArchiveBuilder<W: Write + Seek> {
     version_minor: u32,
     entries: HashMap<String, GenericFileEntry>,
     chunks: AvailableChunkList,
+    // Whether data must only be appended, see `open_for_append`
+    append_only: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -46,6 +48,13 @@ struct SerializableGrfFileEntry200 {
     offset: u32,
 }
 
+/// Returns true if GRFs of the given version can be written by
+/// `GrfArchiveBuilder`, i.e., 2.0 GRFs (1.x file tables aren't implemented
+/// yet).
+pub(crate) fn supports_grf_version(version: GrfVersion) -> bool {
+    version.major == 2 && version.minor == 0
+}
+
 impl<W: Write + Seek> GrfArchiveBuilder<W> {
     pub fn create(mut obj: W, version_major: u32, version_minor: u32) -> Result<Self> {
         let start_offset = obj.seek(io::SeekFrom::Current(0)).unwrap_or(0);
@@ -59,6 +68,7 @@ impl<W: Write + Seek> GrfArchiveBuilder<W> {
             version_minor,
             entries: HashMap::new(),
             chunks: AvailableChunkList::new(),
+            append_only: false,
         })
     }
 
@@ -72,17 +82,7 @@ impl<W: Write + Seek> GrfArchiveBuilder<W> {
             .ok_or(GrufError::EntryNotFound)?
             .clone();
         let content = archive.get_entry_raw_data(&relative_path)?;
-        let offset = {
-            if let Some(grf_entry) = self.entries.get(&relative_path) {
-                self.chunks.realloc_chunk(
-                    grf_entry.offset,
-                    grf_entry.size_compressed as usize,
-                    content.len(),
-                )?
-            } else {
-                self.chunks.alloc_chunk(content.len())?
-            }
-        };
+        let offset = self.alloc_entry_chunk(&relative_path, content.len())?;
 
         self.obj.seek(SeekFrom::Start(self.start_offset + offset))?;
         let mut content_reader = Cursor::new(content);
@@ -109,17 +109,7 @@ impl<W: Write + Seek> GrfArchiveBuilder<W> {
             .ok_or(GrufError::EntryNotFound)?
             .clone();
         let content = thor_archive.get_entry_raw_data(&relative_path)?;
-        let offset = {
-            if let Some(grf_entry) = self.entries.get(&relative_path) {
-                self.chunks.realloc_chunk(
-                    grf_entry.offset,
-                    grf_entry.size_compressed as usize,
-                    content.len(),
-                )?
-            } else {
-                self.chunks.alloc_chunk(content.len())?
-            }
-        };
+        let offset = self.alloc_entry_chunk(&relative_path, content.len())?;
 
         self.obj.seek(SeekFrom::Start(self.start_offset + offset))?;
         let mut content_reader = Cursor::new(content);
@@ -143,17 +133,7 @@ impl<W: Write + Seek> GrfArchiveBuilder<W> {
         // Write compressed data
         let compressed_data = encoder.finish()?;
         let compressed_data_size = compressed_data.len();
-        let offset = {
-            if let Some(grf_entry) = self.entries.get(&relative_path) {
-                self.chunks.realloc_chunk(
-                    grf_entry.offset,
-                    grf_entry.size_compressed as usize,
-                    compressed_data_size,
-                )?
-            } else {
-                self.chunks.alloc_chunk(compressed_data_size)?
-            }
-        };
+        let offset = self.alloc_entry_chunk(&relative_path, compressed_data_size)?;
 
         self.obj.seek(SeekFrom::Start(self.start_offset + offset))?;
         let mut compressed_reader = Cursor::new(compressed_data);
@@ -172,8 +152,10 @@ impl<W: Write + Seek> GrfArchiveBuilder<W> {
 
     pub fn remove_file<S: AsRef<str>>(&mut self, relative_path: S) -> Result<bool> {
         if let Some(entry) = self.entries.remove(relative_path.as_ref()) {
-            self.chunks
-                .free_chunk(entry.offset, entry.size_compressed as usize)?;
+            if !self.append_only {
+                self.chunks
+                    .free_chunk(entry.offset, entry.size_compressed as usize)?;
+            }
             Ok(true)
         } else {
             Ok(false)
@@ -189,19 +171,32 @@ impl<W: Write + Seek> GrfArchiveBuilder<W> {
         let v_file_count = i32::try_from(self.entries.len() + 7)?;
         let file_table_offset = match self.version_major {
             2 => self.write_grf_table_200()?,
-            1 => std::unimplemented!(), // TODO(LinkZ): Implement
-            _ => return Err(GrufError::serialization_error("Wrong file format version")),
+            // TODO(LinkZ): Implement 1.x tables
+            _ => return Err(GrufError::UnsupportedGrfVersion(self.version().as_u32())),
         };
         // Update the header
         self.obj.seek(SeekFrom::Start(self.start_offset))?;
         write_grf_header(
-            (self.version_major << 8) | (self.version_minor),
+            self.version().as_u32(),
             (file_table_offset - GRF_HEADER_SIZE as u64) as u32,
             v_file_count,
             &mut self.obj,
         )
     }
 
+    /// Returns the offset at which the data of `relative_path` must be
+    /// written, reusing the space of its previous data when possible.
+    fn alloc_entry_chunk(&mut self, relative_path: &str, size: usize) -> Result<u64> {
+        match self.entries.get(relative_path) {
+            Some(grf_entry) if !self.append_only => self.chunks.realloc_chunk(
+                grf_entry.offset,
+                grf_entry.size_compressed as usize,
+                size,
+            ),
+            _ => self.chunks.alloc_chunk(size),
+        }
+    }
+
     fn write_grf_table_200(&mut self) -> Result<u64> {
         let mut table: Vec<u8> = Vec::new();
         // Generate table and write files' content
@@ -213,7 +208,7 @@ impl<W: Write + Seek> GrfArchiveBuilder<W> {
                 entry_type: 1,
                 offset: (entry.offset - GRF_HEADER_SIZE as u64) as u32,
             };
-            serialize_as_win1252_cstr_into(&mut table, &relative_path)?;
+            serialize_as_ansi_cstr_into(&mut table, &relative_path)?;
             bincode::serialize_into(&mut table, &grf_file_entry)?;
         }
         // Compress the table
@@ -236,12 +231,38 @@ impl<W: Write + Seek> GrfArchiveBuilder<W> {
         // Return file table's offset
         Ok(table_offset)
     }
+
+    fn version(&self) -> GrfVersion {
+        GrfVersion {
+            major: self.version_major,
+            minor: self.version_minor,
+        }
+    }
 }
 
 impl GrfArchiveBuilder<File> {
     pub fn open<P: AsRef<Path>>(grf_path: P) -> Result<Self> {
+        Self::open_with_mode(grf_path, false)
+    }
+
+    /// Opens an existing GRF, to which data is only appended: new data is
+    /// written after the end of the file, the GRF's existing content
+    /// (including its current file table) is never overwritten and only the
+    /// header is updated by `finish`.
+    ///
+    /// Space left by removed or replaced entries isn't reused, rebuild the
+    /// GRF to reclaim it.
+    pub fn open_for_append<P: AsRef<Path>>(grf_path: P) -> Result<Self> {
+        Self::open_with_mode(grf_path, true)
+    }
+
+    fn open_with_mode<P: AsRef<Path>>(grf_path: P, append_only: bool) -> Result<Self> {
         let mut grf_archive = GrfArchive::open(&grf_path)?;
-        let chunks = dyn_alloc::list_available_chunks(&mut grf_archive)?;
+        let chunks = if append_only {
+            AvailableChunkList::with_end_offset(fs::metadata(&grf_path)?.len())
+        } else {
+            dyn_alloc::list_available_chunks(&mut grf_archive)?
+        };
         let mut entries = HashMap::with_capacity(grf_archive.file_count());
         for entry in grf_archive.get_entries() {
             entries.insert(
@@ -263,6 +284,7 @@ impl GrfArchiveBuilder<File> {
             version_minor: grf_archive.version_minor(),
             entries,
             chunks,
+            append_only,
         })
     }
 }
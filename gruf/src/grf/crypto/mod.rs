@@ -4,7 +4,7 @@ use std::result::Result;
 
 mod des;
 
-const DES_BLOCK_SIZE: usize = 8; // Block size in bytes
+pub const DES_BLOCK_SIZE: usize = 8; // Block size in bytes
 
 pub fn decrypt_file_name(file_name: &[u8]) -> Result<Vec<u8>, &str> {
     let mut mut_vec = file_name.to_vec();
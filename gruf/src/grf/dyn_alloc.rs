@@ -65,6 +65,15 @@ impl AvailableChunkList {
         }
     }
 
+    /// Creates a list without any available chunk, new chunks are allocated
+    /// at `end_offset` and after.
+    pub fn with_end_offset(end_offset: u64) -> AvailableChunkList {
+        AvailableChunkList {
+            end_offset,
+            ..AvailableChunkList::new()
+        }
+    }
+
     /// Acquire a chunk of memory
     pub fn alloc_chunk(&mut self, size: usize) -> Result<u64> {
         let chunk_offset = self.find_suitable_chunk(size);
@@ -104,6 +113,14 @@ impl AvailableChunkList {
             return Ok(offset);
         }
 
+        if new_size <= size {
+            // Shrink in place and release the remaining space
+            if new_size < size {
+                self.free_chunk(new_end_offset, size - new_size)?;
+            }
+            return Ok(offset);
+        }
+
         if let Some(next_chunk) = self.chunks.get(&end_offset) {
             // Next chunk is available
             let next_chunk_size = next_chunk.size;
@@ -146,8 +163,10 @@ impl AvailableChunkList {
         }
         // Check right merge
         if chunk_end_offset == self.end_offset {
-            // "Merge" to the right
+            // "Merge" to the right, the chunk is now part of the unused space
+            // located at the end
             self.end_offset = new_chunk_offset;
+            return Ok(());
         } else if self.chunks.contains_key(&chunk_end_offset) {
             // Merge to the right with another chunk
             let chunk = self
@@ -268,6 +287,40 @@ mod tests {
         assert_eq!(offset5, offset1);
     }
 
+    #[test]
+    fn test_chunk_list_realloc_shrink() {
+        let chunk_size: usize = 64;
+        let mut chunk_list = AvailableChunkList::new();
+        let offset1 = chunk_list.alloc_chunk(chunk_size).unwrap();
+        let offset2 = chunk_list.alloc_chunk(chunk_size).unwrap();
+
+        // Shrink the first chunk, the released space should be reusable
+        let res = chunk_list
+            .realloc_chunk(offset1, chunk_size, chunk_size / 2)
+            .unwrap();
+        assert_eq!(res, offset1);
+        let res = chunk_list.alloc_chunk(chunk_size / 2).unwrap();
+        assert_eq!(res, offset1 + (chunk_size / 2) as u64);
+        // No space left between the first two chunks
+        let res = chunk_list.alloc_chunk(1).unwrap();
+        assert_eq!(res, offset2 + chunk_size as u64);
+    }
+
+    #[test]
+    fn test_chunk_list_free_last() {
+        let chunk_size: usize = 64;
+        let mut chunk_list = AvailableChunkList::new();
+        let offset1 = chunk_list.alloc_chunk(chunk_size).unwrap();
+        let offset2 = chunk_list.alloc_chunk(chunk_size).unwrap();
+
+        // Free the last chunk, its space should only be handed out once
+        chunk_list.free_chunk(offset2, chunk_size).unwrap();
+        let offset3 = chunk_list.alloc_chunk(chunk_size).unwrap();
+        assert_eq!(offset3, offset2);
+        let offset4 = chunk_list.alloc_chunk(chunk_size).unwrap();
+        assert_eq!(offset4, offset1 + 2 * chunk_size as u64);
+    }
+
     #[test]
     fn test_chunk_list_left_merge() {
         let chunk_size: usize = 64;
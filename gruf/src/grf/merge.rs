@@ -0,0 +1,738 @@
+use std::fs::{self, File};
+use std::io::{Read, Seek};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::grf::builder::supports_grf_version;
+use crate::grf::{grf_version, GrfArchive, GrfArchiveBuilder, GRF_HEADER_SIZE};
+use crate::thor::reader::{default_metadata_names, is_metadata_path};
+use crate::thor::{ThorArchive, ThorFileEntry};
+use crate::{GrufError, Result};
+
+// Limits of the 2.0 format: offsets are stored as 32-bit integers relative to
+// the end of the header and the "virtual" file count as a 32-bit signed integer
+const GRF_MAX_ENTRY_COUNT: usize = i32::MAX as usize - 7;
+const GRF_MAX_ARCHIVE_SIZE: u64 = GRF_HEADER_SIZE as u64 + u32::MAX as u64;
+// Size of a 2.0 file table entry, without the file name
+const GRF_TABLE_ENTRY_FIXED_SIZE: usize = 1 + 17;
+const GRF_TABLE_INFO_SIZE: u64 = 2 * std::mem::size_of::<u32>() as u64;
+
+/// Indicates which GRF format limit would be exceeded by a merge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitViolation {
+    /// The GRF would contain more entries than the format supports
+    EntryCount { count: usize, limit: usize },
+    /// The GRF would be too big for its content to be addressable
+    ArchiveSize { size: u64, limit: u64 },
+}
+
+/// Options used when applying an archive to a GRF, see
+/// `ThorArchive::apply_to_grf_with_options`.
+#[derive(Debug, Clone)]
+pub struct GrfApplyOptions {
+    /// Whether the GRF's file name must match the archive's target GRF (see
+    /// `ThorArchive::check_target`). Disable it to knowingly retarget an
+    /// archive.
+    pub check_target: bool,
+    /// Paths of the archive's metadata files, which are never merged into
+    /// the GRF (`RESERVED_METADATA_NAMES` by default).
+    pub metadata_names: Vec<String>,
+    /// Flag that aborts the merge with `GrufError::Cancelled` when set,
+    /// checked before each entry. Entries merged so far are kept.
+    pub cancel_flag: Option<Arc<AtomicBool>>,
+}
+
+impl Default for GrfApplyOptions {
+    fn default() -> Self {
+        Self {
+            check_target: true,
+            metadata_names: default_metadata_names(),
+            cancel_flag: None,
+        }
+    }
+}
+
+/// Merges the content of a THOR archive into an existing GRF file,
+/// incrementally.
+///
+/// Entries' data is written into the GRF's unused space (i.e., gaps left by
+/// removed or moved entries) when possible, and appended at the end of the
+/// GRF otherwise. Entries that already exist are overwritten in place when
+/// their new data isn't bigger than the old one. Only the file table is
+/// rewritten, the rest of the GRF is left untouched.
+///
+/// Only 2.0 GRFs are supported, merging into other GRFs fails with
+/// `GrufError::UnsupportedGrfVersion` before anything is written.
+///
+/// Note: unused space isn't reclaimed, rebuild the GRF to compact it.
+pub fn merge_into_grf<P, R>(grf_path: P, thor_archive: &mut ThorArchive<R>) -> Result<()>
+where
+    P: AsRef<Path>,
+    R: Read + Seek,
+{
+    merge_into_grf_with_progress(grf_path, thor_archive, |_, _| {})
+}
+
+/// Same as `merge_into_grf` but calls `progress_callback` with the number of
+/// processed entries and the total number of entries after each entry.
+pub fn merge_into_grf_with_progress<P, R, F>(
+    grf_path: P,
+    thor_archive: &mut ThorArchive<R>,
+    progress_callback: F,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+    R: Read + Seek,
+    F: FnMut(usize, usize),
+{
+    merge_into_grf_with_options(
+        grf_path,
+        thor_archive,
+        &GrfApplyOptions::default(),
+        progress_callback,
+    )
+}
+
+/// Same as `merge_into_grf_with_progress`, skipping the metadata files and
+/// checking the cancel flag of `options`.
+///
+/// The GRF's file name isn't checked against the archive's target, see
+/// `ThorArchive::apply_to_grf_with_options`.
+pub fn merge_into_grf_with_options<P, R, F>(
+    grf_path: P,
+    thor_archive: &mut ThorArchive<R>,
+    options: &GrfApplyOptions,
+    progress_callback: F,
+) -> Result<()>
+where
+    P: AsRef<Path>,
+    R: Read + Seek,
+    F: FnMut(usize, usize),
+{
+    let grf_path = grf_path.as_ref();
+    let version = grf_version(grf_path)?;
+    if version.major != 2 {
+        return Err(GrufError::UnsupportedGrfVersion(version.as_u32()));
+    }
+    let mut builder = GrfArchiveBuilder::open(grf_path)?;
+    import_thor_entries(
+        &mut builder,
+        thor_archive,
+        &options.metadata_names,
+        options.cancel_flag.as_deref(),
+        progress_callback,
+    )?;
+    builder.finish()
+}
+
+/// Merges the content of a THOR archive into an existing GRF file, by
+/// appending entries' data after the end of the GRF and writing a new file
+/// table.
+///
+/// Unlike `merge_into_grf`, the GRF's existing content is never overwritten,
+/// only its header is updated once everything has been written: if the merge
+/// is interrupted, the GRF still contains its original content.
+///
+/// Only 2.0 GRFs are supported, merging into other GRFs fails with
+/// `GrufError::UnsupportedGrfVersion` before anything is written (see
+/// `ThorArchive::apply_to_grf_append` for a fallback).
+///
+/// Note: space used by removed or replaced entries isn't reused, rebuild the
+/// GRF to compact it.
+pub fn append_into_grf<P, R>(grf_path: P, thor_archive: &mut ThorArchive<R>) -> Result<()>
+where
+    P: AsRef<Path>,
+    R: Read + Seek,
+{
+    let version = grf_version(&grf_path)?;
+    if version.major != 2 {
+        return Err(GrufError::UnsupportedGrfVersion(version.as_u32()));
+    }
+    let mut builder = GrfArchiveBuilder::open_for_append(grf_path)?;
+    import_thor_entries(
+        &mut builder,
+        thor_archive,
+        &default_metadata_names(),
+        None,
+        |_, _| {},
+    )?;
+    builder.finish()
+}
+
+/// Imports the entries of `thor_archive` into `builder`, removing the files
+/// removed by the archive, and calls `progress_callback` after each entry.
+/// Metadata files (see `metadata_names`) are skipped.
+///
+/// Fails with `GrufError::Cancelled` as soon as `cancel_flag` is set.
+fn import_thor_entries<R, F>(
+    builder: &mut GrfArchiveBuilder<File>,
+    thor_archive: &mut ThorArchive<R>,
+    metadata_names: &[String],
+    cancel_flag: Option<&AtomicBool>,
+    mut progress_callback: F,
+) -> Result<()>
+where
+    R: Read + Seek,
+    F: FnMut(usize, usize),
+{
+    let mut thor_entries: Vec<ThorFileEntry> = thor_archive
+        .get_entries()
+        .filter(|e| !is_metadata_path(&e.relative_path, metadata_names))
+        .cloned()
+        .collect();
+    // Read the THOR archive's content sequentially
+    thor_entries.sort_unstable_by_key(|e| e.offset);
+    let entry_count = thor_entries.len();
+    for (entry_number, entry) in thor_entries.into_iter().enumerate() {
+        if matches!(cancel_flag, Some(flag) if flag.load(Ordering::SeqCst)) {
+            return Err(GrufError::Cancelled);
+        }
+        if entry.is_removed {
+            let _ = builder.remove_file(&entry.relative_path)?;
+        } else {
+            builder.import_raw_entry_from_thor(thor_archive, entry.relative_path)?;
+        }
+        progress_callback(1 + entry_number, entry_count);
+    }
+    Ok(())
+}
+
+/// Rebuilds the GRF file located at `grf_path`, with the same version,
+/// containing its files merged with the content of `thor_archive`.
+///
+/// Fails with `GrufError::UnsupportedGrfVersion` if GRFs of that version
+/// cannot be written (see `supports_grf_version`), and with
+/// `GrufError::CorruptedTable` if the GRF's files cannot all be listed. The
+/// new GRF is written next to the original one, which is only replaced once
+/// the new GRF is complete.
+fn rebuild_grf<R: Read + Seek>(grf_path: &Path, thor_archive: &mut ThorArchive<R>) -> Result<()> {
+    // The rebuilt GRF keeps the version of the original one
+    let version = grf_version(grf_path)?;
+    if !supports_grf_version(version) {
+        return Err(GrufError::UnsupportedGrfVersion(version.as_u32()));
+    }
+    let mut grf_archive = GrfArchive::open(grf_path)?;
+    // Don't drop files whose entries couldn't be read
+    let listed_count = grf_archive.get_entries().count();
+    if listed_count != grf_archive.file_count() {
+        return Err(GrufError::CorruptedTable {
+            parsed_count: listed_count,
+        });
+    }
+    let temp_grf_path = grf_path.with_extension("grf.tmp");
+    let result = (|| {
+        let mut builder =
+            GrfArchiveBuilder::create(File::create(&temp_grf_path)?, version.major, version.minor)?;
+        // Files updated or removed by the archive are imported afterwards
+        let kept_paths: Vec<String> = grf_archive
+            .get_entries()
+            .map(|e| e.relative_path.clone())
+            .filter(|relative_path| thor_archive.get_file_entry(relative_path).is_none())
+            .collect();
+        for relative_path in kept_paths {
+            // Content is recompressed, older GRFs' raw data can be encrypted
+            let content = grf_archive.read_file_content(&relative_path)?;
+            builder.add_file(relative_path, content.as_slice())?;
+        }
+        import_thor_entries(
+            &mut builder,
+            thor_archive,
+            &default_metadata_names(),
+            None,
+            |_, _| {},
+        )?;
+        builder.finish()
+    })();
+    match result {
+        Ok(()) => Ok(fs::rename(&temp_grf_path, grf_path)?),
+        Err(e) => {
+            let _ = fs::remove_file(&temp_grf_path);
+            Err(e)
+        }
+    }
+}
+
+impl<R: Read + Seek> ThorArchive<R> {
+    /// Merges the archive into the GRF file located at `grf_path` (see
+    /// `merge_into_grf`).
+    ///
+    /// Fails with `GrufError::TargetMismatch` if the archive targets a GRF
+    /// with another file name, with `GrufError::NotGrfPatch` if it targets
+    /// the client's directory, and with a `GrufError::IoError` if the GRF
+    /// doesn't exist or cannot be written to.
+    pub fn apply_to_grf<P: AsRef<Path>>(&mut self, grf_path: P) -> Result<()> {
+        self.apply_to_grf_with_options(grf_path, &GrfApplyOptions::default())
+    }
+
+    /// Same as `apply_to_grf`, using the given options.
+    pub fn apply_to_grf_with_options<P: AsRef<Path>>(
+        &mut self,
+        grf_path: P,
+        options: &GrfApplyOptions,
+    ) -> Result<()> {
+        let grf_path = grf_path.as_ref();
+        if options.check_target {
+            self.check_grf_target(grf_path)?;
+        }
+        merge_into_grf_with_options(grf_path, self, options, |_, _| {})
+    }
+
+    /// Merges the archive into the GRF file located at `grf_path` by appending
+    /// the new data to the GRF and rewriting only its file table (see
+    /// `append_into_grf`), which only supports 2.0 GRFs.
+    ///
+    /// Other GRFs (i.e., 1.x GRFs) are fully rebuilt instead, keeping their
+    /// version, which fails with `GrufError::UnsupportedGrfVersion` if GRFs
+    /// of that version cannot be written (currently the case of all 1.x
+    /// GRFs), and with `GrufError::CorruptedTable` if their files cannot all
+    /// be listed.
+    ///
+    /// Fails with `GrufError::TargetMismatch` if the archive targets a GRF
+    /// with another file name, and with `GrufError::NotGrfPatch` if it
+    /// targets the client's directory.
+    pub fn apply_to_grf_append<P: AsRef<Path>>(&mut self, grf_path: P) -> Result<()> {
+        let grf_path = grf_path.as_ref();
+        self.check_grf_target(grf_path)?;
+        if grf_version(grf_path)?.major == 2 {
+            append_into_grf(grf_path, self)
+        } else {
+            rebuild_grf(grf_path, self)
+        }
+    }
+
+    fn check_grf_target(&self, grf_path: &Path) -> Result<()> {
+        let grf_name = grf_path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        self.check_target(&grf_name)
+    }
+
+    /// Predicts whether merging the archive into `grf` would exceed the GRF
+    /// format's limits.
+    ///
+    /// The prediction is conservative: it assumes that all the new data is
+    /// appended at the end of the GRF.
+    pub fn merge_would_exceed_grf_limits(&self, grf: &GrfArchive) -> Option<LimitViolation> {
+        check_grf_limits(self, grf, GRF_MAX_ENTRY_COUNT, GRF_MAX_ARCHIVE_SIZE)
+    }
+}
+
+fn check_grf_limits<R: Read + Seek>(
+    thor_archive: &ThorArchive<R>,
+    grf: &GrfArchive,
+    max_entry_count: usize,
+    max_archive_size: u64,
+) -> Option<LimitViolation> {
+    let table_entry_size = |relative_path: &str| relative_path.len() + GRF_TABLE_ENTRY_FIXED_SIZE;
+    let mut entry_count = 0;
+    let mut table_size = 0;
+    let mut data_end = GRF_HEADER_SIZE as u64;
+    for entry in grf.get_entries() {
+        data_end = std::cmp::max(
+            data_end,
+            entry.offset + entry.size_compressed_aligned as u64,
+        );
+        let is_removed = matches!(
+            thor_archive.get_file_entry(&entry.relative_path),
+            Some(e) if e.is_removed
+        );
+        if !is_removed {
+            entry_count += 1;
+            table_size += table_entry_size(&entry.relative_path);
+        }
+    }
+    let mut appended_data_size = 0;
+    for entry in thor_archive
+        .get_entries()
+        .filter(|e| !e.is_removed && !e.is_internal())
+    {
+        appended_data_size += entry.size_compressed as u64;
+        if grf.get_file_entry(&entry.relative_path).is_none() {
+            entry_count += 1;
+            table_size += table_entry_size(&entry.relative_path);
+        }
+    }
+    if entry_count > max_entry_count {
+        return Some(LimitViolation::EntryCount {
+            count: entry_count,
+            limit: max_entry_count,
+        });
+    }
+    // Worst-case size of the zlib-compressed file table
+    let compressed_table_size = (table_size + 5 * (table_size / 16383 + 1) + 6) as u64;
+    let archive_size = data_end + appended_data_size + GRF_TABLE_INFO_SIZE + compressed_table_size;
+    if archive_size > max_archive_size {
+        return Some(LimitViolation::ArchiveSize {
+            size: archive_size,
+            limit: max_archive_size,
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs::{self, File};
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::grf::{GrfArchive, GrfFileEntry};
+    use crate::thor::ThorArchiveBuilder;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_merge_into_grf() {
+        let grf_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/grf");
+        let temp_dir = tempdir().unwrap();
+        let grf_path = temp_dir.path().join("200-small.grf");
+        fs::copy(grf_dir_path.join("200-small.grf"), &grf_path).unwrap();
+        let original_entries: HashMap<String, GrfFileEntry> = GrfArchive::open(&grf_path)
+            .unwrap()
+            .get_entries()
+            .map(|e| (e.relative_path.clone(), e.clone()))
+            .collect();
+        let mut original_paths: Vec<&String> = original_entries.keys().collect();
+        original_paths.sort();
+        let overwritten_path = original_paths[0].clone();
+        let removed_path = original_paths[1].clone();
+        // Generate a patch
+        let thor_path = temp_dir.path().join("patch.thor");
+        {
+            let thor_file = File::create(&thor_path).unwrap();
+            let mut builder = ThorArchiveBuilder::new(thor_file, true, None, false).unwrap();
+            builder
+                .append_file_update(overwritten_path.clone(), &[0u8; 4][..])
+                .unwrap();
+            builder
+                .append_file_update("data\\new_file.txt".to_string(), &[1u8; 256][..])
+                .unwrap();
+            builder.append_file_removal(removed_path.clone());
+        }
+        let mut thor_archive = ThorArchive::open(&thor_path).unwrap();
+        let mut progress = vec![];
+        merge_into_grf_with_progress(&grf_path, &mut thor_archive, |processed, total| {
+            progress.push((processed, total))
+        })
+        .unwrap();
+        assert_eq!(progress, vec![(1, 3), (2, 3), (3, 3)]);
+        // Check result
+        let mut grf_archive = GrfArchive::open(&grf_path).unwrap();
+        assert_eq!(grf_archive.file_count(), original_entries.len());
+        assert!(grf_archive.get_file_entry(&removed_path).is_none());
+        assert_eq!(
+            grf_archive.read_file_content(&overwritten_path).unwrap(),
+            vec![0u8; 4]
+        );
+        assert_eq!(
+            grf_archive.read_file_content("data\\new_file.txt").unwrap(),
+            vec![1u8; 256]
+        );
+        // The overwritten entry's data is smaller, it stays where it was
+        assert_eq!(
+            grf_archive
+                .get_file_entry(&overwritten_path)
+                .unwrap()
+                .offset,
+            original_entries[&overwritten_path].offset
+        );
+        // Untouched entries haven't moved
+        for (relative_path, original_entry) in &original_entries {
+            if *relative_path == overwritten_path || *relative_path == removed_path {
+                continue;
+            }
+            let entry = grf_archive.get_file_entry(relative_path).unwrap();
+            assert_eq!(entry.offset, original_entry.offset);
+        }
+    }
+
+    #[test]
+    fn test_merge_into_grf_cancelled() {
+        let grf_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/grf");
+        let temp_dir = tempdir().unwrap();
+        let grf_path = temp_dir.path().join("200-small.grf");
+        fs::copy(grf_dir_path.join("200-small.grf"), &grf_path).unwrap();
+        let original_count = GrfArchive::open(&grf_path).unwrap().file_count();
+        let thor_path = temp_dir.path().join("patch.thor");
+        {
+            let thor_file = File::create(&thor_path).unwrap();
+            let mut builder = ThorArchiveBuilder::new(thor_file, true, None, false).unwrap();
+            for i in 0..3 {
+                builder
+                    .append_file_update(format!("data\\new_file{}.txt", i), &[1u8; 16][..])
+                    .unwrap();
+            }
+        }
+        let mut thor_archive = ThorArchive::open(&thor_path).unwrap();
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+        let options = GrfApplyOptions {
+            cancel_flag: Some(cancel_flag.clone()),
+            ..Default::default()
+        };
+        let mut progress = vec![];
+        let result = merge_into_grf_with_options(
+            &grf_path,
+            &mut thor_archive,
+            &options,
+            |processed, total| {
+                progress.push((processed, total));
+                cancel_flag.store(true, Ordering::SeqCst);
+            },
+        );
+        assert!(matches!(result, Err(GrufError::Cancelled)));
+        assert_eq!(progress, vec![(1, 3)]);
+        // The entry merged before the cancellation is kept
+        assert_eq!(
+            GrfArchive::open(&grf_path).unwrap().file_count(),
+            original_count + 1
+        );
+    }
+
+    #[test]
+    fn test_apply_to_grf() {
+        let grf_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/grf");
+        let temp_dir = tempdir().unwrap();
+        let grf_path = temp_dir.path().join("200-small.grf");
+        fs::copy(grf_dir_path.join("200-small.grf"), &grf_path).unwrap();
+        let thor_path = temp_dir.path().join("patch.thor");
+        {
+            let thor_file = File::create(&thor_path).unwrap();
+            let mut builder = ThorArchiveBuilder::new(thor_file, true, None, false).unwrap();
+            builder
+                .append_file_update("data\\new_file.txt".to_string(), &[1u8; 256][..])
+                .unwrap();
+        }
+        let mut thor_archive = ThorArchive::open(&thor_path).unwrap();
+        thor_archive.apply_to_grf(&grf_path).unwrap();
+        let mut grf_archive = GrfArchive::open(&grf_path).unwrap();
+        assert_eq!(
+            grf_archive.read_file_content("data\\new_file.txt").unwrap(),
+            vec![1u8; 256]
+        );
+        // Missing GRF
+        match thor_archive.apply_to_grf(temp_dir.path().join("missing.grf")) {
+            Err(GrufError::IoError(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_apply_client_directory_patch_to_grf() {
+        let grf_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/grf");
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let temp_dir = tempdir().unwrap();
+        let grf_path = temp_dir.path().join("200-small.grf");
+        fs::copy(grf_dir_path.join("200-small.grf"), &grf_path).unwrap();
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("dir1.thor")).unwrap();
+        assert!(!thor_archive.targets_grf("200-small.grf"));
+        match thor_archive.apply_to_grf(&grf_path) {
+            Err(GrufError::NotGrfPatch(actual)) => assert_eq!(actual, "200-small.grf"),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert_eq!(
+            fs::read(&grf_path).unwrap(),
+            fs::read(grf_dir_path.join("200-small.grf")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_apply_to_other_grf() {
+        let grf_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/grf");
+        let temp_dir = tempdir().unwrap();
+        let grf_path = temp_dir.path().join("200-small.grf");
+        fs::copy(grf_dir_path.join("200-small.grf"), &grf_path).unwrap();
+        let thor_path = temp_dir.path().join("patch.thor");
+        {
+            let thor_file = File::create(&thor_path).unwrap();
+            let mut builder =
+                ThorArchiveBuilder::new(thor_file, true, Some("data.grf".to_string()), false)
+                    .unwrap();
+            builder
+                .append_file_update("data\\new_file.txt".to_string(), &[1u8; 256][..])
+                .unwrap();
+        }
+        let mut thor_archive = ThorArchive::open(&thor_path).unwrap();
+        assert!(thor_archive.check_target("DATA.GRF").is_ok());
+        match thor_archive.apply_to_grf(&grf_path) {
+            Err(GrufError::TargetMismatch { expected, actual }) => {
+                assert_eq!(expected, "data.grf");
+                assert_eq!(actual, "200-small.grf");
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert_eq!(
+            fs::read(&grf_path).unwrap(),
+            fs::read(grf_dir_path.join("200-small.grf")).unwrap()
+        );
+        // Retarget the archive
+        thor_archive
+            .apply_to_grf_with_options(
+                &grf_path,
+                &GrfApplyOptions {
+                    check_target: false,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let mut grf_archive = GrfArchive::open(&grf_path).unwrap();
+        assert_eq!(
+            grf_archive.read_file_content("data\\new_file.txt").unwrap(),
+            vec![1u8; 256]
+        );
+    }
+
+    #[test]
+    fn test_apply_to_grf_append() {
+        let grf_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/grf");
+        let temp_dir = tempdir().unwrap();
+        let grf_path = temp_dir.path().join("200-small.grf");
+        fs::copy(grf_dir_path.join("200-small.grf"), &grf_path).unwrap();
+        let original_content = fs::read(&grf_path).unwrap();
+        let mut original_paths: Vec<String> = GrfArchive::open(&grf_path)
+            .unwrap()
+            .get_entries()
+            .map(|e| e.relative_path.clone())
+            .collect();
+        original_paths.sort();
+        let overwritten_path = original_paths[0].clone();
+        let removed_path = original_paths[1].clone();
+        let thor_path = temp_dir.path().join("patch.thor");
+        {
+            let thor_file = File::create(&thor_path).unwrap();
+            let mut builder = ThorArchiveBuilder::new(thor_file, true, None, false).unwrap();
+            builder
+                .append_file_update(overwritten_path.clone(), &[0u8; 4][..])
+                .unwrap();
+            builder
+                .append_file_update("data\\new_file.txt".to_string(), &[1u8; 256][..])
+                .unwrap();
+            builder.append_file_removal(removed_path.clone());
+        }
+        let mut thor_archive = ThorArchive::open(&thor_path).unwrap();
+        thor_archive.apply_to_grf_append(&grf_path).unwrap();
+        // Only the header has been overwritten
+        let content = fs::read(&grf_path).unwrap();
+        assert!(content.len() > original_content.len());
+        assert_eq!(
+            content[GRF_HEADER_SIZE..original_content.len()],
+            original_content[GRF_HEADER_SIZE..]
+        );
+        let mut grf_archive = GrfArchive::open(&grf_path).unwrap();
+        assert_eq!(grf_archive.file_count(), original_paths.len());
+        assert!(grf_archive.get_file_entry(&removed_path).is_none());
+        assert!(
+            grf_archive
+                .get_file_entry(&overwritten_path)
+                .unwrap()
+                .offset
+                >= original_content.len() as u64
+        );
+        assert_eq!(
+            grf_archive.read_file_content(&overwritten_path).unwrap(),
+            vec![0u8; 4]
+        );
+        assert_eq!(
+            grf_archive.read_file_content("data\\new_file.txt").unwrap(),
+            vec![1u8; 256]
+        );
+    }
+
+    #[test]
+    fn test_apply_to_grf_append_rebuild() {
+        let grf_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/grf");
+        let temp_dir = tempdir().unwrap();
+        let thor_path = temp_dir.path().join("patch.thor");
+        {
+            let thor_file = File::create(&thor_path).unwrap();
+            let mut builder = ThorArchiveBuilder::new(thor_file, true, None, false).unwrap();
+            builder
+                .append_file_update("data\\new_file.txt".to_string(), &[1u8; 256][..])
+                .unwrap();
+        }
+        let mut thor_archive = ThorArchive::open(&thor_path).unwrap();
+        // 1.x GRFs cannot be written yet, they're left untouched rather than
+        // converted to 2.0 GRFs
+        for grf_file_name in &["103-empty.grf", "103-small.grf"] {
+            let grf_path = temp_dir.path().join(grf_file_name);
+            fs::copy(grf_dir_path.join(grf_file_name), &grf_path).unwrap();
+            match thor_archive.apply_to_grf_append(&grf_path) {
+                Err(GrufError::UnsupportedGrfVersion(v)) => assert_eq!(v, 0x103),
+                r => panic!("unexpected result: {:?}", r),
+            }
+            assert_eq!(
+                fs::read(&grf_path).unwrap(),
+                fs::read(grf_dir_path.join(grf_file_name)).unwrap()
+            );
+            assert!(!grf_path.with_extension("grf.tmp").exists());
+        }
+        // 2.0 GRFs are rebuilt as 2.0 GRFs
+        let grf_path = temp_dir.path().join("200-small.grf");
+        fs::copy(grf_dir_path.join("200-small.grf"), &grf_path).unwrap();
+        let file_count = GrfArchive::open(&grf_path).unwrap().file_count();
+        rebuild_grf(&grf_path, &mut thor_archive).unwrap();
+        let mut grf_archive = GrfArchive::open(&grf_path).unwrap();
+        assert_eq!(grf_archive.version_major(), 2);
+        assert_eq!(grf_archive.version_minor(), 0);
+        assert_eq!(grf_archive.file_count(), file_count + 1);
+        assert_eq!(
+            grf_archive.read_file_content("data\\new_file.txt").unwrap(),
+            vec![1u8; 256]
+        );
+        assert!(!grf_path.with_extension("grf.tmp").exists());
+    }
+
+    #[test]
+    fn test_merge_into_unsupported_grf() {
+        let grf_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/grf");
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let temp_dir = tempdir().unwrap();
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("small.thor")).unwrap();
+        for (grf_file_name, version) in &[("102-small.grf", 0x102), ("103-small.grf", 0x103)] {
+            let grf_path = temp_dir.path().join(grf_file_name);
+            fs::copy(grf_dir_path.join(grf_file_name), &grf_path).unwrap();
+            match merge_into_grf(&grf_path, &mut thor_archive) {
+                Err(GrufError::UnsupportedGrfVersion(v)) => assert_eq!(v, *version),
+                r => panic!("unexpected result: {:?}", r),
+            }
+            // The GRF is left untouched
+            assert_eq!(
+                fs::read(&grf_path).unwrap(),
+                fs::read(grf_dir_path.join(grf_file_name)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_merge_would_exceed_grf_limits() {
+        let grf_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/grf");
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let grf_archive = GrfArchive::open(grf_dir_path.join("200-small.grf")).unwrap();
+        let thor_archive = ThorArchive::open(thor_dir_path.join("small.thor")).unwrap();
+        assert_eq!(
+            thor_archive.merge_would_exceed_grf_limits(&grf_archive),
+            None
+        );
+        // Entry count
+        let max_entry_count = grf_archive.file_count();
+        match check_grf_limits(&thor_archive, &grf_archive, max_entry_count, u64::MAX) {
+            Some(LimitViolation::EntryCount { count, limit }) => {
+                assert!(count > max_entry_count);
+                assert_eq!(limit, max_entry_count);
+            }
+            v => panic!("unexpected result: {:?}", v),
+        }
+        // Archive size
+        let grf_size = fs::metadata(grf_dir_path.join("200-small.grf"))
+            .unwrap()
+            .len();
+        match check_grf_limits(&thor_archive, &grf_archive, usize::MAX, grf_size) {
+            Some(LimitViolation::ArchiveSize { size, limit }) => {
+                assert!(size > grf_size);
+                assert_eq!(limit, grf_size);
+            }
+            v => panic!("unexpected result: {:?}", v),
+        }
+    }
+}
@@ -1,8 +1,13 @@
 pub mod builder;
+pub mod merge;
 pub mod reader;
 
 pub use builder::GrfArchiveBuilder;
-pub use reader::{GrfArchive, GrfFileEntry};
+pub use merge::{
+    append_into_grf, merge_into_grf, merge_into_grf_with_options, merge_into_grf_with_progress,
+    GrfApplyOptions, LimitViolation,
+};
+pub use reader::{grf_version, GrfArchive, GrfFileEntry, GrfVersion};
 
 mod crypto;
 mod dyn_alloc;
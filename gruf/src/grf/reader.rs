@@ -3,14 +3,13 @@ use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fs::File;
 use std::hash::{Hash, Hasher};
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{BufReader, Read, Seek, SeekFrom};
 use std::path::Path;
 use std::str;
 
-use crate::grf::crypto::{decrypt_file_content, decrypt_file_name};
+use crate::archive::{decode_ansi_path, decompress_bounded};
+use crate::grf::crypto::{decrypt_file_content, decrypt_file_name, DES_BLOCK_SIZE};
 use crate::{GrufError, Result};
-use encoding::label::encoding_from_whatwg_label;
-use encoding::DecoderTrap;
 use flate2::read::ZlibDecoder;
 use nom::error::ErrorKind;
 use nom::number::complete::{le_i32, le_u32, le_u8};
@@ -23,25 +22,32 @@ const GRF_TABLE_INFO2_SIZE: usize = 2 * std::mem::size_of::<u32>();
 
 #[derive(Debug)]
 pub struct GrfArchive {
-    obj: Box<File>,
+    obj: Box<BufReader<File>>,
     container: GrfContainer,
 }
 
 impl GrfArchive {
     /// Create a new archive with the underlying object as the reader.
+    ///
+    /// The file is read through a `BufReader`.
     pub fn open<P: AsRef<Path>>(grf_path: P) -> Result<Self> {
-        let mut file = File::open(grf_path)?;
+        let mut file = BufReader::new(File::open(grf_path)?);
         let mut grf_header_buf = [0; GRF_HEADER_SIZE];
         file.read_exact(&mut grf_header_buf)?;
+        if !grf_header_buf.starts_with(GRF_HEADER_MAGIC.as_bytes()) {
+            return Err(GrufError::invalid_magic(
+                &grf_header_buf,
+                GRF_HEADER_MAGIC.len(),
+            ));
+        }
         let (parser_output, grf_header) = parse_grf_header(&grf_header_buf)
             .map_err(|_| GrufError::parsing_error("Failed to parse archive (header)"))?;
 
         match grf_header.version_major {
             2 => {
                 let mut table_info_buf = [0; GRF_TABLE_INFO2_SIZE];
-                file.seek(SeekFrom::Start(
-                    GRF_HEADER_SIZE as u64 + grf_header.file_table_offset,
-                ))?;
+                let table_info_offset = GRF_HEADER_SIZE as u64 + grf_header.file_table_offset;
+                file.seek(SeekFrom::Start(table_info_offset))?;
                 file.read_exact(&mut table_info_buf)?;
                 let (_parser_output, grf_table_info) = parse_grf_table_info_200(&table_info_buf)
                     .map_err(|_| {
@@ -62,12 +68,17 @@ impl GrfArchive {
                     Vec::with_capacity(grf_table_info.table_size_compressed);
                 let mut file_chunk = file.by_ref().take(compressed_table.capacity() as u64);
                 file_chunk.read_to_end(&mut compressed_table)?;
-                let mut decoder = ZlibDecoder::new(compressed_table.as_slice());
-                let mut decompressed_table = vec![];
+                let decoder = ZlibDecoder::new(compressed_table.as_slice());
+                let mut decompressed_table = Vec::with_capacity(grf_table_info.table_size);
                 let _decompressed_size =
-                    decoder.read_to_end(&mut decompressed_table).map_err(|e| {
-                        GrufError::ParsingError(format!("Failed to decompress file table: {}", e))
-                    })?;
+                    decompress_bounded(decoder, grf_table_info.table_size, &mut decompressed_table)
+                        .map_err(|e| match e {
+                            GrufError::IoError(e) => GrufError::TableDecompressionError {
+                                offset: table_info_offset + GRF_TABLE_INFO2_SIZE as u64,
+                                message: e.to_string(),
+                            },
+                            e => e,
+                        })?;
                 // Parse entries
                 let (_output, entries) = parse_grf_file_entries_200(
                     decompressed_table.as_slice(),
@@ -86,7 +97,7 @@ impl GrfArchive {
             1 => {
                 // Only versions 1.1, 1.2 and 1.3 are supported
                 if grf_header.version_minor < 1 || grf_header.version_minor > 3 {
-                    return Err(GrufError::parsing_error("Unsupported archive version"));
+                    return Err(GrufError::UnsupportedGrfVersion(grf_header.version()));
                 }
                 let table_size = parser_output.len();
                 if table_size == 0 {
@@ -115,7 +126,7 @@ impl GrfArchive {
                     },
                 })
             }
-            _ => Err(GrufError::parsing_error("Unsupported archive version")),
+            _ => Err(GrufError::UnsupportedGrfVersion(grf_header.version())),
         }
     }
 
@@ -163,17 +174,26 @@ impl GrfArchive {
         match file_entry.encryption {
             GrfFileEncryption::Unencrypted => {}
             GrfFileEncryption::Encrypted(cycle) => {
+                let trailing_bytes = content.len() % DES_BLOCK_SIZE;
+                if trailing_bytes > 0 {
+                    return Err(GrufError::DesDecryptionError(format!(
+                        "Content size ({}) is not a multiple of the block size",
+                        content.len()
+                    )));
+                }
                 decrypt_file_content(&mut content, cycle);
             }
         }
         // Decompress the content with zlib
-        let mut decoder = ZlibDecoder::new(content.as_slice());
-        let mut decompressed_content = Vec::new();
-        let decompressed_size = decoder.read_to_end(&mut decompressed_content)?;
+        let decoder = ZlibDecoder::new(content.as_slice());
+        let mut decompressed_content = Vec::with_capacity(file_entry.size);
+        let decompressed_size =
+            decompress_bounded(decoder, file_entry.size, &mut decompressed_content)?;
         if decompressed_size != file_entry.size {
-            return Err(GrufError::parsing_error(
-                "Decompressed content is not as expected",
-            ));
+            return Err(GrufError::SizeMismatch {
+                expected: file_entry.size,
+                actual: decompressed_size,
+            });
         }
         Ok(decompressed_content)
     }
@@ -191,6 +211,47 @@ impl GrfArchive {
     }
 }
 
+/// Version of a GRF's format, see `grf_version`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrfVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl GrfVersion {
+    /// Returns the version as stored in the header (e.g., 0x200 for 2.0).
+    pub fn as_u32(&self) -> u32 {
+        (self.major << 8) | self.minor
+    }
+}
+
+/// Reads the version of the GRF located at `grf_path`, without parsing its
+/// file table.
+///
+/// Fails with `GrufError::UnsupportedGrfVersion` if the version isn't
+/// supported by `GrfArchive` (i.e., isn't 1.1, 1.2, 1.3 or 2.0).
+pub fn grf_version<P: AsRef<Path>>(grf_path: P) -> Result<GrfVersion> {
+    let mut file = File::open(grf_path)?;
+    let mut grf_header_buf = [0; GRF_HEADER_SIZE];
+    file.read_exact(&mut grf_header_buf)?;
+    if !grf_header_buf.starts_with(GRF_HEADER_MAGIC.as_bytes()) {
+        return Err(GrufError::invalid_magic(
+            &grf_header_buf,
+            GRF_HEADER_MAGIC.len(),
+        ));
+    }
+    let (_, grf_header) = parse_grf_header(&grf_header_buf)
+        .map_err(|_| GrufError::parsing_error("Failed to parse archive (header)"))?;
+    let version = GrfVersion {
+        major: grf_header.version_major,
+        minor: grf_header.version_minor,
+    };
+    match (version.major, version.minor) {
+        (2, 0) | (1, 1..=3) => Ok(version),
+        _ => Err(GrufError::UnsupportedGrfVersion(version.as_u32())),
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 struct GrfContainer {
     pub header: GrfHeader,
@@ -208,6 +269,17 @@ pub struct GrfHeader {
     pub version_minor: u32,
 }
 
+impl GrfHeader {
+    /// Returns the version as stored in the header (e.g., 0x200 for 2.0).
+    pub fn version(&self) -> u32 {
+        GrfVersion {
+            major: self.version_major,
+            minor: self.version_minor,
+        }
+        .as_u32()
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 enum GrfTableInfo {
     Uncompressed(GrfTableInfo1),
@@ -284,20 +356,12 @@ named!(parse_grf_table_info_200<&[u8], GrfTableInfo2>,
     )
 ));
 
-fn string_from_win_1252(v: &[u8]) -> Result<String> {
-    let decoder = encoding_from_whatwg_label("windows-1252")
-        .ok_or_else(|| GrufError::parsing_error("Decoder unavailable"))?;
-    decoder
-        .decode(v, DecoderTrap::Strict)
-        .map_err(GrufError::parsing_error)
-}
-
 macro_rules! take_obfuscated_name_101 (
     ( $i:expr, $size:expr ) => (
         {
             let input: &[u8] = $i;
             let (parser_output, file_name_bytes) = map_res!(input, take!($size), decrypt_file_name)?;
-            match string_from_win_1252(file_name_bytes.as_slice()) {
+            match decode_ansi_path(file_name_bytes.as_slice()) {
                 Ok(v) => Ok((parser_output , v)),
                 Err(_) => Err(nom::Err::Failure((parser_output, ErrorKind::AlphaNumeric))),
             }
@@ -361,7 +425,7 @@ named!(parse_grf_file_entry_101<&[u8], GrfFileEntry>,
 // Parses file table entries for GRF 2.0
 named!(parse_grf_file_entry_200<&[u8], GrfFileEntry>,
     do_parse!(
-        relative_path: map_res!(take_while!(|ch: u8| ch != 0), string_from_win_1252)
+        relative_path: map_res!(take_while!(|ch: u8| ch != 0), decode_ansi_path)
             >> take!(1) // Null char terminator
             >> size_compressed: le_u32
             >> size_compressed_aligned: le_u32
@@ -400,6 +464,7 @@ mod tests {
     use super::*;
     use hex_literal::hex;
     use std::path::PathBuf;
+    use tempfile::tempdir;
     use twox_hash::XxHash64;
 
     #[test]
@@ -419,11 +484,11 @@ mod tests {
                 (69798, u64::from_be_bytes(hex!("519d99273b1b4d38"))),
             ),
             (
-                "data\\sprite\\\u{B8}\u{F3}\u{BD}\u{BA}\u{C5}\u{CD}\\high_orc.act",
+                "data\\sprite\\몬스터\\high_orc.act",
                 (491076, u64::from_be_bytes(hex!("5f26d5f20679a2af"))),
             ),
             (
-                "data\\sprite\\\u{B8}\u{F3}\u{BD}\u{BA}\u{C5}\u{CD}\\high_orc.spr",
+                "data\\sprite\\몬스터\\high_orc.spr",
                 (250592, u64::from_be_bytes(hex!("b8356a4d4517df6e"))),
             ),
             (
@@ -519,4 +584,50 @@ mod tests {
         assert_eq!(3, digit_count(100));
         assert_eq!(8, digit_count(87654321));
     }
+
+    #[test]
+    fn test_grf_version() {
+        let grf_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/grf");
+        for (grf_file_name, expected_version) in &[
+            ("102-small.grf", 0x102),
+            ("103-empty.grf", 0x103),
+            ("200-small.grf", 0x200),
+        ] {
+            let version = grf_version(grf_dir_path.join(grf_file_name)).unwrap();
+            assert_eq!(version.as_u32(), *expected_version);
+            let grf_archive = GrfArchive::open(grf_dir_path.join(grf_file_name)).unwrap();
+            assert_eq!(version.major, grf_archive.version_major());
+            assert_eq!(version.minor, grf_archive.version_minor());
+        }
+    }
+
+    #[test]
+    fn test_open_invalid_grf() {
+        let grf_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/grf");
+        let temp_dir = tempdir().unwrap();
+        let grf_content = std::fs::read(grf_dir_path.join("200-small.grf")).unwrap();
+        // Invalid magic
+        let grf_path = temp_dir.path().join("invalid-magic.grf");
+        let mut invalid_content = grf_content.clone();
+        invalid_content[0] = b'X';
+        std::fs::write(&grf_path, invalid_content).unwrap();
+        assert!(matches!(
+            GrfArchive::open(&grf_path).unwrap_err(),
+            GrufError::InvalidMagic { found } if found.starts_with(b"Xaster of Magic")
+        ));
+        // Unsupported version
+        let grf_path = temp_dir.path().join("invalid-version.grf");
+        let mut invalid_content = grf_content;
+        let version_offset = GRF_HEADER_SIZE - std::mem::size_of::<u32>();
+        invalid_content[version_offset..GRF_HEADER_SIZE].copy_from_slice(&0x300u32.to_le_bytes());
+        std::fs::write(&grf_path, invalid_content).unwrap();
+        assert!(matches!(
+            GrfArchive::open(&grf_path).unwrap_err(),
+            GrufError::UnsupportedGrfVersion(0x300)
+        ));
+        assert!(matches!(
+            grf_version(&grf_path).unwrap_err(),
+            GrufError::UnsupportedGrfVersion(0x300)
+        ));
+    }
 }
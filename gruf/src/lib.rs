@@ -3,4 +3,5 @@ mod error;
 pub mod grf;
 pub mod thor;
 
+pub use archive::string_from_win_1252;
 pub use error::{GrufError, Result};
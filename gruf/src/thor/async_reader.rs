@@ -0,0 +1,210 @@
+use std::hash::Hash;
+use std::io::SeekFrom;
+use std::path::Path;
+
+use tokio::fs;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+
+use crate::thor::reader::{
+    check_payload_size, check_thor_path_is_safe, decompress_entry, parse_thor_header_chunk,
+    HeaderChunkParsing, PartFileGuard, ThorContainer, HEADER_EXTENDED_MAX_SIZE,
+};
+use crate::thor::{PatchKind, ThorFileEntry, ThorOptions};
+use crate::{GrufError, Result};
+
+/// Asynchronous counterpart of `ThorArchive`, for tokio readers.
+///
+/// The header and the file table are small, they're read in memory and
+/// parsed like `ThorArchive` does. Files' compressed content is read in
+/// memory as well, then decompressed with the archive's decompressor (see
+/// `ThorOptions::decompressor`).
+pub struct ThorArchiveAsync<R> {
+    obj: R,
+    container: ThorContainer,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> ThorArchiveAsync<R> {
+    /// Create a new archive with the underlying object as the reader.
+    pub async fn new(obj: R) -> Result<ThorArchiveAsync<R>> {
+        Self::with_options(obj, &ThorOptions::default()).await
+    }
+
+    /// Create a new archive with the underlying object as the reader, using
+    /// the given parsing options.
+    pub async fn with_options(mut obj: R, options: &ThorOptions) -> Result<ThorArchiveAsync<R>> {
+        let mut thor_header_buf = Vec::with_capacity(HEADER_EXTENDED_MAX_SIZE);
+        (&mut obj)
+            .take(thor_header_buf.capacity() as u64)
+            .read_to_end(&mut thor_header_buf)
+            .await?;
+        let container = match parse_thor_header_chunk(&thor_header_buf, options)? {
+            HeaderChunkParsing::Complete(container) => container,
+            HeaderChunkParsing::MissingTable(pending_table) => {
+                obj.seek(SeekFrom::Start(pending_table.offset())).await?;
+                let mut compressed_table: Vec<u8> =
+                    Vec::with_capacity(pending_table.compressed_size());
+                (&mut obj)
+                    .take(compressed_table.capacity() as u64)
+                    .read_to_end(&mut compressed_table)
+                    .await?;
+                pending_table.parse_with_progress(&compressed_table, options, &mut |_| {})?
+            }
+        };
+        Ok(ThorArchiveAsync { obj, container })
+    }
+
+    /// See `ThorArchive::manifest`.
+    pub fn manifest(&self) -> &ThorContainer {
+        &self.container
+    }
+
+    pub fn use_grf_merging(&self) -> bool {
+        self.container.header.use_grf_merging
+    }
+
+    pub fn target_grf_name(&self) -> String {
+        self.container.header.target_grf_name.clone()
+    }
+
+    /// See `ThorArchive::patch_kind`.
+    pub fn patch_kind(&self) -> PatchKind {
+        self.container.header.patch_kind()
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.container.entries().len()
+    }
+
+    /// See `ThorArchive::get_file_entry`.
+    pub fn get_file_entry<S: AsRef<str> + Hash>(&self, file_path: S) -> Option<&ThorFileEntry> {
+        self.container.get_file_entry(file_path.as_ref())
+    }
+
+    /// Returns the archive's entries, in table order.
+    pub fn get_entries(&self) -> impl Iterator<Item = &'_ ThorFileEntry> {
+        self.container.entries().iter()
+    }
+
+    pub async fn read_file_content<S: AsRef<str> + Hash>(
+        &mut self,
+        file_path: S,
+    ) -> Result<Vec<u8>> {
+        let file_entry = match self.get_file_entry(file_path) {
+            None => return Err(GrufError::EntryNotFound),
+            Some(entry) if entry.is_removed => return Err(GrufError::EntryRemoved),
+            Some(entry) => entry.clone(),
+        };
+        if file_entry.size_compressed == 0 {
+            return Ok(vec![]);
+        }
+
+        self.obj.seek(SeekFrom::Start(file_entry.offset)).await?;
+        let mut compressed_content = Vec::with_capacity(file_entry.size_compressed);
+        (&mut self.obj)
+            .take(file_entry.size_compressed as u64)
+            .read_to_end(&mut compressed_content)
+            .await?;
+        check_payload_size(&file_entry, compressed_content.len())?;
+        decompress_entry(
+            self.container.decompressor(),
+            &file_entry,
+            &compressed_content,
+        )
+    }
+
+    /// See `ThorArchive::extract_file`.
+    pub async fn extract_file<S, P>(&mut self, file_path: S, destination_path: P) -> Result<()>
+    where
+        S: AsRef<str> + Hash,
+        P: AsRef<Path>,
+    {
+        check_thor_path_is_safe(file_path.as_ref())?;
+        let destination_path = destination_path.as_ref();
+        if matches!(self.get_file_entry(file_path.as_ref()), Some(e) if e.is_directory() && !e.is_removed)
+        {
+            return Ok(fs::create_dir_all(destination_path).await?);
+        }
+        let content = self.read_file_content(file_path).await?;
+        if let Some(parent_dir) = destination_path.parent() {
+            fs::create_dir_all(parent_dir).await?;
+        }
+        // Write the content into a temporary file first, see
+        // `ThorArchive::extract_file`
+        let part_file = PartFileGuard::new(destination_path);
+        fs::write(part_file.part_path(), content).await?;
+        part_file.persist()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thor::ThorArchive;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_read_file_content() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        for thor_file_name in &["small.thor", "dir1.thor", "dir2.thor"] {
+            let thor_file_path = thor_dir_path.join(thor_file_name);
+            let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+            let thor_file = fs::File::open(&thor_file_path).await.unwrap();
+            let mut async_thor_archive = ThorArchiveAsync::new(thor_file).await.unwrap();
+            assert_eq!(async_thor_archive.file_count(), thor_archive.file_count());
+            assert_eq!(
+                async_thor_archive.use_grf_merging(),
+                thor_archive.use_grf_merging()
+            );
+            let file_entries: Vec<ThorFileEntry> = thor_archive
+                .get_entries()
+                .filter(|e| !e.is_removed)
+                .cloned()
+                .collect();
+            for entry in file_entries {
+                assert_eq!(
+                    async_thor_archive
+                        .read_file_content(&entry.relative_path)
+                        .await
+                        .unwrap(),
+                    thor_archive
+                        .read_file_content(&entry.relative_path)
+                        .unwrap()
+                );
+            }
+            assert!(matches!(
+                async_thor_archive.read_file_content("missing.txt").await,
+                Err(GrufError::EntryNotFound)
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extract_file() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let temp_dir = tempdir().unwrap();
+        let thor_file = fs::File::open(thor_dir_path.join("dir2.thor"))
+            .await
+            .unwrap();
+        let mut thor_archive = ThorArchiveAsync::new(thor_file).await.unwrap();
+        let entry = thor_archive.get_entries().next().unwrap().clone();
+        let destination_path = temp_dir.path().join("output").join("file.bin");
+        thor_archive
+            .extract_file(&entry.relative_path, &destination_path)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(&destination_path).unwrap(),
+            thor_archive
+                .read_file_content(&entry.relative_path)
+                .await
+                .unwrap()
+        );
+        assert!(matches!(
+            thor_archive
+                .extract_file("..\\evil.txt", temp_dir.path().join("evil.txt"))
+                .await,
+            Err(GrufError::UnsafePath(_))
+        ));
+    }
+}
@@ -1,27 +1,41 @@
 use std::boxed::Box;
 use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::io::{self, Read, Seek, SeekFrom, Write};
 
-use crate::archive::{serialize_as_win1252_str_into, serialize_to_win1252, GenericFileEntry};
+use crate::archive::{serialize_as_ansi_str_into, serialize_to_ansi, GenericFileEntry};
 use crate::thor::{
-    ThorMode, INTEGRITY_FILE_NAME, MULTIPLE_FILES_TABLE_DESC_SIZE, THOR_HEADER_MAGIC,
+    ThorMode, INTEGRITY_FILE_NAME, MULTIPLE_FILES_TABLE_DESC_SIZE, THOR_HEADER_FIXED_SIZE,
+    THOR_HEADER_MAGIC, THOR_MAX_TABLE_OFFSET,
 };
-use crate::Result;
+use crate::{GrufError, Result};
 use crc::crc32::{self, Hasher32};
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
+use indexmap::IndexMap;
 use serde::Serialize;
 
-const THOR_HEADER_FIXED_SIZE: usize = THOR_HEADER_MAGIC.len() + 0x8;
-
+/// Writes multiple-files THOR archives.
+///
+/// Offsets are stored as 32-bit integers: `finish` fails with
+/// `GrufError::OffsetOutOfBounds` if an entry's content is written past
+/// `THOR_MAX_ENTRY_OFFSET` or the file table past `THOR_MAX_TABLE_OFFSET`.
 pub struct ThorArchiveBuilder<W: Write + Seek> {
     obj: Box<W>,
-    entries: HashMap<String, Option<BuilderFileEntry>>,
+    // Entries are written in the order they've been first appended
+    entries: IndexMap<String, Option<BuilderFileEntry>>,
     finished: bool,
+    // Whether room has been made for the header at the start of the archive
+    header_reserved: bool,
     use_grf_merging: bool,
     target_grf_name: String,
     include_checksums: bool,
+    compression: Compression,
+    // Compressed data written so far and its offset, when deduplicating
+    // payloads. Payloads are matched on their whole content, a mere hash
+    // collision would make entries point to another file's data.
+    written_payloads: Option<HashMap<Vec<u8>, u64>>,
+    deduplicated_count: usize,
 }
 
 struct BuilderFileEntry {
@@ -47,6 +61,15 @@ pub struct SerializableFileTableDesc {
     pub file_table_offset: u32,
 }
 
+#[derive(Debug, Serialize)]
+pub struct SerializableSingleFileEntry {
+    reserved: u8,
+    size_compressed: u32,
+    size: u32,
+    // relative_path_size: u8,
+    // relative_path: &'a [u8],
+}
+
 #[derive(Debug, Serialize)]
 pub struct SerializableThorFileEntryAdd {
     // relative_path_size: u8,
@@ -59,35 +82,95 @@ pub struct SerializableThorFileEntryAdd {
 
 impl<W: Write + Seek> ThorArchiveBuilder<W> {
     pub fn new(
-        mut obj: W,
+        obj: W,
         use_grf_merging: bool,
         target_grf_name: Option<String>,
         include_checksums: bool,
     ) -> Result<Self> {
-        let target_grf_name = target_grf_name.unwrap_or_default();
-        // Placeholder for the THOR header
-        let place_holder =
-            vec![
-                0;
-                THOR_HEADER_FIXED_SIZE + target_grf_name.len() + MULTIPLE_FILES_TABLE_DESC_SIZE
-            ];
-        obj.write_all(place_holder.as_slice())?;
+        // Room for the header is made when the first file is appended, so
+        // that the target GRF's name can still be changed until then
         Ok(Self {
             obj: Box::new(obj),
-            entries: HashMap::new(),
+            entries: IndexMap::new(),
             finished: false,
+            header_reserved: false,
             use_grf_merging,
-            target_grf_name,
+            target_grf_name: target_grf_name.unwrap_or_default(),
             include_checksums,
+            compression: Compression::default(),
+            written_payloads: None,
+            deduplicated_count: 0,
         })
     }
 
+    /// Sets the zlib compression level used for files' content and the file
+    /// table (`Compression::default()` by default).
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Enables the deduplication of files' content: compressed data identical
+    /// to data already written is stored once, and all the entries using it
+    /// point to the same offset.
+    ///
+    /// The compressed data is kept in memory until the builder is dropped,
+    /// to compare new payloads with the ones already written.
+    pub fn with_deduplication(mut self) -> Self {
+        self.written_payloads = Some(HashMap::new());
+        self
+    }
+
+    /// Returns the number of entries whose compressed data hasn't been
+    /// written because it was identical to data already written (see
+    /// `ThorArchiveBuilder::with_deduplication`).
+    pub fn deduplicated_count(&self) -> usize {
+        self.deduplicated_count
+    }
+
+    /// Sets whether the archive must be merged into a GRF or applied to the
+    /// client's directory.
+    pub fn set_grf_merging(&mut self, use_grf_merging: bool) {
+        self.use_grf_merging = use_grf_merging;
+    }
+
+    /// Sets the name of the GRF the archive must be merged into (an empty
+    /// name designates the client's default GRF).
+    ///
+    /// The header's size depends on the name's size, which thus cannot change
+    /// once files have been appended.
+    pub fn set_target_grf_name(&mut self, target_grf_name: String) -> Result<()> {
+        if self.header_reserved && target_grf_name.len() != self.target_grf_name.len() {
+            return Err(GrufError::serialization_error(
+                "Target GRF name's size cannot change once files have been appended",
+            ));
+        }
+        self.target_grf_name = target_grf_name;
+        Ok(())
+    }
+
+    /// Writes a placeholder for the THOR header, if it hasn't been done yet.
+    fn reserve_header(&mut self) -> Result<()> {
+        if self.header_reserved {
+            return Ok(());
+        }
+        let place_holder = vec![
+            0;
+            THOR_HEADER_FIXED_SIZE
+                + self.target_grf_name.len()
+                + MULTIPLE_FILES_TABLE_DESC_SIZE
+        ];
+        self.obj.write_all(place_holder.as_slice())?;
+        self.header_reserved = true;
+        Ok(())
+    }
+
     pub fn append_file_update<R>(&mut self, entry_path: String, mut data: R) -> Result<()>
     where
         R: Read,
     {
         // Compress it
-        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        let mut encoder = ZlibEncoder::new(Vec::new(), self.compression);
         let (data_size, data_checksum) = if self.include_checksums {
             copy_and_measure_crc32(data.by_ref(), &mut encoder)?
         } else {
@@ -95,25 +178,71 @@ impl<W: Write + Seek> ThorArchiveBuilder<W> {
         };
         // Write compressed data
         let compressed_data = encoder.finish()?;
-        let compressed_data_size = compressed_data.len();
+        self.append_raw_file_update(
+            entry_path,
+            &compressed_data,
+            u32::try_from(data_size)?,
+            data_checksum,
+        )
+    }
 
-        let offset = self.obj.seek(SeekFrom::Current(0))?;
-        let mut compressed_reader = Cursor::new(compressed_data);
-        let _ = io::copy(&mut compressed_reader, self.obj.by_ref())?;
+    /// Appends an entry whose content is already compressed with zlib (e.g.,
+    /// read from another archive with `ThorArchive::get_entry_raw_data`),
+    /// without recompressing it.
+    ///
+    /// `size` is the size of the decompressed content and `checksum` its
+    /// CRC32 checksum, which is only used when checksums are included (pass
+    /// 0 otherwise). The compressed data isn't checked.
+    pub fn append_raw_file_update(
+        &mut self,
+        entry_path: String,
+        compressed_data: &[u8],
+        size: u32,
+        checksum: u32,
+    ) -> Result<()> {
+        self.reserve_header()?;
+        let offset = match self.written_payload_offset(compressed_data) {
+            Some(offset) => {
+                self.deduplicated_count += 1;
+                offset
+            }
+            None => {
+                let offset = self.obj.seek(SeekFrom::Current(0))?;
+                self.obj.write_all(compressed_data)?;
+                if let Some(written_payloads) = &mut self.written_payloads {
+                    written_payloads.insert(compressed_data.to_vec(), offset);
+                }
+                offset
+            }
+        };
         self.entries.insert(
             entry_path,
             Some(BuilderFileEntry {
                 generic: GenericFileEntry {
                     offset,
-                    size: u32::try_from(data_size)?,
-                    size_compressed: u32::try_from(compressed_data_size)?,
+                    size,
+                    size_compressed: u32::try_from(compressed_data.len())?,
                 },
-                checksum: data_checksum,
+                checksum,
             }),
         );
         Ok(())
     }
 
+    /// Returns the offset of `compressed_data` if it's already been written
+    /// and payloads are deduplicated.
+    fn written_payload_offset(&self, compressed_data: &[u8]) -> Option<u64> {
+        if compressed_data.is_empty() {
+            return None;
+        }
+        let written_payloads = self.written_payloads.as_ref()?;
+        written_payloads.get(compressed_data).copied()
+    }
+
+    pub(crate) fn includes_checksums(&self) -> bool {
+        self.include_checksums
+    }
+
     pub fn append_file_removal(&mut self, entry_path: String) {
         self.entries.insert(entry_path, None);
     }
@@ -128,6 +257,7 @@ impl<W: Write + Seek> ThorArchiveBuilder<W> {
         if self.include_checksums {
             self.append_data_integrity()?;
         }
+        self.reserve_header()?;
         let (file_table_offset, compressed_table_size) = self.write_file_table()?;
         // Update the header
         self.obj.seek(SeekFrom::Start(0))?;
@@ -145,30 +275,14 @@ impl<W: Write + Seek> ThorArchiveBuilder<W> {
         let mut table: Vec<u8> = Vec::new();
         // Generate table and write files' content
         for (relative_path, entry) in &self.entries {
-            let mut rel_path_win1252 = Vec::with_capacity(relative_path.len());
-            serialize_as_win1252_str_into(&mut rel_path_win1252, relative_path)?;
-            match entry {
-                None => {
-                    // No entry, this is a file removal
-                    const REMOVE_FILE: u8 = 1;
-                    serialize_thor_slice_into(&mut table, rel_path_win1252.as_slice())?;
-                    bincode::serialize_into(&mut table, &REMOVE_FILE)?;
-                }
-                Some(entry) => {
-                    // File update or file creation
-                    let thor_file_entry = SerializableThorFileEntryAdd {
-                        flags: 0,
-                        offset: u32::try_from(entry.generic.offset)?,
-                        size: entry.generic.size,
-                        size_compressed: entry.generic.size_compressed,
-                    };
-                    serialize_thor_slice_into(&mut table, rel_path_win1252.as_slice())?;
-                    bincode::serialize_into(&mut table, &thor_file_entry)?;
-                }
-            }
+            serialize_thor_file_entry_into(
+                &mut table,
+                relative_path,
+                entry.as_ref().map(|e| &e.generic),
+            )?;
         }
         // Compress the table
-        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        let mut encoder = ZlibEncoder::new(Vec::new(), self.compression);
         encoder.write_all(&table)?;
         let compressed_table = encoder.finish()?;
         let compressed_table_size = compressed_table.len();
@@ -196,7 +310,7 @@ impl<W: Write + Seek> ThorArchiveBuilder<W> {
                 acc
             }
         });
-        serialize_to_win1252(content.as_str())
+        serialize_to_ansi(content.as_str())
     }
 }
 
@@ -207,6 +321,186 @@ impl<W: Write + Seek> Drop for ThorArchiveBuilder<W> {
     }
 }
 
+/// Collects files and removals in memory, then writes them as an archive
+/// whose mode is chosen from its content.
+///
+/// Archives containing exactly one file and no removals are written in
+/// 'single file' mode, other archives in 'multiple files' mode (see
+/// `write_single_file_archive` and `ThorArchiveBuilder`). The mode can be
+/// forced with `mode`.
+#[derive(Debug, Default, Clone)]
+pub struct ThorBuilder {
+    // `None` marks a removal
+    entries: IndexMap<String, Option<Vec<u8>>>,
+    use_grf_merging: bool,
+    target_grf_name: Option<String>,
+    forced_mode: Option<ThorMode>,
+    compression: Compression,
+}
+
+impl ThorBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a file, replacing any file or removal previously added with the
+    /// same path.
+    pub fn add<S: Into<String>>(mut self, entry_path: S, data: Vec<u8>) -> Self {
+        self.entries.insert(entry_path.into(), Some(data));
+        self
+    }
+
+    /// Adds a file removal, replacing any file previously added with the
+    /// same path.
+    pub fn delete<S: Into<String>>(mut self, entry_path: S) -> Self {
+        self.entries.insert(entry_path.into(), None);
+        self
+    }
+
+    /// Sets the name of the GRF the archive must be merged into (the
+    /// client's default GRF by default).
+    pub fn target_grf<S: Into<String>>(mut self, target_grf_name: S) -> Self {
+        self.target_grf_name = Some(target_grf_name.into());
+        self
+    }
+
+    /// Sets whether the archive must be merged into a GRF or applied to the
+    /// client's directory (the default).
+    pub fn grf_merging(mut self, use_grf_merging: bool) -> Self {
+        self.use_grf_merging = use_grf_merging;
+        self
+    }
+
+    /// Sets the zlib compression level used for files' content and the file
+    /// table (`Compression::default()` by default).
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Forces the archive's mode instead of choosing it from its content.
+    pub fn mode(mut self, mode: ThorMode) -> Self {
+        self.forced_mode = Some(mode);
+        self
+    }
+
+    /// Returns the mode the archive would be written in.
+    ///
+    /// Fails with a `GrufError::SerializationError` if the mode has been
+    /// forced to `ThorMode::SingleFile` but the archive doesn't contain
+    /// exactly one file and no removals, or to `ThorMode::Invalid`.
+    pub fn resolve_mode(&self) -> Result<ThorMode> {
+        let is_single_file =
+            self.entries.len() == 1 && self.entries.values().all(|data| data.is_some());
+        match self.forced_mode {
+            None if is_single_file => Ok(ThorMode::SingleFile),
+            None | Some(ThorMode::MultipleFiles) => Ok(ThorMode::MultipleFiles),
+            Some(ThorMode::SingleFile) if is_single_file => Ok(ThorMode::SingleFile),
+            Some(ThorMode::SingleFile) => Err(GrufError::serialization_error(
+                "Single-file archives must contain exactly one file and no removals",
+            )),
+            Some(ThorMode::Invalid) => Err(GrufError::serialization_error(
+                "Archives cannot be written in invalid mode",
+            )),
+        }
+    }
+
+    /// Writes the archive into `obj` and returns the mode it's been written
+    /// in.
+    pub fn build<W: Write + Seek>(self, obj: W) -> Result<ThorMode> {
+        let mode = self.resolve_mode()?;
+        if mode == ThorMode::SingleFile {
+            let (entry_path, data) = self
+                .entries
+                .iter()
+                .next()
+                .ok_or_else(|| GrufError::serialization_error("Missing file"))?;
+            write_single_file_archive(
+                obj,
+                self.use_grf_merging,
+                self.target_grf_name.as_deref(),
+                entry_path,
+                data.as_deref().unwrap_or_default(),
+                self.compression,
+            )?;
+            return Ok(mode);
+        }
+        let mut builder =
+            ThorArchiveBuilder::new(obj, self.use_grf_merging, self.target_grf_name, false)?
+                .with_compression(self.compression);
+        for (entry_path, data) in self.entries {
+            match data {
+                Some(data) => builder.append_file_update(entry_path, data.as_slice())?,
+                None => builder.append_file_removal(entry_path),
+            }
+        }
+        builder.finish()?;
+        Ok(mode)
+    }
+}
+
+/// Writes a single-file archive, containing only the file `entry_path`
+/// whose content is read from `data` and compressed with `compression`.
+///
+/// Single-file archives are smaller than multiple-files ones, since they
+/// don't have a separate file table, but they cannot contain file removals.
+/// Unlike `ThorArchiveBuilder`, the file's content is compressed in memory
+/// before being written, because it's stored after the entry's description.
+pub fn write_single_file_archive<W, R>(
+    writer: W,
+    use_grf_merging: bool,
+    target_grf_name: Option<&str>,
+    entry_path: &str,
+    mut data: R,
+    compression: Compression,
+) -> Result<()>
+where
+    W: Write,
+    R: Read,
+{
+    let mut encoder = ZlibEncoder::new(Vec::new(), compression);
+    let data_size = io::copy(&mut data, &mut encoder)?;
+    let compressed_data = encoder.finish()?;
+    write_raw_single_file_archive(
+        writer,
+        use_grf_merging,
+        target_grf_name,
+        entry_path,
+        &compressed_data,
+        u32::try_from(data_size)?,
+    )
+}
+
+/// Same as `write_single_file_archive` but with content already compressed
+/// with zlib, whose decompressed size is `size`.
+pub(crate) fn write_raw_single_file_archive<W: Write>(
+    mut writer: W,
+    use_grf_merging: bool,
+    target_grf_name: Option<&str>,
+    entry_path: &str,
+    compressed_data: &[u8],
+    size: u32,
+) -> Result<()> {
+    write_thor_header_prefix(
+        writer.by_ref(),
+        use_grf_merging,
+        1,
+        ThorMode::SingleFile,
+        target_grf_name.unwrap_or_default(),
+    )?;
+    let entry = SerializableSingleFileEntry {
+        reserved: 0,
+        size_compressed: u32::try_from(compressed_data.len())?,
+        size,
+    };
+    bincode::serialize_into(writer.by_ref(), &entry)?;
+    let mut rel_path_ansi = Vec::with_capacity(entry_path.len());
+    serialize_as_ansi_str_into(&mut rel_path_ansi, entry_path)?;
+    serialize_thor_slice_into(writer.by_ref(), rel_path_ansi.as_slice())?;
+    writer.write_all(compressed_data)?;
+    Ok(())
+}
+
 fn write_thor_header<W: Write>(
     writer: &mut W,
     use_grf_merging: bool,
@@ -214,21 +508,87 @@ fn write_thor_header<W: Write>(
     target_grf_name: &str,
     file_table_compressed_size: usize,
     file_table_offset: u64,
+) -> Result<()> {
+    write_thor_header_prefix(
+        writer.by_ref(),
+        use_grf_merging,
+        file_count,
+        ThorMode::MultipleFiles,
+        target_grf_name,
+    )?;
+    write_multiple_files_table_desc(
+        writer.by_ref(),
+        file_table_compressed_size,
+        file_table_offset,
+    )
+}
+
+/// Writes the part of the THOR header that's common to all modes (i.e., from
+/// the magic to the target GRF's name).
+pub(crate) fn write_thor_header_prefix<W: Write>(
+    mut writer: W,
+    use_grf_merging: bool,
+    file_count: usize,
+    mode: ThorMode,
+    target_grf_name: &str,
 ) -> Result<()> {
     let use_grf_merging: u8 = if use_grf_merging { 1 } else { 0 };
+    let mode = thor_mode_to_i16(mode)
+        .ok_or_else(|| GrufError::serialization_error("Invalid THOR mode"))?;
     let grf_header = SerializableThorHeader {
         magic: THOR_HEADER_MAGIC,
         use_grf_merging,
         file_count: u32::try_from(file_count)?,
-        mode: thor_mode_to_i16(ThorMode::MultipleFiles).unwrap(),
+        mode,
     };
+    bincode::serialize_into(writer.by_ref(), &grf_header)?;
+    serialize_thor_str_into(writer.by_ref(), target_grf_name)
+}
+
+/// Writes the file table descriptor of multiple-files archives.
+pub(crate) fn write_multiple_files_table_desc<W: Write>(
+    writer: W,
+    file_table_compressed_size: usize,
+    file_table_offset: u64,
+) -> Result<()> {
     let table_desc = SerializableFileTableDesc {
         file_table_compressed_size: u32::try_from(file_table_compressed_size)?,
-        file_table_offset: u32::try_from(file_table_offset)?,
+        file_table_offset: u32::try_from(file_table_offset)
+            .ok()
+            .filter(|&offset| u64::from(offset) <= THOR_MAX_TABLE_OFFSET)
+            .ok_or(GrufError::OffsetOutOfBounds)?,
     };
-    bincode::serialize_into(writer.by_ref(), &grf_header)?;
-    serialize_thor_str_into(writer.by_ref(), target_grf_name)?;
-    bincode::serialize_into(writer.by_ref(), &table_desc)?;
+    bincode::serialize_into(writer, &table_desc)?;
+    Ok(())
+}
+
+/// Serializes an entry of a multiple-files archive's file table into
+/// `writer`. A `None` entry indicates a file removal.
+pub(crate) fn serialize_thor_file_entry_into<W: Write>(
+    mut writer: W,
+    relative_path: &str,
+    entry: Option<&GenericFileEntry>,
+) -> Result<()> {
+    let mut rel_path_ansi = Vec::with_capacity(relative_path.len());
+    serialize_as_ansi_str_into(&mut rel_path_ansi, relative_path)?;
+    serialize_thor_slice_into(writer.by_ref(), rel_path_ansi.as_slice())?;
+    match entry {
+        None => {
+            // No entry, this is a file removal
+            const REMOVE_FILE: u8 = 1;
+            bincode::serialize_into(writer, &REMOVE_FILE)?;
+        }
+        Some(entry) => {
+            // File update or file creation
+            let thor_file_entry = SerializableThorFileEntryAdd {
+                flags: 0,
+                offset: u32::try_from(entry.offset).map_err(|_| GrufError::OffsetOutOfBounds)?,
+                size: entry.size,
+                size_compressed: entry.size_compressed,
+            };
+            bincode::serialize_into(writer, &thor_file_entry)?;
+        }
+    }
     Ok(())
 }
 
@@ -263,7 +623,7 @@ fn serialize_thor_slice_into<W: Write>(mut writer: W, slice: &[u8]) -> Result<()
 }
 
 /// Computes a CRC32 checksum from a reader.
-fn copy_and_measure_crc32<R: ?Sized, W: ?Sized>(
+pub(crate) fn copy_and_measure_crc32<R: ?Sized, W: ?Sized>(
     reader: &mut R,
     writer: &mut W,
 ) -> Result<(u64, u32)>
@@ -291,10 +651,36 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::thor::{ThorArchive, ThorFileEntry};
-    use std::fs::File;
+    use crate::thor::reader::parse_thor_patch;
+    use crate::thor::{ThorArchive, ThorFileEntry, ThorHeader};
+    use std::collections::HashMap;
+    use std::fs::{self, File};
+    use std::io::Cursor;
+    use std::path::PathBuf;
     use tempfile::tempdir;
 
+    #[test]
+    fn test_offset_limits() {
+        let mut table_desc = vec![];
+        write_multiple_files_table_desc(&mut table_desc, 16, THOR_MAX_TABLE_OFFSET).unwrap();
+        assert!(matches!(
+            write_multiple_files_table_desc(&mut table_desc, 16, THOR_MAX_TABLE_OFFSET + 1),
+            Err(GrufError::OffsetOutOfBounds)
+        ));
+        let mut entry = GenericFileEntry {
+            offset: crate::thor::THOR_MAX_ENTRY_OFFSET,
+            size: 16,
+            size_compressed: 8,
+        };
+        let mut table = vec![];
+        serialize_thor_file_entry_into(&mut table, "file.txt", Some(&entry)).unwrap();
+        entry.offset += 1;
+        assert!(matches!(
+            serialize_thor_file_entry_into(&mut table, "file.txt", Some(&entry)),
+            Err(GrufError::OffsetOutOfBounds)
+        ));
+    }
+
     #[test]
     fn test_empty() {
         let temp_dir = tempdir().unwrap();
@@ -332,6 +718,339 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_round_trip() {
+        let mut archive_content = vec![];
+        {
+            let mut builder =
+                ThorArchiveBuilder::new(Cursor::new(&mut archive_content), false, None, false)
+                    .unwrap();
+            builder.set_grf_merging(true);
+            builder
+                .set_target_grf_name("myserver.grf".to_string())
+                .unwrap();
+            builder
+                .append_file_update("data\\test1".to_string(), &[1u8; 64][..])
+                .unwrap();
+            builder.append_file_removal("data\\test2".to_string());
+            assert!(builder
+                .set_target_grf_name("other.grf".to_string())
+                .is_err());
+            builder.finish().unwrap();
+        }
+        let container = parse_thor_patch(&mut Cursor::new(&archive_content)).unwrap();
+        assert_eq!(
+            container.header,
+            ThorHeader {
+                prefix: None,
+                use_grf_merging: true,
+                file_count: 2,
+                mode: ThorMode::MultipleFiles,
+                target_grf_name: "myserver.grf".to_string(),
+            }
+        );
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[1u8; 64]).unwrap();
+        let expected_entries = [
+            ThorFileEntry {
+                size_compressed: encoder.finish().unwrap().len(),
+                size: 64,
+                relative_path: "data\\test1".to_string(),
+                raw_path: b"data\\test1".to_vec(),
+                is_removed: false,
+                flags: 0,
+                offset: (THOR_HEADER_FIXED_SIZE
+                    + "myserver.grf".len()
+                    + MULTIPLE_FILES_TABLE_DESC_SIZE) as u64,
+            },
+            ThorFileEntry {
+                size_compressed: 0,
+                size: 0,
+                relative_path: "data\\test2".to_string(),
+                raw_path: b"data\\test2".to_vec(),
+                is_removed: true,
+                flags: 1,
+                offset: 0,
+            },
+        ];
+        assert_eq!(container.entries().len(), expected_entries.len());
+        for (entry, expected_entry) in container.entries().iter().zip(&expected_entries) {
+            assert!(entry.is_identical_to(expected_entry), "{:?}", entry);
+        }
+        let mut thor_archive = ThorArchive::new(Cursor::new(&archive_content)).unwrap();
+        assert_eq!(
+            thor_archive.read_file_content("data\\test1").unwrap(),
+            vec![1u8; 64]
+        );
+    }
+
+    #[test]
+    fn test_single_file_archive() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let original_path = thor_dir_path.join("dir1.thor");
+        let mut original_archive = ThorArchive::open(&original_path).unwrap();
+        let content = original_archive.read_file_content("client.exe").unwrap();
+        let mut archive_content = vec![];
+        write_single_file_archive(
+            &mut archive_content,
+            false,
+            None,
+            "client.exe",
+            content.as_slice(),
+            Compression::default(),
+        )
+        .unwrap();
+
+        let mut thor_archive = ThorArchive::new(Cursor::new(&archive_content)).unwrap();
+        assert_eq!(thor_archive.mode(), ThorMode::SingleFile);
+        assert_eq!(thor_archive.file_count(), 1);
+        assert_eq!(thor_archive.single_file_reserved_byte(), Some(0));
+        assert!(!thor_archive.use_grf_merging());
+        let entry = thor_archive.get_file_entry("client.exe").unwrap().clone();
+        let original_entry = original_archive.get_file_entry("client.exe").unwrap();
+        assert_eq!(entry.offset, original_entry.offset);
+        assert_eq!(entry.size, original_entry.size);
+        assert_eq!(
+            entry.offset as usize + entry.size_compressed,
+            archive_content.len()
+        );
+        assert_eq!(
+            thor_archive.read_file_content("client.exe").unwrap(),
+            content
+        );
+        // Everything but the compressed sizes matches the archive generated
+        // by Thor
+        let original_content = fs::read(&original_path).unwrap();
+        let size_compressed_range = THOR_HEADER_FIXED_SIZE + 1..THOR_HEADER_FIXED_SIZE + 5;
+        assert_eq!(
+            archive_content[..size_compressed_range.start],
+            original_content[..size_compressed_range.start]
+        );
+        assert_eq!(
+            archive_content[size_compressed_range.end..entry.offset as usize],
+            original_content[size_compressed_range.end..entry.offset as usize]
+        );
+    }
+
+    #[test]
+    fn test_thor_builder() {
+        let build = |builder: ThorBuilder| {
+            let mut archive_content = vec![];
+            let mode = builder.build(Cursor::new(&mut archive_content)).unwrap();
+            (mode, ThorArchive::from_bytes(archive_content).unwrap())
+        };
+        // A single file
+        let (mode, mut thor_archive) = build(
+            ThorBuilder::new()
+                .add("data\\file.txt", vec![1u8; 64])
+                .target_grf("data.grf")
+                .grf_merging(true),
+        );
+        assert_eq!(mode, ThorMode::SingleFile);
+        assert_eq!(thor_archive.mode(), ThorMode::SingleFile);
+        assert_eq!(thor_archive.target_grf_name(), "data.grf");
+        assert!(thor_archive.use_grf_merging());
+        assert_eq!(
+            thor_archive.read_file_content("data\\file.txt").unwrap(),
+            vec![1u8; 64]
+        );
+        // A single file, forced in 'multiple files' mode
+        let (mode, thor_archive) = build(
+            ThorBuilder::new()
+                .add("data\\file.txt", vec![1u8; 64])
+                .mode(ThorMode::MultipleFiles),
+        );
+        assert_eq!(mode, ThorMode::MultipleFiles);
+        assert_eq!(thor_archive.mode(), ThorMode::MultipleFiles);
+        // Several files or removals
+        for builder in &[
+            ThorBuilder::new()
+                .add("data\\file1.txt", vec![1u8; 64])
+                .add("data\\file2.txt", vec![2u8; 64]),
+            ThorBuilder::new()
+                .add("data\\file1.txt", vec![1u8; 64])
+                .delete("data\\file2.txt"),
+            ThorBuilder::new().delete("data\\file2.txt"),
+            ThorBuilder::new(),
+        ] {
+            let (mode, thor_archive) = build(builder.clone());
+            assert_eq!(mode, ThorMode::MultipleFiles);
+            assert_eq!(thor_archive.mode(), ThorMode::MultipleFiles);
+            assert_eq!(thor_archive.file_count(), builder.entries.len());
+        }
+        // Invalid combinations
+        for builder in &[
+            ThorBuilder::new()
+                .delete("data\\file.txt")
+                .mode(ThorMode::SingleFile),
+            ThorBuilder::new()
+                .add("data\\file1.txt", vec![1u8; 64])
+                .add("data\\file2.txt", vec![2u8; 64])
+                .mode(ThorMode::SingleFile),
+            ThorBuilder::new().mode(ThorMode::SingleFile),
+            ThorBuilder::new().mode(ThorMode::Invalid),
+        ] {
+            assert!(matches!(
+                builder.clone().build(Cursor::new(vec![])),
+                Err(GrufError::SerializationError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_compression_level() {
+        let content: Vec<u8> = (0..4096u32).map(|i| (i % 7) as u8).collect();
+        let build_archive = |compression: Compression| {
+            let mut archive_content = vec![];
+            {
+                let mut builder =
+                    ThorArchiveBuilder::new(Cursor::new(&mut archive_content), false, None, false)
+                        .unwrap()
+                        .with_compression(compression);
+                builder
+                    .append_file_update("data\\test".to_string(), content.as_slice())
+                    .unwrap();
+            }
+            archive_content
+        };
+        let uncompressed_archive = build_archive(Compression::none());
+        let compressed_archive = build_archive(Compression::best());
+        assert!(uncompressed_archive.len() > compressed_archive.len());
+        // `ThorBuilder` uses the level in both modes
+        let build_with_thor_builder = |compression: Compression, mode: ThorMode| {
+            let mut archive_content = vec![];
+            ThorBuilder::new()
+                .add("data\\test", content.clone())
+                .with_compression(compression)
+                .mode(mode)
+                .build(Cursor::new(&mut archive_content))
+                .unwrap();
+            archive_content
+        };
+        let mut archives = vec![uncompressed_archive, compressed_archive];
+        for &mode in &[ThorMode::SingleFile, ThorMode::MultipleFiles] {
+            let uncompressed_archive = build_with_thor_builder(Compression::none(), mode);
+            let compressed_archive = build_with_thor_builder(Compression::best(), mode);
+            assert!(uncompressed_archive.len() > compressed_archive.len());
+            archives.push(uncompressed_archive);
+            archives.push(compressed_archive);
+        }
+        for archive_content in archives.iter() {
+            let mut thor_archive = ThorArchive::new(Cursor::new(archive_content)).unwrap();
+            assert_eq!(
+                thor_archive.read_file_content("data\\test").unwrap(),
+                content
+            );
+        }
+    }
+
+    #[test]
+    fn test_append_raw_file_update() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let mut source_archive = ThorArchive::open(thor_dir_path.join("dir2.thor")).unwrap();
+        let entries: Vec<ThorFileEntry> = source_archive.present_entries().cloned().collect();
+        let mut archive_content = vec![];
+        {
+            let mut builder =
+                ThorArchiveBuilder::new(Cursor::new(&mut archive_content), false, None, false)
+                    .unwrap();
+            for entry in &entries {
+                let raw_data = source_archive
+                    .get_entry_raw_data(&entry.relative_path)
+                    .unwrap();
+                assert_eq!(raw_data.len(), entry.size_compressed);
+                builder
+                    .append_raw_file_update(
+                        entry.relative_path.clone(),
+                        &raw_data,
+                        entry.size as u32,
+                        0,
+                    )
+                    .unwrap();
+            }
+        }
+        let mut thor_archive = ThorArchive::new(Cursor::new(archive_content)).unwrap();
+        for entry in &entries {
+            let copied_entry = thor_archive.get_file_entry(&entry.relative_path).unwrap();
+            assert_eq!(copied_entry.size_compressed, entry.size_compressed);
+            assert_eq!(
+                thor_archive
+                    .read_file_content(&entry.relative_path)
+                    .unwrap(),
+                source_archive
+                    .read_file_content(&entry.relative_path)
+                    .unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_deduplication() {
+        let files: [(&str, &[u8]); 5] = [
+            ("data\\a.txt", b"same content"),
+            ("data\\b.txt", b"other content"),
+            ("data\\c.txt", b"same content"),
+            // Same size, different content
+            ("data\\d.txt", b"some content"),
+            ("data\\empty.txt", b""),
+        ];
+        let build = |deduplicate: bool| {
+            let mut archive_content = vec![];
+            let deduplicated_count = {
+                let mut builder =
+                    ThorArchiveBuilder::new(Cursor::new(&mut archive_content), false, None, true)
+                        .unwrap();
+                if deduplicate {
+                    builder = builder.with_deduplication();
+                }
+                for (path, content) in &files {
+                    builder
+                        .append_file_update(path.to_string(), *content)
+                        .unwrap();
+                }
+                builder.finish().unwrap();
+                builder.deduplicated_count()
+            };
+            (archive_content, deduplicated_count)
+        };
+        let (archive_content, deduplicated_count) = build(false);
+        assert_eq!(deduplicated_count, 0);
+        let (deduplicated_content, deduplicated_count) = build(true);
+        assert_eq!(deduplicated_count, 1);
+        assert!(deduplicated_content.len() < archive_content.len());
+
+        let mut thor_archive = ThorArchive::from_bytes(deduplicated_content).unwrap();
+        assert_eq!(
+            thor_archive.get_file_entry("data\\a.txt").unwrap().offset,
+            thor_archive.get_file_entry("data\\c.txt").unwrap().offset
+        );
+        assert_ne!(
+            thor_archive.get_file_entry("data\\a.txt").unwrap().offset,
+            thor_archive.get_file_entry("data\\d.txt").unwrap().offset
+        );
+        for (path, content) in &files {
+            assert_eq!(thor_archive.read_file_content(path).unwrap(), *content);
+        }
+        assert!(thor_archive.is_valid().unwrap());
+    }
+
+    #[test]
+    fn test_korean_paths() {
+        let temp_dir = tempdir().unwrap();
+        let output_path = temp_dir.path().join("builder.thor");
+        let relative_path = "data\\texture\\유저인터페이스\\test.bmp";
+        {
+            let output_file = File::create(&output_path).unwrap();
+            let mut builder = ThorArchiveBuilder::new(output_file, true, None, true).unwrap();
+            builder
+                .append_file_update(relative_path.to_string(), &[0u8; 16][..])
+                .unwrap();
+        }
+        let mut thor_archive = ThorArchive::open(&output_path).unwrap();
+        assert!(thor_archive.get_file_entry(relative_path).is_some());
+        assert!(thor_archive.is_valid().unwrap());
+    }
+
     #[test]
     fn test_append_file_removal() {
         let temp_dir = tempdir().unwrap();
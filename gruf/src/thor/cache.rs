@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+/// Size-bounded cache of decompressed files' content, which evicts the least
+/// recently used files first. See `ThorArchive::with_cache`.
+///
+/// Files are kept in a doubly linked list (stored in `nodes`, ordered from
+/// the least to the most recently used file), so that lookups, insertions
+/// and evictions are all done in constant time.
+#[derive(Debug, Clone)]
+pub(crate) struct ContentCache {
+    capacity: usize,
+    size: usize,
+    indices: HashMap<String, usize>,
+    nodes: Vec<CacheNode>,
+    // Indices of the nodes that were evicted and can be reused
+    free_indices: Vec<usize>,
+    // Least recently used file
+    head: Option<usize>,
+    // Most recently used file
+    tail: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct CacheNode {
+    relative_path: String,
+    content: Vec<u8>,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+impl ContentCache {
+    /// Creates a cache holding up to `capacity` bytes of decompressed
+    /// content.
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            size: 0,
+            indices: HashMap::new(),
+            nodes: Vec::new(),
+            free_indices: Vec::new(),
+            head: None,
+            tail: None,
+        }
+    }
+
+    /// Returns the cached content of `relative_path`, which becomes the most
+    /// recently used file.
+    pub(crate) fn get(&mut self, relative_path: &str) -> Option<&[u8]> {
+        let index = *self.indices.get(relative_path)?;
+        self.unlink(index);
+        self.push_back(index);
+        Some(self.nodes[index].content.as_slice())
+    }
+
+    /// Evicts all the cached content.
+    pub(crate) fn clear(&mut self) {
+        self.indices.clear();
+        self.nodes.clear();
+        self.free_indices.clear();
+        self.head = None;
+        self.tail = None;
+        self.size = 0;
+    }
+
+    /// Caches the content of `relative_path`, evicting the least recently
+    /// used files if needed. Content bigger than the cache's capacity isn't
+    /// cached.
+    pub(crate) fn insert(&mut self, relative_path: String, content: Vec<u8>) {
+        if let Some(index) = self.indices.get(&relative_path).copied() {
+            self.remove(index);
+        }
+        if content.len() > self.capacity {
+            return;
+        }
+        while self.size + content.len() > self.capacity {
+            match self.head {
+                Some(index) => self.remove(index),
+                None => break,
+            }
+        }
+        self.size += content.len();
+        let node = CacheNode {
+            relative_path: relative_path.clone(),
+            content,
+            prev: None,
+            next: None,
+        };
+        let index = match self.free_indices.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        self.push_back(index);
+        self.indices.insert(relative_path, index);
+    }
+
+    /// Evicts the file stored at `index`.
+    fn remove(&mut self, index: usize) {
+        self.unlink(index);
+        let node = &mut self.nodes[index];
+        self.size -= node.content.len();
+        self.indices.remove(&node.relative_path);
+        // Release the memory right away, the node is reused later on
+        node.relative_path = String::new();
+        node.content = Vec::new();
+        self.free_indices.push(index);
+    }
+
+    /// Detaches the node at `index` from the list.
+    fn unlink(&mut self, index: usize) {
+        let (prev, next) = (self.nodes[index].prev, self.nodes[index].next);
+        match prev {
+            Some(prev) => self.nodes[prev].next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.nodes[next].prev = prev,
+            None => self.tail = prev,
+        }
+    }
+
+    /// Attaches the node at `index` at the end of the list, as the most
+    /// recently used file.
+    fn push_back(&mut self, index: usize) {
+        self.nodes[index].prev = self.tail;
+        self.nodes[index].next = None;
+        match self.tail {
+            Some(tail) => self.nodes[tail].next = Some(index),
+            None => self.head = Some(index),
+        }
+        self.tail = Some(index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_cache() {
+        let mut cache = ContentCache::new(8);
+        cache.insert("a".to_string(), vec![b'a'; 4]);
+        cache.insert("b".to_string(), vec![b'b'; 4]);
+        assert_eq!(cache.get("a"), Some(&[b'a'; 4][..]));
+        // 'b' is the least recently used file
+        cache.insert("c".to_string(), vec![b'c'; 2]);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(&[b'a'; 4][..]));
+        assert_eq!(cache.get("c"), Some(&[b'c'; 2][..]));
+        // Too big to be cached
+        cache.insert("d".to_string(), vec![b'd'; 9]);
+        assert_eq!(cache.get("d"), None);
+        assert_eq!(cache.get("a"), Some(&[b'a'; 4][..]));
+        // Replaced content
+        cache.insert("a".to_string(), vec![b'A'; 6]);
+        assert_eq!(cache.get("a"), Some(&[b'A'; 6][..]));
+        assert_eq!(cache.get("c"), Some(&[b'c'; 2][..]));
+        assert_eq!(cache.size, 8);
+        cache.clear();
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.size, 0);
+    }
+
+    #[test]
+    fn test_content_cache_eviction_order() {
+        let mut cache = ContentCache::new(4);
+        for path in &["a", "b", "c", "d"] {
+            cache.insert(path.to_string(), vec![0; 1]);
+        }
+        // Least recently used first: 'b', 'd', 'a', 'c'
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+        cache.insert("e".to_string(), vec![0; 2]);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("d"), None);
+        // Evicted nodes are reused
+        assert_eq!(cache.nodes.len(), 4);
+        assert_eq!(cache.size, 4);
+        cache.insert("f".to_string(), vec![0; 1]);
+        assert_eq!(cache.get("a"), None);
+        for path in &["c", "e", "f"] {
+            assert!(cache.get(path).is_some());
+        }
+        assert_eq!(cache.indices.len(), 3);
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt;
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+
+use flate2::read::ZlibDecoder;
+
+use crate::archive::decompress_bounded;
+use crate::thor::reader::{is_zlib_header, uses_preset_dictionary, ZLIB_HEADER_SIZE};
+use crate::{GrufError, Result};
+
+/// Decompresses entries' content and file tables, see
+/// `ThorOptions::decompressor`.
+pub trait Decompressor: fmt::Debug + Send + Sync {
+    /// Decompresses `input`, whose decompressed size is expected to be
+    /// `hint_size` (the entry's size, or the maximum size of the file table).
+    ///
+    /// Implementations may return more or less data than `hint_size`, the
+    /// size of entries' content is checked afterwards.
+    fn decompress(&self, input: &[u8], hint_size: usize) -> Result<Vec<u8>>;
+
+    /// Same as `decompress` but appends the decompressed data to `output`,
+    /// e.g., to reuse the same buffer for several entries.
+    fn decompress_into(&self, input: &[u8], hint_size: usize, output: &mut Vec<u8>) -> Result<()> {
+        output.extend_from_slice(&self.decompress(input, hint_size)?);
+        Ok(())
+    }
+
+    /// Returns a reader that decompresses `input` on the fly, used to parse
+    /// file tables and by the methods that stream entries' content (e.g.,
+    /// `ThorArchive::open_file` or `ThorArchive::readers`).
+    ///
+    /// By default, `input` is read entirely and decompressed at once with
+    /// `decompress`, i.e., the content is buffered in memory.
+    fn decompress_stream<'a>(
+        &self,
+        mut input: Box<dyn Read + 'a>,
+        hint_size: usize,
+    ) -> Result<Box<dyn Read + 'a>> {
+        let mut compressed_data = Vec::new();
+        input.read_to_end(&mut compressed_data)?;
+        let decompressed_data = self.decompress(&compressed_data, hint_size)?;
+        Ok(Box::new(Cursor::new(decompressed_data)))
+    }
+}
+
+/// Decompressor for zlib streams, the format used by THOR archives (and the
+/// default decompressor).
+///
+/// Decompression stops as soon as more than `hint_size` bytes are
+/// decompressed, with `GrufError::DecompressionTooLarge`. Data that doesn't
+/// start with a zlib header is rejected with `GrufError::NotZlibData`, whose
+/// path and offset are filled in by the archive's methods.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ZlibDecompressor;
+
+impl Decompressor for ZlibDecompressor {
+    fn decompress(&self, input: &[u8], hint_size: usize) -> Result<Vec<u8>> {
+        let mut output = Vec::with_capacity(hint_size);
+        self.decompress_into(input, hint_size, &mut output)?;
+        Ok(output)
+    }
+
+    fn decompress_into(&self, input: &[u8], hint_size: usize, output: &mut Vec<u8>) -> Result<()> {
+        if !is_zlib_header(input) {
+            return Err(not_zlib_data());
+        }
+        if uses_preset_dictionary(input) {
+            return Err(GrufError::invalid_content(
+                "Content is compressed with a preset dictionary",
+            ));
+        }
+        decompress_bounded(ZlibDecoder::new(input), hint_size, output)?;
+        Ok(())
+    }
+
+    fn decompress_stream<'a>(
+        &self,
+        mut input: Box<dyn Read + 'a>,
+        _hint_size: usize,
+    ) -> Result<Box<dyn Read + 'a>> {
+        // Check the zlib header before handing out the decoder
+        let mut zlib_header = Vec::with_capacity(ZLIB_HEADER_SIZE);
+        input
+            .by_ref()
+            .take(ZLIB_HEADER_SIZE as u64)
+            .read_to_end(&mut zlib_header)?;
+        if !is_zlib_header(&zlib_header) {
+            return Err(not_zlib_data());
+        }
+        Ok(Box::new(ZlibDecoder::new(
+            Cursor::new(zlib_header).chain(input),
+        )))
+    }
+}
+
+/// `GrufError::NotZlibData` without the entry's path and offset, which
+/// decompressors don't know about.
+fn not_zlib_data() -> GrufError {
+    GrufError::NotZlibData {
+        relative_path: String::new(),
+        offset: 0,
+    }
+}
+
+/// Decompressor used by archives and containers.
+///
+/// Containers are compared regardless of their decompressor, which doesn't
+/// change the parsed metadata.
+#[derive(Clone)]
+pub(crate) struct SharedDecompressor(pub(crate) Arc<dyn Decompressor>);
+
+impl SharedDecompressor {
+    pub(crate) fn get(&self) -> &dyn Decompressor {
+        self.0.as_ref()
+    }
+}
+
+impl fmt::Debug for SharedDecompressor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl PartialEq for SharedDecompressor {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for SharedDecompressor {}
@@ -0,0 +1,147 @@
+use std::io::{Read, Seek};
+
+use crate::thor::{ThorArchive, ThorFileEntry};
+use crate::Result;
+
+/// Differences between two archives, as returned by `diff_archives`.
+///
+/// Paths are sorted. Internal entries (e.g., 'data.integrity') are ignored.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ThorDiff {
+    /// Paths listed in the second archive only
+    pub added: Vec<String>,
+    /// Paths listed in the first archive only
+    pub removed: Vec<String>,
+    /// Paths listed in both archives, whose entries differ
+    pub changed: Vec<String>,
+}
+
+impl ThorDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compares the entries of two archives (e.g., two revisions of a patch).
+///
+/// An entry present in both archives is considered changed if it's marked
+/// for removal in only one of them or if its decompressed size differs.
+/// Files' content isn't read, see `diff_archives_with_content`.
+pub fn diff_archives<R1, R2>(a: &ThorArchive<R1>, b: &ThorArchive<R2>) -> ThorDiff
+where
+    R1: Read + Seek,
+    R2: Read + Seek,
+{
+    let mut diff = ThorDiff::default();
+    let mut common_paths = Vec::new();
+    for entry_a in a.get_entries().filter(|e| !e.is_internal()) {
+        match b.get_file_entry(&entry_a.relative_path) {
+            None => diff.removed.push(entry_a.relative_path.clone()),
+            Some(entry_b) => common_paths.push((entry_a, entry_b)),
+        }
+    }
+    diff.added = b
+        .get_entries()
+        .filter(|e| !e.is_internal() && a.get_file_entry(&e.relative_path).is_none())
+        .map(|e| e.relative_path.clone())
+        .collect();
+    diff.changed = common_paths
+        .into_iter()
+        .filter(|(entry_a, entry_b)| entries_differ(entry_a, entry_b))
+        .map(|(entry_a, _)| entry_a.relative_path.clone())
+        .collect();
+    diff.added.sort_unstable();
+    diff.removed.sort_unstable();
+    diff.changed.sort_unstable();
+    diff
+}
+
+/// Same as `diff_archives` but entries whose decompressed size is the same
+/// in both archives are also considered changed if the CRC32 checksum of
+/// their content differs.
+///
+/// This decompresses the content of all the entries present in both
+/// archives.
+pub fn diff_archives_with_content<R1, R2>(
+    a: &mut ThorArchive<R1>,
+    b: &mut ThorArchive<R2>,
+) -> Result<ThorDiff>
+where
+    R1: Read + Seek,
+    R2: Read + Seek,
+{
+    let mut diff = diff_archives(a, b);
+    let unchanged_paths: Vec<String> = a
+        .present_entries()
+        .filter(|e| !e.is_internal())
+        .filter(|e| matches!(b.get_file_entry(&e.relative_path), Some(e) if !e.is_removed))
+        .filter(|e| diff.changed.binary_search(&e.relative_path).is_err())
+        .map(|e| e.relative_path.clone())
+        .collect();
+    for relative_path in unchanged_paths {
+        if a.file_crc32(&relative_path)? != b.file_crc32(&relative_path)? {
+            diff.changed.push(relative_path);
+        }
+    }
+    diff.changed.sort_unstable();
+    Ok(diff)
+}
+
+fn entries_differ(entry_a: &ThorFileEntry, entry_b: &ThorFileEntry) -> bool {
+    entry_a.is_removed != entry_b.is_removed || entry_a.size != entry_b.size
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thor::test_support::build_thor_archive;
+
+    #[test]
+    fn test_diff_archives() {
+        let mut a = ThorArchive::from_bytes(
+            build_thor_archive(
+                vec![
+                    ("data\\same.txt", "same"),
+                    ("data\\resized.txt", "short"),
+                    ("data\\edited.txt", "edited 1"),
+                    ("data\\dropped.txt", "dropped"),
+                    ("data\\restored.txt", "restored"),
+                ],
+                &["data\\deleted.txt"],
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let mut b = ThorArchive::from_bytes(
+            build_thor_archive(
+                vec![
+                    ("data\\same.txt", "same"),
+                    ("data\\resized.txt", "longer content"),
+                    ("data\\edited.txt", "edited 2"),
+                    ("data\\new.txt", "new"),
+                ],
+                &["data\\deleted.txt", "data\\restored.txt"],
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let diff = diff_archives(&a, &b);
+        assert_eq!(diff.added, vec!["data\\new.txt"]);
+        assert_eq!(diff.removed, vec!["data\\dropped.txt"]);
+        assert_eq!(
+            diff.changed,
+            vec!["data\\resized.txt", "data\\restored.txt"]
+        );
+        assert!(diff_archives(&a, &a).is_empty());
+
+        let diff = diff_archives_with_content(&mut a, &mut b).unwrap();
+        assert_eq!(
+            diff.changed,
+            vec![
+                "data\\edited.txt",
+                "data\\resized.txt",
+                "data\\restored.txt"
+            ]
+        );
+    }
+}
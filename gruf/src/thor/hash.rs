@@ -0,0 +1,109 @@
+use std::io::{self, Write};
+
+use crc::crc32::{self, Hasher32};
+use md5::{Digest, Md5};
+use serde::Serialize;
+use sha1::Sha1;
+
+/// Hash algorithms supported by `ThorArchive::content_manifest`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum HashAlgo {
+    /// CRC32 (IEEE), the checksum used by `data.integrity` files
+    Crc32,
+    Md5,
+    Sha1,
+}
+
+/// Writer that only computes the hash of the data written to it.
+pub(crate) enum ContentHasher {
+    // The digest embeds its lookup table
+    Crc32(Box<crc32::Digest>),
+    Md5(Md5),
+    Sha1(Sha1),
+}
+
+impl ContentHasher {
+    pub(crate) fn new(algo: HashAlgo) -> Self {
+        match algo {
+            HashAlgo::Crc32 => ContentHasher::Crc32(Box::new(crc32::Digest::new(crc32::IEEE))),
+            HashAlgo::Md5 => ContentHasher::Md5(Md5::new()),
+            HashAlgo::Sha1 => ContentHasher::Sha1(Sha1::new()),
+        }
+    }
+
+    /// Returns the hash of the data written so far, as a lowercase
+    /// hexadecimal string.
+    pub(crate) fn finish_hex(self) -> String {
+        let digest = match self {
+            ContentHasher::Crc32(digest) => digest.sum32().to_be_bytes().to_vec(),
+            ContentHasher::Md5(md5) => md5.finalize().to_vec(),
+            ContentHasher::Sha1(sha1) => sha1.finalize().to_vec(),
+        };
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+impl Write for ContentHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ContentHasher::Crc32(digest) => Hasher32::write(digest.as_mut(), buf),
+            ContentHasher::Md5(md5) => md5.update(buf),
+            ContentHasher::Sha1(sha1) => sha1.update(buf),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex_hash(algo: HashAlgo, data: &[u8]) -> String {
+        let mut hasher = ContentHasher::new(algo);
+        // Feed the data in uneven chunks
+        for chunk in data.chunks(7) {
+            hasher.write_all(chunk).unwrap();
+        }
+        hasher.finish_hex()
+    }
+
+    #[test]
+    fn test_content_hasher() {
+        let long_data = vec![b'a'; 1000];
+        let test_vectors: [(&[u8], &str, &str, &str); 4] = [
+            (
+                b"",
+                "00000000",
+                "d41d8cd98f00b204e9800998ecf8427e",
+                "da39a3ee5e6b4b0d3255bfef95601890afd80709",
+            ),
+            (
+                b"abc",
+                "352441c2",
+                "900150983cd24fb0d6963f7d28e17f72",
+                "a9993e364706816aba3e25717850c26c9cd0d89d",
+            ),
+            (
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+                "171a3f5f",
+                "8215ef0796a20bcaaae116d3876c664a",
+                "84983e441c3bd26ebaae4aa1f95129e5e54670f1",
+            ),
+            (
+                &long_data,
+                "9a38da03",
+                "cabe45dcc9ae5b66ba86600cca6b8ba8",
+                "291e9a6c66994949b57ba5e650361e98fc36b1ba",
+            ),
+        ];
+        for (data, crc32, md5, sha1) in &test_vectors {
+            assert_eq!(hex_hash(HashAlgo::Crc32, data), *crc32);
+            assert_eq!(hex_hash(HashAlgo::Md5, data), *md5);
+            assert_eq!(hex_hash(HashAlgo::Sha1, data), *sha1);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::convert::TryFrom;
+use std::io::{Read, Seek, Write};
+
+use crate::thor::{ThorArchive, ThorArchiveBuilder, ThorFileEntry};
+use crate::Result;
+use indexmap::IndexMap;
+
+/// Entry of a merged archive, see `merge_archives`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergedEntry {
+    /// File updated with the content of `entry`, from the input archive at
+    /// position `input_index`
+    Update {
+        input_index: usize,
+        entry: ThorFileEntry,
+    },
+    /// File marked for removal
+    Removal,
+}
+
+/// Consolidated entries of several archives, as returned by
+/// `merge_archives`.
+#[derive(Debug, Default)]
+pub struct MergedEntries {
+    // Entries are kept in the order they've been first seen
+    entries: IndexMap<String, MergedEntry>,
+}
+
+impl MergedEntries {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get<S: AsRef<str>>(&self, file_path: S) -> Option<&MergedEntry> {
+        self.entries.get(file_path.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &MergedEntry)> {
+        self.entries
+            .iter()
+            .map(|(path, entry)| (path.as_str(), entry))
+    }
+
+    /// Appends the merged entries to `builder`. `inputs` MUST be the archives
+    /// the entries have been merged from, in the same order.
+    ///
+    /// Files' content is copied as is (i.e., without being recompressed). If
+    /// `builder` deduplicates payloads (see
+    /// `ThorArchiveBuilder::with_deduplication`), files whose content is
+    /// identical are stored once.
+    pub fn write_into<R, W>(
+        &self,
+        inputs: &mut [ThorArchive<R>],
+        builder: &mut ThorArchiveBuilder<W>,
+    ) -> Result<()>
+    where
+        R: Read + Seek,
+        W: Write + Seek,
+    {
+        for (relative_path, merged_entry) in &self.entries {
+            match merged_entry {
+                MergedEntry::Removal => builder.append_file_removal(relative_path.clone()),
+                MergedEntry::Update { input_index, entry } => {
+                    let input = &mut inputs[*input_index];
+                    let raw_content = input.get_entry_raw_data(relative_path)?;
+                    let checksum = if builder.includes_checksums() {
+                        input.file_crc32(relative_path)?
+                    } else {
+                        0
+                    };
+                    builder.append_raw_file_update(
+                        relative_path.clone(),
+                        &raw_content,
+                        u32::try_from(entry.size)?,
+                        checksum,
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Merges several archives (e.g., incremental patches) into a single set of
+/// entries, which can then be written with `MergedEntries::write_into`.
+///
+/// Archives are applied in order: a file updated or removed by an archive
+/// overrides what previous archives did to it. Internal entries (e.g.,
+/// 'data.integrity') are ignored, the builder generates its own.
+pub fn merge_archives<R: Read + Seek>(inputs: &[ThorArchive<R>]) -> MergedEntries {
+    let mut merged_entries = MergedEntries::default();
+    for (input_index, input) in inputs.iter().enumerate() {
+        for entry in input.get_entries().filter(|e| !e.is_internal()) {
+            let merged_entry = if entry.is_removed {
+                MergedEntry::Removal
+            } else {
+                MergedEntry::Update {
+                    input_index,
+                    entry: entry.clone(),
+                }
+            };
+            merged_entries
+                .entries
+                .insert(entry.relative_path.clone(), merged_entry);
+        }
+    }
+    merged_entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thor::test_support::build_thor_archive_with_checksums;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_merge_archives() {
+        let mut inputs = vec![
+            ThorArchive::from_bytes(
+                build_thor_archive_with_checksums(
+                    vec![("data\\kept.txt", "kept"), ("data\\dropped.txt", "dropped")],
+                    &["data\\restored.txt"],
+                )
+                .unwrap(),
+            )
+            .unwrap(),
+            ThorArchive::from_bytes(
+                build_thor_archive_with_checksums(
+                    vec![
+                        ("data\\kept.txt", "kept 2"),
+                        ("data\\restored.txt", "restored"),
+                    ],
+                    &[],
+                )
+                .unwrap(),
+            )
+            .unwrap(),
+            ThorArchive::from_bytes(
+                build_thor_archive_with_checksums(
+                    Vec::<(&str, &str)>::new(),
+                    &["data\\dropped.txt"],
+                )
+                .unwrap(),
+            )
+            .unwrap(),
+        ];
+        let merged_entries = merge_archives(&inputs);
+        assert_eq!(merged_entries.len(), 3);
+        assert!(matches!(
+            merged_entries.get("data\\kept.txt"),
+            Some(MergedEntry::Update { input_index: 1, .. })
+        ));
+        assert_eq!(
+            merged_entries.get("data\\dropped.txt"),
+            Some(&MergedEntry::Removal)
+        );
+        assert!(matches!(
+            merged_entries.get("data\\restored.txt"),
+            Some(MergedEntry::Update { input_index: 1, .. })
+        ));
+        assert!(merged_entries.get("data.integrity").is_none());
+
+        let mut archive_content = vec![];
+        {
+            let mut builder =
+                ThorArchiveBuilder::new(Cursor::new(&mut archive_content), false, None, true)
+                    .unwrap();
+            merged_entries
+                .write_into(&mut inputs, &mut builder)
+                .unwrap();
+        }
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        assert_eq!(thor_archive.present_count(), 3); // Including 'data.integrity'
+        assert_eq!(
+            thor_archive.read_file_content("data\\kept.txt").unwrap(),
+            b"kept 2"
+        );
+        assert_eq!(
+            thor_archive
+                .read_file_content("data\\restored.txt")
+                .unwrap(),
+            b"restored"
+        );
+        assert!(
+            thor_archive
+                .get_file_entry("data\\dropped.txt")
+                .unwrap()
+                .is_removed
+        );
+        assert!(thor_archive.is_valid().unwrap());
+    }
+
+    #[test]
+    fn test_write_into_with_deduplication() {
+        let mut inputs = vec![
+            ThorArchive::from_bytes(
+                build_thor_archive_with_checksums(
+                    vec![("data\\a.txt", "v1"), ("data\\b.txt", "v2")],
+                    &[],
+                )
+                .unwrap(),
+            )
+            .unwrap(),
+            ThorArchive::from_bytes(
+                build_thor_archive_with_checksums(vec![("data\\a.txt", "v2")], &[]).unwrap(),
+            )
+            .unwrap(),
+        ];
+        let merged_entries = merge_archives(&inputs);
+        let mut archive_content = vec![];
+        {
+            let mut builder =
+                ThorArchiveBuilder::new(Cursor::new(&mut archive_content), false, None, true)
+                    .unwrap()
+                    .with_deduplication();
+            merged_entries
+                .write_into(&mut inputs, &mut builder)
+                .unwrap();
+            assert_eq!(builder.deduplicated_count(), 1);
+        }
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        for path in &["data\\a.txt", "data\\b.txt"] {
+            assert_eq!(thor_archive.read_file_content(path).unwrap(), b"v2");
+        }
+        assert!(thor_archive.is_valid().unwrap());
+    }
+}
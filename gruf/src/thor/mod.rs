@@ -1,17 +1,81 @@
+#[cfg(feature = "async")]
+pub mod async_reader;
 pub mod builder;
+mod cache;
+mod decompressor;
+pub mod diff;
+mod hash;
+pub mod merge;
 pub mod reader;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
 
-pub use builder::ThorArchiveBuilder;
+use std::fmt;
+
+use serde::Serialize;
+
+#[cfg(feature = "async")]
+pub use async_reader::ThorArchiveAsync;
+pub use builder::{write_single_file_archive, ThorArchiveBuilder, ThorBuilder};
+pub use decompressor::{Decompressor, ZlibDecompressor};
+pub use diff::{diff_archives, diff_archives_with_content, ThorDiff};
+pub use hash::HashAlgo;
+pub use merge::{merge_archives, MergedEntries, MergedEntry};
 pub use reader::{
-    patch_list_from_string, ThorArchive, ThorFileEntry, ThorPatchInfo, ThorPatchList,
+    fuzz_parse, normalize_thor_path, patch_list_from_string, safe_join, ApplyReport,
+    CollisionPolicy, CompressionReport, ExtractMode, ExtractOptions, ExtractReport, IntegrityTable,
+    ManifestReport, MultipleFilesTableDesc, PathCollision, PathSeparator, RepackOptions,
+    RepackReport, SingleFileTableDesc, SortKey, ThorArchive, ThorArchiveHeader, ThorFileEntry,
+    ThorHeader, ThorOptions, ThorPatchInfo, ThorPatchList, ThorStats, ThorTable, TranscodeReport,
+    ValidationReport,
 };
 
 const THOR_HEADER_MAGIC: &[u8; 24] = b"ASSF (C) 2007 Aeomin DEV";
+const THOR_HEADER_FIXED_SIZE: usize = THOR_HEADER_MAGIC.len() + 0x8;
 const INTEGRITY_FILE_NAME: &str = "data.integrity";
+
+/// Names of the files that describe an archive rather than belong to the
+/// client, i.e., 'data.integrity' (CRC32 checksums of the archive's files).
+///
+/// Server emulators and patch tools don't all use the same names, the list
+/// of names used when extracting or applying an archive can be overridden
+/// (see `ExtractOptions::metadata_names`).
+pub const RESERVED_METADATA_NAMES: &[&str] = &[INTEGRITY_FILE_NAME];
 const MULTIPLE_FILES_TABLE_DESC_SIZE: usize = 2 * std::mem::size_of::<i32>();
-#[derive(Debug, PartialEq, Eq)]
-enum ThorMode {
+
+/// Maximum offset of an entry's content. Offsets are stored as 32-bit
+/// unsigned integers, relative to the header's magic.
+pub const THOR_MAX_ENTRY_OFFSET: u64 = u32::MAX as u64;
+/// Maximum offset of the file table of multiple-files archives. The offset is
+/// stored as a 32-bit signed integer, relative to the header's magic.
+pub const THOR_MAX_TABLE_OFFSET: u64 = i32::MAX as u64;
+
+/// Layout of a THOR archive's file table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ThorMode {
     SingleFile,
     MultipleFiles,
     Invalid,
 }
+
+/// Where a THOR archive's files must be applied.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum PatchKind {
+    /// Files are extracted into the client's directory
+    ClientDirectory,
+    /// Files are merged into the client's default GRF
+    DefaultGrf,
+    /// Files are merged into the GRF with the given name
+    NamedGrf(String),
+}
+
+impl fmt::Display for ThorMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ThorMode::SingleFile => "SingleFile",
+            ThorMode::MultipleFiles => "MultipleFiles",
+            ThorMode::Invalid => "Invalid",
+        };
+        f.write_str(name)
+    }
+}
@@ -1,27 +1,71 @@
+use std::borrow::Cow;
 use std::boxed::Box;
-use std::collections::HashMap;
-use std::fs::File;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{self, File};
 use std::hash::{Hash, Hasher};
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::path::Path;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
 
+use crate::archive::{decompress_bounded, GenericFileEntry, ANSI_PATH_ENCODING};
+use crate::thor::builder::{
+    copy_and_measure_crc32, serialize_thor_file_entry_into, write_multiple_files_table_desc,
+    write_raw_single_file_archive, write_thor_header_prefix,
+};
+use crate::thor::cache::ContentCache;
+use crate::thor::decompressor::{Decompressor, SharedDecompressor, ZlibDecompressor};
+use crate::thor::hash::{ContentHasher, HashAlgo};
 use crate::thor::{
-    ThorMode, INTEGRITY_FILE_NAME, MULTIPLE_FILES_TABLE_DESC_SIZE, THOR_HEADER_MAGIC,
+    PatchKind, ThorArchiveBuilder, ThorMode, INTEGRITY_FILE_NAME, MULTIPLE_FILES_TABLE_DESC_SIZE,
+    RESERVED_METADATA_NAMES, THOR_HEADER_FIXED_SIZE, THOR_HEADER_MAGIC, THOR_MAX_ENTRY_OFFSET,
 };
 use crate::{GrufError, Result};
-use crc::crc32;
-use encoding::label::encoding_from_whatwg_label;
-use encoding::DecoderTrap;
-use flate2::read::ZlibDecoder;
+use crc::crc32::{self, Hasher32};
+use encoding::all::{UTF_16BE, UTF_16LE, UTF_8};
+use encoding::{DecoderTrap, EncodingRef};
+use filetime::FileTime;
+use flate2::read::DeflateDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use nom::error::{ErrorKind, ParseError};
 use nom::number::complete::{le_i16, le_i32, le_u32, le_u8};
 use nom::*;
+use once_cell::sync::OnceCell;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::Serialize;
 
 // Packed structs' sizes in bytes
 const MAX_FILE_NAME_SIZE: usize = 256;
 const HEADER_MAX_SIZE: usize = THOR_HEADER_MAGIC.len() + 0x8 + MAX_FILE_NAME_SIZE;
+// Size of the length/version field preceding the magic in length-prefixed
+// archives
+const HEADER_PREFIX_SIZE: usize = 4;
+// Maximum number of bytes needed to parse the header, including the table
+// descriptor and the entry of single-file archives
+pub(crate) const HEADER_EXTENDED_MAX_SIZE: usize = HEADER_PREFIX_SIZE
+    + HEADER_MAX_SIZE
+    + MULTIPLE_FILES_TABLE_DESC_SIZE
+    + SINGLE_FILE_ENTRY_MAX_SIZE;
 const SINGLE_FILE_ENTRY_MAX_SIZE: usize = 9 + MAX_FILE_NAME_SIZE;
+// Size of the blocks read when reading multiple entries at once
+const READ_BLOCK_SIZE: usize = 1024 * 1024;
+// Default limits used when parsing archives
+const DEFAULT_MAX_ENTRIES: usize = 1 << 20;
+const DEFAULT_MAX_DECOMPRESSED_TABLE_SIZE: usize = 256 * 1024 * 1024;
+// Size of the chunks in which file tables are decompressed
+const TABLE_READ_CHUNK_SIZE: usize = 64 * 1024;
 
 pub type ThorPatchList = Vec<ThorPatchInfo>;
+/// CRC32 checksums of files' content, indexed by relative path
+pub type IntegrityTable = HashMap<String, u32>;
 
 /// Parses Thor's plist.txt file
 pub fn patch_list_from_string(content: &str) -> ThorPatchList {
@@ -62,6 +106,301 @@ impl ThorPatchInfo {
     }
 }
 
+/// Checks that a THOR relative path (Windows style) cannot escape the
+/// directory it's extracted into.
+pub(crate) fn check_thor_path_is_safe(thor_relative_path: &str) -> Result<()> {
+    let is_rooted =
+        thor_relative_path.starts_with(&['\\', '/'][..]) || thor_relative_path.contains(':');
+    let has_parent_component = thor_relative_path
+        .split(&['\\', '/'][..])
+        .any(|component| component == "..");
+    if is_rooted || has_parent_component {
+        return Err(GrufError::UnsafePath(thor_relative_path.to_string()));
+    }
+    Ok(())
+}
+
+/// Returns the last component of a THOR relative path (Windows style).
+fn thor_path_file_name(thor_relative_path: &str) -> &str {
+    thor_relative_path
+        .rsplit(&['\\', '/'][..])
+        .next()
+        .unwrap_or(thor_relative_path)
+}
+
+/// Appends `index` to a file name, before its extension (e.g.,
+/// "icon_num_1.bmp").
+fn indexed_file_name(file_name: &str, index: usize) -> String {
+    match file_name.rfind('.') {
+        Some(dot_pos) if dot_pos > 0 => format!(
+            "{}_{}{}",
+            &file_name[..dot_pos],
+            index,
+            &file_name[dot_pos..]
+        ),
+        _ => format!("{}_{}", file_name, index),
+    }
+}
+
+/// Joins a THOR relative path (Windows style) to a native path.
+fn join_thor_path(path: &Path, thor_relative_path: &str) -> PathBuf {
+    let mut result = PathBuf::from(path);
+    for component in thor_relative_path.split(&['\\', '/'][..]) {
+        result.push(component);
+    }
+    result
+}
+
+/// Returns true if `path` is a directory containing at least one entry, in
+/// which case it cannot be removed with `fs::remove_dir`.
+///
+/// `io::ErrorKind::DirectoryNotEmpty` isn't available in all the supported
+/// versions of Rust, the directory is checked instead.
+fn is_non_empty_directory(path: &Path) -> bool {
+    match fs::read_dir(path) {
+        Ok(mut dir_entries) => dir_entries.next().is_some(),
+        Err(_) => false,
+    }
+}
+
+/// Joins a THOR relative path (Windows style) to `base`, making sure that
+/// the result is located inside `base`.
+///
+/// Rooted paths, paths with a drive letter and paths containing `..`
+/// components are rejected with `GrufError::UnsafePath`.
+pub fn safe_join<P: AsRef<Path>>(base: P, thor_relative_path: &str) -> Result<PathBuf> {
+    check_thor_path_is_safe(thor_relative_path)?;
+    Ok(join_thor_path(base.as_ref(), thor_relative_path))
+}
+
+/// Reads the compressed content of `file_entry` from `reader`.
+///
+/// Fails with `GrufError::TruncatedPayload` if the content ends past the end
+/// of the archive.
+fn read_entry_raw_data<R: Read + Seek>(
+    reader: &mut R,
+    file_entry: &ThorFileEntry,
+) -> Result<Vec<u8>> {
+    if file_entry.size_compressed == 0 {
+        return Ok(vec![]);
+    }
+
+    reader.seek(SeekFrom::Start(file_entry.offset))?;
+    let mut content: Vec<u8> = Vec::with_capacity(file_entry.size_compressed);
+    let mut file_chunk = reader.take(content.capacity() as u64);
+    file_chunk.read_to_end(&mut content)?;
+    check_payload_size(file_entry, content.len())?;
+    Ok(content)
+}
+
+/// Fails with `GrufError::TruncatedPayload` if less than the entry's
+/// compressed size could be read from the archive.
+pub(crate) fn check_payload_size(file_entry: &ThorFileEntry, available: usize) -> Result<()> {
+    if available < file_entry.size_compressed {
+        return Err(GrufError::TruncatedPayload {
+            relative_path: file_entry.relative_path.clone(),
+            expected: file_entry.size_compressed,
+            available,
+        });
+    }
+    Ok(())
+}
+
+/// Checks that the compressed content of `file_entry` is located within an
+/// archive of `archive_size` bytes.
+///
+/// Fails with `GrufError::OffsetOutOfBounds` if the content starts past the
+/// end of the archive and with `GrufError::TruncatedPayload` if it starts
+/// within the archive but ends past its end.
+fn check_payload_range(file_entry: &ThorFileEntry, archive_size: u64) -> Result<()> {
+    if file_entry.offset > archive_size {
+        return Err(GrufError::OffsetOutOfBounds);
+    }
+    let available = archive_size - file_entry.offset;
+    check_payload_size(file_entry, usize::try_from(available).unwrap_or(usize::MAX))
+}
+
+/// Returns the compressed content of `file_entry` from `archive_content`,
+/// the content of the whole archive.
+fn entry_raw_data_in<'a>(
+    archive_content: &'a [u8],
+    file_entry: &ThorFileEntry,
+) -> Result<&'a [u8]> {
+    check_payload_range(file_entry, archive_content.len() as u64)?;
+    let start = usize::try_from(file_entry.offset)?;
+    Ok(&archive_content[start..start + file_entry.size_compressed])
+}
+
+/// Decompresses an entry's content with `decompressor` (zlib by default)
+/// and checks its size.
+pub(crate) fn decompress_entry(
+    decompressor: &dyn Decompressor,
+    file_entry: &ThorFileEntry,
+    content: &[u8],
+) -> Result<Vec<u8>> {
+    let mut decompressed_content = Vec::with_capacity(file_entry.size);
+    decompress_entry_into(decompressor, file_entry, content, &mut decompressed_content)?;
+    Ok(decompressed_content)
+}
+
+/// Same as `decompress_entry` but appends the decompressed content to `buf`.
+fn decompress_entry_into(
+    decompressor: &dyn Decompressor,
+    file_entry: &ThorFileEntry,
+    content: &[u8],
+    buf: &mut Vec<u8>,
+) -> Result<()> {
+    // Empty files may be stored without any zlib stream
+    if file_entry.size_compressed == 0 {
+        return Ok(());
+    }
+    let start = buf.len();
+    decompressor
+        .decompress_into(content, file_entry.size, buf)
+        .map_err(|e| entry_decompression_error(file_entry, e))?;
+    let decompressed_size = buf.len() - start;
+    if decompressed_size > file_entry.size {
+        return Err(GrufError::DecompressionTooLarge(file_entry.size));
+    }
+    if decompressed_size != file_entry.size {
+        return Err(GrufError::SizeMismatch {
+            expected: file_entry.size,
+            actual: decompressed_size,
+        });
+    }
+    Ok(())
+}
+
+/// Returns a reader that decompresses `file_entry`'s content, read from
+/// `content`, on the fly.
+fn stream_entry<'a>(
+    decompressor: &dyn Decompressor,
+    file_entry: &ThorFileEntry,
+    content: impl Read + 'a,
+) -> Result<Box<dyn Read + 'a>> {
+    decompressor
+        .decompress_stream(Box::new(content), file_entry.size)
+        .map_err(|e| entry_decompression_error(file_entry, e))
+}
+
+/// Adds `file_entry`'s path and offset to the errors returned by
+/// decompressors.
+pub(crate) fn entry_decompression_error(file_entry: &ThorFileEntry, e: GrufError) -> GrufError {
+    match e {
+        GrufError::IoError(e) => decompression_error(file_entry, e),
+        GrufError::NotZlibData { .. } => GrufError::NotZlibData {
+            relative_path: file_entry.relative_path.clone(),
+            offset: file_entry.offset,
+        },
+        e => e,
+    }
+}
+
+/// Returns a `GrufError::DecompressionError` for `file_entry`'s content.
+pub(crate) fn decompression_error(file_entry: &ThorFileEntry, e: io::Error) -> GrufError {
+    GrufError::DecompressionError {
+        relative_path: file_entry.relative_path.clone(),
+        offset: file_entry.offset,
+        message: e.to_string(),
+    }
+}
+
+/// Indicates whether `data` starts with a valid zlib header: deflate
+/// compression, a window of at most 32 KiB and a valid check value.
+pub(crate) fn is_zlib_header(data: &[u8]) -> bool {
+    match data {
+        [cmf, flg, ..] => {
+            (cmf & 0x0f) == ZLIB_DEFLATE_METHOD
+                && (cmf >> 4) <= ZLIB_MAX_WINDOW_BITS
+                && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0
+        }
+        _ => false,
+    }
+}
+
+// Compression method and maximum window size (as log2 - 8) of zlib streams
+const ZLIB_DEFLATE_METHOD: u8 = 8;
+const ZLIB_MAX_WINDOW_BITS: u8 = 7;
+// zlib's FDICT flag, set in the header's second byte
+const ZLIB_FDICT_FLAG: u8 = 0x20;
+pub(crate) const ZLIB_HEADER_SIZE: usize = 2;
+const ZLIB_DICT_ID_SIZE: usize = 4;
+const ZLIB_ADLER32_SIZE: usize = 4;
+const ZLIB_MAX_DICTIONARY_SIZE: usize = 32 * 1024;
+
+/// Indicates whether zlib-compressed `content` requires a preset dictionary
+/// to be decompressed.
+pub(crate) fn uses_preset_dictionary(content: &[u8]) -> bool {
+    content.len() >= ZLIB_HEADER_SIZE && (content[1] & ZLIB_FDICT_FLAG) != 0
+}
+
+/// Decompresses an entry's content, compressed with zlib and a preset
+/// dictionary, and checks its size and checksum. Content compressed without a
+/// dictionary is decompressed with `decompressor`.
+fn decompress_file_content_with_dict(
+    decompressor: &dyn Decompressor,
+    file_entry: &ThorFileEntry,
+    content: &[u8],
+    dictionary: &[u8],
+) -> Result<Vec<u8>> {
+    if !is_zlib_header(content) || !uses_preset_dictionary(content) {
+        return decompress_entry(decompressor, file_entry, content);
+    }
+    const DEFLATE_DATA_OFFSET: usize = ZLIB_HEADER_SIZE + ZLIB_DICT_ID_SIZE;
+    if content.len() < DEFLATE_DATA_OFFSET + ZLIB_ADLER32_SIZE {
+        return Err(GrufError::invalid_content(
+            "Compressed content is too short",
+        ));
+    }
+    if dictionary.len() > ZLIB_MAX_DICTIONARY_SIZE {
+        return Err(GrufError::invalid_content("Dictionary is too big"));
+    }
+    let read_be_u32 = |b: &[u8]| {
+        u32::from(b[0]) << 24 | u32::from(b[1]) << 16 | u32::from(b[2]) << 8 | u32::from(b[3])
+    };
+    if read_be_u32(&content[ZLIB_HEADER_SIZE..]) != adler32(dictionary) {
+        return Err(GrufError::invalid_content("Dictionary doesn't match"));
+    }
+    // flate2's default backend doesn't support preset dictionaries. Prepend
+    // the dictionary to the deflate data as a (non-final) stored block so that
+    // the compressed data can reference it.
+    let adler32_offset = content.len() - ZLIB_ADLER32_SIZE;
+    let dictionary_size = dictionary.len() as u16;
+    let mut deflate_stream = Vec::with_capacity(5 + dictionary.len() + content.len());
+    deflate_stream.push(0); // BFINAL = 0, BTYPE = 00 (stored)
+    deflate_stream.extend_from_slice(&dictionary_size.to_le_bytes());
+    deflate_stream.extend_from_slice(&(!dictionary_size).to_le_bytes());
+    deflate_stream.extend_from_slice(dictionary);
+    deflate_stream.extend_from_slice(&content[DEFLATE_DATA_OFFSET..adler32_offset]);
+    let decoder = DeflateDecoder::new(deflate_stream.as_slice());
+    let mut decompressed_content = Vec::with_capacity(dictionary.len() + file_entry.size);
+    decompress_bounded(
+        decoder,
+        dictionary.len() + file_entry.size,
+        &mut decompressed_content,
+    )?;
+    let decompressed_content = decompressed_content.split_off(dictionary.len());
+    if decompressed_content.len() != file_entry.size {
+        return Err(GrufError::SizeMismatch {
+            expected: file_entry.size,
+            actual: decompressed_content.len(),
+        });
+    }
+    if read_be_u32(&content[adler32_offset..]) != adler32(&decompressed_content) {
+        return Err(GrufError::invalid_content("Checksum mismatch"));
+    }
+    Ok(decompressed_content)
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + u32::from(*byte)) % MOD_ADLER;
+        (a, (b + a) % MOD_ADLER)
+    });
+    (b << 16) | a
+}
+
 fn parse_data_integrity_info(data: &str) -> HashMap<&str, u32> {
     let vec_lines: Vec<_> = data.lines().collect();
     vec_lines
@@ -79,500 +418,7742 @@ fn parse_data_integrity_info(data: &str) -> HashMap<&str, u32> {
         .collect()
 }
 
-#[derive(Debug)]
-pub struct ThorArchive<R: ?Sized> {
-    obj: Box<R>,
-    container: ThorContainer,
+/// Options used when parsing THOR archives.
+#[derive(Clone)]
+pub struct ThorOptions {
+    /// Maximum number of entries parsed from the file table
+    pub max_entries: usize,
+    /// Canonicalize entries' paths (i.e., lowercase them and use backslashes
+    /// as separators) like the game client does. Entries whose paths collide
+    /// once canonicalized are reported and the last one in the file table
+    /// prevails.
+    pub canonicalize_paths: bool,
+    /// Encoding of the paths that aren't prefixed with a BOM (EUC-KR by
+    /// default)
+    pub path_encoding: EncodingRef,
+    /// How malformed bytes in paths are handled. Archives containing
+    /// malformed paths fail to parse with `DecoderTrap::Strict`, other traps
+    /// make the parsing lossy (see `ThorStats::paths_with_replacement_chars`).
+    pub path_decoder_trap: DecoderTrap,
+    /// Maximum size of the decompressed file table, archives whose table
+    /// inflates past it fail to parse with `GrufError::DecompressionTooLarge`
+    pub max_decompressed_table_size: usize,
+    /// Reject archives whose header announces a number of files different
+    /// from the number of entries listed in the file table, with
+    /// `GrufError::EntryCountMismatch`
+    pub strict: bool,
+    /// Separator used in the keys entries are indexed by (backslashes, the
+    /// separator used by the game client, by default). Whatever the
+    /// separators used by the archive, entries can then be looked up with
+    /// both separators (see `ThorStats::mixed_separators`).
+    pub canonical_separator: PathSeparator,
+    /// Decompressor used for entries' content and the file table
+    /// (`ZlibDecompressor` by default), e.g., for archives produced by tools
+    /// that store files uncompressed. Decompressors that don't implement
+    /// `Decompressor::decompress_stream` make the methods streaming content
+    /// (e.g., `ThorArchive::open_file`) buffer it in memory.
+    pub decompressor: Arc<dyn Decompressor>,
 }
 
-impl ThorArchive<File> {
-    pub fn open(thor_archive_path: &Path) -> Result<ThorArchive<File>> {
-        let file = File::open(thor_archive_path)?;
-        ThorArchive::new(file)
-    }
+/// Separators used in paths stored in THOR archives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathSeparator {
+    /// '\\', the separator used by the game client
+    Backslash,
+    /// '/'
+    Slash,
 }
 
-impl<R: Read + Seek> ThorArchive<R> {
-    /// Create a new archive with the underlying object as the reader.
-    pub fn new(mut obj: R) -> Result<ThorArchive<R>> {
-        let thor_patch = parse_thor_patch(&mut obj)?;
-        Ok(ThorArchive {
-            obj: Box::new(obj),
-            container: thor_patch,
-        })
+impl PathSeparator {
+    pub fn as_char(self) -> char {
+        match self {
+            PathSeparator::Backslash => '\\',
+            PathSeparator::Slash => '/',
+        }
     }
+}
 
-    pub fn use_grf_merging(&self) -> bool {
-        self.container.header.use_grf_merging
+impl ThorOptions {
+    fn path_decoding(&self) -> PathDecoding {
+        PathDecoding {
+            encoding: self.path_encoding,
+            trap: self.path_decoder_trap,
+        }
     }
+}
 
-    pub fn file_count(&self) -> usize {
-        self.container.entries.len()
+impl Default for ThorOptions {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_MAX_ENTRIES,
+            canonicalize_paths: false,
+            path_encoding: ANSI_PATH_ENCODING,
+            path_decoder_trap: DecoderTrap::Strict,
+            max_decompressed_table_size: DEFAULT_MAX_DECOMPRESSED_TABLE_SIZE,
+            strict: false,
+            canonical_separator: PathSeparator::Backslash,
+            decompressor: Arc::new(ZlibDecompressor),
+        }
     }
+}
 
-    pub fn target_grf_name(&self) -> String {
-        self.container.header.target_grf_name.clone()
+impl fmt::Debug for ThorOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThorOptions")
+            .field("max_entries", &self.max_entries)
+            .field("canonicalize_paths", &self.canonicalize_paths)
+            .field("path_encoding", &self.path_encoding.name())
+            .field(
+                "path_decoder_trap",
+                &decoder_trap_name(self.path_decoder_trap),
+            )
+            .field(
+                "max_decompressed_table_size",
+                &self.max_decompressed_table_size,
+            )
+            .field("strict", &self.strict)
+            .field("canonical_separator", &self.canonical_separator)
+            .field("decompressor", &self.decompressor)
+            .finish()
     }
+}
 
-    pub fn get_entry_raw_data<S: AsRef<str> + Hash>(&mut self, file_path: S) -> Result<Vec<u8>> {
-        let file_entry = self
-            .get_file_entry(file_path)
-            .ok_or(GrufError::EntryNotFound)?
-            .clone();
-        if file_entry.size_compressed == 0 {
-            return Ok(vec![]);
+/// Encoding and error handling used to decode paths.
+#[derive(Clone, Copy)]
+struct PathDecoding {
+    encoding: EncodingRef,
+    trap: DecoderTrap,
+}
+
+impl PathDecoding {
+    /// Decodes `v` and indicates whether the decoding was lossy (i.e., if
+    /// malformed bytes had to be handled by the trap).
+    fn decode(&self, v: &[u8]) -> Result<(String, bool)> {
+        if let Ok(decoded) = self.encoding.decode(v, DecoderTrap::Strict) {
+            return Ok((decoded, false));
         }
+        self.encoding
+            .decode(v, self.trap)
+            .map(|decoded| (decoded, true))
+            .map_err(GrufError::parsing_error)
+    }
+}
 
-        self.obj.seek(SeekFrom::Start(file_entry.offset))?;
-        let mut content: Vec<u8> = Vec::with_capacity(file_entry.size_compressed);
-        let mut file_chunk = self.obj.by_ref().take(content.capacity() as u64);
-        file_chunk.read_to_end(&mut content)?;
-        Ok(content)
+impl fmt::Debug for PathDecoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PathDecoding")
+            .field("encoding", &self.encoding.name())
+            .field("trap", &decoder_trap_name(self.trap))
+            .finish()
     }
+}
 
-    pub fn read_file_content<S: AsRef<str> + Hash>(&mut self, file_path: S) -> Result<Vec<u8>> {
-        let file_entry = self
-            .get_file_entry(file_path)
-            .ok_or(GrufError::EntryNotFound)?
-            .clone();
-        if file_entry.size_compressed == 0 {
-            return Ok(vec![]);
-        }
+fn decoder_trap_name(trap: DecoderTrap) -> &'static str {
+    match trap {
+        DecoderTrap::Strict => "Strict",
+        DecoderTrap::Replace => "Replace",
+        DecoderTrap::Ignore => "Ignore",
+        DecoderTrap::Call(_) => "Call",
+    }
+}
 
-        self.obj.seek(SeekFrom::Start(file_entry.offset))?;
-        let mut content: Vec<u8> = Vec::with_capacity(file_entry.size_compressed);
-        let mut file_chunk = self.obj.by_ref().take(content.capacity() as u64);
-        file_chunk.read_to_end(&mut content)?;
-        // Decompress the content with zlib
-        let mut decoder = ZlibDecoder::new(content.as_slice());
-        let mut decompressed_content = Vec::new();
-        let decompressed_size = decoder.read_to_end(&mut decompressed_content)?;
-        if decompressed_size != file_entry.size {
-            return Err(GrufError::parsing_error(
-                "Decompressed content is not as expected",
-            ));
+/// Entries whose paths are identical once canonicalized.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PathCollision {
+    pub canonical_path: String,
+    /// Original paths of the colliding entries, in the file table's order
+    pub original_paths: Vec<String>,
+}
+
+/// Indicates how errors should be handled when extracting multiple files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtractMode {
+    /// Stop at the first file that cannot be extracted
+    StopOnError,
+    /// Extract every file that can be extracted and report failures
+    ContinueOnError,
+}
+
+/// Order of the entries returned by `ThorArchive::entries_sorted_by`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// By path, in ascending order
+    Path,
+    /// By compressed size, biggest entries first
+    CompressedSize,
+    /// By decompressed size, biggest entries first
+    DecompressedSize,
+}
+
+/// Indicates how files whose names collide are handled when extracting an
+/// archive into a flat directory, see `ThorArchive::extract_all_flat`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    /// Fail with `GrufError::NameCollision` before extracting anything
+    Error,
+    /// Only extract the first file with a given name
+    Skip,
+    /// Append an index to the names of the following files (e.g.,
+    /// "icon_num_1.bmp")
+    Rename,
+}
+
+/// Options used when extracting multiple files.
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    pub mode: ExtractMode,
+    /// Modification time set on all extracted files. If `None`, files keep
+    /// the time at which they were written.
+    pub mtime: Option<SystemTime>,
+    /// Flag that aborts the extraction with `GrufError::Cancelled` when set.
+    /// The file being written when that happens is deleted, files that have
+    /// already been extracted are kept.
+    pub cancel_flag: Option<Arc<AtomicBool>>,
+    /// Whether metadata files (see `metadata_names`) are left out of the
+    /// files written. They can still be read, e.g., with
+    /// `ThorArchive::read_integrity_table`.
+    pub skip_integrity_file: bool,
+    /// Paths of the metadata files, compared case-insensitively
+    /// (`RESERVED_METADATA_NAMES` by default).
+    pub metadata_names: Vec<String>,
+}
+
+impl ExtractOptions {
+    /// Indicates whether `entry` is left out of the files written.
+    pub fn is_skipped(&self, entry: &ThorFileEntry) -> bool {
+        self.skip_integrity_file && is_metadata_path(&entry.relative_path, &self.metadata_names)
+    }
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            mode: ExtractMode::StopOnError,
+            mtime: None,
+            cancel_flag: None,
+            skip_integrity_file: false,
+            metadata_names: default_metadata_names(),
         }
-        Ok(decompressed_content)
     }
+}
 
-    pub fn extract_file<S: AsRef<str> + Hash>(
-        &mut self,
-        file_path: S,
-        destination_path: &Path,
-    ) -> Result<()> {
-        let content = self.read_file_content(file_path)?;
-        let mut file = File::create(destination_path)?;
-        Ok(file.write_all(content.as_slice())?)
+/// Returns `RESERVED_METADATA_NAMES`, as owned strings.
+pub(crate) fn default_metadata_names() -> Vec<String> {
+    RESERVED_METADATA_NAMES
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// Indicates whether `relative_path` is one of `metadata_names`.
+pub(crate) fn is_metadata_path(relative_path: &str, metadata_names: &[String]) -> bool {
+    metadata_names
+        .iter()
+        .any(|name| name.eq_ignore_ascii_case(relative_path))
+}
+
+/// Options used when repacking an archive, see `ThorArchive::repack_to`.
+#[derive(Debug, Clone, Default)]
+pub struct RepackOptions {
+    /// Compression level used to recompress files' content. If `None`, the
+    /// compressed content is copied as is.
+    pub compression: Option<Compression>,
+    /// Whether identical compressed content is stored once, see
+    /// `ThorArchiveBuilder::with_deduplication`.
+    pub deduplicate: bool,
+}
+
+/// Summary of a repacked archive, as returned by `ThorArchive::repack_to`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RepackReport {
+    /// Number of entries written (excluding 'data.integrity')
+    pub written_count: usize,
+    /// Number of written entries sharing the content of another entry
+    pub deduplicated_count: usize,
+}
+
+/// Summary of a transcoded archive, as returned by
+/// `ThorArchive::transcode_to`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TranscodeReport {
+    /// Number of files recompressed (excluding 'data.integrity')
+    pub transcoded_count: usize,
+    /// Total size of the recompressed files' data, before transcoding
+    pub size_compressed_before: u64,
+    /// Total size of the recompressed files' data, after transcoding
+    pub size_compressed_after: u64,
+}
+
+impl TranscodeReport {
+    /// Returns the number of bytes gained (if positive) or saved (if
+    /// negative) by transcoding.
+    pub fn size_delta(&self) -> i64 {
+        self.size_compressed_after as i64 - self.size_compressed_before as i64
     }
 
-    pub fn get_file_entry<S: AsRef<str> + Hash>(&self, file_path: S) -> Option<&ThorFileEntry> {
-        self.container.entries.get(file_path.as_ref())
+    fn add(&mut self, entry: &ThorFileEntry, compressed_data: &[u8]) {
+        self.transcoded_count += 1;
+        self.size_compressed_before += entry.size_compressed as u64;
+        self.size_compressed_after += compressed_data.len() as u64;
     }
+}
 
-    pub fn get_entries(&self) -> impl Iterator<Item = &'_ ThorFileEntry> {
-        self.container.entries.values()
+/// Upper bound of the size of each section of an archive under construction.
+#[derive(Debug, Clone)]
+struct PartLayout {
+    entry_count: usize,
+    header_size: u64,
+    data_size: u64,
+    table_size: u64,
+    integrity_size: Option<u64>,
+}
+
+impl PartLayout {
+    fn new(target_grf_name: &str, include_checksums: bool) -> Self {
+        let mut layout = Self {
+            entry_count: 0,
+            header_size: (THOR_HEADER_FIXED_SIZE
+                + target_grf_name.len()
+                + MULTIPLE_FILES_TABLE_DESC_SIZE) as u64,
+            data_size: 0,
+            table_size: 0,
+            integrity_size: None,
+        };
+        if include_checksums {
+            layout.table_size += Self::table_entry_size(INTEGRITY_FILE_NAME);
+            layout.integrity_size = Some(0);
+        }
+        layout
     }
 
-    /// Checks if the container has been unintentionnaly corrupted
-    pub fn is_valid(&mut self) -> Result<bool> {
-        let integrity_data = self.read_file_content(INTEGRITY_FILE_NAME)?;
-        let integrity_data_as_str = string_from_win_1252(integrity_data.as_slice())?;
-        let integrity_info = parse_data_integrity_info(integrity_data_as_str.as_str());
-        for (file_path, hash) in integrity_info {
-            let file_content = match self.read_file_content(file_path) {
-                Ok(v) => v,
-                Err(_) => return Ok(false),
-            };
-            if crc32::checksum_ieee(file_content.as_slice()) != hash {
-                return Ok(false);
+    fn add_entry(&mut self, entry: &ThorFileEntry) {
+        self.entry_count += 1;
+        self.table_size += Self::table_entry_size(&entry.relative_path);
+        if !entry.is_removed {
+            self.data_size += entry.size_compressed as u64;
+            if let Some(integrity_size) = self.integrity_size.as_mut() {
+                // "path=0x00000000\r\n"
+                *integrity_size += entry.relative_path.chars().count() as u64 + 13;
             }
         }
-        Ok(true)
+    }
+
+    fn size(&self) -> u64 {
+        let integrity_size = self.integrity_size.map_or(0, zlib_compress_bound);
+        self.header_size + self.data_size + integrity_size + zlib_compress_bound(self.table_size)
+    }
+
+    fn table_entry_size(relative_path: &str) -> u64 {
+        // Path size, path, flags, offset, compressed size and size
+        1 + relative_path.chars().count() as u64 + 1 + 12
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct ThorContainer {
-    pub header: ThorHeader,
-    table: ThorTable,
-    pub entries: HashMap<String, ThorFileEntry>,
+/// Worst-case size of `size` bytes of data once compressed with zlib.
+fn zlib_compress_bound(size: u64) -> u64 {
+    size + 5 * (size / 16383 + 1) + 6
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct ThorHeader {
-    pub use_grf_merging: bool, // false -> client directory, true -> GRF
-    pub file_count: usize,
-    mode: ThorMode,
-    pub target_grf_name: String, // If empty (size == 0) -> default GRF
+/// Reader that exposes `inner` as if it started at `base`, so that archives
+/// embedded in another file can be parsed as standalone archives.
+struct BaseOffsetReader<'a, R> {
+    inner: &'a mut R,
+    base: u64,
 }
 
-#[derive(Debug, PartialEq, Eq)]
-enum ThorTable {
-    SingleFile(SingleFileTableDesc),
-    MultipleFiles(MultipleFilesTableDesc),
+impl<'a, R: Seek> BaseOffsetReader<'a, R> {
+    /// Creates the reader, positioned at `base`.
+    fn new(inner: &'a mut R, base: u64) -> io::Result<Self> {
+        inner.seek(SeekFrom::Start(base))?;
+        Ok(Self { inner, base })
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-struct SingleFileTableDesc {
-    file_table_offset: u64,
+impl<'a, R: Read> Read for BaseOffsetReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
 }
 
-#[derive(Debug, PartialEq, Eq)]
-struct MultipleFilesTableDesc {
-    file_table_compressed_size: usize,
-    file_table_offset: u64,
+impl<'a, R: Seek> Seek for BaseOffsetReader<'a, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => SeekFrom::Start(self.base.saturating_add(offset)),
+            pos => pos,
+        };
+        self.inner.seek(pos)?.checked_sub(self.base).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before the start of the archive",
+            )
+        })
+    }
 }
 
-#[derive(Debug, Clone, Eq)]
-pub struct ThorFileEntry {
-    pub size_compressed: usize,
-    pub size: usize,
-    pub relative_path: String,
-    pub is_removed: bool,
-    pub offset: u64,
+/// Reader over a section of an object shared by several readers.
+struct SharedSectionReader<'a, R> {
+    obj: Rc<RefCell<&'a mut Box<R>>>,
+    position: u64,
+    end: u64,
 }
 
-impl ThorFileEntry {
-    pub fn is_internal(&self) -> bool {
-        self.relative_path == INTEGRITY_FILE_NAME
+impl<'a, R: Read + Seek> Read for SharedSectionReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = (self.end - self.position) as usize;
+        let read_size = std::cmp::min(buf.len(), remaining);
+        if read_size == 0 {
+            return Ok(0);
+        }
+        let mut obj = self.obj.borrow_mut();
+        obj.seek(SeekFrom::Start(self.position))?;
+        let read_size = obj.read(&mut buf[..read_size])?;
+        self.position += read_size as u64;
+        Ok(read_size)
     }
 }
 
-impl Hash for ThorFileEntry {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.relative_path.hash(state);
-    }
+/// Decompressing reader returned by `ThorArchive::open_file`.
+enum EntryContentReader<'a> {
+    /// Empty entries have no zlib stream
+    Empty,
+    Stream(Box<dyn Read + 'a>),
 }
 
-impl PartialEq for ThorFileEntry {
-    fn eq(&self, other: &ThorFileEntry) -> bool {
-        self.relative_path == other.relative_path
+impl Read for EntryContentReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            EntryContentReader::Empty => Ok(0),
+            EntryContentReader::Stream(decoder) => decoder.read(buf),
+        }
     }
 }
 
-fn i16_to_thor_mode(i: i16) -> ThorMode {
-    match i {
-        33 => ThorMode::SingleFile,
-        48 => ThorMode::MultipleFiles,
-        _ => ThorMode::Invalid,
+/// Writer that only computes the CRC32 checksum of the data written to it.
+struct Crc32Writer(crc32::Digest);
+
+impl Write for Crc32Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Hasher32::write(&mut self.0, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
     }
 }
 
-/// Checks entries' flags
-/// If LSB is 1, the entry indicates a file deletion
-fn is_file_removed(flags: u8) -> bool {
-    (flags & 0b1) == 1
+/// Temporary file that a file is extracted to, next to its destination.
+///
+/// The temporary file is renamed to its destination by `persist`, once its
+/// content is complete, and deleted if the guard is dropped before (e.g., on
+/// errors or panics) so that partially extracted files are never left
+/// behind.
+pub(crate) struct PartFileGuard {
+    part_path: PathBuf,
+    destination_path: PathBuf,
+    persisted: bool,
 }
 
-named!(parse_thor_header<&[u8], ThorHeader>,
-    do_parse!(
-        tag!(THOR_HEADER_MAGIC)
-            >> use_grf_merging: le_u8
-            >> file_count: le_u32
-            >> mode: le_i16
-            >> target_grf_name_size: le_u8
-            >> target_grf_name: take_str!(target_grf_name_size)
-            >> (ThorHeader {
-                use_grf_merging: use_grf_merging == 1,
-                file_count: file_count as usize,
-                mode: i16_to_thor_mode(mode),
-                target_grf_name: target_grf_name.to_string(),
-            }
-    )
-));
+impl PartFileGuard {
+    /// Creates the temporary file of `destination_path` (i.e., with a
+    /// `.part` extension appended to its name).
+    pub(crate) fn create(destination_path: &Path) -> Result<(Self, File)> {
+        let guard = Self::new(destination_path);
+        let file = File::create(guard.part_path())?;
+        Ok((guard, file))
+    }
 
-named!(parse_single_file_table<&[u8], SingleFileTableDesc>,
-    do_parse!(
-        take!(1)
-        >> (SingleFileTableDesc {
-            file_table_offset: 0, // Offset in the 'data' field
+    /// Same as `create` but lets the caller create the temporary file.
+    pub(crate) fn new(destination_path: &Path) -> Self {
+        let mut part_file_name = destination_path
+            .file_name()
+            .map(|name| name.to_os_string())
+            .unwrap_or_default();
+        part_file_name.push(".part");
+        Self {
+            part_path: destination_path.with_file_name(part_file_name),
+            destination_path: destination_path.to_path_buf(),
+            persisted: false,
         }
-    )
-));
+    }
 
-named!(parse_multiple_files_table<&[u8], MultipleFilesTableDesc>,
-    do_parse!(
-        file_table_compressed_size: le_i32
-        >> file_table_offset: le_i32
-        >> (MultipleFilesTableDesc {
-            file_table_compressed_size: file_table_compressed_size as usize,
-            file_table_offset: file_table_offset as u64, // Offset in the 'data' field
-        }
-    )
-));
+    pub(crate) fn part_path(&self) -> &Path {
+        &self.part_path
+    }
 
-fn string_from_win_1252(v: &[u8]) -> Result<String> {
-    let decoder = encoding_from_whatwg_label("windows-1252")
-        .ok_or_else(|| GrufError::parsing_error("Decoder unavailable"))?;
-    decoder
-        .decode(v, DecoderTrap::Strict)
-        .map_err(GrufError::parsing_error)
+    /// Moves the temporary file to its destination, replacing the existing
+    /// file if any. The temporary file must have been closed.
+    pub(crate) fn persist(mut self) -> Result<()> {
+        fs::rename(&self.part_path, &self.destination_path)?;
+        self.persisted = true;
+        Ok(())
+    }
 }
 
-macro_rules! take_string_ansi (
-    ( $i:expr, $size:expr ) => (
-        {
-            let input: &[u8] = $i;
-            map_res!(input, take!($size), string_from_win_1252)
+impl Drop for PartFileGuard {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.part_path);
         }
-     );
-);
+    }
+}
 
-named!(parse_single_file_entry<&[u8], ThorFileEntry>,
-    do_parse!(
-        size_compressed: le_i32
-        >> size: le_i32
-        >> relative_path_size: le_u8
-        >> relative_path: take_string_ansi!(relative_path_size)
-        >> (ThorFileEntry {
-            size_compressed: size_compressed as usize,
-            size: size as usize,
-            relative_path,
-            is_removed: false,
-            offset: 0, // This field is set outside the parser
+/// Writes `content` into `destination_path` through a `PartFileGuard`.
+#[cfg(feature = "parallel")]
+fn write_file_atomically(destination_path: &Path, content: &[u8]) -> Result<()> {
+    let (part_file, mut file) = PartFileGuard::create(destination_path)?;
+    file.write_all(content)?;
+    drop(file);
+    part_file.persist()
+}
+
+/// Writer that fails as soon as its cancel flag is set.
+struct CancellableWriter<'a, W> {
+    inner: W,
+    cancel_flag: Option<&'a AtomicBool>,
+}
+
+impl<'a, W: Write> CancellableWriter<'a, W> {
+    fn check_cancel_flag(&self) -> io::Result<()> {
+        if is_cancelled(self.cancel_flag) {
+            // Mapped to `GrufError::Cancelled` by the caller
+            return Err(io::ErrorKind::Other.into());
         }
-    )
-));
+        Ok(())
+    }
+}
 
-/// Uses the given parser only if the flag is as expected
-/// This is used to avoid parsing unexisting fields for files marked for deletion
-macro_rules! take_if_not_removed (
-    ( $i:expr, $parser:expr, $flags:expr ) => (
-        {
-            let input: &[u8] = $i;
-            if is_file_removed($flags) {
+impl<'a, W: Write> Write for CancellableWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.check_cancel_flag()?;
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.check_cancel_flag()?;
+        self.inner.flush()
+    }
+}
+
+fn is_cancelled(cancel_flag: Option<&AtomicBool>) -> bool {
+    matches!(cancel_flag, Some(flag) if flag.load(Ordering::SeqCst))
+}
+
+/// Extracts `entry` into `destination_path`, reading its content from
+/// `reader` (see `ThorArchive::extract_all_parallel`).
+#[cfg(feature = "parallel")]
+fn extract_entry_with_reader<R: Read + Seek>(
+    reader: &mut io::Result<R>,
+    decompressor: &dyn Decompressor,
+    entry: &ThorFileEntry,
+    destination_path: &Path,
+    mtime: Option<SystemTime>,
+) -> Result<()> {
+    let reader = reader
+        .as_mut()
+        .map_err(|e| io::Error::new(e.kind(), e.to_string()))?;
+    let dest_path = safe_join(destination_path, &entry.relative_path)?;
+    if entry.is_directory() {
+        fs::create_dir_all(&dest_path)?;
+    } else {
+        let content = read_entry_raw_data(reader, entry)?;
+        let content = decompress_entry(decompressor, entry, content.as_slice())?;
+        if let Some(parent_dir) = dest_path.parent() {
+            fs::create_dir_all(parent_dir)?;
+        }
+        write_file_atomically(&dest_path, &content)?;
+    }
+    if let Some(mtime) = mtime {
+        filetime::set_file_mtime(&dest_path, FileTime::from_system_time(mtime))?;
+    }
+    Ok(())
+}
+
+/// Result of the validation of an archive's content, see
+/// `ThorArchive::validate`.
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub checked_count: usize,
+    pub failures: Vec<(String, GrufError)>, // Relative path and error
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Summary of an extraction.
+#[derive(Debug, Default)]
+pub struct ExtractReport {
+    pub extracted_count: usize,
+    pub failures: Vec<(String, GrufError)>, // Relative path and error
+}
+
+/// Summary of the application of an archive to a directory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ApplyReport {
+    pub written_count: usize,
+    pub deleted_count: usize,
+    pub skipped_count: usize,
+}
+
+/// Result of the validation of an archive against a list of expected paths.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ManifestReport {
+    pub missing_required: Vec<String>,
+    pub present_forbidden: Vec<String>,
+}
+
+impl ManifestReport {
+    pub fn is_valid(&self) -> bool {
+        self.missing_required.is_empty() && self.present_forbidden.is_empty()
+    }
+}
+
+/// Summary of how well the entries of an archive were compressed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionReport {
+    /// Number of entries whose compressed data is smaller than their content
+    pub compressed_entries: usize,
+    /// Number of entries whose compressed data isn't smaller than their
+    /// content (e.g., already-compressed assets)
+    pub incompressible_entries: usize,
+    /// Total size of the entries' content
+    pub total_size: u64,
+    /// Total size of the entries' compressed data
+    pub total_size_compressed: u64,
+}
+
+/// Summary of the parsing of an archive's file table, see
+/// `ThorArchive::stats`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ThorStats {
+    /// Number of entries listed in the file table
+    pub entries_parsed: usize,
+    /// Number of entries dropped because a later entry has the same path
+    pub duplicates_dropped: usize,
+    /// Number of entries whose path contains malformed bytes, replaced with
+    /// U+FFFD (see `ThorOptions::path_decoder_trap`)
+    pub paths_with_replacement_chars: usize,
+    /// Whether entries' paths use both backslashes and forward slashes as
+    /// separators, which hints at an archive built inconsistently
+    pub mixed_separators: bool,
+}
+
+/// THOR archive opened for reading.
+///
+/// Reading an entry's content moves the archive's reader, which is why most
+/// reading methods take `&mut self`. Every read seeks to the entry's content
+/// first, so reads don't depend on where previous ones (even interrupted
+/// ones) left the reader. Readers borrowed from the archive (e.g., with
+/// `ThorArchive::entry_reader`) keep it borrowed until they're dropped,
+/// which prevents interleaving them with other reads.
+///
+/// Methods that only use the archive's metadata take `&self` and
+/// `ThorArchive<R>` is `Send` and `Sync` whenever `R` is, so an archive can
+/// be shared between threads that read entries:
+/// - with their own reader (see `ThorArchive::read_file_content_with`),
+/// - or, for archives backed by a `File` (or a `BufReader<File>`, see
+///   `ThorArchive::open`), with positioned reads that don't move the file's
+///   cursor (see `ThorArchive::read_file_content_shared`).
+///
+/// Archives whose reader can be cloned (e.g., `Cursor`s) can be cloned as
+/// well. Cloned archives have their own reader, with its own position.
+#[derive(Debug, Clone)]
+pub struct ThorArchive<R: ?Sized> {
+    obj: Box<R>,
+    container: ThorContainer,
+    path_decoding: PathDecoding,
+    normalized_index: OnceCell<HashMap<String, String>>,
+    // Buffer reused for compressed content, see `read_file_content_into`
+    scratch_buffer: Vec<u8>,
+    // Recently read content, see `ThorArchive::with_cache`
+    cache: Option<ContentCache>,
+    // Mapped archive content, see `ThorArchive::open_mmap`
+    #[cfg(feature = "mmap")]
+    mmap: Option<Arc<memmap2::Mmap>>,
+}
+
+impl ThorArchive<BufReader<File>> {
+    /// Opens the archive located at `thor_archive_path`.
+    ///
+    /// The file is read through a `BufReader`, so that the small reads made
+    /// while parsing the archive don't each result in a system call.
+    ///
+    /// Failing to open the file results in a `GrufError::IoError` while an
+    /// invalid archive results in a `GrufError::ParsingError`.
+    pub fn open<P: AsRef<Path>>(thor_archive_path: P) -> Result<ThorArchive<BufReader<File>>> {
+        let file = File::open(thor_archive_path)?;
+        ThorArchive::new(BufReader::new(file))
+    }
+
+    /// Same as `ThorArchive::<File>::read_file_content_shared`, the buffer
+    /// is bypassed.
+    #[cfg(any(unix, windows))]
+    pub fn read_file_content_shared<S: AsRef<str> + Hash>(&self, file_path: S) -> Result<Vec<u8>> {
+        self.read_file_content_at(self.obj.get_ref(), file_path)
+    }
+}
+
+impl ThorArchive<File> {
+    /// Opens the archive located at `thor_archive_path` and maps it in
+    /// memory.
+    ///
+    /// Files' content is then decompressed directly from the mapped memory,
+    /// instead of being read with a system call per file. Other methods
+    /// (e.g., streaming ones) still use the file's handle.
+    ///
+    /// # Safety
+    ///
+    /// The file MUST NOT be modified or truncated (by this process or
+    /// another one) while the archive is in use, doing so is undefined
+    /// behavior. Only use this for archives that are never written to once
+    /// created (e.g., patches stored on a server).
+    #[cfg(feature = "mmap")]
+    pub unsafe fn open_mmap<P: AsRef<Path>>(thor_archive_path: P) -> Result<ThorArchive<File>> {
+        let file = File::open(thor_archive_path)?;
+        let mmap = memmap2::Mmap::map(&file)?;
+        let mut thor_archive = ThorArchive::new(file)?;
+        thor_archive.mmap = Some(Arc::new(mmap));
+        Ok(thor_archive)
+    }
+}
+
+impl ThorArchive<File> {
+    /// Same as `read_file_content` but reads the entry's data with a
+    /// positioned read, which makes it possible to read entries from several
+    /// threads concurrently.
+    #[cfg(any(unix, windows))]
+    pub fn read_file_content_shared<S: AsRef<str> + Hash>(&self, file_path: S) -> Result<Vec<u8>> {
+        self.read_file_content_at(&self.obj, file_path)
+    }
+}
+
+impl<R: Read + Seek> ThorArchive<R> {
+    /// Reads an entry's content from `file`, the file backing the archive,
+    /// with positioned reads.
+    #[cfg(any(unix, windows))]
+    fn read_file_content_at<S: AsRef<str> + Hash>(
+        &self,
+        file: &File,
+        file_path: S,
+    ) -> Result<Vec<u8>> {
+        let file_entry = self.get_content_entry(file_path)?;
+        check_payload_range(file_entry, file.metadata()?.len())?;
+        let mut content = vec![0; file_entry.size_compressed];
+        read_exact_at(file, &mut content, file_entry.offset)?;
+        decompress_entry(
+            self.container.decompressor.get(),
+            file_entry,
+            content.as_slice(),
+        )
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.read_exact_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "failed to fill whole buffer",
+                ))
+            }
+            Ok(read_size) => {
+                buf = &mut buf[read_size..];
+                offset += read_size as u64;
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+impl ThorArchive<Cursor<Vec<u8>>> {
+    /// Parses an archive that's entirely loaded in memory (e.g., a patch
+    /// that was just downloaded).
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<ThorArchive<Cursor<Vec<u8>>>> {
+        ThorArchive::new(Cursor::new(bytes))
+    }
+}
+
+impl<R: Read + Seek> ThorArchive<R> {
+    /// Create a new archive with the underlying object as the reader.
+    pub fn new(obj: R) -> Result<ThorArchive<R>> {
+        Self::with_options(obj, &ThorOptions::default())
+    }
+
+    /// Create a new archive with the underlying object as the reader, using
+    /// the given parsing options.
+    pub fn with_options(obj: R, options: &ThorOptions) -> Result<ThorArchive<R>> {
+        Self::with_options_and_progress(obj, options, &mut |_| {})
+    }
+
+    /// Same as `ThorArchive::new` but calls `progress_callback` with the
+    /// number of entries parsed so far, periodically while the file table is
+    /// being parsed (e.g., to give feedback when opening an archive with a
+    /// huge table).
+    pub fn new_with_progress<F>(obj: R, mut progress_callback: F) -> Result<ThorArchive<R>>
+    where
+        F: FnMut(usize),
+    {
+        Self::with_options_and_progress(obj, &ThorOptions::default(), &mut progress_callback)
+    }
+
+    fn with_options_and_progress(
+        mut obj: R,
+        options: &ThorOptions,
+        progress_callback: &mut dyn FnMut(usize),
+    ) -> Result<ThorArchive<R>> {
+        let thor_patch = parse_thor_patch_with_progress(&mut obj, options, progress_callback)?;
+        Ok(Self::from_parts(obj, thor_patch, options))
+    }
+
+    /// Create a new archive from an archive embedded in the underlying
+    /// object, whose header starts at `start_offset` (e.g., when the archive
+    /// is wrapped in another container).
+    ///
+    /// Entries' offsets (and the offset of the file table) are relative to
+    /// the start of the underlying object, not to `start_offset`.
+    pub fn new_at(mut obj: R, start_offset: u64) -> Result<ThorArchive<R>> {
+        let options = ThorOptions::default();
+        let mut thor_patch = parse_thor_patch_with_options(
+            &mut BaseOffsetReader::new(&mut obj, start_offset)?,
+            &options,
+        )?;
+        thor_patch.shift_offsets(start_offset)?;
+        Ok(Self::from_parts(obj, thor_patch, &options))
+    }
+
+    fn from_parts(obj: R, container: ThorContainer, options: &ThorOptions) -> ThorArchive<R> {
+        ThorArchive {
+            obj: Box::new(obj),
+            container,
+            path_decoding: options.path_decoding(),
+            normalized_index: OnceCell::new(),
+            scratch_buffer: Vec::new(),
+            cache: None,
+            #[cfg(feature = "mmap")]
+            mmap: None,
+        }
+    }
+
+    /// Parses the archive's header and file table descriptor only, e.g., to
+    /// quickly list the target GRF of a lot of archives.
+    ///
+    /// The file table isn't read until `ThorArchiveHeader::load_entries` is
+    /// called.
+    pub fn open_header_only(obj: R) -> Result<ThorArchiveHeader<R>> {
+        ThorArchiveHeader::with_options(obj, &ThorOptions::default())
+    }
+
+    /// Returns the underlying reader, rewound to the start of the archive
+    /// (e.g., to hand the file over to something else once the archive has
+    /// been read).
+    pub fn into_inner(mut self) -> Result<R> {
+        self.obj.seek(SeekFrom::Start(self.container.base_offset))?;
+        Ok(*self.obj)
+    }
+
+    /// Enables caching the content of the files read with
+    /// `read_file_content` (and the methods based on it), e.g., to verify
+    /// files before applying them without reading them twice.
+    ///
+    /// The cache holds decompressed content: `capacity_bytes` bounds the
+    /// size of the decompressed content kept in memory. The least recently
+    /// read files are evicted first and files bigger than `capacity_bytes`
+    /// aren't cached.
+    pub fn with_cache(mut self, capacity_bytes: usize) -> Self {
+        self.cache = Some(ContentCache::new(capacity_bytes));
+        self
+    }
+
+    pub fn header(&self) -> &ThorHeader {
+        &self.container.header
+    }
+
+    /// Returns the descriptor of the archive's file table.
+    pub fn table(&self) -> &ThorTable {
+        &self.container.table
+    }
+
+    /// Returns the absolute offset of the archive's file table.
+    pub fn file_table_offset(&self) -> u64 {
+        self.container.table.file_table_offset()
+    }
+
+    /// Returns the size of the archive's compressed file table, or `None`
+    /// for single-file archives.
+    pub fn file_table_compressed_size(&self) -> Option<usize> {
+        self.container.table.file_table_compressed_size()
+    }
+
+    /// Returns the parsed archive's metadata (header, table and entries),
+    /// which can be serialized to produce a listing of the archive's content.
+    pub fn manifest(&self) -> &ThorContainer {
+        &self.container
+    }
+
+    /// Consumes the archive and returns its parsed metadata (see
+    /// `ThorArchive::manifest`), without copying the entries. The underlying
+    /// reader is dropped.
+    pub fn into_container(self) -> ThorContainer {
+        self.container
+    }
+
+    /// Indicates whether the archive must be merged into a GRF or applied to
+    /// the client's directory.
+    pub fn use_grf_merging(&self) -> bool {
+        self.container.header.use_grf_merging
+    }
+
+    /// Returns the layout of the archive's file table (never
+    /// `ThorMode::Invalid` for a parsed archive).
+    pub fn mode(&self) -> ThorMode {
+        self.container.header.mode
+    }
+
+    /// Returns the number of entries parsed from the archive's file table.
+    ///
+    /// This may differ from the number of files announced by the header
+    /// (`ThorHeader::file_count`, see `ThorArchive::verify_entry_count`).
+    pub fn file_count(&self) -> usize {
+        self.container.entries.len()
+    }
+
+    /// Same as `ThorArchive::file_count`.
+    pub fn len(&self) -> usize {
+        self.file_count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.container.entries.is_empty()
+    }
+
+    pub fn contains_file<S: AsRef<str> + Hash>(&self, file_path: S) -> bool {
+        self.get_file_entry(file_path).is_some()
+    }
+
+    /// Returns the number of entries that mark files for removal.
+    pub fn removed_count(&self) -> usize {
+        self.removed_entries().count()
+    }
+
+    /// Returns the number of entries that have content (i.e., that aren't
+    /// removals).
+    pub fn present_count(&self) -> usize {
+        self.file_count() - self.removed_count()
+    }
+
+    /// Returns the total size of the entries' compressed content. Removed
+    /// entries are ignored.
+    pub fn total_compressed_size(&self) -> u64 {
+        self.present_entries()
+            .map(|e| e.size_compressed as u64)
+            .sum()
+    }
+
+    /// Returns the total size of the entries' decompressed content. Removed
+    /// entries are ignored.
+    pub fn total_decompressed_size(&self) -> u64 {
+        self.present_entries().map(|e| e.size as u64).sum()
+    }
+
+    /// Returns the name of the GRF the archive must be merged into, empty if
+    /// it's the client's default GRF.
+    ///
+    /// Prefer `patch_kind`, which makes the default case explicit.
+    pub fn target_grf_name(&self) -> String {
+        self.container.header.target_grf_name.clone()
+    }
+
+    /// Returns where the archive's files must be applied, combining
+    /// `use_grf_merging` and `target_grf_name`.
+    pub fn patch_kind(&self) -> PatchKind {
+        self.container.header.patch_kind()
+    }
+
+    /// Checks whether the archive should be merged into the GRF named
+    /// `grf_name` (case-insensitive).
+    ///
+    /// Archives that don't specify a target GRF are meant to be merged into
+    /// the default GRF and are thus considered to target any GRF. Archives
+    /// that don't use GRF merging don't target any GRF.
+    pub fn targets_grf(&self, grf_name: &str) -> bool {
+        match self.patch_kind() {
+            PatchKind::ClientDirectory => false,
+            PatchKind::DefaultGrf => true,
+            PatchKind::NamedGrf(target_grf_name) => target_grf_name.eq_ignore_ascii_case(grf_name),
+        }
+    }
+
+    /// Checks that the archive targets the GRF named `actual_grf`
+    /// (case-insensitive), before merging the archive into it.
+    ///
+    /// Fails with `GrufError::TargetMismatch` if the archive targets another
+    /// GRF, and with `GrufError::NotGrfPatch` if its files must be extracted
+    /// into the client's directory. Archives that don't specify a target GRF
+    /// target any GRF.
+    pub fn check_target(&self, actual_grf: &str) -> Result<()> {
+        match self.patch_kind() {
+            PatchKind::ClientDirectory => Err(GrufError::NotGrfPatch(actual_grf.to_string())),
+            PatchKind::DefaultGrf => Ok(()),
+            PatchKind::NamedGrf(expected) if expected.eq_ignore_ascii_case(actual_grf) => Ok(()),
+            PatchKind::NamedGrf(expected) => Err(GrufError::TargetMismatch {
+                expected,
+                actual: actual_grf.to_string(),
+            }),
+        }
+    }
+
+    /// Returns the range of bytes containing the content of all the files
+    /// stored in the archive (i.e., without the header and the file table).
+    ///
+    /// Returns an empty range for archives without file content.
+    pub fn data_span(&self) -> Range<u64> {
+        self.get_entries()
+            .filter(|e| !e.is_removed && e.size_compressed > 0)
+            .map(|e| e.offset..e.offset + e.size_compressed as u64)
+            .fold(None, |span: Option<Range<u64>>, range| match span {
+                None => Some(range),
+                Some(span) => {
+                    Some(std::cmp::min(span.start, range.start)..std::cmp::max(span.end, range.end))
+                }
+            })
+            .unwrap_or(0..0)
+    }
+
+    /// Returns the range of bytes containing the compressed content of
+    /// `file_path` in the underlying object (see `get_entry_raw_data`), or
+    /// `None` if the entry doesn't exist or is a removal.
+    ///
+    /// The range is absolute, i.e., it includes the start offset of archives
+    /// opened with `new_at`.
+    pub fn entry_data_range<S: AsRef<str> + Hash>(&self, file_path: S) -> Option<Range<u64>> {
+        match self.get_file_entry(file_path) {
+            Some(entry) if !entry.is_removed => {
+                Some(entry.offset..entry.offset + entry.size_compressed as u64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Alias of `rewrite_target_grf`.
+    pub fn set_target_grf_in_place<W: Write + Seek>(
+        &mut self,
+        writer: W,
+        target_grf_name: &str,
+    ) -> Result<()> {
+        self.rewrite_target_grf(writer, target_grf_name)
+    }
+
+    /// Writes a copy of the archive into `writer` whose header is rewritten
+    /// with `target_grf_name` as the target GRF's name.
+    ///
+    /// Fails with a `GrufError::SerializationError`, before anything is
+    /// written, if the name is longer than 255 bytes.
+    ///
+    /// Files' content is copied as is (i.e., without being recompressed). For
+    /// multiple-files archives, the file table is rewritten with offsets
+    /// adjusted to the new header's size. The copy always uses the standard
+    /// layout (i.e., the prefix of length-prefixed archives isn't kept).
+    pub fn rewrite_target_grf<W: Write + Seek>(
+        &mut self,
+        mut writer: W,
+        target_grf_name: &str,
+    ) -> Result<()> {
+        let header = &self.container.header;
+        if target_grf_name.len() > u8::MAX as usize {
+            return Err(GrufError::serialization_error(
+                "Target GRF name is too long",
+            ));
+        }
+        let old_prefix_size = self.container.base_offset as usize
+            + header.magic_offset() as usize
+            + THOR_HEADER_FIXED_SIZE
+            + header.target_grf_name.len();
+        let new_prefix_size = THOR_HEADER_FIXED_SIZE + target_grf_name.len();
+        writer.seek(SeekFrom::Start(0))?;
+        write_thor_header_prefix(
+            writer.by_ref(),
+            header.use_grf_merging,
+            header.file_count,
+            header.mode,
+            target_grf_name,
+        )?;
+        match &self.container.table {
+            ThorTable::SingleFile(_) => {
+                // The entry doesn't store its offset, copy everything as is
+                self.obj.seek(SeekFrom::Start(old_prefix_size as u64))?;
+                io::copy(self.obj.by_ref(), &mut writer)?;
+            }
+            ThorTable::MultipleFiles(table) => {
+                let old_data_offset = (old_prefix_size + MULTIPLE_FILES_TABLE_DESC_SIZE) as u64;
+                let new_data_offset = (new_prefix_size + MULTIPLE_FILES_TABLE_DESC_SIZE) as u64;
+                // Files' content is expected to be stored between the header
+                // and the file table
+                let data_span = self.data_span();
+                if !data_span.is_empty()
+                    && (data_span.start < old_data_offset
+                        || data_span.end > table.file_table_offset)
+                {
+                    return Err(GrufError::serialization_error(
+                        "Unsupported THOR archive layout",
+                    ));
+                }
+                let move_offset = |offset: u64| {
+                    offset
+                        .checked_sub(old_data_offset)
+                        .and_then(|offset| offset.checked_add(new_data_offset))
+                        .ok_or(GrufError::OffsetOutOfBounds)
+                };
+                // Generate the new table
+                let mut table_content: Vec<u8> = Vec::new();
+                for entry in &self.container.entries {
+                    let generic_entry = if entry.is_removed {
+                        None
+                    } else {
+                        Some(GenericFileEntry {
+                            offset: move_offset(entry.offset)?,
+                            size: u32::try_from(entry.size)?,
+                            size_compressed: u32::try_from(entry.size_compressed)?,
+                        })
+                    };
+                    serialize_thor_file_entry_into(
+                        &mut table_content,
+                        &entry.relative_path,
+                        generic_entry.as_ref(),
+                    )?;
+                }
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&table_content)?;
+                let compressed_table = encoder.finish()?;
+                // Write the table descriptor, files' content and the table
+                let new_table_offset = move_offset(table.file_table_offset)?;
+                let data_size = new_table_offset - new_data_offset;
+                write_multiple_files_table_desc(
+                    writer.by_ref(),
+                    compressed_table.len(),
+                    new_table_offset,
+                )?;
+                self.obj.seek(SeekFrom::Start(old_data_offset))?;
+                let mut data_chunk = self.obj.by_ref().take(data_size);
+                io::copy(&mut data_chunk, &mut writer)?;
+                writer.write_all(&compressed_table)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrites the archive into `obj` without its removal entries, e.g., to
+    /// flatten a cumulative patch.
+    ///
+    /// The new archive shares the archive's header properties and contains
+    /// its own 'data.integrity' file if the archive contains one. It's
+    /// always written in 'multiple files' mode.
+    pub fn repack_to<W: Write + Seek>(
+        &mut self,
+        obj: W,
+        options: &RepackOptions,
+    ) -> Result<RepackReport> {
+        let include_checksums = self.get_file_entry(INTEGRITY_FILE_NAME).is_some();
+        let mut builder = ThorArchiveBuilder::new(
+            obj,
+            self.use_grf_merging(),
+            Some(self.target_grf_name()),
+            include_checksums,
+        )?;
+        if let Some(compression) = options.compression {
+            builder = builder.with_compression(compression);
+        }
+        if options.deduplicate {
+            builder = builder.with_deduplication();
+        }
+        let file_entries: Vec<ThorFileEntry> = self
+            .present_entries()
+            .filter(|e| !e.is_internal())
+            .cloned()
+            .collect();
+        for entry in &file_entries {
+            if let Some(compression) = options.compression {
+                let (compressed_data, size, checksum) =
+                    self.recompress_entry(entry, compression)?;
+                builder.append_raw_file_update(
+                    entry.relative_path.clone(),
+                    &compressed_data,
+                    size,
+                    checksum,
+                )?;
+                continue;
+            }
+            let raw_content = self.get_entry_raw_data(&entry.relative_path)?;
+            let checksum = if include_checksums {
+                self.file_crc32(&entry.relative_path)?
+            } else {
+                0
+            };
+            builder.append_raw_file_update(
+                entry.relative_path.clone(),
+                &raw_content,
+                u32::try_from(entry.size)?,
+                checksum,
+            )?;
+        }
+        builder.finish()?;
+        Ok(RepackReport {
+            written_count: file_entries.len(),
+            deduplicated_count: builder.deduplicated_count(),
+        })
+    }
+
+    /// Rewrites the archive into `obj`, with files' content recompressed at
+    /// the given compression level.
+    ///
+    /// Unlike `repack_to`, the archive is otherwise left as is: its entries
+    /// (removals included) are written in the same order, in the same mode
+    /// and with the same header properties. Files are recompressed one at a
+    /// time, and their decompressed size is checked against their entry.
+    pub fn transcode_to<W: Write + Seek>(
+        &mut self,
+        obj: W,
+        level: Compression,
+    ) -> Result<TranscodeReport> {
+        let mut report = TranscodeReport::default();
+        if self.mode() == ThorMode::SingleFile {
+            let entry = self
+                .get_entries()
+                .next()
+                .cloned()
+                .ok_or_else(|| GrufError::serialization_error("Missing file"))?;
+            let (compressed_data, size, _) = self.recompress_entry(&entry, level)?;
+            write_raw_single_file_archive(
+                obj,
+                self.use_grf_merging(),
+                Some(&self.container.header.target_grf_name),
+                &entry.relative_path,
+                &compressed_data,
+                size,
+            )?;
+            report.add(&entry, &compressed_data);
+            return Ok(report);
+        }
+
+        let include_checksums = self.get_file_entry(INTEGRITY_FILE_NAME).is_some();
+        let mut builder = ThorArchiveBuilder::new(
+            obj,
+            self.use_grf_merging(),
+            Some(self.target_grf_name()),
+            include_checksums,
+        )?
+        .with_compression(level);
+        // 'data.integrity' is regenerated by the builder
+        let file_entries: Vec<ThorFileEntry> = self
+            .get_entries()
+            .filter(|e| !e.is_internal())
+            .cloned()
+            .collect();
+        for entry in &file_entries {
+            if entry.is_removed {
+                builder.append_file_removal(entry.relative_path.clone());
+                continue;
+            }
+            let (compressed_data, size, checksum) = self.recompress_entry(entry, level)?;
+            builder.append_raw_file_update(
+                entry.relative_path.clone(),
+                &compressed_data,
+                size,
+                checksum,
+            )?;
+            report.add(entry, &compressed_data);
+        }
+        builder.finish()?;
+        Ok(report)
+    }
+
+    /// Decompresses the content of `entry` and compresses it again with
+    /// `level`, streaming the content. Returns the compressed data, the
+    /// content's size and its CRC32 checksum.
+    ///
+    /// Used by both `repack_to` and `transcode_to`.
+    fn recompress_entry(
+        &mut self,
+        entry: &ThorFileEntry,
+        level: Compression,
+    ) -> Result<(Vec<u8>, u32, u32)> {
+        // Entries without data (e.g., directories) are kept that way
+        if entry.size_compressed == 0 {
+            return Ok((vec![], 0, 0));
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), level);
+        // Stop decompressing as soon as the content is bigger than expected
+        let mut reader = self
+            .open_file(&entry.relative_path)?
+            .take(entry.size as u64 + 1);
+        let (size, checksum) = copy_and_measure_crc32(&mut reader, &mut encoder)?;
+        if size > entry.size as u64 {
+            return Err(GrufError::DecompressionTooLarge(entry.size));
+        }
+        if size != entry.size as u64 {
+            return Err(GrufError::SizeMismatch {
+                expected: entry.size,
+                actual: size as usize,
+            });
+        }
+        Ok((encoder.finish()?, u32::try_from(size)?, checksum))
+    }
+
+    /// Splits the archive into several archives (parts) of at most
+    /// `max_part_bytes` bytes each. `create_part` is called with the index of
+    /// each part and returns the object the part is written into.
+    ///
+    /// Entries are sorted by path, so that entries located in the same
+    /// directory tend to end up in the same part. Parts share the archive's
+    /// header properties and contain their own 'data.integrity' file if the
+    /// archive contains one. An entry too big to fit in a part is stored alone
+    /// in its own part, which then exceeds the limit.
+    ///
+    /// The returned builders can be completed further, they're finished when
+    /// dropped.
+    pub fn split<W, F>(
+        &mut self,
+        max_part_bytes: u64,
+        mut create_part: F,
+    ) -> Result<Vec<ThorArchiveBuilder<W>>>
+    where
+        W: Write + Seek,
+        F: FnMut(usize) -> Result<W>,
+    {
+        let include_checksums = self.get_file_entry(INTEGRITY_FILE_NAME).is_some();
+        let use_grf_merging = self.use_grf_merging();
+        let target_grf_name = self.target_grf_name();
+        let mut file_entries: Vec<ThorFileEntry> = self
+            .get_entries()
+            .filter(|e| !e.is_internal())
+            .cloned()
+            .collect();
+        file_entries.sort_unstable_by(|a, b| a.relative_path.cmp(&b.relative_path));
+
+        let mut parts: Vec<ThorArchiveBuilder<W>> = Vec::new();
+        let mut part_layout = PartLayout::new(&target_grf_name, include_checksums);
+        for entry in file_entries {
+            let mut entry_layout = part_layout.clone();
+            entry_layout.add_entry(&entry);
+            if parts.is_empty()
+                || (entry_layout.size() > max_part_bytes && part_layout.entry_count > 0)
+            {
+                let part = create_part(parts.len())?;
+                parts.push(ThorArchiveBuilder::new(
+                    part,
+                    use_grf_merging,
+                    Some(target_grf_name.clone()),
+                    include_checksums,
+                )?);
+                entry_layout = PartLayout::new(&target_grf_name, include_checksums);
+                entry_layout.add_entry(&entry);
+            }
+            part_layout = entry_layout;
+
+            let builder = parts
+                .last_mut()
+                .ok_or_else(|| GrufError::serialization_error("Missing part"))?;
+            if entry.is_removed {
+                builder.append_file_removal(entry.relative_path);
+                continue;
+            }
+            let raw_content = self.get_entry_raw_data(&entry.relative_path)?;
+            // Note: the checksum of empty content is 0
+            let checksum = if include_checksums && !raw_content.is_empty() {
+                crc32::checksum_ieee(&decompress_entry(
+                    self.container.decompressor.get(),
+                    &entry,
+                    &raw_content,
+                )?)
+            } else {
+                0
+            };
+            builder.append_raw_file_update(
+                entry.relative_path,
+                &raw_content,
+                u32::try_from(entry.size)?,
+                checksum,
+            )?;
+        }
+        Ok(parts)
+    }
+
+    /// Decodes the paths of the archive's entries again, from the bytes stored
+    /// in the file table, using `encoding` for the paths that aren't prefixed
+    /// with a BOM (see `ThorOptions::path_encoding`).
+    ///
+    /// All the entries of the table are indexed again, including the ones
+    /// dropped as duplicates under the previous encoding. Paths that collide
+    /// once decoded are reported by `path_collisions` and `stats`.
+    /// Fails with `GrufError::PathDecodingError`, leaving the archive
+    /// untouched, if a path cannot be decoded.
+    pub fn redecode_paths(&mut self, encoding: EncodingRef) -> Result<()> {
+        let path_decoding = PathDecoding {
+            encoding,
+            ..self.path_decoding
+        };
+        let mut entries = self
+            .container
+            .table_entries
+            .clone()
+            .unwrap_or_else(|| self.container.entries.clone());
+        let mut lossy_path_count = 0;
+        for entry in &mut entries {
+            let (relative_path, lossy_path) = decode_thor_path(&entry.raw_path, path_decoding)
+                .map_err(|_| GrufError::PathDecodingError)?;
+            entry.relative_path = relative_path;
+            lossy_path_count += lossy_path as usize;
+        }
+        let indexed_entries = index_entries(
+            entries,
+            self.container.canonicalize_paths,
+            self.container.canonical_separator,
+        );
+        self.container.entries = indexed_entries.entries;
+        self.container.entry_index = indexed_entries.entry_index;
+        self.container.table_entries = indexed_entries.table_entries;
+        self.container.mixed_separators = indexed_entries.mixed_separators;
+        self.container.path_collisions = indexed_entries.path_collisions;
+        self.container.duplicate_count = indexed_entries.duplicate_count;
+        self.container.lossy_path_count = lossy_path_count;
+        self.path_decoding = path_decoding;
+        self.normalized_index = OnceCell::new();
+        if let Some(cache) = &mut self.cache {
+            cache.clear();
+        }
+        Ok(())
+    }
+
+    /// Returns statistics about the parsing of the archive's file table, to
+    /// audit archives parsed with lenient options.
+    pub fn stats(&self) -> ThorStats {
+        self.container.stats()
+    }
+
+    /// Returns the entries whose paths collided when parsing the archive with
+    /// `ThorOptions::canonicalize_paths` set.
+    pub fn path_collisions(&self) -> &[PathCollision] {
+        &self.container.path_collisions
+    }
+
+    /// Checks that the number of files announced by the header matches the
+    /// number of entries of the archive.
+    ///
+    /// The check fails for archives whose file table lists the same path
+    /// several times (see `ThorStats::duplicates_dropped`) or whose header is
+    /// inconsistent with the table.
+    pub fn verify_entry_count(&self) -> bool {
+        self.container.header.file_count == self.container.entries.len()
+    }
+
+    /// Checks that the content of every entry is stored within the archive,
+    /// decompresses cleanly and has the expected size.
+    ///
+    /// Unlike parsing, which only reads the header and the file table, this
+    /// reads the whole archive. Problems are listed in the returned report
+    /// instead of aborting the validation, errors are only returned if the
+    /// archive's size cannot be determined.
+    pub fn validate(&mut self) -> Result<ValidationReport> {
+        let archive_size = self.obj.seek(SeekFrom::End(0))?;
+        let file_entries: Vec<ThorFileEntry> = self.present_entries().cloned().collect();
+        let mut report = ValidationReport::default();
+        for entry in file_entries {
+            report.checked_count += 1;
+            let result = check_payload_range(&entry, archive_size).and_then(|_| {
+                self.read_file_to_writer(&entry.relative_path, &mut io::sink())
+                    .map(|_| ())
+            });
+            if let Err(e) = result {
+                report.failures.push((entry.relative_path, e));
+            }
+        }
+        Ok(report)
+    }
+
+    /// Returns the length/version field that precedes the header's magic in
+    /// length-prefixed archives, or `None` for archives with the standard
+    /// layout.
+    pub fn header_prefix(&self) -> Option<u32> {
+        self.container.header.prefix
+    }
+
+    /// Returns the reserved byte that precedes the entry of single-file
+    /// archives, or `None` for multiple-files archives.
+    pub fn single_file_reserved_byte(&self) -> Option<u8> {
+        match &self.container.table {
+            ThorTable::SingleFile(table) => Some(table.reserved),
+            ThorTable::MultipleFiles(_) => None,
+        }
+    }
+
+    /// Looks up an entry that has content (i.e., that isn't a removal).
+    fn get_content_entry<S: AsRef<str> + Hash>(&self, file_path: S) -> Result<&ThorFileEntry> {
+        match self.get_file_entry(file_path) {
+            None => Err(GrufError::EntryNotFound),
+            Some(entry) if entry.is_removed => Err(GrufError::EntryRemoved),
+            Some(entry) => Ok(entry),
+        }
+    }
+
+    /// Returns the data of `file_path` as it's stored in the archive (i.e.,
+    /// compressed with zlib), without decompressing it.
+    ///
+    /// See `ThorArchiveBuilder::append_raw_file_update` to copy entries to
+    /// another archive without recompressing them.
+    pub fn get_entry_raw_data<S: AsRef<str> + Hash>(&mut self, file_path: S) -> Result<Vec<u8>> {
+        let file_entry = self.get_content_entry(file_path)?.clone();
+        read_entry_raw_data(&mut self.obj, &file_entry)
+    }
+
+    pub fn read_file_content<S: AsRef<str> + Hash>(&mut self, file_path: S) -> Result<Vec<u8>> {
+        let file_entry = self.get_content_entry(file_path)?.clone();
+        self.read_entry_content(&file_entry)
+    }
+
+    /// Same as `read_file_content` but the content is written into `buf`,
+    /// which is cleared first. Returns the size of the content.
+    ///
+    /// Reusing the same `buf` when reading many files avoids allocating a new
+    /// buffer for each file. The buffer used for compressed content is reused
+    /// as well, it's kept by the archive and grows up to the size of the
+    /// biggest file read this way.
+    pub fn read_file_content_into<S: AsRef<str> + Hash>(
+        &mut self,
+        file_path: S,
+        buf: &mut Vec<u8>,
+    ) -> Result<usize> {
+        let file_entry = self.get_content_entry(file_path)?.clone();
+        buf.clear();
+        buf.reserve(file_entry.size);
+        if let Some(content) = self.mapped_raw_data(&file_entry)? {
+            decompress_entry_into(self.container.decompressor.get(), &file_entry, content, buf)?;
+            return Ok(buf.len());
+        }
+        self.scratch_buffer.clear();
+        if file_entry.size_compressed > 0 {
+            self.obj.seek(SeekFrom::Start(file_entry.offset))?;
+            let mut file_chunk = self.obj.by_ref().take(file_entry.size_compressed as u64);
+            file_chunk.read_to_end(&mut self.scratch_buffer)?;
+            check_payload_size(&file_entry, self.scratch_buffer.len())?;
+        }
+        decompress_entry_into(
+            self.container.decompressor.get(),
+            &file_entry,
+            &self.scratch_buffer,
+            buf,
+        )?;
+        Ok(buf.len())
+    }
+
+    /// Reads the content of `file_path`, ignoring case differences if no
+    /// entry matches it exactly (see `ThorArchive::get_file_entry_ci`).
+    pub fn read_file_content_ci<S: AsRef<str> + Hash>(&mut self, file_path: S) -> Result<Vec<u8>> {
+        let file_entry = match self.get_file_entry_ci(file_path) {
+            None => return Err(GrufError::EntryNotFound),
+            Some(entry) if entry.is_removed => return Err(GrufError::EntryRemoved),
+            Some(entry) => entry.clone(),
+        };
+        self.read_entry_content(&file_entry)
+    }
+
+    /// Reads the content of the entry at position `index` in the archive's
+    /// file table (see `ThorArchive::entry_at`).
+    pub fn read_file_at(&mut self, index: usize) -> Result<Vec<u8>> {
+        let file_entry = match self.entry_at(index) {
+            None => return Err(GrufError::EntryNotFound),
+            Some(entry) if entry.is_removed => return Err(GrufError::EntryRemoved),
+            Some(entry) => entry.clone(),
+        };
+        self.read_entry_content(&file_entry)
+    }
+
+    fn read_entry_content(&mut self, file_entry: &ThorFileEntry) -> Result<Vec<u8>> {
+        if let Some(cache) = &mut self.cache {
+            if let Some(content) = cache.get(&file_entry.relative_path) {
+                return Ok(content.to_vec());
+            }
+        }
+        let content = self.read_entry_content_uncached(file_entry)?;
+        if let Some(cache) = &mut self.cache {
+            cache.insert(file_entry.relative_path.clone(), content.clone());
+        }
+        Ok(content)
+    }
+
+    fn read_entry_content_uncached(&mut self, file_entry: &ThorFileEntry) -> Result<Vec<u8>> {
+        if let Some(content) = self.mapped_raw_data(file_entry)? {
+            return decompress_entry(self.container.decompressor.get(), file_entry, content);
+        }
+        let content = read_entry_raw_data(&mut self.obj, file_entry)?;
+        decompress_entry(
+            self.container.decompressor.get(),
+            file_entry,
+            content.as_slice(),
+        )
+    }
+
+    /// Returns the compressed content of `file_entry` from the mapped
+    /// archive, if the archive has been mapped in memory.
+    #[cfg(feature = "mmap")]
+    fn mapped_raw_data(&self, file_entry: &ThorFileEntry) -> Result<Option<&[u8]>> {
+        let mmap = match &self.mmap {
+            None => return Ok(None),
+            Some(mmap) => mmap,
+        };
+        entry_raw_data_in(mmap, file_entry).map(Some)
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    fn mapped_raw_data(&self, _file_entry: &ThorFileEntry) -> Result<Option<&[u8]>> {
+        Ok(None)
+    }
+
+    /// Reads the content of `file_path` through `reader` instead of the
+    /// archive's own reader.
+    ///
+    /// `reader` MUST point at the same file the archive was parsed from (e.g.,
+    /// a second handle opened on the same path). Since only the archive's
+    /// metadata is used, several threads can extract entries concurrently,
+    /// each with its own reader, while sharing the archive.
+    pub fn read_file_content_with<R2, S>(&self, reader: &mut R2, file_path: S) -> Result<Vec<u8>>
+    where
+        R2: Read + Seek,
+        S: AsRef<str> + Hash,
+    {
+        let file_entry = self.get_content_entry(file_path)?;
+        let content = read_entry_raw_data(reader, file_entry)?;
+        decompress_entry(
+            self.container.decompressor.get(),
+            file_entry,
+            content.as_slice(),
+        )
+    }
+
+    /// Reads the content of `file_path`, compressed with zlib and the given
+    /// preset dictionary.
+    ///
+    /// This is needed for entries produced by tools that compress files with a
+    /// preset dictionary (e.g., to improve the compression ratio of small
+    /// files), which `read_file_content` cannot decompress. Entries compressed
+    /// without a dictionary are read as usual.
+    pub fn read_file_content_with_dict<S: AsRef<str> + Hash>(
+        &mut self,
+        file_path: S,
+        dictionary: &[u8],
+    ) -> Result<Vec<u8>> {
+        let file_entry = self.get_content_entry(file_path.as_ref())?.clone();
+        let content = self.get_entry_raw_data(file_path)?;
+        decompress_file_content_with_dict(
+            self.container.decompressor.get(),
+            &file_entry,
+            &content,
+            dictionary,
+        )
+    }
+
+    /// Returns a reader that decompresses the content of `file_path` on the
+    /// fly.
+    pub fn entry_reader<S: AsRef<str> + Hash>(
+        &mut self,
+        file_path: S,
+    ) -> Result<Box<dyn Read + '_>> {
+        Ok(Box::new(self.open_file(file_path)?))
+    }
+
+    /// Same as `entry_reader` but returns the decoder itself, which avoids an
+    /// allocation and lets the reader be wrapped (e.g., in a `BufReader`)
+    /// without dynamic dispatch.
+    ///
+    /// The decoder reads from the archive's underlying reader, only one file
+    /// can thus be read at a time.
+    pub fn open_file<S: AsRef<str> + Hash>(&mut self, file_path: S) -> Result<impl Read + '_> {
+        let file_entry = self.get_content_entry(file_path)?.clone();
+        if file_entry.size_compressed == 0 {
+            return Ok(EntryContentReader::Empty);
+        }
+        self.obj.seek(SeekFrom::Start(file_entry.offset))?;
+        let file_chunk = self.obj.by_ref().take(file_entry.size_compressed as u64);
+        let decoder = stream_entry(self.container.decompressor.get(), &file_entry, file_chunk)?;
+        Ok(EntryContentReader::Stream(decoder))
+    }
+
+    /// Reads `len` bytes of the content of `file_path`, starting at `offset`.
+    ///
+    /// The content is only decompressed up to `offset + len`, which is
+    /// cheaper than reading the whole file when only its beginning is needed
+    /// (e.g., to check a file's signature). The returned data is shorter than
+    /// `len` if the content ends before `offset + len`.
+    pub fn read_file_range<S: AsRef<str> + Hash>(
+        &mut self,
+        file_path: S,
+        offset: u64,
+        len: usize,
+    ) -> Result<Vec<u8>> {
+        let mut reader = self.entry_reader(file_path)?;
+        // Decompressed data has to be skipped, zlib streams aren't seekable
+        io::copy(&mut reader.by_ref().take(offset), &mut io::sink())?;
+        let mut content = Vec::with_capacity(len);
+        reader.take(len as u64).read_to_end(&mut content)?;
+        Ok(content)
+    }
+
+    /// Decompresses the content of `file_path` into `writer`, without
+    /// buffering the whole file in memory.
+    ///
+    /// Returns the number of decompressed bytes written. Fails with
+    /// `GrufError::SizeMismatch` or `GrufError::DecompressionTooLarge` if it
+    /// doesn't match the entry's size, in which case `writer` has already
+    /// received (part of) the decompressed bytes.
+    pub fn read_file_to_writer<S, W>(&mut self, file_path: S, writer: &mut W) -> Result<u64>
+    where
+        S: AsRef<str> + Hash,
+        W: Write + ?Sized,
+    {
+        let expected_size = self.get_content_entry(file_path.as_ref())?.size;
+        let reader = self.entry_reader(file_path)?;
+        // Stop decompressing as soon as the content is bigger than expected
+        let written_size = io::copy(&mut reader.take(expected_size as u64 + 1), writer)?;
+        if written_size > expected_size as u64 {
+            return Err(GrufError::DecompressionTooLarge(expected_size));
+        }
+        if written_size != expected_size as u64 {
+            return Err(GrufError::SizeMismatch {
+                expected: expected_size,
+                actual: written_size as usize,
+            });
+        }
+        Ok(written_size)
+    }
+
+    /// Computes the CRC32 checksum of the decompressed content of
+    /// `file_path`, without buffering the whole file in memory.
+    ///
+    /// Fails like `read_file_to_writer` if the content's size doesn't match
+    /// the entry's size.
+    pub fn file_crc32<S: AsRef<str> + Hash>(&mut self, file_path: S) -> Result<u32> {
+        let mut crc32_writer = Crc32Writer(crc32::Digest::new(crc32::IEEE));
+        self.read_file_to_writer(file_path, &mut crc32_writer)?;
+        Ok(crc32_writer.0.sum32())
+    }
+
+    /// Computes the hash of the decompressed content of each file of the
+    /// archive, in table order, e.g., to publish a manifest that clients can
+    /// check their files against.
+    ///
+    /// Returns the files' paths alongside their hash, as a lowercase
+    /// hexadecimal string (big-endian for CRC32). Removed entries, directory
+    /// markers and internal files are skipped. Content is streamed through
+    /// the hasher, files aren't buffered in memory.
+    pub fn content_manifest(&mut self, algo: HashAlgo) -> Result<Vec<(String, String)>> {
+        let file_paths: Vec<String> = self
+            .container
+            .entries
+            .iter()
+            .filter(|e| !e.is_removed && !e.is_directory() && !e.is_internal())
+            .map(|e| e.relative_path.clone())
+            .collect();
+        let mut manifest = Vec::with_capacity(file_paths.len());
+        for file_path in file_paths {
+            let mut hasher = ContentHasher::new(algo);
+            self.read_file_to_writer(&file_path, &mut hasher)?;
+            manifest.push((file_path, hasher.finish_hex()));
+        }
+        Ok(manifest)
+    }
+
+    /// Returns the path of all the files contained in the archive, in the
+    /// order they're stored in, alongside a reader that decompresses their
+    /// content on the fly.
+    ///
+    /// Readers share the archive's underlying object and can be used in any
+    /// order. Removed entries are skipped. Items are errors for entries whose
+    /// decompression can't start (e.g., `GrufError::NotZlibData`).
+    pub fn readers(&mut self) -> impl Iterator<Item = Result<(String, impl Read + '_)>> + '_ {
+        let mut file_entries: Vec<ThorFileEntry> = self
+            .container
+            .entries
+            .iter()
+            .filter(|e| !e.is_removed)
+            .cloned()
+            .collect();
+        file_entries.sort_unstable_by_key(|e| e.offset);
+        let decompressor = self.container.decompressor.get();
+        let obj = Rc::new(RefCell::new(&mut self.obj));
+        file_entries.into_iter().map(move |entry| {
+            let reader: Box<dyn Read + '_> = if entry.size_compressed == 0 {
+                Box::new(io::empty())
+            } else {
+                let section_reader = SharedSectionReader {
+                    obj: Rc::clone(&obj),
+                    position: entry.offset,
+                    end: entry.offset + entry.size_compressed as u64,
+                };
+                stream_entry(decompressor, &entry, section_reader)?
+            };
+            Ok((entry.relative_path, reader))
+        })
+    }
+
+    /// Returns all the entries of the archive, in table order, alongside
+    /// their decompressed content.
+    ///
+    /// Entries are read one at a time, as the iterator advances. Removed
+    /// entries are returned with empty content, so that the archive can be
+    /// transcoded entirely (e.g., into a `ThorArchiveBuilder`).
+    pub fn entries_with_content(
+        &mut self,
+    ) -> impl Iterator<Item = Result<(ThorFileEntry, Vec<u8>)>> + '_ {
+        let mut index = 0;
+        std::iter::from_fn(move || {
+            let entry = self.container.entries.get(index)?.clone();
+            index += 1;
+            let content = if entry.is_removed {
+                Ok(vec![])
+            } else {
+                self.read_entry_content(&entry)
+            };
+            Some(content.map(|content| (entry, content)))
+        })
+    }
+
+    /// Reads the content of all the files contained in the archive, in the
+    /// order they're stored in, and passes it to `callback`.
+    ///
+    /// Neighboring entries are read by blocks, which reduces the number of
+    /// I/O operations for archives that contain many small files. Removed
+    /// entries are skipped.
+    pub fn read_all_in_order<F>(&mut self, mut callback: F) -> Result<()>
+    where
+        F: FnMut(&ThorFileEntry, Vec<u8>) -> Result<()>,
+    {
+        let mut file_entries: Vec<&ThorFileEntry> = self
+            .container
+            .entries
+            .iter()
+            .filter(|e| !e.is_removed)
+            .collect();
+        file_entries.sort_unstable_by_key(|e| e.offset);
+        let mut block: Vec<u8> = Vec::new();
+        let mut block_offset = 0;
+        for file_entry in file_entries {
+            if file_entry.size_compressed == 0 {
+                callback(file_entry, vec![])?;
+                continue;
+            }
+            let entry_end_offset = file_entry.offset + file_entry.size_compressed as u64;
+            let block_end_offset = block_offset + block.len() as u64;
+            if file_entry.offset < block_offset || entry_end_offset > block_end_offset {
+                // Entry isn't fully contained in the current block, read a new one
+                block_offset = file_entry.offset;
+                block.clear();
+                self.obj.seek(SeekFrom::Start(block_offset))?;
+                let block_size = std::cmp::max(READ_BLOCK_SIZE, file_entry.size_compressed);
+                let mut file_chunk = self.obj.by_ref().take(block_size as u64);
+                file_chunk.read_to_end(&mut block)?;
+            }
+            let content_start = (file_entry.offset - block_offset) as usize;
+            check_payload_size(file_entry, block.len().saturating_sub(content_start))?;
+            let content_end = content_start + file_entry.size_compressed;
+            let content = decompress_entry(
+                self.container.decompressor.get(),
+                file_entry,
+                &block[content_start..content_end],
+            )?;
+            callback(file_entry, content)?;
+        }
+        Ok(())
+    }
+
+    /// Extracts the file at `file_path` into `destination_path`, creating
+    /// parent directories if needed.
+    ///
+    /// Entries whose path is rooted or contains `..` components are rejected
+    /// with `GrufError::UnsafePath`.
+    ///
+    /// The content is first written into a temporary file (i.e., the
+    /// destination's path with a `.part` extension appended), which only
+    /// replaces the destination file once the content has been extracted
+    /// entirely. The temporary file is deleted otherwise, even if the thread
+    /// panics, so that the destination is never left partially written.
+    pub fn extract_file<S, P>(&mut self, file_path: S, destination_path: P) -> Result<()>
+    where
+        S: AsRef<str> + Hash,
+        P: AsRef<Path>,
+    {
+        self.extract_file_with_cancel_flag(file_path.as_ref(), destination_path.as_ref(), None)
+    }
+
+    fn extract_file_with_cancel_flag(
+        &mut self,
+        file_path: &str,
+        destination_path: &Path,
+        cancel_flag: Option<&AtomicBool>,
+    ) -> Result<()> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("extract_file", path = file_path).entered();
+        // Fail early, before creating the destination file
+        let file_entry = self.get_content_entry(file_path)?;
+        // Check the entry's own path, which the lookup key may differ from
+        // (e.g., with canonicalized paths)
+        check_thor_path_is_safe(&file_entry.relative_path)?;
+        if file_entry.is_directory() {
+            fs::create_dir_all(destination_path)?;
+            return Ok(());
+        }
+        if let Some(parent_dir) = destination_path.parent() {
+            fs::create_dir_all(parent_dir)?;
+        }
+        let (part_file, file) = PartFileGuard::create(destination_path)?;
+        let mut writer = CancellableWriter {
+            inner: io::BufWriter::new(file),
+            cancel_flag,
+        };
+        let result = self
+            .read_file_to_writer(file_path, &mut writer)
+            .and_then(|_| writer.flush().map_err(GrufError::from));
+        // Close the file before moving it
+        drop(writer);
+        if let Err(e) = result {
+            // Dropping the guard deletes the truncated file
+            drop(part_file);
+            if is_cancelled(cancel_flag) {
+                return Err(GrufError::Cancelled);
+            }
+            #[cfg(feature = "tracing")]
+            tracing::warn!(error = %e, "failed to extract file");
+            return Err(e);
+        }
+        part_file.persist()?;
+        #[cfg(feature = "tracing")]
+        if let Some(entry) = self.get_file_entry(file_path) {
+            tracing::debug!(
+                size_compressed = entry.size_compressed,
+                size = entry.size,
+                "extracted file"
+            );
+        }
+        Ok(())
+    }
+
+    /// Extracts all the files contained in the archive into
+    /// `destination_path`, while preserving the archive's directory tree.
+    ///
+    /// Removed entries are skipped and entries with unsafe paths (see
+    /// `extract_file`) are rejected. Returns the number of extracted files.
+    pub fn extract_all<P: AsRef<Path>>(&mut self, destination_path: P) -> Result<usize> {
+        let report = self.extract_all_with_options(destination_path, &ExtractOptions::default())?;
+        Ok(report.extracted_count)
+    }
+
+    /// Extracts all the files contained in the archive into
+    /// `destination_path`, using the given extraction options.
+    ///
+    /// In `ExtractMode::ContinueOnError` mode, files that cannot be extracted
+    /// are listed in the returned report instead of aborting the extraction.
+    /// Cancellation (see `ExtractOptions::cancel_flag`) always aborts it.
+    pub fn extract_all_with_options<P: AsRef<Path>>(
+        &mut self,
+        destination_path: P,
+        options: &ExtractOptions,
+    ) -> Result<ExtractReport> {
+        let file_entries: Vec<ThorFileEntry> = self
+            .get_entries()
+            .filter(|e| !e.is_removed && !options.is_skipped(e))
+            .cloned()
+            .collect();
+        self.extract_entries(file_entries, destination_path.as_ref(), options)
+    }
+
+    /// Extracts all the files contained in the archive directly into
+    /// `destination_path`, without their directories (e.g., for debugging
+    /// purposes).
+    ///
+    /// Files are named after the last component of their path. Names are
+    /// compared case-insensitively and collisions are handled according to
+    /// `collision_policy`, in table order. Removed entries, directory markers
+    /// and metadata files (see `ExtractOptions::skip_integrity_file`) are
+    /// skipped. Returns the number of extracted files.
+    pub fn extract_all_flat<P: AsRef<Path>>(
+        &mut self,
+        destination_path: P,
+        collision_policy: CollisionPolicy,
+    ) -> Result<usize> {
+        let options = ExtractOptions {
+            skip_integrity_file: true,
+            ..Default::default()
+        };
+        let mut used_names = HashSet::new();
+        let mut flat_entries = Vec::new();
+        for entry in self
+            .present_entries()
+            .filter(|e| !e.is_directory() && !options.is_skipped(e))
+        {
+            let file_name = thor_path_file_name(&entry.relative_path);
+            let fold_name =
+                |name: &str| fold_thor_path(name, PathSeparator::Backslash, true).into_owned();
+            let file_name = if used_names.insert(fold_name(file_name)) {
+                file_name.to_string()
+            } else {
+                match collision_policy {
+                    CollisionPolicy::Error => {
+                        return Err(GrufError::NameCollision(entry.relative_path.clone()))
+                    }
+                    CollisionPolicy::Skip => continue,
+                    CollisionPolicy::Rename => (1..)
+                        .map(|index| indexed_file_name(file_name, index))
+                        .find(|name| used_names.insert(fold_name(name)))
+                        .unwrap_or_default(),
+                }
+            };
+            flat_entries.push((entry.clone(), file_name));
+        }
+        // Extract files in the order they're stored in
+        flat_entries.sort_unstable_by_key(|(entry, _)| entry.offset);
+        let destination_path = destination_path.as_ref();
+        for (entry, file_name) in &flat_entries {
+            self.extract_file(&entry.relative_path, destination_path.join(file_name))?;
+        }
+        Ok(flat_entries.len())
+    }
+
+    /// Extracts the files matching `glob` (see `ThorArchive::entries_matching`)
+    /// into `destination_path`, while preserving the archive's directory tree.
+    ///
+    /// Returns the number of extracted files.
+    #[cfg(feature = "glob")]
+    pub fn extract_matching<P: AsRef<Path>>(
+        &mut self,
+        glob: &str,
+        destination_path: P,
+    ) -> Result<usize> {
+        let file_entries: Vec<ThorFileEntry> = self
+            .entries_matching(glob)?
+            .filter(|e| !e.is_removed)
+            .cloned()
+            .collect();
+        let report = self.extract_entries(
+            file_entries,
+            destination_path.as_ref(),
+            &ExtractOptions::default(),
+        )?;
+        Ok(report.extracted_count)
+    }
+
+    fn extract_entries(
+        &mut self,
+        mut file_entries: Vec<ThorFileEntry>,
+        destination_path: &Path,
+        options: &ExtractOptions,
+    ) -> Result<ExtractReport> {
+        // Extract files in the order they're stored in
+        file_entries.sort_unstable_by_key(|e| e.offset);
+        let cancel_flag = options.cancel_flag.as_deref();
+        let mut report = ExtractReport::default();
+        for entry in file_entries {
+            if is_cancelled(cancel_flag) {
+                return Err(GrufError::Cancelled);
+            }
+            let result = safe_join(destination_path, &entry.relative_path)
+                .and_then(|dest_path| {
+                    self.extract_file_with_cancel_flag(
+                        &entry.relative_path,
+                        &dest_path,
+                        cancel_flag,
+                    )?;
+                    Ok(dest_path)
+                })
+                .and_then(|dest_path| match options.mtime {
+                    Some(mtime) => {
+                        filetime::set_file_mtime(&dest_path, FileTime::from_system_time(mtime))
+                            .map_err(GrufError::from)
+                    }
+                    None => Ok(()),
+                });
+            match result {
+                Ok(()) => report.extracted_count += 1,
+                Err(GrufError::Cancelled) => return Err(GrufError::Cancelled),
+                Err(e) => match options.mode {
+                    ExtractMode::StopOnError => return Err(e),
+                    ExtractMode::ContinueOnError => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(
+                            path = %entry.relative_path,
+                            error = %e,
+                            "skipping file that cannot be extracted"
+                        );
+                        report.failures.push((entry.relative_path, e))
+                    }
+                },
+            }
+        }
+        Ok(report)
+    }
+
+    /// Same as `extract_all` but entries are read, decompressed and written
+    /// concurrently, on rayon's global thread pool.
+    ///
+    /// Each worker thread reads the archive through its own reader, obtained
+    /// by calling `open_reader` (e.g., `|| File::open(&thor_archive_path)`),
+    /// which MUST give access to the same file the archive was parsed from.
+    #[cfg(feature = "parallel")]
+    pub fn extract_all_parallel<P, F, R2>(
+        &self,
+        destination_path: P,
+        open_reader: F,
+    ) -> Result<usize>
+    where
+        P: AsRef<Path>,
+        F: Fn() -> io::Result<R2> + Sync,
+        R2: Read + Seek,
+    {
+        let report = self.extract_all_parallel_with_options(
+            destination_path,
+            &ExtractOptions::default(),
+            open_reader,
+        )?;
+        Ok(report.extracted_count)
+    }
+
+    /// Same as `extract_all_with_options` but entries are extracted
+    /// concurrently (see `extract_all_parallel`).
+    ///
+    /// The cancellation flag is checked before each entry. In
+    /// `ExtractMode::StopOnError` mode, entries that haven't been started yet
+    /// are left alone once an entry fails and the first failure (in table
+    /// order) is returned.
+    #[cfg(feature = "parallel")]
+    pub fn extract_all_parallel_with_options<P, F, R2>(
+        &self,
+        destination_path: P,
+        options: &ExtractOptions,
+        open_reader: F,
+    ) -> Result<ExtractReport>
+    where
+        P: AsRef<Path>,
+        F: Fn() -> io::Result<R2> + Sync,
+        R2: Read + Seek,
+    {
+        let destination_path = destination_path.as_ref();
+        let file_entries: Vec<&ThorFileEntry> = self
+            .present_entries()
+            .filter(|e| !options.is_skipped(e))
+            .collect();
+        let decompressor = self.container.decompressor.get();
+        let cancel_flag = options.cancel_flag.as_deref();
+        // Set once an entry fails in `ExtractMode::StopOnError` mode
+        let stop_flag = AtomicBool::new(false);
+        // `None` for the entries that have been left alone
+        let results: Vec<Option<Result<()>>> = file_entries
+            .par_iter()
+            .map_init(&open_reader, |reader, entry| {
+                if is_cancelled(cancel_flag) {
+                    return Some(Err(GrufError::Cancelled));
+                }
+                if stop_flag.load(Ordering::SeqCst) {
+                    return None;
+                }
+                let result = extract_entry_with_reader(
+                    reader,
+                    decompressor,
+                    entry,
+                    destination_path,
+                    options.mtime,
+                );
+                if result.is_err() && options.mode == ExtractMode::StopOnError {
+                    stop_flag.store(true, Ordering::SeqCst);
+                }
+                Some(result)
+            })
+            .collect();
+        if results
+            .iter()
+            .any(|result| matches!(result, Some(Err(GrufError::Cancelled))))
+        {
+            return Err(GrufError::Cancelled);
+        }
+        let mut report = ExtractReport::default();
+        for (entry, result) in file_entries.into_iter().zip(results) {
+            match result {
+                None => {}
+                Some(Ok(())) => report.extracted_count += 1,
+                Some(Err(e)) => match options.mode {
+                    ExtractMode::StopOnError => return Err(e),
+                    ExtractMode::ContinueOnError => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(
+                            path = %entry.relative_path,
+                            error = %e,
+                            "skipping file that cannot be extracted"
+                        );
+                        report.failures.push((entry.relative_path.clone(), e))
+                    }
+                },
+            }
+        }
+        Ok(report)
+    }
+
+    /// Returns the disk space the archive's files occupy once applied to a
+    /// directory, i.e., the total decompressed size of the entries that have
+    /// content (directory markers and metadata files excluded, see
+    /// `apply_to_directory`).
+    pub fn required_disk_space(&self) -> u64 {
+        self.required_disk_space_with_options(&ExtractOptions {
+            skip_integrity_file: true,
+            ..Default::default()
+        })
+    }
+
+    /// Same as `required_disk_space`, with metadata files skipped according
+    /// to `options` (its other fields are ignored).
+    pub fn required_disk_space_with_options(&self, options: &ExtractOptions) -> u64 {
+        self.present_entries()
+            .filter(|e| !e.is_directory() && !options.is_skipped(e))
+            .map(|e| e.size as u64)
+            .sum()
+    }
+
+    /// Checks that the file system containing `path` has enough space
+    /// available to extract the archive (see `required_disk_space`), plus
+    /// `margin` bytes, e.g., before applying the archive to a directory.
+    ///
+    /// `path` doesn't have to exist yet (e.g., when installing a client), the
+    /// file system of its nearest existing ancestor is queried instead.
+    ///
+    /// Fails with `GrufError::InsufficientSpace` otherwise. Space freed by
+    /// overwritten or removed files isn't taken into account.
+    pub fn has_enough_space<P: AsRef<Path>>(&self, path: P, margin: u64) -> Result<()> {
+        self.has_enough_space_with_options(
+            path,
+            margin,
+            &ExtractOptions {
+                skip_integrity_file: true,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Same as `has_enough_space`, with metadata files skipped according to
+    /// `options` (see `required_disk_space_with_options`).
+    pub fn has_enough_space_with_options<P: AsRef<Path>>(
+        &self,
+        path: P,
+        margin: u64,
+        options: &ExtractOptions,
+    ) -> Result<()> {
+        let path = path.as_ref();
+        let existing_path = path
+            .ancestors()
+            .map(|ancestor| {
+                // Relative paths end with an empty ancestor
+                if ancestor.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    ancestor
+                }
+            })
+            .find(|ancestor| ancestor.exists())
+            .unwrap_or(path);
+        let available = fs2::available_space(existing_path)?;
+        let required = self
+            .required_disk_space_with_options(options)
+            .saturating_add(margin);
+        if available < required {
+            return Err(GrufError::InsufficientSpace {
+                required,
+                available,
+            });
+        }
+        Ok(())
+    }
+
+    /// Applies the archive to the directory `client_dir`: files are written
+    /// and files marked for removal are deleted, in one pass.
+    ///
+    /// Removed files that don't exist and metadata files (see
+    /// `RESERVED_METADATA_NAMES`) are skipped. Directories marked for removal
+    /// are only deleted if they're empty, and skipped if they're missing or
+    /// not empty (other failures are returned). Entries with unsafe paths
+    /// (see `extract_file`) are rejected.
+    pub fn apply_to_directory<P: AsRef<Path>>(&mut self, client_dir: P) -> Result<ApplyReport> {
+        let options = ExtractOptions {
+            skip_integrity_file: true,
+            ..Default::default()
+        };
+        self.apply_to_directory_with_options(client_dir, &options)
+    }
+
+    /// Same as `apply_to_directory`, with metadata files skipped according
+    /// to `options` (its other fields are ignored).
+    pub fn apply_to_directory_with_options<P: AsRef<Path>>(
+        &mut self,
+        client_dir: P,
+        options: &ExtractOptions,
+    ) -> Result<ApplyReport> {
+        let mut file_entries: Vec<ThorFileEntry> = self.get_entries().cloned().collect();
+        // Process files in the order they're stored in
+        file_entries.sort_unstable_by_key(|e| e.offset);
+        let mut report = ApplyReport::default();
+        for entry in file_entries {
+            if options.is_skipped(&entry) {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(path = %entry.relative_path, "skipping metadata file");
+                report.skipped_count += 1;
+                continue;
+            }
+            let dest_path = safe_join(client_dir.as_ref(), &entry.relative_path)?;
+            if !entry.is_removed {
+                self.extract_file(&entry.relative_path, &dest_path)?;
+                report.written_count += 1;
+                continue;
+            }
+            if entry.is_directory() {
+                match fs::remove_dir(&dest_path) {
+                    Ok(()) => report.deleted_count += 1,
+                    // Missing or not empty
+                    Err(e)
+                        if e.kind() == io::ErrorKind::NotFound
+                            || is_non_empty_directory(&dest_path) =>
+                    {
+                        report.skipped_count += 1
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+                continue;
+            }
+            match fs::remove_file(&dest_path) {
+                Ok(()) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(path = %entry.relative_path, "deleted file");
+                    report.deleted_count += 1
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(path = %entry.relative_path, "skipping missing file");
+                    report.skipped_count += 1
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Looks up an entry by path.
+    ///
+    /// Separators don't matter: paths stored in THOR archives normally use
+    /// backslashes as separators but "data/file.txt" and "data\\file.txt"
+    /// match the same entry, whichever of them is stored in the archive (see
+    /// `ThorOptions::canonical_separator`).
+    pub fn get_file_entry<S: AsRef<str> + Hash>(&self, file_path: S) -> Option<&ThorFileEntry> {
+        self.container.get_file_entry(file_path.as_ref())
+    }
+
+    /// Same as `get_file_entry` but returns a copy of the entry, which can be
+    /// kept while reading from the archive.
+    pub fn get_file_entry_owned<S: AsRef<str> + Hash>(
+        &self,
+        file_path: S,
+    ) -> Option<ThorFileEntry> {
+        self.get_file_entry(file_path).cloned()
+    }
+
+    /// Returns the entry at position `index` in the archive's file table, in
+    /// the order of `ThorArchive::get_entries`.
+    pub fn entry_at(&self, index: usize) -> Option<&ThorFileEntry> {
+        self.container.entries.get(index)
+    }
+
+    /// Looks up an entry, ignoring case differences if no entry matches
+    /// `file_path` exactly.
+    ///
+    /// Only ASCII letters are case-folded (see `normalize_thor_path`).
+    pub fn get_file_entry_ci<S: AsRef<str> + Hash>(&self, file_path: S) -> Option<&ThorFileEntry> {
+        self.get_file_entry(file_path.as_ref())
+            .or_else(|| self.get_file_entry_normalized(file_path))
+    }
+
+    /// Looks up an entry by its normalized path (see `normalize_thor_path`).
+    pub fn get_file_entry_normalized<S: AsRef<str> + Hash>(
+        &self,
+        file_path: S,
+    ) -> Option<&ThorFileEntry> {
+        let original_path = self
+            .normalized_index()
+            .get(&normalize_thor_path(file_path.as_ref()))?;
+        self.get_file_entry(original_path)
+    }
+
+    /// Returns a map of normalized paths (see `normalize_thor_path`) to the
+    /// paths stored in the archive.
+    ///
+    /// The index is built on first use. If several paths share the same
+    /// normalized path, the index refers to the lowest one.
+    pub fn normalized_index(&self) -> &HashMap<String, String> {
+        let entries = &self.container.entries;
+        self.normalized_index.get_or_init(move || {
+            let mut index: HashMap<String, String> = HashMap::with_capacity(entries.len());
+            for relative_path in entries.iter().map(|e| &e.relative_path) {
+                let original_path = index
+                    .entry(normalize_thor_path(relative_path))
+                    .or_insert_with(|| relative_path.clone());
+                if relative_path < original_path {
+                    *original_path = relative_path.clone();
+                }
+            }
+            index
+        })
+    }
+
+    /// Checks that the archive contains all the `required` paths and none of
+    /// the `forbidden` ones. Paths are normalized before being compared (see
+    /// `normalize_thor_path`) and removed entries are ignored.
+    pub fn validate_manifest(&self, required: &[&str], forbidden: &[&str]) -> ManifestReport {
+        let contains =
+            |path: &str| matches!(self.get_file_entry_normalized(path), Some(e) if !e.is_removed);
+        ManifestReport {
+            missing_required: required
+                .iter()
+                .filter(|path| !contains(path))
+                .map(|path| path.to_string())
+                .collect(),
+            present_forbidden: forbidden
+                .iter()
+                .filter(|path| contains(path))
+                .map(|path| path.to_string())
+                .collect(),
+        }
+    }
+
+    /// Computes how effective the compression of the archive's entries is.
+    ///
+    /// Only the entries' metadata is used. Removed and empty entries are
+    /// ignored.
+    pub fn compression_effectiveness(&self) -> CompressionReport {
+        let mut report = CompressionReport::default();
+        for entry in self.get_entries().filter(|e| !e.is_removed && e.size > 0) {
+            if entry.size_compressed < entry.size {
+                report.compressed_entries += 1;
+            } else {
+                report.incompressible_entries += 1;
+            }
+            report.total_size += entry.size as u64;
+            report.total_size_compressed += entry.size_compressed as u64;
+        }
+        report
+    }
+
+    /// Returns the entries of the archive, in the order they appear in the
+    /// archive's file table.
+    pub fn get_entries(&self) -> impl Iterator<Item = &'_ ThorFileEntry> {
+        self.container.entries.iter()
+    }
+
+    /// Returns the entries that mark files for removal, in table order.
+    pub fn removed_entries(&self) -> impl Iterator<Item = &'_ ThorFileEntry> {
+        self.get_entries().filter(|e| e.is_removed)
+    }
+
+    /// Returns the entries that have content (i.e., that aren't removals), in
+    /// table order.
+    pub fn present_entries(&self) -> impl Iterator<Item = &'_ ThorFileEntry> {
+        self.get_entries().filter(|e| !e.is_removed)
+    }
+
+    /// Splits the entries into the ones that have content and the ones that
+    /// mark files for removal (see `present_entries` and `removed_entries`),
+    /// e.g., to delete files before writing new ones. Entries are kept in
+    /// table order.
+    pub fn partition_entries(&self) -> (Vec<&ThorFileEntry>, Vec<&ThorFileEntry>) {
+        self.get_entries().partition(|e| !e.is_removed)
+    }
+
+    /// Returns the entries that have content, sorted according to `key`
+    /// (e.g., to extract the biggest files first). Entries of the same size
+    /// are sorted by path, so that the order is deterministic.
+    pub fn entries_sorted_by(&self, key: SortKey) -> Vec<&ThorFileEntry> {
+        let mut entries: Vec<&ThorFileEntry> = self.present_entries().collect();
+        match key {
+            SortKey::Path => entries.sort_unstable_by(|a, b| a.relative_path.cmp(&b.relative_path)),
+            SortKey::CompressedSize => entries.sort_unstable_by(|a, b| {
+                b.size_compressed
+                    .cmp(&a.size_compressed)
+                    .then_with(|| a.relative_path.cmp(&b.relative_path))
+            }),
+            SortKey::DecompressedSize => entries.sort_unstable_by(|a, b| {
+                b.size
+                    .cmp(&a.size)
+                    .then_with(|| a.relative_path.cmp(&b.relative_path))
+            }),
+        }
+        entries
+    }
+
+    /// Returns the entries whose path starts with `prefix` (e.g.,
+    /// "data\\texture\\"), in table order. Forward slashes are treated as
+    /// backslashes, in `prefix` and in entries' paths.
+    pub fn entries_with_prefix<'a>(
+        &'a self,
+        prefix: &str,
+    ) -> impl Iterator<Item = &'a ThorFileEntry> + 'a {
+        let prefix = fold_thor_path(prefix, PathSeparator::Backslash, false).into_owned();
+        self.get_entries().filter(move |e| {
+            fold_thor_path(&e.relative_path, PathSeparator::Backslash, false).starts_with(&prefix)
+        })
+    }
+
+    /// Returns the entries whose path matches `glob` (e.g.,
+    /// "data/texture/**/*.bmp"), in table order.
+    ///
+    /// Both slashes and backslashes are treated as separators, in `glob` and
+    /// in entries' paths. `*` doesn't match separators, use `**` to match
+    /// nested directories. Fails with `GrufError::InvalidGlob` if `glob`
+    /// cannot be parsed.
+    #[cfg(feature = "glob")]
+    pub fn entries_matching(&self, glob: &str) -> Result<impl Iterator<Item = &'_ ThorFileEntry>> {
+        let matcher = globset::GlobBuilder::new(&fold_thor_path(glob, PathSeparator::Slash, false))
+            .literal_separator(true)
+            .build()
+            .map_err(|e| GrufError::InvalidGlob(e.to_string()))?
+            .compile_matcher();
+        Ok(self.get_entries().filter(move |e| {
+            matcher.is_match(fold_thor_path(&e.relative_path, PathSeparator::Slash, false).as_ref())
+        }))
+    }
+
+    /// Returns the entries along with the absolute offset of their data in
+    /// the archive, whatever the archive's mode and layout are.
+    ///
+    /// This is the offset used to read the entries' content. Removed entries
+    /// don't have any content and are skipped.
+    pub fn entries_with_absolute_offset(&self) -> impl Iterator<Item = (&ThorFileEntry, u64)> {
+        self.present_entries().map(|e| (e, e.offset))
+    }
+
+    /// Returns the entries in the order they appear in the archive's table.
+    ///
+    /// Archives built from the same files, appended in the same order, list
+    /// their entries in the same order.
+    pub fn entries_in_table_order(&self) -> &[ThorFileEntry] {
+        &self.container.entries
+    }
+
+    /// Returns the entries whose content is either absent from `manifest` or
+    /// doesn't match the CRC32 checksum it contains.
+    ///
+    /// `manifest` maps relative paths to CRC32 checksums, like the ones found
+    /// in 'data.integrity'. Removed and internal entries are ignored.
+    pub fn entries_new_or_changed(
+        &mut self,
+        manifest: &HashMap<String, u32>,
+    ) -> Result<Vec<&ThorFileEntry>> {
+        let file_paths: Vec<String> = self
+            .get_entries()
+            .filter(|e| !e.is_removed && !e.is_internal())
+            .map(|e| e.relative_path.clone())
+            .collect();
+        let mut changed_file_paths = Vec::new();
+        for file_path in file_paths {
+            let has_changed = match manifest.get(&file_path) {
+                None => true,
+                Some(&hash) => self.file_crc32(&file_path)? != hash,
+            };
+            if has_changed {
+                changed_file_paths.push(file_path);
+            }
+        }
+        let container = &self.container;
+        Ok(changed_file_paths
+            .iter()
+            .filter_map(move |file_path| container.get_file_entry(file_path))
+            .collect())
+    }
+
+    /// Reads and parses the archive's 'data.integrity' file, which contains
+    /// the CRC32 checksums of the archive's files.
+    ///
+    /// Returns `None` if the archive doesn't contain such a file.
+    pub fn read_integrity_table(&mut self) -> Result<Option<IntegrityTable>> {
+        let integrity_data = match self.read_file_content(INTEGRITY_FILE_NAME) {
+            Ok(v) => v,
+            Err(GrufError::EntryNotFound) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let (integrity_data_as_str, _) = self.path_decoding.decode(integrity_data.as_slice())?;
+        Ok(Some(
+            parse_data_integrity_info(integrity_data_as_str.as_str())
+                .into_iter()
+                .map(|(file_path, hash)| (file_path.to_string(), hash))
+                .collect(),
+        ))
+    }
+
+    /// Checks the content of the files listed in the archive's
+    /// 'data.integrity' file against their checksums.
+    ///
+    /// Returns the (sorted) paths of the files that cannot be read or whose
+    /// content doesn't match their checksum. Fails with
+    /// `GrufError::EntryNotFound` if the archive doesn't contain a
+    /// 'data.integrity' file.
+    pub fn verify_against_integrity(&mut self) -> Result<Vec<String>> {
+        let integrity_table = self
+            .read_integrity_table()?
+            .ok_or(GrufError::EntryNotFound)?;
+        let mut invalid_file_paths = Vec::new();
+        for (file_path, hash) in integrity_table {
+            let is_valid = match self.file_crc32(&file_path) {
+                Ok(file_hash) => file_hash == hash,
+                Err(_) => false,
+            };
+            if !is_valid {
+                invalid_file_paths.push(file_path);
+            }
+        }
+        invalid_file_paths.sort_unstable();
+        Ok(invalid_file_paths)
+    }
+
+    /// Checks if the container has been unintentionnaly corrupted
+    pub fn is_valid(&mut self) -> Result<bool> {
+        Ok(self.verify_against_integrity()?.is_empty())
+    }
+}
+
+/// Iterates over the archive's entries, in table order (see
+/// `ThorArchive::get_entries`).
+impl<'a, R: ?Sized> IntoIterator for &'a ThorArchive<R> {
+    type Item = &'a ThorFileEntry;
+    type IntoIter = std::slice::Iter<'a, ThorFileEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.container.entries.iter()
+    }
+}
+
+/// Archive whose header has been parsed but whose file table hasn't been
+/// read yet, returned by `ThorArchive::open_header_only`.
+pub struct ThorArchiveHeader<R> {
+    obj: R,
+    parsing: HeaderChunkParsing,
+    options: ThorOptions,
+}
+
+impl<R: Read + Seek> ThorArchiveHeader<R> {
+    /// Parses the archive's header with the given parsing options, which
+    /// are also used by `ThorArchiveHeader::load_entries`.
+    pub fn with_options(mut obj: R, options: &ThorOptions) -> Result<ThorArchiveHeader<R>> {
+        let parsing = read_thor_header_chunk(&mut obj, options)?;
+        Ok(ThorArchiveHeader {
+            obj,
+            parsing,
+            options: options.clone(),
+        })
+    }
+
+    pub fn header(&self) -> &ThorHeader {
+        match &self.parsing {
+            HeaderChunkParsing::Complete(container) => &container.header,
+            HeaderChunkParsing::MissingTable(pending_table) => &pending_table.header,
+        }
+    }
+
+    /// Returns the number of files announced by the header.
+    pub fn file_count(&self) -> usize {
+        self.header().file_count
+    }
+
+    pub fn target_grf_name(&self) -> &str {
+        &self.header().target_grf_name
+    }
+
+    pub fn use_grf_merging(&self) -> bool {
+        self.header().use_grf_merging
+    }
+
+    pub fn mode(&self) -> ThorMode {
+        self.header().mode
+    }
+
+    /// Reads and parses the archive's file table.
+    pub fn load_entries(mut self) -> Result<ThorArchive<R>> {
+        let container = match self.parsing {
+            HeaderChunkParsing::Complete(container) => container,
+            HeaderChunkParsing::MissingTable(pending_table) => {
+                read_pending_table(&mut self.obj, pending_table, &self.options, &mut |_| {})?
+            }
+        };
+        Ok(ThorArchive::from_parts(self.obj, container, &self.options))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ThorContainer {
+    pub header: ThorHeader,
+    table: ThorTable,
+    /// Entries, in the order they appear in the archive's table
+    entries: Vec<ThorFileEntry>,
+    #[serde(skip)]
+    entry_index: HashMap<String, usize>,
+    /// All the entries of the archive's table, duplicates included, if some
+    /// have been dropped from `entries`
+    #[serde(skip)]
+    table_entries: Option<Vec<ThorFileEntry>>,
+    pub path_collisions: Vec<PathCollision>,
+    /// Number of entries whose path contains malformed bytes
+    lossy_path_count: usize,
+    /// Number of entries overridden by a later entry with the same path
+    duplicate_count: usize,
+    /// Whether entries' paths use both backslashes and forward slashes as
+    /// separators
+    mixed_separators: bool,
+    /// Whether entries' paths have been canonicalized, and so must be looked
+    /// up
+    #[serde(skip)]
+    canonicalize_paths: bool,
+    /// Separator used in the keys of `entry_index`
+    #[serde(skip)]
+    canonical_separator: PathSeparator,
+    /// Offset of the archive in the underlying object, offsets are shifted
+    /// by this amount (see `ThorArchive::new_at`)
+    #[serde(skip)]
+    base_offset: u64,
+    // See `ThorOptions::decompressor`
+    #[serde(skip)]
+    decompressor: SharedDecompressor,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ThorHeader {
+    pub prefix: Option<u32>,   // Set for length-prefixed archives
+    pub use_grf_merging: bool, // false -> client directory, true -> GRF
+    pub file_count: usize,
+    pub mode: ThorMode,
+    pub target_grf_name: String, // If empty (size == 0) -> default GRF
+}
+
+impl ThorHeader {
+    /// See `ThorArchive::patch_kind`.
+    pub fn patch_kind(&self) -> PatchKind {
+        if !self.use_grf_merging {
+            PatchKind::ClientDirectory
+        } else if self.target_grf_name.is_empty() {
+            PatchKind::DefaultGrf
+        } else {
+            PatchKind::NamedGrf(self.target_grf_name.clone())
+        }
+    }
+
+    /// Returns the offset of the header's magic in the archive.
+    fn magic_offset(&self) -> u64 {
+        match self.prefix {
+            Some(_) => HEADER_PREFIX_SIZE as u64,
+            None => 0,
+        }
+    }
+}
+
+/// Descriptor of an archive's file table, as stored after the header.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum ThorTable {
+    SingleFile(SingleFileTableDesc),
+    MultipleFiles(MultipleFilesTableDesc),
+}
+
+impl ThorTable {
+    /// Returns the absolute offset of the file table in the archive.
+    pub fn file_table_offset(&self) -> u64 {
+        match self {
+            ThorTable::SingleFile(table) => table.file_table_offset,
+            ThorTable::MultipleFiles(table) => table.file_table_offset,
+        }
+    }
+
+    /// Returns the size of the compressed file table, or `None` for
+    /// single-file archives, whose table isn't compressed.
+    pub fn file_table_compressed_size(&self) -> Option<usize> {
+        match self {
+            ThorTable::SingleFile(_) => None,
+            ThorTable::MultipleFiles(table) => Some(table.file_table_compressed_size),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SingleFileTableDesc {
+    file_table_offset: u64,
+    reserved: u8, // Unknown purpose, stored for inspection purposes
+}
+
+impl SingleFileTableDesc {
+    /// Returns the absolute offset of the file table in the archive.
+    pub fn file_table_offset(&self) -> u64 {
+        self.file_table_offset
+    }
+
+    /// Returns the byte stored after the header, whose purpose is unknown.
+    pub fn reserved(&self) -> u8 {
+        self.reserved
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MultipleFilesTableDesc {
+    file_table_compressed_size: usize,
+    file_table_offset: u64,
+}
+
+impl MultipleFilesTableDesc {
+    /// Returns the size of the compressed file table.
+    pub fn file_table_compressed_size(&self) -> usize {
+        self.file_table_compressed_size
+    }
+
+    /// Returns the absolute offset of the file table in the archive.
+    pub fn file_table_offset(&self) -> u64 {
+        self.file_table_offset
+    }
+}
+
+/// Entry of a THOR archive.
+///
+/// Entries are identified by their relative path: `PartialEq`, `Eq` and
+/// `Hash` only take `relative_path` into account, so that sets of entries
+/// contain at most one entry per path. Use `ThorFileEntry::is_identical_to`
+/// to compare all the fields.
+#[derive(Debug, Clone, Eq, Serialize)]
+pub struct ThorFileEntry {
+    pub size_compressed: usize,
+    pub size: usize,
+    pub relative_path: String,
+    /// Path as stored in the file table, before decoding (see
+    /// `ThorArchive::redecode_paths`)
+    #[serde(skip)]
+    pub raw_path: Vec<u8>,
+    /// Derived from the lowest bit of `flags`
+    pub is_removed: bool,
+    /// Raw flags stored in the file table (always 0 for single-file
+    /// archives). Only the lowest bit, which marks removals, is known;
+    /// others are kept for inspection purposes.
+    pub flags: u8,
+    /// Absolute offset of the entry's data in the archive. The format
+    /// cannot address data located past `THOR_MAX_ENTRY_OFFSET` (relative to
+    /// the header's magic).
+    pub offset: u64,
+}
+
+impl ThorFileEntry {
+    pub fn is_internal(&self) -> bool {
+        self.relative_path == INTEGRITY_FILE_NAME
+    }
+
+    /// Indicates whether the entry marks a directory (i.e., its path ends
+    /// with a separator), which is created instead of a file when
+    /// extracting the archive.
+    pub fn is_directory(&self) -> bool {
+        self.relative_path.ends_with('\\')
+    }
+
+    /// Indicates whether all the fields of both entries are equal, unlike
+    /// `==` which only compares paths.
+    pub fn is_identical_to(&self, other: &ThorFileEntry) -> bool {
+        self.relative_path == other.relative_path
+            && self.raw_path == other.raw_path
+            && self.size_compressed == other.size_compressed
+            && self.size == other.size
+            && self.is_removed == other.is_removed
+            && self.flags == other.flags
+            && self.offset == other.offset
+    }
+}
+
+impl fmt::Display for ThorFileEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_removed {
+            write!(f, "DELETE {}", self.relative_path)
+        } else {
+            write!(
+                f,
+                "{} ({}/{} bytes)",
+                self.relative_path, self.size_compressed, self.size
+            )
+        }
+    }
+}
+
+impl Hash for ThorFileEntry {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.relative_path.hash(state);
+    }
+}
+
+impl PartialEq for ThorFileEntry {
+    fn eq(&self, other: &ThorFileEntry) -> bool {
+        self.relative_path == other.relative_path
+    }
+}
+
+fn i16_to_thor_mode(i: i16) -> ThorMode {
+    match i {
+        33 => ThorMode::SingleFile,
+        48 => ThorMode::MultipleFiles,
+        _ => ThorMode::Invalid,
+    }
+}
+
+/// Reads the raw mode of a header that has been parsed successfully
+fn raw_thor_mode(header_buf: &[u8]) -> i16 {
+    let mode_offset = THOR_HEADER_MAGIC.len() + 1 + 4;
+    i16::from_le_bytes([header_buf[mode_offset], header_buf[mode_offset + 1]])
+}
+
+/// Checks entries' flags
+/// If LSB is 1, the entry indicates a file deletion
+fn is_file_removed(flags: u8) -> bool {
+    (flags & 0b1) == 1
+}
+
+named!(parse_thor_header<&[u8], ThorHeader>,
+    do_parse!(
+        tag!(THOR_HEADER_MAGIC)
+            >> use_grf_merging: le_u8
+            >> file_count: le_u32
+            >> mode: le_i16
+            >> target_grf_name_size: le_u8
+            >> target_grf_name: take_str!(target_grf_name_size)
+            >> (ThorHeader {
+                prefix: None,
+                use_grf_merging: use_grf_merging == 1,
+                file_count: file_count as usize,
+                mode: i16_to_thor_mode(mode),
+                target_grf_name: target_grf_name.to_string(),
+            }
+    )
+));
+
+named!(parse_single_file_table<&[u8], SingleFileTableDesc>,
+    do_parse!(
+        reserved: le_u8
+        >> (SingleFileTableDesc {
+            file_table_offset: 0, // Set by the caller
+            reserved,
+        }
+    )
+));
+
+named!(parse_multiple_files_table<&[u8], MultipleFilesTableDesc, EntryParsingError>,
+    do_parse!(
+        file_table_compressed_size: le_size
+        >> file_table_offset: le_table_offset
+        >> (MultipleFilesTableDesc {
+            file_table_compressed_size,
+            file_table_offset, // Offset in the 'data' field
+        }
+    )
+));
+
+/// Errors returned by the parsers of file tables and their entries, see
+/// `entry_parsing_error`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryParsingError {
+    /// Truncated or malformed data
+    Malformed(ErrorKind),
+    /// A path couldn't be decoded
+    PathDecoding,
+    /// A size is negative
+    InvalidSize(i32),
+    /// An offset is negative
+    NegativeOffset,
+}
+
+impl<I> ParseError<I> for EntryParsingError {
+    fn from_error_kind(_input: I, kind: ErrorKind) -> Self {
+        EntryParsingError::Malformed(kind)
+    }
+
+    fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+type EntryParsingResult<'a, O> = IResult<&'a [u8], O, EntryParsingError>;
+
+/// Parses a size (or offset) stored as a 32-bit signed integer. Negative
+/// values stop parsing.
+fn le_size(input: &[u8]) -> EntryParsingResult<'_, usize> {
+    let (output, size) = le_i32(input)?;
+    match usize::try_from(size) {
+        Ok(size) => Ok((output, size)),
+        Err(_) => Err(nom::Err::Failure(EntryParsingError::InvalidSize(size))),
+    }
+}
+
+/// Parses the offset of a multiple-files archive's file table. Negative
+/// offsets stop parsing.
+fn le_table_offset(input: &[u8]) -> EntryParsingResult<'_, u64> {
+    let (output, offset) = le_i32(input)?;
+    match u64::try_from(offset) {
+        Ok(offset) => Ok((output, offset)),
+        Err(_) => Err(nom::Err::Failure(EntryParsingError::NegativeOffset)),
+    }
+}
+
+/// Splits the length/version field preceding the magic of length-prefixed
+/// archives from the rest of the header.
+///
+/// Archives with the standard layout are returned as is.
+fn split_header_prefix(buf: &[u8]) -> (Option<u32>, &[u8]) {
+    if buf.starts_with(THOR_HEADER_MAGIC) || buf.len() < HEADER_PREFIX_SIZE {
+        return (None, buf);
+    }
+    let (prefix, header) = buf.split_at(HEADER_PREFIX_SIZE);
+    if !header.starts_with(THOR_HEADER_MAGIC) {
+        return (None, buf);
+    }
+    let mut prefix_bytes = [0u8; HEADER_PREFIX_SIZE];
+    prefix_bytes.copy_from_slice(prefix);
+    (Some(u32::from_le_bytes(prefix_bytes)), header)
+}
+
+/// Normalizes a path for comparison purposes: separators are replaced with
+/// forward slashes and ASCII letters are lowercased (see `fold_thor_path`).
+pub fn normalize_thor_path<S: AsRef<str>>(path: S) -> String {
+    fold_thor_path(path.as_ref(), PathSeparator::Slash, true).into_owned()
+}
+
+/// Normalizes `path` for lookups and comparisons: separators (i.e., '/' and
+/// '\\') are replaced with `separator` and, if `fold_case` is set, ASCII
+/// letters are lowercased.
+///
+/// Other characters (e.g., Korean or accented letters) are left untouched,
+/// like the game client does when comparing paths. Paths used as keys and
+/// the paths they're compared with must all be normalized with this
+/// function, so that they match whichever way they were built.
+fn fold_thor_path(path: &str, separator: PathSeparator, fold_case: bool) -> Cow<'_, str> {
+    let separator = separator.as_char();
+    let is_separator = |c: char| c == '/' || c == '\\';
+    let needs_folding =
+        |c: char| (is_separator(c) && c != separator) || (fold_case && c.is_ascii_uppercase());
+    if !path.contains(needs_folding) {
+        return Cow::Borrowed(path);
+    }
+    Cow::Owned(
+        path.chars()
+            .map(|c| match c {
+                c if is_separator(c) => separator,
+                c if fold_case => c.to_ascii_lowercase(),
+                c => c,
+            })
+            .collect(),
+    )
+}
+
+/// Decodes a file path stored in a THOR archive.
+///
+/// Paths prefixed with a UTF-8 or UTF-16 BOM are decoded accordingly, other
+/// paths are decoded with the given encoding. Also indicates whether the
+/// decoding was lossy.
+fn decode_thor_path(v: &[u8], path_decoding: PathDecoding) -> Result<(String, bool)> {
+    const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+    const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+    const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+    let (encoding, path): (EncodingRef, &[u8]) = if v.starts_with(UTF8_BOM) {
+        (UTF_8, &v[UTF8_BOM.len()..])
+    } else if v.starts_with(UTF16LE_BOM) {
+        (UTF_16LE, &v[UTF16LE_BOM.len()..])
+    } else if v.starts_with(UTF16BE_BOM) {
+        (UTF_16BE, &v[UTF16BE_BOM.len()..])
+    } else {
+        return path_decoding.decode(v);
+    };
+    PathDecoding {
+        encoding,
+        ..path_decoding
+    }
+    .decode(path)
+}
+
+macro_rules! take_string_ansi (
+    ( $i:expr, $size:expr, $path_decoding:expr ) => (
+        {
+            let input: &[u8] = $i;
+            let (parser_output, path_bytes) = take!(input, $size)?;
+            match decode_thor_path(path_bytes, $path_decoding) {
+                Ok((path, lossy)) => Ok((parser_output, (path, lossy, path_bytes.to_vec()))),
+                // Stop parsing
+                Err(_) => Err(nom::Err::Failure(EntryParsingError::PathDecoding)),
+            }
+        }
+     );
+);
+
+/// Converts an error returned by an entry parser, `msg` is used for errors
+/// that aren't related to paths.
+fn entry_parsing_error(e: nom::Err<EntryParsingError>, msg: &str) -> GrufError {
+    match e {
+        nom::Err::Failure(EntryParsingError::PathDecoding) => GrufError::PathDecodingError,
+        nom::Err::Failure(EntryParsingError::NegativeOffset) => GrufError::OffsetOutOfBounds,
+        nom::Err::Failure(EntryParsingError::InvalidSize(size)) => GrufError::InvalidSize(size),
+        _ => GrufError::parsing_error(msg),
+    }
+}
+
+// Entry parsers also indicate whether the entry's path was decoded lossily
+fn parse_single_file_entry(
+    input: &[u8],
+    path_decoding: PathDecoding,
+) -> EntryParsingResult<'_, (ThorFileEntry, bool)> {
+    do_parse!(input,
+        size_compressed: le_size
+        >> size: le_size
+        >> relative_path_size: le_u8
+        >> relative_path: take_string_ansi!(relative_path_size, path_decoding)
+        >> (ThorFileEntry {
+            size_compressed,
+            size,
+            relative_path: relative_path.0,
+            raw_path: relative_path.2,
+            is_removed: false,
+            flags: 0,
+            offset: 0, // This field is set outside the parser
+        }, relative_path.1
+    ))
+}
+
+/// Uses the given parser only if the flag is as expected
+/// This is used to avoid parsing unexisting fields for files marked for deletion
+macro_rules! take_if_not_removed (
+    ( $i:expr, $parser:expr, $flags:expr ) => (
+        {
+            let input: &[u8] = $i;
+            if is_file_removed($flags) {
                 value!(input, 0)
             } else {
-                $parser(input)
+                $parser(input)
+            }
+        }
+        );
+);
+
+fn parse_multiple_files_entry(
+    input: &[u8],
+    path_decoding: PathDecoding,
+) -> EntryParsingResult<'_, (ThorFileEntry, bool)> {
+    do_parse!(input,
+        relative_path_size: le_u8
+        >> relative_path: take_string_ansi!(relative_path_size, path_decoding)
+        >> flags: le_u8
+        >> offset: take_if_not_removed!(le_u32, flags)
+        >> size_compressed: take_if_not_removed!(le_size, flags)
+        >> size: take_if_not_removed!(le_size, flags)
+        >> (ThorFileEntry {
+            size_compressed,
+            size,
+            relative_path: relative_path.0,
+            raw_path: relative_path.2,
+            is_removed: is_file_removed(flags),
+            flags,
+            offset: offset as u64,
+        }, relative_path.1
+    ))
+}
+
+/// Returns the size of the multiple-files table entry located at the
+/// beginning of `buf`, if `buf` contains it entirely.
+fn multiple_files_entry_size(buf: &[u8]) -> Option<usize> {
+    let relative_path_size = *buf.first()? as usize;
+    let flags = *buf.get(1 + relative_path_size)?;
+    let entry_size = if is_file_removed(flags) {
+        2 + relative_path_size
+    } else {
+        2 + relative_path_size + 3 * std::mem::size_of::<u32>()
+    };
+    if buf.len() < entry_size {
+        return None;
+    }
+    Some(entry_size)
+}
+
+/// Parses the entries of a multiple-files table, incrementally, as the
+/// decompressed table is read from `reader`.
+///
+/// Returns the entries and the number of entries whose path is lossy.
+/// `progress_callback` is called with the number of entries parsed so far
+/// after each chunk of the table.
+fn read_multiple_files_entries<R: Read>(
+    reader: R,
+    options: &ThorOptions,
+    progress_callback: &mut dyn FnMut(usize),
+) -> Result<(Vec<ThorFileEntry>, usize)> {
+    let limit = options.max_decompressed_table_size;
+    let mut reader = reader.take((limit as u64).saturating_add(1));
+    let mut entries = Vec::new();
+    let mut lossy_path_count = 0;
+    let mut decompressed_size = 0;
+    let mut chunk = vec![0; TABLE_READ_CHUNK_SIZE];
+    // Decompressed bytes that haven't been parsed yet
+    let mut pending_data: Vec<u8> = Vec::new();
+    loop {
+        let read_size = match reader.read(&mut chunk) {
+            Ok(read_size) => read_size,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        decompressed_size += read_size;
+        if decompressed_size > limit {
+            return Err(GrufError::DecompressionTooLarge(limit));
+        }
+        pending_data.extend_from_slice(&chunk[..read_size]);
+        let mut parsed_size = 0;
+        while let Some(entry_size) = multiple_files_entry_size(&pending_data[parsed_size..]) {
+            if entries.len() == options.max_entries {
+                return Err(GrufError::TooManyEntries(options.max_entries));
+            }
+            let entry_data = &pending_data[parsed_size..parsed_size + entry_size];
+            let (_, (entry, lossy_path)) =
+                parse_multiple_files_entry(entry_data, options.path_decoding())
+                    .map_err(|e| entry_parsing_error(e, "Failed to parse THOR file entries"))?;
+            entries.push(entry);
+            lossy_path_count += lossy_path as usize;
+            parsed_size += entry_size;
+        }
+        if parsed_size > 0 {
+            progress_callback(entries.len());
+        }
+        pending_data.drain(..parsed_size);
+        if read_size == 0 {
+            // The table ends in the middle of an entry
+            if !pending_data.is_empty() {
+                return Err(GrufError::CorruptedTable {
+                    parsed_count: entries.len(),
+                });
+            }
+            return Ok((entries, lossy_path_count));
+        }
+    }
+}
+
+/// Indexes entries by path, with separators replaced with
+/// `canonical_separator`. Later entries override earlier ones.
+///
+/// Keys must be built the same way when looking entries up, see
+/// `ThorContainer::get_file_entry`.
+struct IndexedEntries {
+    entries: Vec<ThorFileEntry>,
+    entry_index: HashMap<String, usize>,
+    table_entries: Option<Vec<ThorFileEntry>>,
+    path_collisions: Vec<PathCollision>,
+    duplicate_count: usize,
+    mixed_separators: bool,
+}
+
+fn index_entries(
+    entries: Vec<ThorFileEntry>,
+    canonicalize_paths: bool,
+    canonical_separator: PathSeparator,
+) -> IndexedEntries {
+    let mut indexed_entries = IndexedEntries {
+        entries: Vec::with_capacity(entries.len()),
+        entry_index: HashMap::with_capacity(entries.len()),
+        table_entries: None,
+        path_collisions: Vec::new(),
+        duplicate_count: 0,
+        mixed_separators: false,
+    };
+    // Original paths of the entries, indexed by canonical path
+    let mut original_paths: HashMap<String, Vec<String>> = HashMap::new();
+    let (mut uses_slashes, mut uses_backslashes) = (false, false);
+    for mut entry in entries {
+        uses_slashes |= entry.relative_path.contains('/');
+        uses_backslashes |= entry.relative_path.contains('\\');
+        if canonicalize_paths {
+            let canonical_path = canonicalize_thor_path(&entry.relative_path);
+            original_paths
+                .entry(canonical_path.clone())
+                .or_default()
+                .push(std::mem::replace(&mut entry.relative_path, canonical_path));
+        }
+        // Later entries take precedence but keep the position of the first one
+        let key = fold_thor_path(&entry.relative_path, canonical_separator, false);
+        let duplicate_index = indexed_entries.entry_index.get(key.as_ref()).copied();
+        // Keep the whole table once an entry is dropped, no entry has been
+        // overridden before the first duplicate
+        if duplicate_index.is_some() && indexed_entries.table_entries.is_none() {
+            indexed_entries.table_entries = Some(indexed_entries.entries.clone());
+        }
+        if let Some(table_entries) = &mut indexed_entries.table_entries {
+            table_entries.push(entry.clone());
+        }
+        match duplicate_index {
+            Some(i) => {
+                indexed_entries.entries[i] = entry;
+                indexed_entries.duplicate_count += 1;
+            }
+            None => {
+                let key = key.into_owned();
+                indexed_entries
+                    .entry_index
+                    .insert(key, indexed_entries.entries.len());
+                indexed_entries.entries.push(entry);
+            }
+        }
+    }
+    indexed_entries.mixed_separators = uses_slashes && uses_backslashes;
+    indexed_entries.path_collisions = original_paths
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|(canonical_path, original_paths)| PathCollision {
+            canonical_path,
+            original_paths,
+        })
+        .collect();
+    indexed_entries
+        .path_collisions
+        .sort_unstable_by(|a, b| a.canonical_path.cmp(&b.canonical_path));
+    indexed_entries
+}
+
+/// Canonicalizes a path the way the game client does.
+fn canonicalize_thor_path(path: &str) -> String {
+    fold_thor_path(path, PathSeparator::Backslash, true).into_owned()
+}
+
+pub fn parse_thor_patch<R: Seek + Read>(reader: &mut R) -> Result<ThorContainer> {
+    parse_thor_patch_with_options(reader, &ThorOptions::default())
+}
+
+pub fn parse_thor_patch_with_options<R: Seek + Read>(
+    reader: &mut R,
+    options: &ThorOptions,
+) -> Result<ThorContainer> {
+    parse_thor_patch_with_progress(reader, options, &mut |_| {})
+}
+
+/// Entry point for fuzzers: parses `data` as a THOR archive and, if that
+/// succeeds, reads the content of all of its files from memory.
+///
+/// Errors are expected and ignored, panics and aborts are bugs.
+pub fn fuzz_parse(data: &[u8]) {
+    if parse_thor_patch(&mut Cursor::new(data)).is_err() {
+        return;
+    }
+    let mut thor_archive = match ThorArchive::new(Cursor::new(data)) {
+        Ok(thor_archive) => thor_archive,
+        Err(_) => return,
+    };
+    let paths: Vec<String> = thor_archive
+        .get_entries()
+        .map(|e| e.relative_path.clone())
+        .collect();
+    for path in paths {
+        let _ = thor_archive.read_file_content(&path);
+        let _ = thor_archive.read_file_to_writer(&path, &mut io::sink());
+    }
+}
+
+fn parse_thor_patch_with_progress<R: Seek + Read>(
+    reader: &mut R,
+    options: &ThorOptions,
+    progress_callback: &mut dyn FnMut(usize),
+) -> Result<ThorContainer> {
+    #[cfg(feature = "tracing")]
+    let span = tracing::debug_span!(
+        "parse_thor_patch",
+        mode = tracing::field::Empty,
+        file_count = tracing::field::Empty,
+        target_grf = tracing::field::Empty,
+    )
+    .entered();
+    let result = parse_thor_patch_from_reader(reader, options, progress_callback);
+    #[cfg(feature = "tracing")]
+    match &result {
+        Ok(container) => {
+            span.record("mode", &tracing::field::display(container.header.mode));
+            span.record("file_count", &container.entries.len());
+            span.record("target_grf", &container.header.target_grf_name.as_str());
+            tracing::debug!("parsed archive");
+        }
+        Err(e) => tracing::warn!(error = %e, "failed to parse archive"),
+    }
+    result
+}
+
+fn parse_thor_patch_from_reader<R: Seek + Read>(
+    reader: &mut R,
+    options: &ThorOptions,
+    progress_callback: &mut dyn FnMut(usize),
+) -> Result<ThorContainer> {
+    match read_thor_header_chunk(reader, options)? {
+        HeaderChunkParsing::Complete(container) => Ok(container),
+        HeaderChunkParsing::MissingTable(pending_table) => {
+            read_pending_table(reader, pending_table, options, progress_callback)
+        }
+    }
+}
+
+fn read_thor_header_chunk<R: Read>(
+    reader: &mut R,
+    options: &ThorOptions,
+) -> Result<HeaderChunkParsing> {
+    let mut thor_header_buf = Vec::with_capacity(HEADER_EXTENDED_MAX_SIZE);
+    let mut reader_chunk = reader.take(thor_header_buf.capacity() as u64);
+    reader_chunk.read_to_end(&mut thor_header_buf)?;
+    parse_thor_header_chunk(&thor_header_buf, options)
+}
+
+fn read_pending_table<R: Seek + Read>(
+    reader: &mut R,
+    pending_table: PendingTable,
+    options: &ThorOptions,
+    progress_callback: &mut dyn FnMut(usize),
+) -> Result<ThorContainer> {
+    reader.seek(SeekFrom::Start(pending_table.offset()))?;
+    let mut compressed_table: Vec<u8> = Vec::with_capacity(pending_table.compressed_size());
+    let mut file_chunk = reader.take(compressed_table.capacity() as u64);
+    file_chunk.read_to_end(&mut compressed_table)?;
+    pending_table.parse_with_progress(&compressed_table, options, progress_callback)
+}
+
+impl ThorContainer {
+    /// Returns the entries, in the order they appear in the archive's table.
+    pub fn entries(&self) -> &[ThorFileEntry] {
+        &self.entries
+    }
+
+    /// See `ThorArchive::stats`.
+    pub fn stats(&self) -> ThorStats {
+        ThorStats {
+            entries_parsed: self.entries.len() + self.duplicate_count,
+            duplicates_dropped: self.duplicate_count,
+            paths_with_replacement_chars: self.lossy_path_count,
+            mixed_separators: self.mixed_separators,
+        }
+    }
+
+    /// Returns the decompressor the container was parsed with (see
+    /// `ThorOptions::decompressor`).
+    pub(crate) fn decompressor(&self) -> &dyn Decompressor {
+        self.decompressor.get()
+    }
+
+    /// See `ThorArchive::get_file_entry`.
+    pub(crate) fn get_file_entry(&self, file_path: &str) -> Option<&ThorFileEntry> {
+        let canonical_path;
+        let file_path = if self.canonicalize_paths {
+            canonical_path = canonicalize_thor_path(file_path);
+            canonical_path.as_str()
+        } else {
+            file_path
+        };
+        let key = fold_thor_path(file_path, self.canonical_separator, false);
+        self.entry_index
+            .get(key.as_ref())
+            .map(|&i| &self.entries[i])
+    }
+
+    /// Groups the entries by parent directory, in table order, e.g., to
+    /// display the archive's content as a tree.
+    ///
+    /// Directories are keyed by their path, with backslashes as separators
+    /// and without trailing separator. Files located at the root are keyed
+    /// by an empty string. Directory markers (see
+    /// `ThorFileEntry::is_directory`) are grouped with their parent
+    /// directory.
+    pub fn entries_by_directory(&self) -> BTreeMap<String, Vec<&ThorFileEntry>> {
+        let mut directories: BTreeMap<String, Vec<&ThorFileEntry>> = BTreeMap::new();
+        for entry in &self.entries {
+            let path = fold_thor_path(&entry.relative_path, PathSeparator::Backslash, false);
+            let path = path.trim_end_matches('\\');
+            let directory = match path.rfind('\\') {
+                Some(separator_index) => &path[..separator_index],
+                None => "",
+            };
+            directories
+                .entry(directory.to_string())
+                .or_default()
+                .push(entry);
+        }
+        directories
+    }
+
+    /// Reads the content of `file_path` from `archive_content`, the content
+    /// of the archive the container was parsed from (e.g., with
+    /// `parse_thor_patch`).
+    ///
+    /// Fails with `GrufError::OffsetOutOfBounds` if the entry's content isn't
+    /// located within `archive_content`.
+    pub fn read_entry_content(&self, archive_content: &[u8], file_path: &str) -> Result<Vec<u8>> {
+        let file_entry = match self.get_file_entry(file_path) {
+            None => return Err(GrufError::EntryNotFound),
+            Some(entry) if entry.is_removed => return Err(GrufError::EntryRemoved),
+            Some(entry) => entry,
+        };
+        if file_entry.size_compressed == 0 {
+            return Ok(vec![]);
+        }
+        decompress_entry(
+            self.decompressor(),
+            file_entry,
+            entry_raw_data_in(archive_content, file_entry)?,
+        )
+    }
+
+    /// Makes the offsets of the entries and of the file table relative to an
+    /// object in which the archive is stored at `base` (see
+    /// `ThorArchive::new_at`).
+    fn shift_offsets(&mut self, base: u64) -> Result<()> {
+        let shifted_offset =
+            |offset: u64| offset.checked_add(base).ok_or(GrufError::OffsetOutOfBounds);
+        for entry in self.content_entries_mut() {
+            entry.offset = shifted_offset(entry.offset)?;
+        }
+        match &mut self.table {
+            ThorTable::SingleFile(table) => {
+                table.file_table_offset = shifted_offset(table.file_table_offset)?
+            }
+            ThorTable::MultipleFiles(table) => {
+                table.file_table_offset = shifted_offset(table.file_table_offset)?
+            }
+        }
+        self.base_offset = shifted_offset(self.base_offset)?;
+        Ok(())
+    }
+
+    /// Moves the content of all the entries by `delta` bytes, e.g., when
+    /// relocating the archive's data.
+    ///
+    /// Fails with `GrufError::OffsetOutOfBounds`, without modifying any
+    /// entry, if an offset would be located before the header's magic or
+    /// past `THOR_MAX_ENTRY_OFFSET` (both relative to the start of the
+    /// archive, for archives opened with `ThorArchive::new_at`). Removed
+    /// entries have no content and are left untouched.
+    pub fn rebase_offsets(&mut self, delta: i64) -> Result<()> {
+        let magic_offset = self.base_offset + self.header.magic_offset();
+        let rebased_offset = |offset: u64| {
+            let new_offset = i128::from(offset) + i128::from(delta);
+            let relative_offset = new_offset - i128::from(magic_offset);
+            if relative_offset < 0 || relative_offset > i128::from(THOR_MAX_ENTRY_OFFSET) {
+                return Err(GrufError::OffsetOutOfBounds);
+            }
+            Ok(u64::try_from(new_offset)?)
+        };
+        let new_offsets = self
+            .content_entries_mut()
+            .map(|e| rebased_offset(e.offset))
+            .collect::<Result<Vec<u64>>>()?;
+        for (entry, new_offset) in self.content_entries_mut().zip(new_offsets) {
+            entry.offset = new_offset;
+        }
+        Ok(())
+    }
+
+    /// Returns the entries that have content, including the ones dropped as
+    /// duplicates, whose offsets must be kept in sync.
+    fn content_entries_mut(&mut self) -> impl Iterator<Item = &mut ThorFileEntry> {
+        self.entries
+            .iter_mut()
+            .chain(self.table_entries.iter_mut().flatten())
+            .filter(|e| !e.is_removed)
+    }
+}
+
+/// In strict mode, checks that the number of entries parsed from the file
+/// table (duplicates included) matches the header's file count.
+fn check_entry_count(
+    header: &ThorHeader,
+    parsed_count: usize,
+    options: &ThorOptions,
+) -> Result<()> {
+    if options.strict && header.file_count != parsed_count {
+        return Err(GrufError::EntryCountMismatch {
+            expected: header.file_count,
+            parsed: parsed_count,
+        });
+    }
+    Ok(())
+}
+
+/// Result of parsing the first `HEADER_EXTENDED_MAX_SIZE` bytes of an
+/// archive.
+pub(crate) enum HeaderChunkParsing {
+    /// The archive has been parsed entirely
+    Complete(ThorContainer),
+    /// The archive's file table, located somewhere else, still has to be
+    /// parsed
+    MissingTable(PendingTable),
+}
+
+/// Multiple-files archive whose file table hasn't been parsed yet.
+pub(crate) struct PendingTable {
+    header: ThorHeader,
+    table: MultipleFilesTableDesc,
+}
+
+impl PendingTable {
+    /// Returns the offset of the compressed file table.
+    pub(crate) fn offset(&self) -> u64 {
+        self.table.file_table_offset
+    }
+
+    /// Returns the size of the compressed file table.
+    pub(crate) fn compressed_size(&self) -> usize {
+        self.table.file_table_compressed_size
+    }
+
+    /// Parses the compressed file table, read from `offset()`, calling
+    /// `progress_callback` with the number of entries parsed so far (see
+    /// `ThorArchive::new_with_progress`).
+    pub(crate) fn parse_with_progress(
+        self,
+        compressed_table: &[u8],
+        options: &ThorOptions,
+        progress_callback: &mut dyn FnMut(usize),
+    ) -> Result<ThorContainer> {
+        let PendingTable { header, table } = self;
+        // The table's offset or size points past the end of the archive
+        if compressed_table.len() < table.file_table_compressed_size {
+            return Err(GrufError::TruncatedTable);
+        }
+        let table_decompression_error = |e| match e {
+            GrufError::IoError(e) => GrufError::TableDecompressionError {
+                offset: table.file_table_offset,
+                message: e.to_string(),
+            },
+            GrufError::NotZlibData { .. } => GrufError::TableDecompressionError {
+                offset: table.file_table_offset,
+                message: "table isn't zlib data".to_string(),
+            },
+            e => e,
+        };
+        let compressed_table = &compressed_table[..table.file_table_compressed_size];
+        // Entries are parsed while the table is being decompressed, with
+        // decompressors that support it
+        let (mut entries, lossy_path_count) = options
+            .decompressor
+            .decompress_stream(
+                Box::new(compressed_table),
+                options.max_decompressed_table_size,
+            )
+            .and_then(|decoder| read_multiple_files_entries(decoder, options, progress_callback))
+            .map_err(table_decompression_error)?;
+        check_entry_count(&header, entries.len(), options)?;
+        let magic_offset = header.magic_offset();
+        for entry in entries.iter_mut().filter(|e| !e.is_removed) {
+            entry.offset = entry
+                .offset
+                .checked_add(magic_offset)
+                .ok_or(GrufError::OffsetOutOfBounds)?;
+        }
+        let indexed_entries = index_entries(
+            entries,
+            options.canonicalize_paths,
+            options.canonical_separator,
+        );
+        Ok(ThorContainer {
+            lossy_path_count,
+            header,
+            table: ThorTable::MultipleFiles(table),
+            entries: indexed_entries.entries,
+            entry_index: indexed_entries.entry_index,
+            table_entries: indexed_entries.table_entries,
+            path_collisions: indexed_entries.path_collisions,
+            duplicate_count: indexed_entries.duplicate_count,
+            mixed_separators: indexed_entries.mixed_separators,
+            canonicalize_paths: options.canonicalize_paths,
+            canonical_separator: options.canonical_separator,
+            base_offset: 0,
+            decompressor: SharedDecompressor(Arc::clone(&options.decompressor)),
+        })
+    }
+}
+
+/// Returns the number of bytes of `buf` consumed by a parser that returned
+/// `rest`.
+fn consumed_len(buf: &[u8], rest: &[u8]) -> u64 {
+    buf.len().saturating_sub(rest.len()) as u64
+}
+
+/// Parses the beginning of an archive (up to `HEADER_EXTENDED_MAX_SIZE`
+/// bytes, less if the archive is smaller).
+pub(crate) fn parse_thor_header_chunk(
+    thor_header_buf: &[u8],
+    options: &ThorOptions,
+) -> Result<HeaderChunkParsing> {
+    let (prefix, header_buf) = split_header_prefix(thor_header_buf);
+    if !header_buf.starts_with(THOR_HEADER_MAGIC) {
+        return Err(GrufError::invalid_magic(
+            header_buf,
+            THOR_HEADER_MAGIC.len(),
+        ));
+    }
+    let (output, mut header) = parse_thor_header(header_buf)
+        .map_err(|_| GrufError::parsing_error("Failed to parse THOR header"))?;
+    header.prefix = prefix;
+    // Offsets stored in the archive are relative to the header's magic
+    let magic_offset = header.magic_offset();
+    match header.mode {
+        ThorMode::Invalid => Err(GrufError::InvalidThorMode(raw_thor_mode(header_buf))),
+        ThorMode::SingleFile => {
+            // Parse table
+            let table_offset = consumed_len(thor_header_buf, output);
+            let (output, mut table) = parse_single_file_table(output)
+                .map_err(|_| GrufError::parsing_error("Failed to parse THOR file table"))?;
+            table.file_table_offset = table_offset;
+            // The entry's data directly follows its table entry
+            // Parse the single entry
+            let (output, (mut entry, lossy_path)) =
+                parse_single_file_entry(output, options.path_decoding())
+                    .map_err(|e| entry_parsing_error(e, "Failed to parse THOR file entry"))?;
+            entry.offset = consumed_len(thor_header_buf, output);
+            check_entry_count(&header, 1, options)?;
+            let indexed_entries = index_entries(
+                vec![entry],
+                options.canonicalize_paths,
+                options.canonical_separator,
+            );
+            Ok(HeaderChunkParsing::Complete(ThorContainer {
+                lossy_path_count: lossy_path as usize,
+                header,
+                table: ThorTable::SingleFile(table),
+                entries: indexed_entries.entries,
+                entry_index: indexed_entries.entry_index,
+                table_entries: indexed_entries.table_entries,
+                path_collisions: indexed_entries.path_collisions,
+                duplicate_count: indexed_entries.duplicate_count,
+                mixed_separators: indexed_entries.mixed_separators,
+                canonicalize_paths: options.canonicalize_paths,
+                canonical_separator: options.canonical_separator,
+                base_offset: 0,
+                decompressor: SharedDecompressor(Arc::clone(&options.decompressor)),
+            }))
+        }
+        ThorMode::MultipleFiles => {
+            let (output, mut table) = parse_multiple_files_table(output)
+                .map_err(|e| entry_parsing_error(e, "Failed to parse THOR file table"))?;
+            table.file_table_offset = table
+                .file_table_offset
+                .checked_add(magic_offset)
+                .ok_or(GrufError::OffsetOutOfBounds)?;
+            // The table cannot overlap the header
+            if table.file_table_offset < consumed_len(thor_header_buf, output) {
+                return Err(GrufError::OffsetOutOfBounds);
+            }
+            Ok(HeaderChunkParsing::MissingTable(PendingTable {
+                header,
+                table,
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::decode_ansi_path;
+    use crate::thor::test_support::{
+        build_thor_archive, build_thor_archive_with_checksums, zlib_compress, RawThorArchiveBuilder,
+    };
+    use crate::thor::{ThorArchiveBuilder, ThorBuilder, THOR_MAX_TABLE_OFFSET};
+    use hex_literal::hex;
+    use std::io::Cursor;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_patch_list_from_string() {
+        let plist_content = "//869 iteminfo_20170423.thor
+870 iteminfo_20170423_.thor
+871 sprites_20170427.thor
+872 sprites_20170429.thor
+623 2016-01_01.thor
+//873 rodex_20170501.thor
+//874 strings_20170501.thor
+875 rodex_20170501_.thor";
+        let expected_content: HashMap<usize, &str> = [
+            (623, "2016-01_01.thor"),
+            (870, "iteminfo_20170423_.thor"),
+            (871, "sprites_20170427.thor"),
+            (872, "sprites_20170429.thor"),
+            (875, "rodex_20170501_.thor"),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        //Empty patch list
+        let empty_thor_patch_list = patch_list_from_string("");
+        assert_eq!(empty_thor_patch_list.len(), 0);
+        // TODO(LinkZ): Ensure patch list is ordered by patch index
+        // Regular patch list
+        let thor_patch_list = patch_list_from_string(plist_content);
+        assert_eq!(thor_patch_list.len(), expected_content.len());
+        for patch_info in thor_patch_list {
+            assert!(expected_content.contains_key(&patch_info.index));
+            assert_eq!(patch_info.file_name, expected_content[&patch_info.index]);
+        }
+    }
+
+    #[test]
+    fn test_open_empty_container() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let thor_file_path = thor_dir_path.join("empty.thor");
+        let thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+        assert_eq!(thor_archive.file_count(), 0);
+        assert_eq!(thor_archive.target_grf_name(), "");
+        assert!(thor_archive.use_grf_merging());
+    }
+
+    #[test]
+    fn test_parse_header_zero_file_count() {
+        let mut header_content = vec![];
+        write_thor_header_prefix(&mut header_content, true, 0, ThorMode::MultipleFiles, "")
+            .unwrap();
+        let (_, header) = parse_thor_header(&header_content).unwrap();
+        assert_eq!(header.file_count, 0);
+    }
+
+    #[test]
+    fn test_multiple_files_entry_size() {
+        let long_path = "a".repeat(255);
+        let paths = [
+            "",
+            "data\\file.txt",
+            "data\\\u{D14C}\u{C2A4}\u{D2B8}.txt",
+            &long_path,
+        ];
+        let file_entry = GenericFileEntry {
+            offset: 0x1234,
+            size: 16,
+            size_compressed: 12,
+        };
+        for path in &paths {
+            for entry in &[None, Some(&file_entry)] {
+                let mut entry_data = Vec::new();
+                serialize_thor_file_entry_into(&mut entry_data, path, *entry).unwrap();
+                assert_eq!(
+                    multiple_files_entry_size(&entry_data),
+                    Some(entry_data.len())
+                );
+                // Trailing data belongs to the next entries
+                let entry_size = entry_data.len();
+                entry_data.extend_from_slice(&[0; 32]);
+                assert_eq!(multiple_files_entry_size(&entry_data), Some(entry_size));
+                // Truncated entries aren't complete yet
+                for truncated_size in 0..entry_size {
+                    assert_eq!(
+                        multiple_files_entry_size(&entry_data[..truncated_size]),
+                        None
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_incremental_table_parsing() {
+        // Table bigger than the chunks it's decompressed in
+        let paths: Vec<String> = (0..4000)
+            .map(|i| format!("data\\texture\\{:0>40}.bmp", i))
+            .collect();
+        let archive_content =
+            build_thor_archive(paths.iter().map(|path| (path.as_str(), vec![])), &[]).unwrap();
+        let thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        assert_eq!(thor_archive.file_count(), paths.len());
+        let parsed_paths: Vec<&String> = thor_archive
+            .get_entries()
+            .map(|e| &e.relative_path)
+            .collect();
+        assert_eq!(parsed_paths, paths.iter().collect::<Vec<&String>>());
+
+        // Table ending in the middle of its third entry, a present file whose
+        // offset is cut
+        let archive_content = RawThorArchiveBuilder::new()
+            .removal(b"data\\a.txt")
+            .removal(b"data\\b.txt")
+            .raw_entry(b"\x0adata\\c.txt\x00\x00\x00")
+            .build()
+            .unwrap();
+        match ThorArchive::new(Cursor::new(archive_content)) {
+            Err(e @ GrufError::CorruptedTable { parsed_count: 2 }) => {
+                assert_eq!(e.to_string(), "file table is corrupted after 2 entries")
+            }
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_entry_flags() {
+        let mut present_entry = b"\x0adata\\a.txt\x80".to_vec();
+        present_entry.extend_from_slice(&[0u8; 12]); // Offset and sizes
+        let archive_content = RawThorArchiveBuilder::new()
+            .raw_entry(&present_entry)
+            .raw_entry(b"\x0adata\\b.txt\x03")
+            .build()
+            .unwrap();
+
+        let thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        let entry = thor_archive.get_file_entry("data\\a.txt").unwrap();
+        assert_eq!(entry.flags, 0x80);
+        assert!(!entry.is_removed);
+        let entry = thor_archive.get_file_entry("data\\b.txt").unwrap();
+        assert_eq!(entry.flags, 0x03);
+        assert!(entry.is_removed);
+        // Flags don't take part in entries' identity
+        let other_entry = ThorFileEntry {
+            flags: 0x01,
+            ..entry.clone()
+        };
+        assert_eq!(*entry, other_entry);
+        assert!(!entry.is_identical_to(&other_entry));
+    }
+
+    #[test]
+    fn test_new_with_progress() {
+        let paths: Vec<String> = (0..4000)
+            .map(|i| format!("data\\texture\\{:0>40}.bmp", i))
+            .collect();
+        let archive_content =
+            build_thor_archive(paths.iter().map(|path| (path.as_str(), vec![])), &[]).unwrap();
+        let mut progress = vec![];
+        let thor_archive =
+            ThorArchive::new_with_progress(Cursor::new(archive_content), |entries_parsed| {
+                progress.push(entries_parsed)
+            })
+            .unwrap();
+        assert_eq!(thor_archive.file_count(), paths.len());
+        // Called once per chunk of the decompressed table
+        assert!(progress.len() > 1);
+        assert!(progress.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(progress.last(), Some(&paths.len()));
+    }
+
+    #[test]
+    fn test_strict_entry_count() {
+        let strict_options = ThorOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        for thor_file_name in &["tiny.thor", "small.thor", "dir1.thor", "dir2.thor"] {
+            let thor_file = File::open(thor_dir_path.join(thor_file_name)).unwrap();
+            assert!(ThorArchive::with_options(thor_file, &strict_options).is_ok());
+        }
+
+        // The header announces more files than the table lists
+        let archive_content = RawThorArchiveBuilder::new()
+            .removal(b"data\\a.txt")
+            .removal(b"data\\b.txt")
+            .file_count(3)
+            .build()
+            .unwrap();
+        let thor_archive = ThorArchive::new(Cursor::new(archive_content.clone())).unwrap();
+        assert_eq!(thor_archive.file_count(), 2);
+        assert!(!thor_archive.verify_entry_count());
+        match ThorArchive::with_options(Cursor::new(archive_content), &strict_options) {
+            Err(GrufError::EntryCountMismatch { expected, parsed }) => {
+                assert_eq!(expected, 3);
+                assert_eq!(parsed, 2);
+            }
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_open_truncated_container() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let archive_content = fs::read(thor_dir_path.join("tiny.thor")).unwrap();
+        let table_offset = match ThorArchive::new(Cursor::new(archive_content.clone()))
+            .unwrap()
+            .container
+            .table
+        {
+            ThorTable::MultipleFiles(table) => table.file_table_offset() as usize,
+            ThorTable::SingleFile(_) => panic!("unexpected archive mode"),
+        };
+        // Truncate the archive anywhere inside or before the file table
+        for truncated_size in &[archive_content.len() - 1, table_offset + 1, table_offset] {
+            let truncated_content = archive_content[..*truncated_size].to_vec();
+            assert!(matches!(
+                ThorArchive::new(Cursor::new(truncated_content)),
+                Err(GrufError::TruncatedTable)
+            ));
+        }
+        // Table offset past the end of the archive
+        let mut invalid_content = archive_content.clone();
+        let table_offset_pos = THOR_HEADER_FIXED_SIZE + 4;
+        invalid_content[table_offset_pos..table_offset_pos + 4]
+            .copy_from_slice(&(archive_content.len() as u32 + 16).to_le_bytes());
+        assert!(matches!(
+            ThorArchive::new(Cursor::new(invalid_content)),
+            Err(GrufError::TruncatedTable)
+        ));
+        // Table offset inside the header
+        let mut invalid_content = archive_content.clone();
+        invalid_content[table_offset_pos..table_offset_pos + 4]
+            .copy_from_slice(&4u32.to_le_bytes());
+        assert!(matches!(
+            ThorArchive::new(Cursor::new(invalid_content)),
+            Err(GrufError::OffsetOutOfBounds)
+        ));
+    }
+
+    #[test]
+    fn test_table_offset_bounds() {
+        // The table directly follows its descriptor, whatever offset is stored
+        let build_archive = |table_offset: u64| {
+            RawThorArchiveBuilder::new()
+                .removal(b"data\\a.txt")
+                .removal(b"data\\b.txt")
+                .table_offset(table_offset)
+                .build()
+                .unwrap()
+        };
+        let data_offset = (THOR_HEADER_FIXED_SIZE + MULTIPLE_FILES_TABLE_DESC_SIZE) as u64;
+        let archive_len = build_archive(data_offset).len() as u64;
+
+        // Offset right after the header, i.e., no files' content
+        let mut thor_archive = ThorArchive::from_bytes(build_archive(data_offset)).unwrap();
+        assert_eq!(thor_archive.file_table_offset(), data_offset);
+        assert_eq!(thor_archive.removed_count(), 2);
+        let mut copied_content = vec![];
+        thor_archive
+            .rewrite_target_grf(Cursor::new(&mut copied_content), "data.grf")
+            .unwrap();
+        let copied_archive = ThorArchive::from_bytes(copied_content).unwrap();
+        assert_eq!(copied_archive.file_table_offset(), data_offset + 8);
+        // Offsets inside the header
+        for table_offset in &[0, 1, data_offset - 1] {
+            assert!(matches!(
+                ThorArchive::from_bytes(build_archive(*table_offset)),
+                Err(GrufError::OffsetOutOfBounds)
+            ));
+        }
+        // Offsets too close to or past the end of the archive
+        for table_offset in &[
+            archive_len - 1,
+            archive_len,
+            archive_len + 1,
+            THOR_MAX_TABLE_OFFSET,
+        ] {
+            assert!(matches!(
+                ThorArchive::from_bytes(build_archive(*table_offset)),
+                Err(GrufError::TruncatedTable)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_open_thor_container() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        {
+            let thor_file_path = thor_dir_path.join("dir1.thor");
+            let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+            assert_eq!(thor_archive.file_count(), 1);
+            assert_eq!(thor_archive.target_grf_name(), "");
+            assert!(!thor_archive.use_grf_merging());
+            assert_eq!(thor_archive.mode(), ThorMode::SingleFile);
+            assert_eq!(thor_archive.header().file_count, 1);
+            assert_eq!(thor_archive.single_file_reserved_byte(), Some(0));
+            assert!(matches!(
+                thor_archive.is_valid().unwrap_err(),
+                GrufError::EntryNotFound
+            ));
+            let entry = thor_archive.get_entries().next().unwrap();
+            assert_eq!(entry.offset, 52);
+            assert_eq!(entry.size, 22528);
+            assert_eq!(entry.size_compressed, 20136);
+            assert!(!entry.is_removed);
+            assert_eq!(entry.relative_path, "client.exe");
+            assert_eq!(
+                thor_archive.file_table_offset(),
+                THOR_HEADER_FIXED_SIZE as u64
+            );
+            assert_eq!(thor_archive.file_table_compressed_size(), None);
+            match thor_archive.table() {
+                ThorTable::SingleFile(table) => {
+                    assert_eq!(table.file_table_offset(), THOR_HEADER_FIXED_SIZE as u64)
+                }
+                ThorTable::MultipleFiles(_) => panic!("unexpected archive mode"),
+            }
+            let content = thor_archive.read_file_content("client.exe").unwrap();
+            assert_eq!(content.len(), 22528);
+            // Executables start with the "MZ" signature
+            assert!(content.starts_with(b"MZ"));
+            let temp_dir = tempdir().unwrap();
+            assert_eq!(thor_archive.extract_all(temp_dir.path()).unwrap(), 1);
+            assert_eq!(
+                fs::read(temp_dir.path().join("client.exe")).unwrap(),
+                content
+            );
+        }
+        {
+            let expected_content: HashMap<&str, usize> = [
+                ("ASPLnchr.exe", 248568),
+                ("savedata\\MiniPartyInfo.lua", 0),
+                ("savedata\\OptionInfo.lua", 2703),
+                ("savedata\\UserKeys.lua", 20),
+            ]
+            .iter()
+            .cloned()
+            .collect();
+            let check_dir2_thor_entries = |thor: &mut ThorArchive<BufReader<File>>| {
+                let file_entries: Vec<ThorFileEntry> = thor.get_entries().cloned().collect();
+                for file_entry in file_entries {
+                    let file_path: &str = &file_entry.relative_path[..];
+                    assert!(expected_content.contains_key(file_path));
+                    let expected_size = expected_content[file_path];
+                    assert_eq!(file_entry.size, expected_size);
+                    let _content = thor.read_file_content(file_path).unwrap();
+                }
+            };
+            let thor_file_path = thor_dir_path.join("dir2.thor");
+            let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+            assert_eq!(thor_archive.file_count(), expected_content.len());
+            assert_eq!(thor_archive.target_grf_name(), "");
+            assert!(!thor_archive.use_grf_merging());
+            assert_eq!(thor_archive.mode(), ThorMode::MultipleFiles);
+            assert_eq!(thor_archive.single_file_reserved_byte(), None);
+            assert!(matches!(
+                thor_archive.is_valid().unwrap_err(),
+                GrufError::EntryNotFound
+            ));
+            check_dir2_thor_entries(&mut thor_archive);
+        }
+        {
+            let expected_content: HashMap<&str, usize> = [
+                ("data.integrity", 63),
+                (
+                    "data\\texture\\유저인터페이스\\inventory\\icon_num.bmp",
+                    560,
+                ),
+            ]
+            .iter()
+            .cloned()
+            .collect();
+            let check_tiny_thor_entries = |thor: &mut ThorArchive<BufReader<File>>| {
+                let file_entries: Vec<ThorFileEntry> = thor.get_entries().cloned().collect();
+                for file_entry in file_entries {
+                    let file_path: &str = &file_entry.relative_path[..];
+                    assert!(expected_content.contains_key(file_path));
+                    let expected_size = expected_content[file_path];
+                    assert_eq!(file_entry.size, expected_size);
+                    let _content = thor.read_file_content(file_path).unwrap();
+                }
+            };
+            let thor_file_path = thor_dir_path.join("tiny.thor");
+            let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+            assert_eq!(thor_archive.file_count(), expected_content.len());
+            assert_eq!(thor_archive.target_grf_name(), "");
+            assert!(thor_archive.use_grf_merging());
+            check_tiny_thor_entries(&mut thor_archive);
+            assert!(thor_archive.is_valid().unwrap());
+        }
+        {
+            let thor_file_path = thor_dir_path.join("small.thor");
+            let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+            assert_eq!(thor_archive.file_count(), 16);
+            assert_eq!(thor_archive.target_grf_name(), "data.grf");
+            assert!(thor_archive.use_grf_merging());
+            assert!(thor_archive.is_valid().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_open_errors() {
+        let temp_dir = tempdir().unwrap();
+        let missing_file_path = temp_dir.path().join("missing.thor");
+        assert!(matches!(
+            ThorArchive::open(&missing_file_path),
+            Err(GrufError::IoError(e)) if e.kind() == io::ErrorKind::NotFound
+        ));
+        let invalid_file_path = temp_dir.path().join("invalid.thor");
+        fs::write(&invalid_file_path, b"not a THOR archive").unwrap();
+        let err = ThorArchive::open(&invalid_file_path).unwrap_err();
+        assert!(matches!(
+            &err,
+            GrufError::InvalidMagic { found } if found == b"not a THOR archive"
+        ));
+        assert_eq!(
+            err.to_string(),
+            "invalid archive header magic (found: 6e6f7420612054484f522061726368697665)"
+        );
+        // GRF archive
+        let grf_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/grf");
+        assert!(matches!(
+            ThorArchive::open(grf_dir_path.join("200-small.grf")),
+            Err(GrufError::InvalidMagic { found }) if found.starts_with(b"Master of Magic")
+                && found.len() == THOR_HEADER_MAGIC.len()
+        ));
+    }
+
+    #[test]
+    fn test_path_encoding_option() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let options = ThorOptions {
+            path_encoding: encoding::all::WINDOWS_1252,
+            ..Default::default()
+        };
+        let file = File::open(thor_dir_path.join("tiny.thor")).unwrap();
+        let mut thor_archive = ThorArchive::with_options(file, &options).unwrap();
+        assert!(thor_archive
+            .get_file_entry("data\\texture\\\u{c0}\u{af}\u{c0}\u{fa}\u{c0}\u{ce}\u{c5}\u{cd}\u{c6}\u{e4}\u{c0}\u{cc}\u{bd}\u{ba}\\inventory\\icon_num.bmp")
+            .is_some());
+        // The integrity file is decoded with the same encoding
+        assert!(thor_archive.is_valid().unwrap());
+    }
+
+    #[test]
+    fn test_redecode_paths() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let korean_path = "data\\texture\\\u{c720}\u{c800}\u{c778}\u{d130}\u{d398}\u{c774}\u{c2a4}\\inventory\\icon_num.bmp";
+        let latin_path = "data\\texture\\\u{c0}\u{af}\u{c0}\u{fa}\u{c0}\u{ce}\u{c5}\u{cd}\u{c6}\u{e4}\u{c0}\u{cc}\u{bd}\u{ba}\\inventory\\icon_num.bmp";
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("tiny.thor")).unwrap();
+        let content = thor_archive.read_file_content(korean_path).unwrap();
+        let entry = thor_archive.get_file_entry(korean_path).unwrap();
+        assert_eq!(
+            entry.raw_path,
+            crate::archive::serialize_to_ansi(korean_path).unwrap()
+        );
+        assert_eq!(
+            crate::string_from_win_1252(&entry.raw_path).unwrap(),
+            latin_path
+        );
+
+        thor_archive
+            .redecode_paths(encoding::all::WINDOWS_1252)
+            .unwrap();
+        assert!(thor_archive.get_file_entry(korean_path).is_none());
+        assert_eq!(thor_archive.read_file_content(latin_path).unwrap(), content);
+
+        // Paths that cannot be decoded leave the archive untouched
+        assert!(matches!(
+            thor_archive.redecode_paths(encoding::all::ASCII),
+            Err(GrufError::PathDecodingError)
+        ));
+        assert!(thor_archive.get_file_entry(latin_path).is_some());
+
+        thor_archive.redecode_paths(ANSI_PATH_ENCODING).unwrap();
+        assert!(thor_archive.get_file_entry(latin_path).is_none());
+        assert_eq!(
+            thor_archive.read_file_content(korean_path).unwrap(),
+            content
+        );
+        assert!(thor_archive.is_valid().unwrap());
+    }
+
+    #[test]
+    fn test_redecode_paths_restores_duplicates() {
+        // Both paths are decoded to "data\\\u{FFFD}.txt" in EUC-KR, but are
+        // distinct in Windows-1252
+        let files: [(&[u8], &[u8]); 3] = [
+            (b"data\\\x80.txt", b"first"),
+            (b"data\\other.txt", b"other"),
+            (b"data\\\xff.txt", b"second"),
+        ];
+        let mut builder = RawThorArchiveBuilder::new();
+        for (path, data) in &files {
+            let compressed_data = zlib_compress(data, Compression::default());
+            builder = builder.file(path, &compressed_data, data.len() as u32);
+        }
+        let options = ThorOptions {
+            path_decoder_trap: DecoderTrap::Replace,
+            ..Default::default()
+        };
+        let mut thor_archive =
+            ThorArchive::with_options(Cursor::new(builder.build().unwrap()), &options).unwrap();
+        assert_eq!(thor_archive.file_count(), 2);
+        assert_eq!(thor_archive.stats().duplicates_dropped, 1);
+        assert_eq!(
+            thor_archive
+                .read_file_content("data\\\u{FFFD}.txt")
+                .unwrap(),
+            b"second"
+        );
+
+        thor_archive
+            .redecode_paths(encoding::all::WINDOWS_1252)
+            .unwrap();
+        assert_eq!(thor_archive.file_count(), 3);
+        assert_eq!(thor_archive.stats().duplicates_dropped, 0);
+        assert_eq!(thor_archive.stats().paths_with_replacement_chars, 0);
+        assert_eq!(
+            thor_archive
+                .read_file_content("data\\\u{20AC}.txt")
+                .unwrap(),
+            b"first"
+        );
+        assert_eq!(
+            thor_archive.read_file_content("data\\\u{FF}.txt").unwrap(),
+            b"second"
+        );
+
+        // Going back to the original encoding drops the duplicate again
+        thor_archive.redecode_paths(ANSI_PATH_ENCODING).unwrap();
+        assert_eq!(thor_archive.file_count(), 2);
+        assert_eq!(thor_archive.stats().duplicates_dropped, 1);
+        assert_eq!(thor_archive.stats().entries_parsed, 3);
+    }
+
+    #[test]
+    fn test_lossy_path_decoding() {
+        // Multiple-files archive containing a single removed entry, whose path
+        // isn't valid EUC-KR
+        let invalid_path: &[u8] = b"data\\\xff\xfe\xfd.txt";
+        let archive_content = RawThorArchiveBuilder::new()
+            .removal(invalid_path)
+            .build()
+            .unwrap();
+
+        assert!(matches!(
+            ThorArchive::new(Cursor::new(archive_content.clone())),
+            Err(GrufError::PathDecodingError)
+        ));
+        let options = ThorOptions {
+            path_decoder_trap: DecoderTrap::Replace,
+            ..Default::default()
+        };
+        let thor_archive =
+            ThorArchive::with_options(Cursor::new(archive_content), &options).unwrap();
+        assert_eq!(thor_archive.stats().paths_with_replacement_chars, 1);
+        assert_eq!(
+            thor_archive.stats(),
+            ThorStats {
+                entries_parsed: 1,
+                duplicates_dropped: 0,
+                paths_with_replacement_chars: 1,
+                mixed_separators: false,
+            }
+        );
+        let entry = thor_archive.get_entries().next().unwrap();
+        assert!(entry.relative_path.starts_with("data\\"));
+        assert!(entry.relative_path.contains('\u{FFFD}'));
+        assert!(entry.is_removed);
+    }
+
+    #[test]
+    fn test_truncated_payload() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let mut archive_content = fs::read(thor_dir_path.join("dir1.thor")).unwrap();
+        // 'client.exe' is stored at offset 52
+        archive_content.truncate(52 + 1000);
+        let is_truncated_payload = |result: Result<_>| {
+            matches!(
+                result,
+                Err(GrufError::TruncatedPayload {
+                    relative_path,
+                    expected: 20136,
+                    available: 1000,
+                }) if relative_path == "client.exe"
+            )
+        };
+        let container = parse_thor_patch(&mut Cursor::new(&archive_content)).unwrap();
+        assert!(is_truncated_payload(
+            container
+                .read_entry_content(&archive_content, "client.exe")
+                .map(|_| ())
+        ));
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        assert!(is_truncated_payload(
+            thor_archive.read_file_content("client.exe").map(|_| ())
+        ));
+        assert!(is_truncated_payload(
+            thor_archive.read_all_in_order(|_, _| Ok(()))
+        ));
+        assert!(is_truncated_payload(
+            thor_archive
+                .read_file_content_into("client.exe", &mut vec![])
+                .map(|_| ())
+        ));
+        let mut report = thor_archive.validate().unwrap();
+        assert_eq!(report.failures.len(), 1);
+        assert!(is_truncated_payload(Err(report.failures.remove(0).1)));
+    }
+
+    #[test]
+    fn test_validate() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        for thor_file_name in &["tiny.thor", "small.thor", "dir1.thor", "dir2.thor"] {
+            let mut thor_archive = ThorArchive::open(thor_dir_path.join(thor_file_name)).unwrap();
+            let report = thor_archive.validate().unwrap();
+            assert!(report.is_valid());
+            assert_eq!(report.checked_count, thor_archive.present_count());
+        }
+
+        let archive_content = RawThorArchiveBuilder::new()
+            .external_file(b"data\\corrupted.txt", 0, 8, 16)
+            .external_file(b"data\\outside.txt", 0x1000, 8, 16)
+            .external_file(b"data\\empty.txt", 0, 0, 0)
+            .build()
+            .unwrap();
+
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        let report = thor_archive.validate().unwrap();
+        assert!(!report.is_valid());
+        assert_eq!(report.checked_count, 3);
+        assert_eq!(report.failures.len(), 2);
+        assert_eq!(report.failures[0].0, "data\\corrupted.txt");
+        assert!(
+            matches!(report.failures[1], (ref path, GrufError::OffsetOutOfBounds) if path == "data\\outside.txt")
+        );
+    }
+
+    #[test]
+    fn test_empty_entries() {
+        // Multiple-files archive containing an empty file, stored without any
+        // zlib stream, and a file whose content isn't a zlib stream
+        let archive_content = RawThorArchiveBuilder::new()
+            .external_file(b"data\\empty.txt", 0, 0, 0)
+            .external_file(b"data\\invalid.txt", 0, 8, 0)
+            .build()
+            .unwrap();
+
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        assert!(thor_archive
+            .read_file_content("data\\empty.txt")
+            .unwrap()
+            .is_empty());
+        let mut content = vec![];
+        assert_eq!(
+            thor_archive
+                .read_file_to_writer("data\\empty.txt", &mut content)
+                .unwrap(),
+            0
+        );
+        assert_eq!(thor_archive.file_crc32("data\\empty.txt").unwrap(), 0);
+        // The second entry's "content" is the archive's header
+        match thor_archive.read_file_content("data\\invalid.txt") {
+            Err(GrufError::NotZlibData {
+                relative_path,
+                offset,
+            }) => {
+                assert_eq!(relative_path, "data\\invalid.txt");
+                assert_eq!(offset, 0);
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert!(matches!(
+            thor_archive.read_file_to_writer("data\\invalid.txt", &mut io::sink()),
+            Err(GrufError::NotZlibData { .. })
+        ));
+
+        let temp_dir = tempdir().unwrap();
+        let destination_path = temp_dir.path().join("empty.txt");
+        thor_archive
+            .extract_file("data\\empty.txt", &destination_path)
+            .unwrap();
+        assert_eq!(fs::metadata(&destination_path).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_corrupt_zlib_stream() {
+        assert!(is_zlib_header(&[0x78, 0x9c]));
+        assert!(is_zlib_header(&[0x78, 0xda, 0x00]));
+        assert!(!is_zlib_header(&[0x78]));
+        assert!(!is_zlib_header(&[0x78, 0x9d]));
+        assert!(!is_zlib_header(b"AS"));
+
+        // Multiple-files archive containing a file with a valid zlib header
+        // followed by garbage, stored before the file table
+        let payload: &[u8] = &[0x78, 0x9c, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+        let payload_offset = (THOR_HEADER_FIXED_SIZE + MULTIPLE_FILES_TABLE_DESC_SIZE) as u32;
+        let archive_content = RawThorArchiveBuilder::new()
+            .file(b"data\\corrupt.txt", payload, 16)
+            .build()
+            .unwrap();
+
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        match thor_archive.read_file_content("data\\corrupt.txt") {
+            Err(GrufError::DecompressionError {
+                relative_path,
+                offset,
+                ..
+            }) => {
+                assert_eq!(relative_path, "data\\corrupt.txt");
+                assert_eq!(offset, u64::from(payload_offset));
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_fuzz_parse_mutated_fixtures() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        // Deterministic pseudo-random generator (xorshift)
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next_random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for dir_entry in fs::read_dir(thor_dir_path).unwrap() {
+            let fixture = fs::read(dir_entry.unwrap().path()).unwrap();
+            fuzz_parse(&fixture);
+            // Keep the test fast, bigger fixtures aren't more interesting
+            if fixture.len() > 32 * 1024 {
+                continue;
+            }
+            // Truncated archives
+            for len in (0..fixture.len()).step_by(fixture.len() / 64 + 1) {
+                fuzz_parse(&fixture[..len]);
+            }
+            // Overwritten header and table bytes
+            let table_start = fixture.len().saturating_sub(256);
+            for i in (0..fixture.len().min(128)).chain(table_start..fixture.len()) {
+                for &b in &[0x00, 0xff] {
+                    let mut mutated = fixture.clone();
+                    mutated[i] = b;
+                    fuzz_parse(&mutated);
+                }
+            }
+            // Randomly flipped bits
+            for _ in 0..128 {
+                let mut mutated = fixture.clone();
+                for _ in 0..1 + next_random() % 4 {
+                    let i = (next_random() % mutated.len() as u64) as usize;
+                    mutated[i] ^= 1 << (next_random() % 8);
+                }
+                fuzz_parse(&mutated);
+            }
+        }
+    }
+
+    #[test]
+    fn test_entries_by_directory() {
+        let archive_content = build_thor_archive(
+            vec![
+                ("root.txt", vec![]),
+                ("data\\a.txt", vec![]),
+                ("data/b.txt", vec![]),
+                ("data\\texture\\", vec![]),
+                ("data\\texture\\c.bmp", vec![]),
+            ],
+            &["data\\removed.txt"],
+        )
+        .unwrap();
+        let container = parse_thor_patch(&mut Cursor::new(archive_content)).unwrap();
+        let directories: Vec<(String, Vec<&str>)> = container
+            .entries_by_directory()
+            .into_iter()
+            .map(|(directory, entries)| {
+                let paths = entries.iter().map(|e| e.relative_path.as_str()).collect();
+                (directory, paths)
+            })
+            .collect();
+        assert_eq!(
+            directories,
+            vec![
+                ("".to_string(), vec!["root.txt"]),
+                (
+                    "data".to_string(),
+                    vec![
+                        "data/b.txt",
+                        "data\\a.txt",
+                        "data\\texture\\",
+                        "data\\removed.txt"
+                    ]
+                ),
+                ("data\\texture".to_string(), vec!["data\\texture\\c.bmp"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_content_manifest() {
+        let archive_content = build_thor_archive(
+            vec![
+                ("data\\abc.txt", b"abc".to_vec()),
+                ("data\\empty.txt", vec![]),
+                ("data\\texture\\", vec![]),
+            ],
+            &["data\\removed.txt"],
+        )
+        .unwrap();
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        assert_eq!(
+            thor_archive.content_manifest(HashAlgo::Md5).unwrap(),
+            vec![
+                (
+                    "data\\abc.txt".to_string(),
+                    "900150983cd24fb0d6963f7d28e17f72".to_string()
+                ),
+                (
+                    "data\\empty.txt".to_string(),
+                    "d41d8cd98f00b204e9800998ecf8427e".to_string()
+                ),
+            ]
+        );
+        let sha1_manifest = thor_archive.content_manifest(HashAlgo::Sha1).unwrap();
+        assert_eq!(
+            sha1_manifest[0].1,
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+
+        // CRC32 hashes match the ones stored in integrity files
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("tiny.thor")).unwrap();
+        let manifest = thor_archive.content_manifest(HashAlgo::Crc32).unwrap();
+        assert_eq!(manifest.len(), 1);
+        for (file_path, hash) in manifest {
+            let crc32 = thor_archive.file_crc32(&file_path).unwrap();
+            assert_eq!(hash, format!("{:08x}", crc32));
+        }
+    }
+
+    #[test]
+    fn test_negative_sizes() {
+        // Multiple-files archive containing an entry with a negative size
+        let path: &[u8] = b"data\\file.txt";
+        let archive_content = RawThorArchiveBuilder::new()
+            .external_file(path, 0, -1i32 as u32, 16)
+            .build()
+            .unwrap();
+        assert!(matches!(
+            ThorArchive::new(Cursor::new(archive_content)),
+            Err(GrufError::InvalidSize(-1))
+        ));
+
+        // Negative table size
+        let mut archive_content = vec![];
+        write_thor_header_prefix(&mut archive_content, true, 1, ThorMode::MultipleFiles, "")
+            .unwrap();
+        archive_content.extend_from_slice(&(-16i32).to_le_bytes());
+        archive_content.extend_from_slice(
+            &((THOR_HEADER_FIXED_SIZE + MULTIPLE_FILES_TABLE_DESC_SIZE) as i32).to_le_bytes(),
+        );
+        assert!(matches!(
+            ThorArchive::new(Cursor::new(archive_content)),
+            Err(GrufError::InvalidSize(-16))
+        ));
+
+        // Negative table offset
+        let mut archive_content = vec![];
+        write_thor_header_prefix(&mut archive_content, true, 1, ThorMode::MultipleFiles, "")
+            .unwrap();
+        archive_content.extend_from_slice(&16i32.to_le_bytes());
+        archive_content.extend_from_slice(&i32::MIN.to_le_bytes());
+        assert!(matches!(
+            ThorArchive::new(Cursor::new(archive_content)),
+            Err(GrufError::OffsetOutOfBounds)
+        ));
+
+        // Single-file archive
+        let mut archive_content = vec![];
+        write_thor_header_prefix(&mut archive_content, false, 1, ThorMode::SingleFile, "").unwrap();
+        archive_content.push(0); // Reserved
+        archive_content.extend_from_slice(&16i32.to_le_bytes());
+        archive_content.extend_from_slice(&i32::MIN.to_le_bytes());
+        archive_content.push(path.len() as u8);
+        archive_content.extend_from_slice(path);
+        assert!(matches!(
+            ThorArchive::new(Cursor::new(archive_content)),
+            Err(GrufError::InvalidSize(i32::MIN))
+        ));
+    }
+
+    #[test]
+    fn test_open_header_only() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        for thor_file_name in &["small.thor", "dir1.thor", "dir2.thor"] {
+            let thor_file_path = thor_dir_path.join(thor_file_name);
+            let thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+            let thor_header =
+                ThorArchive::open_header_only(File::open(&thor_file_path).unwrap()).unwrap();
+            assert_eq!(thor_header.header(), thor_archive.header());
+            assert_eq!(thor_header.file_count(), thor_archive.header().file_count);
+            assert_eq!(thor_header.mode(), thor_archive.mode());
+            assert_eq!(
+                thor_header.target_grf_name(),
+                thor_archive.target_grf_name()
+            );
+            let loaded_archive = thor_header.load_entries().unwrap();
+            assert_eq!(loaded_archive.manifest(), thor_archive.manifest());
+        }
+
+        // The file table isn't read
+        let mut archive_content = build_thor_archive(vec![("data\\a.txt", "a")], &[]).unwrap();
+        let table_offset = ThorArchive::from_bytes(archive_content.clone())
+            .unwrap()
+            .file_table_offset() as usize;
+        archive_content.truncate(table_offset);
+        let thor_header = ThorArchive::open_header_only(Cursor::new(archive_content)).unwrap();
+        assert_eq!(thor_header.file_count(), 1);
+        assert_eq!(thor_header.mode(), ThorMode::MultipleFiles);
+        assert!(matches!(
+            thor_header.load_entries(),
+            Err(GrufError::TruncatedTable)
+        ));
+    }
+
+    #[test]
+    fn test_table_location() {
+        let files: [(&str, &[u8]); 2] = [
+            ("data\\first.txt", b"first"),
+            ("data\\second.txt", b"second"),
+        ];
+        let compressed_files: Vec<Vec<u8>> = files
+            .iter()
+            .map(|(_, data)| zlib_compress(data, Compression::default()))
+            .collect();
+        let data_size: usize = compressed_files.iter().map(|data| data.len()).sum();
+        let header_size = THOR_HEADER_FIXED_SIZE + MULTIPLE_FILES_TABLE_DESC_SIZE;
+        // Build archives whose file table is stored after the files' content
+        // (like `ThorArchiveBuilder` does) and before it. The table is stored
+        // as is, so that its size doesn't depend on the offsets.
+        for &table_trails_data in &[true, false] {
+            let mut builder =
+                RawThorArchiveBuilder::new().table_compression(Some(Compression::none()));
+            for ((path, data), compressed_data) in files.iter().zip(&compressed_files) {
+                builder = builder.file(path.as_bytes(), compressed_data, data.len() as u32);
+            }
+            if !table_trails_data {
+                builder = builder.table_first();
+            }
+            let archive_content = builder.build().unwrap();
+            let table_size = archive_content.len() - header_size - data_size;
+            let (table_offset, data_offset) = if table_trails_data {
+                (header_size + data_size, header_size)
+            } else {
+                (header_size, header_size + table_size)
+            };
+
+            let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+            assert_eq!(thor_archive.file_count(), 2);
+            assert_eq!(thor_archive.file_table_offset(), table_offset as u64);
+            assert_eq!(thor_archive.file_table_compressed_size(), Some(table_size));
+            assert_eq!(
+                thor_archive
+                    .get_file_entry("data\\first.txt")
+                    .unwrap()
+                    .offset,
+                data_offset as u64
+            );
+            for (path, data) in &files {
+                assert_eq!(thor_archive.read_file_content(path).unwrap(), *data);
+            }
+            assert!(thor_archive.validate().unwrap().is_valid());
+        }
+
+        // Corrupted table
+        let archive_content = RawThorArchiveBuilder::new()
+            .raw_entry(&[0xff; 16])
+            .table_compression(None)
+            .build()
+            .unwrap();
+        match ThorArchive::from_bytes(archive_content) {
+            Err(e @ GrufError::TableDecompressionError { .. }) => assert!(e
+                .to_string()
+                .starts_with("failed to decompress file table at offset 40:")),
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_container_read_entry_content() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        for thor_file_name in &["small.thor", "dir1.thor", "prefixed-tiny.thor"] {
+            let thor_file_path = thor_dir_path.join(thor_file_name);
+            let archive_content = fs::read(&thor_file_path).unwrap();
+            let container = parse_thor_patch(&mut Cursor::new(&archive_content)).unwrap();
+            let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+            for entry in container.entries.iter().filter(|e| !e.is_removed) {
+                assert_eq!(
+                    container
+                        .read_entry_content(&archive_content, &entry.relative_path)
+                        .unwrap(),
+                    thor_archive
+                        .read_file_content(&entry.relative_path)
+                        .unwrap()
+                );
+            }
+            assert!(matches!(
+                container.read_entry_content(&archive_content, "missing.txt"),
+                Err(GrufError::EntryNotFound)
+            ));
+            // Truncated archive
+            let entry = container.entries.iter().find(|e| !e.is_removed).unwrap();
+            assert!(matches!(
+                container.read_entry_content(
+                    &archive_content[..entry.offset as usize],
+                    &entry.relative_path
+                ),
+                Err(GrufError::TruncatedPayload { available: 0, .. })
+            ));
+            assert!(matches!(
+                container.read_entry_content(
+                    &archive_content[..entry.offset as usize - 1],
+                    &entry.relative_path
+                ),
+                Err(GrufError::OffsetOutOfBounds)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_into_inner() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let archive_content = fs::read(thor_dir_path.join("small.thor")).unwrap();
+        let mut thor_archive = ThorArchive::from_bytes(archive_content.clone()).unwrap();
+        let file_path = thor_archive
+            .present_entries()
+            .last()
+            .unwrap()
+            .relative_path
+            .clone();
+        let _ = thor_archive.read_file_content(&file_path).unwrap();
+        let mut reader = thor_archive.into_inner().unwrap();
+        assert_eq!(reader.position(), 0);
+        let mut content = vec![];
+        reader.read_to_end(&mut content).unwrap();
+        assert_eq!(content, archive_content);
+    }
+
+    #[test]
+    fn test_new_at() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        for thor_file_name in &["tiny.thor", "prefixed-tiny.thor", "dir1.thor", "small.thor"] {
+            let archive_content = fs::read(thor_dir_path.join(thor_file_name)).unwrap();
+            let mut thor_archive = ThorArchive::from_bytes(archive_content.clone()).unwrap();
+            // Archive wrapped with padding bytes
+            let padding_size = 123;
+            let mut wrapped_content = vec![0xAA; padding_size];
+            wrapped_content.extend_from_slice(&archive_content);
+            let mut embedded_archive =
+                ThorArchive::new_at(Cursor::new(wrapped_content.clone()), padding_size as u64)
+                    .unwrap();
+            assert_eq!(embedded_archive.file_count(), thor_archive.file_count());
+            assert_eq!(
+                embedded_archive.target_grf_name(),
+                thor_archive.target_grf_name()
+            );
+            let file_paths: Vec<String> = thor_archive
+                .present_entries()
+                .map(|e| e.relative_path.clone())
+                .collect();
+            for file_path in &file_paths {
+                assert_eq!(
+                    embedded_archive.get_file_entry(file_path).unwrap().offset,
+                    thor_archive.get_file_entry(file_path).unwrap().offset + padding_size as u64
+                );
+                assert_eq!(
+                    embedded_archive.read_file_content(file_path).unwrap(),
+                    thor_archive.read_file_content(file_path).unwrap()
+                );
+                let data_range = embedded_archive.entry_data_range(file_path).unwrap();
+                assert_eq!(
+                    &wrapped_content[data_range.start as usize..data_range.end as usize],
+                    thor_archive
+                        .get_entry_raw_data(file_path)
+                        .unwrap()
+                        .as_slice()
+                );
+            }
+            assert!(embedded_archive.validate().unwrap().failures.is_empty());
+            // Copies don't include the padding
+            let mut copy = Cursor::new(vec![]);
+            let target_grf_name = thor_archive.target_grf_name();
+            embedded_archive
+                .rewrite_target_grf(&mut copy, &target_grf_name)
+                .unwrap();
+            let mut expected_copy = Cursor::new(vec![]);
+            thor_archive
+                .rewrite_target_grf(&mut expected_copy, &target_grf_name)
+                .unwrap();
+            assert_eq!(copy.into_inner(), expected_copy.into_inner());
+            assert_eq!(
+                embedded_archive.into_inner().unwrap().position(),
+                padding_size as u64
+            );
+        }
+        // Wrong offset
+        let archive_content = fs::read(thor_dir_path.join("tiny.thor")).unwrap();
+        assert!(matches!(
+            ThorArchive::new_at(Cursor::new(archive_content), 1),
+            Err(GrufError::InvalidMagic { .. })
+        ));
+    }
+
+    #[test]
+    fn test_with_cache() {
+        let archive_content = build_thor_archive(
+            vec![
+                ("data\\a.txt", vec![b'a'; 16]),
+                ("data\\b.txt", vec![b'b'; 16]),
+            ],
+            &[],
+        )
+        .unwrap();
+        let temp_dir = tempdir().unwrap();
+        let thor_file_path = temp_dir.path().join("cached.thor");
+        fs::write(&thor_file_path, &archive_content).unwrap();
+        let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap().with_cache(16);
+        assert_eq!(
+            thor_archive.read_file_content("data\\a.txt").unwrap(),
+            vec![b'a'; 16]
+        );
+        // Overwrite the files' content, cached content is still returned
+        let data_offset = thor_archive
+            .get_entries()
+            .map(|e| e.offset as usize)
+            .min()
+            .unwrap();
+        let table_offset = thor_archive.file_table_offset() as usize;
+        let mut corrupted_content = archive_content;
+        for b in &mut corrupted_content[data_offset..table_offset] {
+            *b = 0;
+        }
+        fs::write(&thor_file_path, &corrupted_content).unwrap();
+        assert_eq!(
+            thor_archive.read_file_content("data\\a.txt").unwrap(),
+            vec![b'a'; 16]
+        );
+        assert!(thor_archive.read_file_content("data\\b.txt").is_err());
+        assert!(ThorArchive::open(&thor_file_path)
+            .unwrap()
+            .read_file_content("data\\a.txt")
+            .is_err());
+    }
+
+    #[test]
+    fn test_entries_sorted_by() {
+        let archive_content = build_thor_archive(
+            vec![
+                ("data\\b.txt", vec![b'b'; 16]),
+                ("data\\a.txt", vec![b'a'; 16]),
+                ("data\\c.txt", vec![b'c'; 64]),
+                ("data\\d.bin", (0..=255).collect()),
+            ],
+            &["data\\removed.txt"],
+        )
+        .unwrap();
+        let thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        let sorted_paths = |key| -> Vec<&str> {
+            thor_archive
+                .entries_sorted_by(key)
+                .into_iter()
+                .map(|e| e.relative_path.as_str())
+                .collect()
+        };
+        assert_eq!(
+            sorted_paths(SortKey::Path),
+            vec!["data\\a.txt", "data\\b.txt", "data\\c.txt", "data\\d.bin"]
+        );
+        assert_eq!(
+            sorted_paths(SortKey::DecompressedSize),
+            vec!["data\\d.bin", "data\\c.txt", "data\\a.txt", "data\\b.txt"]
+        );
+        // Repeated bytes compress well, unlike 'd.bin'
+        assert_eq!(sorted_paths(SortKey::CompressedSize)[0], "data\\d.bin");
+        let sorted_entries = thor_archive.entries_sorted_by(SortKey::CompressedSize);
+        assert!(sorted_entries
+            .windows(2)
+            .all(|w| w[0].size_compressed >= w[1].size_compressed));
+    }
+
+    #[test]
+    fn test_entries_with_prefix() {
+        let archive_content = build_thor_archive(
+            vec![
+                ("data\\texture\\a.bmp", vec![1]),
+                ("data\\texture\\effect\\b.bmp", vec![2]),
+                ("data\\sprite\\c.spr", vec![3]),
+            ],
+            &["data\\texture\\removed.bmp"],
+        )
+        .unwrap();
+        let thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        let paths = |entries: Vec<&ThorFileEntry>| -> Vec<String> {
+            entries.iter().map(|e| e.relative_path.clone()).collect()
+        };
+        let expected_paths = vec![
+            "data\\texture\\a.bmp",
+            "data\\texture\\effect\\b.bmp",
+            "data\\texture\\removed.bmp",
+        ];
+        assert_eq!(
+            paths(
+                thor_archive
+                    .entries_with_prefix("data\\texture\\")
+                    .collect()
+            ),
+            expected_paths
+        );
+        assert_eq!(
+            paths(thor_archive.entries_with_prefix("data/texture/").collect()),
+            expected_paths
+        );
+        assert_eq!(thor_archive.entries_with_prefix("").count(), 4);
+        assert_eq!(thor_archive.entries_with_prefix("data\\Texture").count(), 0);
+    }
+
+    #[test]
+    fn test_fold_thor_path() {
+        // Only ASCII letters are case-folded
+        assert_eq!(
+            fold_thor_path("Data/Texture\\ÀB.BMP", PathSeparator::Backslash, true),
+            "data\\texture\\Àb.bmp"
+        );
+        assert_eq!(
+            fold_thor_path("Data/Texture\\ÀB.BMP", PathSeparator::Slash, false),
+            "Data/Texture/ÀB.BMP"
+        );
+        assert!(matches!(
+            fold_thor_path(
+                "data\\유저인터페이스\\a.bmp",
+                PathSeparator::Backslash,
+                true
+            ),
+            Cow::Borrowed(_)
+        ));
+        // Canonical and normalized paths only differ by their separators
+        assert_eq!(canonicalize_thor_path("DATA/File.Txt"), "data\\file.txt");
+        assert_eq!(normalize_thor_path("DATA\\File.Txt"), "data/file.txt");
+    }
+
+    #[cfg(feature = "glob")]
+    #[test]
+    fn test_entries_matching() {
+        let archive_content = build_thor_archive(
+            vec![
+                ("data\\texture\\a.bmp", vec![1]),
+                ("data\\texture\\effect\\b.bmp", vec![2]),
+                ("data\\sprite\\c.spr", vec![3]),
+            ],
+            &["data\\texture\\removed.bmp"],
+        )
+        .unwrap();
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        let matching_paths = |thor_archive: &ThorArchive<_>, glob: &str| -> Vec<String> {
+            thor_archive
+                .entries_matching(glob)
+                .unwrap()
+                .map(|e| e.relative_path.clone())
+                .collect()
+        };
+        assert_eq!(
+            matching_paths(&thor_archive, "data/texture/*"),
+            vec!["data\\texture\\a.bmp", "data\\texture\\removed.bmp"]
+        );
+        assert_eq!(
+            matching_paths(&thor_archive, "data\\texture\\**\\*.bmp"),
+            vec![
+                "data\\texture\\a.bmp",
+                "data\\texture\\effect\\b.bmp",
+                "data\\texture\\removed.bmp"
+            ]
+        );
+        assert_eq!(
+            matching_paths(&thor_archive, "**/*.spr"),
+            vec!["data\\sprite\\c.spr"]
+        );
+        assert!(matches!(
+            thor_archive.entries_matching("data/[texture"),
+            Err(GrufError::InvalidGlob(_))
+        ));
+
+        let temp_dir = tempdir().unwrap();
+        assert_eq!(
+            thor_archive
+                .extract_matching("data/texture/**", temp_dir.path())
+                .unwrap(),
+            2
+        );
+        let texture_dir = temp_dir.path().join("data").join("texture");
+        assert_eq!(fs::read(texture_dir.join("a.bmp")).unwrap(), vec![1]);
+        assert_eq!(
+            fs::read(texture_dir.join("effect").join("b.bmp")).unwrap(),
+            vec![2]
+        );
+        assert!(!temp_dir.path().join("data").join("sprite").exists());
+    }
+
+    #[test]
+    fn test_rebase_offsets() {
+        let archive_content = build_thor_archive(
+            vec![
+                ("data\\a.txt", b"a".to_vec()),
+                ("data\\b.txt", b"b".to_vec()),
+            ],
+            &["data\\removed.txt"],
+        )
+        .unwrap();
+        let mut container = parse_thor_patch(&mut Cursor::new(&archive_content)).unwrap();
+        let original_offsets: Vec<u64> = container.entries.iter().map(|e| e.offset).collect();
+        container.rebase_offsets(100).unwrap();
+        container.rebase_offsets(-60).unwrap();
+        for (entry, original_offset) in container.entries.iter().zip(&original_offsets) {
+            if entry.is_removed {
+                assert_eq!(entry.offset, *original_offset);
+            } else {
+                assert_eq!(entry.offset, original_offset + 40);
+            }
+        }
+        // Offsets are left untouched on failure
+        let smallest_offset = container.get_file_entry("data\\a.txt").unwrap().offset;
+        assert!(matches!(
+            container.rebase_offsets(-(smallest_offset as i64) - 1),
+            Err(GrufError::OffsetOutOfBounds)
+        ));
+        assert!(matches!(
+            container.rebase_offsets(THOR_MAX_ENTRY_OFFSET as i64),
+            Err(GrufError::OffsetOutOfBounds)
+        ));
+        assert!(matches!(
+            container.rebase_offsets(i64::MIN),
+            Err(GrufError::OffsetOutOfBounds)
+        ));
+        assert_eq!(
+            container.get_file_entry("data\\a.txt").unwrap().offset,
+            smallest_offset
+        );
+        container.rebase_offsets(-(smallest_offset as i64)).unwrap();
+        assert_eq!(container.get_file_entry("data\\a.txt").unwrap().offset, 0);
+    }
+
+    #[test]
+    fn test_rebase_offsets_embedded() {
+        let archive_content =
+            build_thor_archive(vec![("data\\a.txt", b"a".to_vec())], &[]).unwrap();
+        let padding_size = 64;
+        let mut wrapped_content = vec![0xAA; padding_size];
+        wrapped_content.extend_from_slice(&archive_content);
+        let mut container = ThorArchive::new_at(Cursor::new(wrapped_content), padding_size as u64)
+            .unwrap()
+            .into_container();
+        // Entries cannot be moved into the bytes preceding the archive
+        let offset = container.get_file_entry("data\\a.txt").unwrap().offset;
+        assert!(matches!(
+            container.rebase_offsets(padding_size as i64 - offset as i64 - 1),
+            Err(GrufError::OffsetOutOfBounds)
+        ));
+        container
+            .rebase_offsets(padding_size as i64 - offset as i64)
+            .unwrap();
+        assert_eq!(
+            container.get_file_entry("data\\a.txt").unwrap().offset,
+            padding_size as u64
+        );
+        // The upper bound is relative to the start of the archive as well
+        container
+            .rebase_offsets(THOR_MAX_ENTRY_OFFSET as i64)
+            .unwrap();
+        assert!(matches!(
+            container.rebase_offsets(1),
+            Err(GrufError::OffsetOutOfBounds)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_mode() {
+        for &raw_mode in &[0i16, 34, -48] {
+            let mut archive_content = THOR_HEADER_MAGIC.to_vec();
+            archive_content.push(0); // GRF merging
+            archive_content.extend_from_slice(&1u32.to_le_bytes()); // File count
+            archive_content.extend_from_slice(&raw_mode.to_le_bytes());
+            archive_content.push(0); // Target GRF name size
+            archive_content.extend_from_slice(&[0u8; 16]);
+            match ThorArchive::new(Cursor::new(archive_content)) {
+                Err(GrufError::InvalidThorMode(mode)) => assert_eq!(mode, raw_mode),
+                r => panic!("unexpected result: {:?}", r.map(|_| ())),
+            }
+        }
+        assert_eq!(
+            GrufError::InvalidThorMode(34).to_string(),
+            "invalid THOR archive mode: 34"
+        );
+    }
+
+    #[test]
+    fn test_duplicate_entries() {
+        // Multiple-files archive listing the same removed entry twice
+        let path: &[u8] = b"data\\removed.txt";
+        let archive_content = RawThorArchiveBuilder::new()
+            .removal(path)
+            .removal(path)
+            .build()
+            .unwrap();
+
+        let thor_archive = ThorArchive::new(Cursor::new(archive_content)).unwrap();
+        assert_eq!(thor_archive.file_count(), 1);
+        assert_eq!(thor_archive.stats().duplicates_dropped, 1);
+        assert!(!thor_archive.verify_entry_count());
+        let stats = thor_archive.stats();
+        assert_eq!(stats.entries_parsed, thor_archive.file_count() + 1);
+        assert_eq!(stats.duplicates_dropped, 1);
+        assert_eq!(stats.paths_with_replacement_chars, 0);
+
+        // The integrity file is listed twice in archives generated by Thor
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let thor_archive = ThorArchive::open(thor_dir_path.join("tiny.thor")).unwrap();
+        assert_eq!(thor_archive.stats().duplicates_dropped, 1);
+        assert!(!thor_archive.verify_entry_count());
+
+        let mut archive_content = vec![];
+        {
+            let mut builder =
+                ThorArchiveBuilder::new(Cursor::new(&mut archive_content), false, None, false)
+                    .unwrap();
+            builder
+                .append_file_update("file.txt".to_string(), &[1u8; 16][..])
+                .unwrap();
+        }
+        let thor_archive = ThorArchive::new(Cursor::new(archive_content)).unwrap();
+        assert_eq!(thor_archive.stats().duplicates_dropped, 0);
+        assert!(thor_archive.verify_entry_count());
+    }
+
+    #[test]
+    fn test_read_removed_entry() {
+        let temp_dir = tempdir().unwrap();
+        let thor_file_path = temp_dir.path().join("removal.thor");
+        let removed_path = "data\\removed.txt";
+        {
+            let thor_file = File::create(&thor_file_path).unwrap();
+            let mut builder = ThorArchiveBuilder::new(thor_file, false, None, false).unwrap();
+            builder.append_file_removal(removed_path.to_string());
+        }
+        let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+        assert!(matches!(
+            thor_archive.read_file_content(removed_path),
+            Err(GrufError::EntryRemoved)
+        ));
+        assert!(matches!(
+            thor_archive.get_entry_raw_data(removed_path),
+            Err(GrufError::EntryRemoved)
+        ));
+        assert!(matches!(
+            thor_archive.read_file_content("missing.txt"),
+            Err(GrufError::EntryNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_extract_file() {
+        let temp_dir = tempdir().unwrap();
+        let thor_file_path = temp_dir.path().join("extract.thor");
+        {
+            let thor_file = File::create(&thor_file_path).unwrap();
+            let mut builder = ThorArchiveBuilder::new(thor_file, false, None, false).unwrap();
+            builder
+                .append_file_update("data\\file.txt".to_string(), &b"content"[..])
+                .unwrap();
+            builder
+                .append_file_update("..\\evil.txt".to_string(), &b"evil"[..])
+                .unwrap();
+            builder
+                .append_file_update("\\rooted.txt".to_string(), &b"evil"[..])
+                .unwrap();
+            builder
+                .append_file_update("C:\\drive.txt".to_string(), &b"evil"[..])
+                .unwrap();
+        }
+        let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+        let output_dir = temp_dir.path().join("output");
+        let dest_path = output_dir.join("nested").join("file.txt");
+        thor_archive
+            .extract_file("data\\file.txt", &dest_path)
+            .unwrap();
+        assert_eq!(fs::read(&dest_path).unwrap(), b"content");
+        for unsafe_path in &["..\\evil.txt", "\\rooted.txt", "C:\\drive.txt"] {
+            assert!(matches!(
+                thor_archive.extract_file(unsafe_path, output_dir.join("evil.txt")),
+                Err(GrufError::UnsafePath(_))
+            ));
+        }
+        assert!(!output_dir.join("evil.txt").exists());
+    }
+
+    #[test]
+    fn test_extract_file_part_file() {
+        let temp_dir = tempdir().unwrap();
+        let content = vec![b'a'; 4096];
+        let mut thor_data = build_thor_archive(vec![("data\\file.txt", &content)], &[]).unwrap();
+        let mut thor_archive = ThorArchive::from_bytes(thor_data.clone()).unwrap();
+        let entry = thor_archive
+            .get_file_entry("data\\file.txt")
+            .unwrap()
+            .clone();
+        let dest_path = temp_dir.path().join("file.txt");
+        let part_path = temp_dir.path().join("file.txt.part");
+        thor_archive
+            .extract_file("data\\file.txt", &dest_path)
+            .unwrap();
+        assert_eq!(fs::read(&dest_path).unwrap(), content);
+        assert!(!part_path.exists());
+
+        // Corrupt the deflate stream: the previous file must be left untouched
+        let payload_end = entry.offset as usize + entry.size_compressed;
+        for b in &mut thor_data[entry.offset as usize + ZLIB_HEADER_SIZE..payload_end] {
+            *b = !*b;
+        }
+        fs::write(&dest_path, b"previous").unwrap();
+        let mut thor_archive = ThorArchive::from_bytes(thor_data).unwrap();
+        assert!(thor_archive
+            .extract_file("data\\file.txt", &dest_path)
+            .is_err());
+        assert_eq!(fs::read(&dest_path).unwrap(), b"previous");
+        assert!(!part_path.exists());
+
+        // The part file is removed when unwinding too
+        let result = std::panic::catch_unwind(|| {
+            let (_part_file, _file) = PartFileGuard::create(&dest_path).unwrap();
+            assert!(part_path.exists());
+            panic!("interrupted extraction");
+        });
+        assert!(result.is_err());
+        assert!(!part_path.exists());
+        assert_eq!(fs::read(&dest_path).unwrap(), b"previous");
+    }
+
+    #[test]
+    fn test_entries_new_or_changed() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let thor_file_path = thor_dir_path.join("tiny.thor");
+        let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+        let integrity_data = thor_archive.read_file_content(INTEGRITY_FILE_NAME).unwrap();
+        let integrity_data_as_str = decode_ansi_path(integrity_data.as_slice()).unwrap();
+        let mut manifest: HashMap<String, u32> =
+            parse_data_integrity_info(integrity_data_as_str.as_str())
+                .into_iter()
+                .map(|(file_path, hash)| (file_path.to_string(), hash))
+                .collect();
+        // Up-to-date manifest
+        assert!(thor_archive
+            .entries_new_or_changed(&manifest)
+            .unwrap()
+            .is_empty());
+        // Outdated manifest
+        for hash in manifest.values_mut() {
+            *hash = !*hash;
+        }
+        assert_eq!(
+            thor_archive
+                .entries_new_or_changed(&manifest)
+                .unwrap()
+                .len(),
+            1
+        );
+        // Empty manifest
+        let changed_entries = thor_archive
+            .entries_new_or_changed(&HashMap::new())
+            .unwrap();
+        assert_eq!(changed_entries.len(), 1);
+        assert!(!changed_entries[0].is_internal());
+    }
+
+    #[test]
+    fn test_extract_all() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let temp_dir = tempdir().unwrap();
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("dir2.thor")).unwrap();
+        assert_eq!(thor_archive.extract_all(temp_dir.path()).unwrap(), 4);
+        let extracted_file_path = temp_dir.path().join("savedata").join("OptionInfo.lua");
+        assert_eq!(
+            fs::read(extracted_file_path).unwrap(),
+            thor_archive
+                .read_file_content("savedata\\OptionInfo.lua")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_extract_all_continue_on_error() {
+        let temp_dir = tempdir().unwrap();
+        let thor_file_path = temp_dir.path().join("corrupt.thor");
+        {
+            let thor_file = File::create(&thor_file_path).unwrap();
+            let mut builder = ThorArchiveBuilder::new(thor_file, false, None, false).unwrap();
+            builder
+                .append_file_update("good.txt".to_string(), &[1u8; 64][..])
+                .unwrap();
+            builder
+                .append_file_update("bad.txt".to_string(), &[2u8; 64][..])
+                .unwrap();
+        }
+        // Corrupt the content of "bad.txt"
+        let bad_entry = ThorArchive::open(&thor_file_path)
+            .unwrap()
+            .get_file_entry("bad.txt")
+            .unwrap()
+            .clone();
+        let mut thor_data = fs::read(&thor_file_path).unwrap();
+        let bad_entry_offset = bad_entry.offset as usize;
+        for byte in &mut thor_data[bad_entry_offset..bad_entry_offset + bad_entry.size_compressed] {
+            *byte = 0xFF;
+        }
+        fs::write(&thor_file_path, thor_data).unwrap();
+
+        let output_dir_path = temp_dir.path().join("output");
+        let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+        assert!(thor_archive.extract_all(&output_dir_path).is_err());
+        let options = ExtractOptions {
+            mode: ExtractMode::ContinueOnError,
+            ..Default::default()
+        };
+        let report = thor_archive
+            .extract_all_with_options(&output_dir_path, &options)
+            .unwrap();
+        assert_eq!(report.extracted_count, 1);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].0, "bad.txt");
+        assert!(output_dir_path.join("good.txt").exists());
+    }
+
+    #[test]
+    fn test_apply_to_directory() {
+        let temp_dir = tempdir().unwrap();
+        let thor_file_path = temp_dir.path().join("patch.thor");
+        {
+            let thor_file = File::create(&thor_file_path).unwrap();
+            let mut builder = ThorArchiveBuilder::new(thor_file, false, None, false).unwrap();
+            builder
+                .append_file_update("data\\new.txt".to_string(), &[1u8; 16][..])
+                .unwrap();
+            builder.append_file_removal("data\\old.txt".to_string());
+            builder.append_file_removal("data\\missing.txt".to_string());
+        }
+        let client_dir = temp_dir.path().join("client");
+        fs::create_dir_all(client_dir.join("data")).unwrap();
+        fs::write(client_dir.join("data").join("old.txt"), b"old").unwrap();
+
+        let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+        let report = thor_archive.apply_to_directory(&client_dir).unwrap();
+        assert_eq!(
+            report,
+            ApplyReport {
+                written_count: 1,
+                deleted_count: 1,
+                skipped_count: 1,
+            }
+        );
+        assert_eq!(
+            fs::read(client_dir.join("data").join("new.txt")).unwrap(),
+            vec![1u8; 16]
+        );
+        assert!(!client_dir.join("data").join("old.txt").exists());
+    }
+
+    #[test]
+    fn test_skip_metadata_files() {
+        let mut archive_content = vec![];
+        {
+            let mut builder =
+                ThorArchiveBuilder::new(Cursor::new(&mut archive_content), false, None, true)
+                    .unwrap();
+            builder
+                .append_file_update("data\\a.txt".to_string(), &b"a"[..])
+                .unwrap();
+            builder
+                .append_file_update("PATCH.INFO".to_string(), &b"info"[..])
+                .unwrap();
+        }
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        let temp_dir = tempdir().unwrap();
+        let extracted_paths = |dir: &Path| {
+            let mut paths: Vec<String> = ["data/a.txt", INTEGRITY_FILE_NAME, "PATCH.INFO"]
+                .iter()
+                .filter(|path| dir.join(path).exists())
+                .map(|path| path.to_string())
+                .collect();
+            paths.sort();
+            paths
+        };
+        let cases = [
+            (ExtractOptions::default(), 3),
+            (
+                ExtractOptions {
+                    skip_integrity_file: true,
+                    ..Default::default()
+                },
+                2,
+            ),
+            (
+                ExtractOptions {
+                    skip_integrity_file: true,
+                    metadata_names: vec![INTEGRITY_FILE_NAME.to_string(), "patch.info".to_string()],
+                    ..Default::default()
+                },
+                1,
+            ),
+        ];
+        for (index, (options, expected_count)) in cases.iter().enumerate() {
+            let extract_dir = temp_dir.path().join(format!("extract{}", index));
+            let report = thor_archive
+                .extract_all_with_options(&extract_dir, options)
+                .unwrap();
+            assert_eq!(report.extracted_count, *expected_count);
+            assert_eq!(extracted_paths(&extract_dir).len(), *expected_count);
+            let apply_dir = temp_dir.path().join(format!("apply{}", index));
+            let report = thor_archive
+                .apply_to_directory_with_options(&apply_dir, options)
+                .unwrap();
+            assert_eq!(report.written_count, *expected_count);
+            assert_eq!(report.skipped_count, 3 - *expected_count);
+            assert_eq!(extracted_paths(&apply_dir), extracted_paths(&extract_dir));
+        }
+        assert!(!temp_dir
+            .path()
+            .join("extract1")
+            .join(INTEGRITY_FILE_NAME)
+            .exists());
+        assert!(!temp_dir.path().join("extract2").join("PATCH.INFO").exists());
+        // Metadata files are skipped by default when applying the archive
+        let apply_dir = temp_dir.path().join("apply");
+        assert_eq!(
+            thor_archive
+                .apply_to_directory(&apply_dir)
+                .unwrap()
+                .written_count,
+            2
+        );
+        assert!(!apply_dir.join(INTEGRITY_FILE_NAME).exists());
+        // But they can still be read
+        assert!(thor_archive.read_integrity_table().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_required_disk_space() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let thor_archive = ThorArchive::open(thor_dir_path.join("dir1.thor")).unwrap();
+        assert_eq!(thor_archive.required_disk_space(), 22528);
+        let thor_archive = ThorArchive::open(thor_dir_path.join("dir-marker.thor")).unwrap();
+        assert_eq!(thor_archive.required_disk_space(), 7);
+
+        let temp_dir = tempdir().unwrap();
+        assert!(thor_archive.has_enough_space(temp_dir.path(), 0).is_ok());
+        assert!(matches!(
+            thor_archive.has_enough_space(temp_dir.path(), u64::MAX),
+            Err(GrufError::InsufficientSpace {
+                required: u64::MAX,
+                ..
+            })
+        ));
+        // Directories that don't exist yet
+        assert!(thor_archive
+            .has_enough_space(temp_dir.path().join("missing").join("client"), 0)
+            .is_ok());
+
+        // Metadata files aren't written
+        let thor_archive = ThorArchive::from_bytes(
+            build_thor_archive_with_checksums(vec![("data\\a.txt", "a")], &[]).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(thor_archive.required_disk_space(), 1);
+        let options = ExtractOptions {
+            skip_integrity_file: true,
+            metadata_names: vec!["data\\a.txt".to_string()],
+            ..Default::default()
+        };
+        assert!(thor_archive.required_disk_space_with_options(&options) > 1);
+        assert_eq!(
+            thor_archive.required_disk_space_with_options(&ExtractOptions::default()),
+            thor_archive.required_disk_space_with_options(&options) + 1
+        );
+    }
+
+    #[test]
+    fn test_extract_all_flat() {
+        let archive_content = build_thor_archive(
+            vec![
+                ("data\\texture\\a\\icon_num.bmp", "a"),
+                ("data\\texture\\b\\ICON_NUM.bmp", "b"),
+                ("data\\texture\\c\\icon_num.bmp", "c"),
+                ("data\\texture\\icon_num_1.bmp", "d"),
+                ("data\\readme", "e"),
+                ("readme", "f"),
+                (INTEGRITY_FILE_NAME, "g"),
+            ],
+            &["data\\removed.txt"],
+        )
+        .unwrap();
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        let temp_dir = tempdir().unwrap();
+
+        let error_dir = temp_dir.path().join("error");
+        match thor_archive.extract_all_flat(&error_dir, CollisionPolicy::Error) {
+            Err(GrufError::NameCollision(path)) => {
+                assert_eq!(path, "data\\texture\\b\\ICON_NUM.bmp")
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert!(!error_dir.exists());
+
+        let skip_dir = temp_dir.path().join("skip");
+        assert_eq!(
+            thor_archive
+                .extract_all_flat(&skip_dir, CollisionPolicy::Skip)
+                .unwrap(),
+            3
+        );
+        assert_eq!(fs::read(skip_dir.join("icon_num.bmp")).unwrap(), b"a");
+        assert_eq!(fs::read(skip_dir.join("icon_num_1.bmp")).unwrap(), b"d");
+        assert_eq!(fs::read(skip_dir.join("readme")).unwrap(), b"e");
+        assert!(!skip_dir.join(INTEGRITY_FILE_NAME).exists());
+
+        let rename_dir = temp_dir.path().join("rename");
+        assert_eq!(
+            thor_archive
+                .extract_all_flat(&rename_dir, CollisionPolicy::Rename)
+                .unwrap(),
+            6
+        );
+        let mut file_names: Vec<String> = fs::read_dir(&rename_dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        file_names.sort_unstable();
+        assert_eq!(
+            file_names,
+            vec![
+                "ICON_NUM_1.bmp",
+                "icon_num.bmp",
+                "icon_num_1_1.bmp",
+                "icon_num_2.bmp",
+                "readme",
+                "readme_1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_directory_markers() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let thor_file_path = thor_dir_path.join("dir-marker.thor");
+        let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+        let directory_paths: Vec<&str> = thor_archive
+            .get_entries()
+            .filter(|e| e.is_directory())
+            .map(|e| e.relative_path.as_str())
+            .collect();
+        assert_eq!(directory_paths, vec!["data\\empty\\", "data\\removed\\"]);
+
+        let temp_dir = tempdir().unwrap();
+        let extract_dir = temp_dir.path().join("extracted");
+        assert_eq!(thor_archive.extract_all(&extract_dir).unwrap(), 2);
+        assert!(extract_dir.join("data").join("empty").is_dir());
+        assert_eq!(
+            fs::read(extract_dir.join("data").join("file.txt")).unwrap(),
+            b"content"
+        );
+
+        // Only empty directories are deleted
+        for (removed_dir_content, deleted_count, skipped_count) in
+            &[(None, 1, 0), (Some(b"a"), 0, 1)]
+        {
+            let client_dir = temp_dir.path().join("client");
+            let removed_dir = client_dir.join("data").join("removed");
+            fs::create_dir_all(&removed_dir).unwrap();
+            if let Some(content) = removed_dir_content {
+                fs::write(removed_dir.join("a.txt"), content).unwrap();
+            }
+            let report = thor_archive.apply_to_directory(&client_dir).unwrap();
+            assert_eq!(
+                report,
+                ApplyReport {
+                    written_count: 2,
+                    deleted_count: *deleted_count,
+                    skipped_count: *skipped_count,
+                }
+            );
+            assert!(client_dir.join("data").join("empty").is_dir());
+            assert_eq!(removed_dir.exists(), removed_dir_content.is_some());
+            fs::remove_dir_all(&client_dir).unwrap();
+        }
+        // Missing directories are skipped, other failures are reported
+        let client_dir = temp_dir.path().join("client");
+        let report = thor_archive.apply_to_directory(&client_dir).unwrap();
+        assert_eq!(report.skipped_count, 1);
+        let removed_dir = client_dir.join("data").join("removed");
+        fs::write(&removed_dir, b"not a directory").unwrap();
+        assert!(matches!(
+            thor_archive.apply_to_directory(&client_dir),
+            Err(GrufError::IoError(_))
+        ));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_extract_all_parallel() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let thor_file_path = thor_dir_path.join("small.thor");
+        let temp_dir = tempdir().unwrap();
+        let serial_dir = temp_dir.path().join("serial");
+        let parallel_dir = temp_dir.path().join("parallel");
+        let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+        let extracted_count = thor_archive.extract_all(&serial_dir).unwrap();
+        assert_eq!(
+            thor_archive
+                .extract_all_parallel(&parallel_dir, || File::open(&thor_file_path))
+                .unwrap(),
+            extracted_count
+        );
+        for entry in thor_archive.present_entries() {
+            assert_eq!(
+                fs::read(join_thor_path(&parallel_dir, &entry.relative_path)).unwrap(),
+                fs::read(join_thor_path(&serial_dir, &entry.relative_path)).unwrap()
+            );
+        }
+        assert!(thor_archive
+            .extract_all_parallel(&parallel_dir, || File::open(
+                temp_dir.path().join("missing")
+            ))
+            .is_err());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_extract_all_parallel_with_options() {
+        let archive_content = build_thor_archive(
+            vec![
+                ("data\\a.txt", b"a".to_vec()),
+                ("..\\evil.txt", b"evil".to_vec()),
+                ("data\\b.txt", b"b".to_vec()),
+                (INTEGRITY_FILE_NAME, vec![]),
+            ],
+            &[],
+        )
+        .unwrap();
+        let thor_archive = ThorArchive::from_bytes(archive_content.clone()).unwrap();
+        let open_reader =
+            || -> io::Result<Cursor<Vec<u8>>> { Ok(Cursor::new(archive_content.clone())) };
+        let temp_dir = tempdir().unwrap();
+        let output_dir = temp_dir.path().join("client");
+
+        // Flag set before the extraction
+        let options = ExtractOptions {
+            cancel_flag: Some(Arc::new(AtomicBool::new(true))),
+            ..Default::default()
+        };
+        assert!(matches!(
+            thor_archive.extract_all_parallel_with_options(&output_dir, &options, open_reader),
+            Err(GrufError::Cancelled)
+        ));
+        assert!(!output_dir.exists());
+
+        assert!(matches!(
+            thor_archive.extract_all_parallel_with_options(
+                &output_dir,
+                &ExtractOptions::default(),
+                open_reader
+            ),
+            Err(GrufError::UnsafePath(_))
+        ));
+
+        let mtime = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+        let options = ExtractOptions {
+            mode: ExtractMode::ContinueOnError,
+            mtime: Some(mtime),
+            skip_integrity_file: true,
+            ..Default::default()
+        };
+        let report = thor_archive
+            .extract_all_parallel_with_options(&output_dir, &options, open_reader)
+            .unwrap();
+        assert_eq!(report.extracted_count, 2);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].0, "..\\evil.txt");
+        assert!(matches!(report.failures[0].1, GrufError::UnsafePath(_)));
+        assert!(!temp_dir.path().join("evil.txt").exists());
+        assert!(!output_dir.join(INTEGRITY_FILE_NAME).exists());
+        for (file_name, content) in &[("a.txt", b"a"), ("b.txt", b"b")] {
+            let file_path = output_dir.join("data").join(file_name);
+            assert_eq!(&fs::read(&file_path).unwrap(), content);
+            assert_eq!(fs::metadata(&file_path).unwrap().modified().unwrap(), mtime);
+        }
+    }
+
+    #[test]
+    fn test_safe_join() {
+        let base = Path::new("client");
+        assert_eq!(
+            safe_join(base, "data\\texture/file.bmp").unwrap(),
+            base.join("data").join("texture").join("file.bmp")
+        );
+        for unsafe_path in &[
+            "..\\..\\windows\\system32\\evil.dll",
+            "data\\..\\..\\evil.txt",
+            "data/../../evil.txt",
+            "\\windows\\evil.txt",
+            "/etc/evil",
+            "C:\\windows\\evil.txt",
+            "c:evil.txt",
+        ] {
+            assert!(matches!(
+                safe_join(base, unsafe_path),
+                Err(GrufError::UnsafePath(path)) if path == *unsafe_path
+            ));
+        }
+    }
+
+    #[test]
+    fn test_apply_unsafe_paths() {
+        let temp_dir = tempdir().unwrap();
+        let client_dir = temp_dir.path().join("client");
+        let victim_path = temp_dir.path().join("victim.txt");
+        fs::write(&victim_path, b"victim").unwrap();
+        for (update_path, removal_path) in &[
+            (Some("..\\victim.txt"), None),
+            (None, Some("..\\victim.txt")),
+            (None, Some("data\\..\\..\\victim.txt")),
+        ] {
+            let mut archive_content = vec![];
+            {
+                let mut builder =
+                    ThorArchiveBuilder::new(Cursor::new(&mut archive_content), false, None, false)
+                        .unwrap();
+                if let Some(path) = update_path {
+                    builder
+                        .append_file_update(path.to_string(), &b"evil"[..])
+                        .unwrap();
+                }
+                if let Some(path) = removal_path {
+                    builder.append_file_removal(path.to_string());
+                }
+            }
+            let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+            assert!(matches!(
+                thor_archive.apply_to_directory(&client_dir),
+                Err(GrufError::UnsafePath(_))
+            ));
+            assert!(matches!(
+                thor_archive.extract_all(&client_dir),
+                Err(GrufError::UnsafePath(_)) | Ok(0)
+            ));
+            assert_eq!(fs::read(&victim_path).unwrap(), b"victim");
+        }
+    }
+
+    #[test]
+    fn test_extract_all_windows_paths() {
+        let temp_dir = tempdir().unwrap();
+        let thor_file_path = temp_dir.path().join("paths.thor");
+        {
+            let thor_file = File::create(&thor_file_path).unwrap();
+            let mut builder = ThorArchiveBuilder::new(thor_file, false, None, false).unwrap();
+            builder
+                .append_file_update("data\\texture\\file.bmp".to_string(), &[1u8; 16][..])
+                .unwrap();
+            builder
+                .append_file_update("data\\..\\..\\evil.txt".to_string(), &[2u8; 16][..])
+                .unwrap();
+        }
+        assert_eq!(
+            join_thor_path(temp_dir.path(), "data\\texture\\file.bmp"),
+            temp_dir
+                .path()
+                .join("data")
+                .join("texture")
+                .join("file.bmp")
+        );
+        let output_dir_path = temp_dir.path().join("output");
+        let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+        let options = ExtractOptions {
+            mode: ExtractMode::ContinueOnError,
+            ..Default::default()
+        };
+        let report = thor_archive
+            .extract_all_with_options(&output_dir_path, &options)
+            .unwrap();
+        assert_eq!(report.extracted_count, 1);
+        assert_eq!(report.failures.len(), 1);
+        assert!(matches!(report.failures[0].1, GrufError::UnsafePath(_)));
+        assert_eq!(
+            fs::read(
+                output_dir_path
+                    .join("data")
+                    .join("texture")
+                    .join("file.bmp")
+            )
+            .unwrap(),
+            vec![1u8; 16]
+        );
+        assert!(!temp_dir.path().join("evil.txt").exists());
+    }
+
+    /// Reader that sets a flag once a given position has been read.
+    struct FlagSettingReader {
+        inner: Cursor<Vec<u8>>,
+        position: u64,
+        flag: Arc<AtomicBool>,
+    }
+
+    impl Read for FlagSettingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let read_size = self.inner.read(buf)?;
+            if self.inner.position() > self.position {
+                self.flag.store(true, Ordering::SeqCst);
+            }
+            Ok(read_size)
+        }
+    }
+
+    impl Seek for FlagSettingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn test_extract_all_cancelled() {
+        // Incompressible content, so that it's read and written in several
+        // chunks
+        let mut seed = 0x1234_5678_u32;
+        let big_content: Vec<u8> = (0..1024 * 1024)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        let mut archive_content = vec![];
+        {
+            let mut builder =
+                ThorArchiveBuilder::new(Cursor::new(&mut archive_content), false, None, false)
+                    .unwrap();
+            builder
+                .append_file_update("data\\small.txt".to_string(), &[1u8; 16][..])
+                .unwrap();
+            builder
+                .append_file_update("data\\big.bin".to_string(), big_content.as_slice())
+                .unwrap();
+            builder
+                .append_file_update("data\\last.txt".to_string(), &[2u8; 16][..])
+                .unwrap();
+        }
+        let temp_dir = tempdir().unwrap();
+        let output_dir_path = temp_dir.path().join("data");
+
+        // Flag set before the extraction
+        let mut thor_archive = ThorArchive::from_bytes(archive_content.clone()).unwrap();
+        let options = ExtractOptions {
+            cancel_flag: Some(Arc::new(AtomicBool::new(true))),
+            ..Default::default()
+        };
+        assert!(matches!(
+            thor_archive.extract_all_with_options(temp_dir.path(), &options),
+            Err(GrufError::Cancelled)
+        ));
+        assert!(!output_dir_path.exists());
+
+        // Flag set in the middle of "big.bin"'s extraction
+        let big_entry_position = {
+            let thor_archive = ThorArchive::from_bytes(archive_content.clone()).unwrap();
+            let big_entry = thor_archive.get_file_entry("data\\big.bin").unwrap();
+            big_entry.offset + big_entry.size_compressed as u64 / 2
+        };
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+        let reader = FlagSettingReader {
+            inner: Cursor::new(archive_content),
+            position: big_entry_position,
+            flag: cancel_flag.clone(),
+        };
+        let mut thor_archive = ThorArchive::new(reader).unwrap();
+        // Parsing the file table, which is at the end of the archive, sets the flag
+        cancel_flag.store(false, Ordering::SeqCst);
+        let options = ExtractOptions {
+            mode: ExtractMode::ContinueOnError,
+            cancel_flag: Some(cancel_flag),
+            ..Default::default()
+        };
+        let destination_path = temp_dir.path().to_path_buf();
+        let extraction = std::thread::spawn(move || {
+            thor_archive.extract_all_with_options(destination_path, &options)
+        });
+        assert!(matches!(
+            extraction.join().unwrap(),
+            Err(GrufError::Cancelled)
+        ));
+        assert_eq!(
+            fs::read(output_dir_path.join("small.txt")).unwrap(),
+            vec![1u8; 16]
+        );
+        assert!(!output_dir_path.join("big.bin").exists());
+        assert!(!output_dir_path.join("last.txt").exists());
+    }
+
+    #[test]
+    fn test_extract_all_with_mtime() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let temp_dir = tempdir().unwrap();
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("dir2.thor")).unwrap();
+        let mtime = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_500_000_000);
+        let options = ExtractOptions {
+            mtime: Some(mtime),
+            ..Default::default()
+        };
+        let report = thor_archive
+            .extract_all_with_options(temp_dir.path(), &options)
+            .unwrap();
+        assert_eq!(report.extracted_count, thor_archive.file_count());
+        for entry in thor_archive.get_entries() {
+            let extracted_file_path = join_thor_path(temp_dir.path(), &entry.relative_path);
+            let metadata = fs::metadata(extracted_file_path).unwrap();
+            assert_eq!(metadata.modified().unwrap(), mtime);
+        }
+    }
+
+    #[test]
+    fn test_open_single_entry_multiple_files_container() {
+        let temp_dir = tempdir().unwrap();
+        let thor_file_path = temp_dir.path().join("single_entry.thor");
+        let content: Vec<u8> = (0..=255).collect();
+        {
+            let thor_file = File::create(&thor_file_path).unwrap();
+            let mut builder = ThorArchiveBuilder::new(thor_file, false, None, false).unwrap();
+            builder
+                .append_file_update("data\\file.bin".to_string(), content.as_slice())
+                .unwrap();
+        }
+        let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+        assert_eq!(thor_archive.single_file_reserved_byte(), None);
+        assert_eq!(thor_archive.container.header.file_count, 1);
+        assert_eq!(thor_archive.container.entries.len(), 1);
+        assert_eq!(thor_archive.file_count(), 1);
+        assert_eq!(
+            thor_archive.read_file_content("data\\file.bin").unwrap(),
+            content
+        );
+        let extracted_file_path = temp_dir.path().join("file.bin");
+        thor_archive
+            .extract_file("data\\file.bin", &extracted_file_path)
+            .unwrap();
+        assert_eq!(fs::read(extracted_file_path).unwrap(), content);
+    }
+
+    #[test]
+    fn test_readers() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("small.thor")).unwrap();
+        let entries: Vec<ThorFileEntry> = thor_archive.get_entries().cloned().collect();
+        let mut expected_content: HashMap<String, Vec<u8>> = HashMap::new();
+        for entry in entries.iter().filter(|e| !e.is_removed) {
+            let content = thor_archive
+                .read_file_content(&entry.relative_path)
+                .unwrap();
+            let mut streamed_content = vec![];
+            thor_archive
+                .entry_reader(&entry.relative_path)
+                .unwrap()
+                .read_to_end(&mut streamed_content)
+                .unwrap();
+            assert_eq!(streamed_content, content);
+            let mut file_reader =
+                io::BufReader::new(thor_archive.open_file(&entry.relative_path).unwrap());
+            let mut copied_content = vec![];
+            io::copy(&mut file_reader, &mut copied_content).unwrap();
+            assert_eq!(copied_content, content);
+            expected_content.insert(entry.relative_path.clone(), content);
+        }
+        // Read the files' content in reverse order
+        let mut readers: Vec<(String, _)> = thor_archive.readers().map(Result::unwrap).collect();
+        assert_eq!(readers.len(), expected_content.len());
+        while let Some((relative_path, mut reader)) = readers.pop() {
+            let mut content = vec![];
+            reader.read_to_end(&mut content).unwrap();
+            assert_eq!(content, expected_content[&relative_path]);
+        }
+    }
+
+    #[test]
+    fn test_sequential_reads() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("dir2.thor")).unwrap();
+        let mut file_entries: Vec<ThorFileEntry> = thor_archive
+            .present_entries()
+            .filter(|e| e.size > 0)
+            .cloned()
+            .collect();
+        assert!(file_entries.len() >= 2);
+        // Read entries in the reverse order of their content, so that the
+        // reader is never already positioned where the next read starts
+        file_entries.sort_unstable_by_key(|e| std::cmp::Reverse(e.offset));
+        let expected_content: Vec<Vec<u8>> = file_entries
+            .iter()
+            .map(|e| {
+                ThorArchive::open(thor_dir_path.join("dir2.thor"))
+                    .unwrap()
+                    .read_file_content(&e.relative_path)
+                    .unwrap()
+            })
+            .collect();
+        for (entry, content) in file_entries.iter().zip(&expected_content) {
+            assert_eq!(
+                &thor_archive
+                    .read_file_content(&entry.relative_path)
+                    .unwrap(),
+                content
+            );
+            // Same read twice in a row
+            assert_eq!(
+                &thor_archive
+                    .read_file_content(&entry.relative_path)
+                    .unwrap(),
+                content
+            );
+        }
+        // Reads following partial reads
+        for (entry, content) in file_entries.iter().zip(&expected_content) {
+            let mut buf = [0u8; 1];
+            thor_archive
+                .entry_reader(&entry.relative_path)
+                .unwrap()
+                .read_exact(&mut buf)
+                .unwrap();
+            assert_eq!(buf[0], content[0]);
+            let other_entry = file_entries
+                .iter()
+                .find(|e| e.relative_path != entry.relative_path)
+                .unwrap();
+            let other_content = thor_archive
+                .read_file_content(&other_entry.relative_path)
+                .unwrap();
+            assert_eq!(other_content.len(), other_entry.size);
+            assert_eq!(
+                &thor_archive
+                    .read_file_content(&entry.relative_path)
+                    .unwrap(),
+                content
+            );
+        }
+    }
+
+    #[test]
+    fn test_entries_with_content() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("small.thor")).unwrap();
+        let entries: Vec<ThorFileEntry> = thor_archive.get_entries().cloned().collect();
+        // Transcode the archive
+        let mut archive_content = vec![];
+        {
+            let mut builder =
+                ThorArchiveBuilder::new(Cursor::new(&mut archive_content), true, None, false)
+                    .unwrap();
+            let mut entry_count = 0;
+            for result in thor_archive.entries_with_content() {
+                let (entry, content) = result.unwrap();
+                assert!(entry.is_identical_to(&entries[entry_count]));
+                entry_count += 1;
+                if entry.is_removed {
+                    assert!(content.is_empty());
+                    builder.append_file_removal(entry.relative_path);
+                } else {
+                    builder
+                        .append_file_update(entry.relative_path, content.as_slice())
+                        .unwrap();
+                }
+            }
+            assert_eq!(entry_count, entries.len());
+        }
+        let mut transcoded_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        assert_eq!(transcoded_archive.file_count(), entries.len());
+        for entry in &entries {
+            let transcoded_entry = transcoded_archive
+                .get_file_entry(&entry.relative_path)
+                .unwrap();
+            assert_eq!(transcoded_entry.is_removed, entry.is_removed);
+            if !entry.is_removed {
+                assert_eq!(
+                    transcoded_archive
+                        .read_file_content(&entry.relative_path)
+                        .unwrap(),
+                    thor_archive
+                        .read_file_content(&entry.relative_path)
+                        .unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_clone() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let archive_content = fs::read(thor_dir_path.join("small.thor")).unwrap();
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        let file_paths: Vec<String> = thor_archive
+            .present_entries()
+            .map(|e| e.relative_path.clone())
+            .collect();
+        let mut cloned_archive = thor_archive.clone();
+        assert_eq!(cloned_archive.manifest(), thor_archive.manifest());
+        // Interleaved reads, through readers with independent positions
+        let mut reader = thor_archive.entry_reader(&file_paths[0]).unwrap();
+        let mut content = vec![0u8; 1];
+        reader.read_exact(&mut content).unwrap();
+        let cloned_content = cloned_archive.read_file_content(&file_paths[1]).unwrap();
+        reader.read_to_end(&mut content).unwrap();
+        drop(reader);
+        assert_eq!(
+            content,
+            thor_archive.read_file_content(&file_paths[0]).unwrap()
+        );
+        assert_eq!(
+            cloned_content,
+            thor_archive.read_file_content(&file_paths[1]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_read_file_content_into() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("small.thor")).unwrap();
+        let file_paths: Vec<String> = thor_archive
+            .present_entries()
+            .map(|e| e.relative_path.clone())
+            .collect();
+        let mut buf = vec![0xFF; 4];
+        for file_path in &file_paths {
+            let size = thor_archive
+                .read_file_content_into(file_path, &mut buf)
+                .unwrap();
+            assert_eq!(size, buf.len());
+            assert_eq!(buf, thor_archive.read_file_content(file_path).unwrap());
+        }
+        // The buffer's capacity is reused
+        let capacity = buf.capacity();
+        for file_path in &file_paths {
+            thor_archive
+                .read_file_content_into(file_path, &mut buf)
+                .unwrap();
+        }
+        assert_eq!(buf.capacity(), capacity);
+        assert!(matches!(
+            thor_archive.read_file_content_into("missing.txt", &mut buf),
+            Err(GrufError::EntryNotFound)
+        ));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_open_mmap() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        for thor_file_name in &["small.thor", "dir1.thor", "dir2.thor"] {
+            let thor_file_path = thor_dir_path.join(thor_file_name);
+            let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+            let mut mapped_archive = unsafe { ThorArchive::open_mmap(&thor_file_path) }.unwrap();
+            let file_entries: Vec<ThorFileEntry> =
+                thor_archive.present_entries().cloned().collect();
+            let mut buf = vec![];
+            for entry in file_entries {
+                let content = thor_archive
+                    .read_file_content(&entry.relative_path)
+                    .unwrap();
+                assert_eq!(
+                    mapped_archive
+                        .read_file_content(&entry.relative_path)
+                        .unwrap(),
+                    content
+                );
+                mapped_archive
+                    .read_file_content_into(&entry.relative_path, &mut buf)
+                    .unwrap();
+                assert_eq!(buf, content);
+            }
+        }
+
+        // Content located past the end of the archive
+        let temp_dir = tempdir().unwrap();
+        let thor_file_path = temp_dir.path().join("outside.thor");
+        let archive_content = RawThorArchiveBuilder::new()
+            .external_file(b"data\\outside.txt", 0x1000, 8, 16)
+            .build()
+            .unwrap();
+        fs::write(&thor_file_path, archive_content).unwrap();
+        let mut mapped_archive = unsafe { ThorArchive::open_mmap(&thor_file_path) }.unwrap();
+        assert!(matches!(
+            mapped_archive.read_file_content("data\\outside.txt"),
+            Err(GrufError::OffsetOutOfBounds)
+        ));
+    }
+
+    #[test]
+    fn test_read_file_content_with() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let thor_path = thor_dir_path.join("small.thor");
+        let mut thor_archive = ThorArchive::open(&thor_path).unwrap();
+        let mut other_handle = File::open(&thor_path).unwrap();
+        let entries: Vec<ThorFileEntry> = thor_archive.get_entries().cloned().collect();
+        for entry in entries.iter().filter(|e| !e.is_removed) {
+            let content = thor_archive
+                .read_file_content_with(&mut other_handle, &entry.relative_path)
+                .unwrap();
+            assert_eq!(
+                content,
+                thor_archive
+                    .read_file_content(&entry.relative_path)
+                    .unwrap()
+            );
+        }
+        assert!(matches!(
+            thor_archive.read_file_content_with(&mut other_handle, "missing.txt"),
+            Err(GrufError::EntryNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_read_file_to_writer() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("small.thor")).unwrap();
+        let entries: Vec<ThorFileEntry> = thor_archive.get_entries().cloned().collect();
+        for entry in entries.iter().filter(|e| !e.is_removed) {
+            let mut content = vec![];
+            let written = thor_archive
+                .read_file_to_writer(&entry.relative_path, &mut content)
+                .unwrap();
+            assert_eq!(written, entry.size as u64);
+            assert_eq!(
+                content,
+                thor_archive
+                    .read_file_content(&entry.relative_path)
+                    .unwrap()
+            );
+        }
+        assert!(matches!(
+            thor_archive.read_file_to_writer("missing.txt", &mut io::sink()),
+            Err(GrufError::EntryNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_read_size_mismatch() {
+        // A valid zlib stream of a shorter content than the entry's size
+        let archive_content = RawThorArchiveBuilder::new()
+            .file(
+                b"file.txt",
+                &zlib_compress(&[1u8; 63], Compression::default()),
+                64,
+            )
+            .build()
+            .unwrap();
+
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        assert!(matches!(
+            thor_archive.read_file_content("file.txt"),
+            Err(GrufError::SizeMismatch {
+                expected: 64,
+                actual: 63
+            })
+        ));
+        assert!(matches!(
+            thor_archive.read_file_to_writer("file.txt", &mut io::sink()),
+            Err(GrufError::SizeMismatch {
+                expected: 64,
+                actual: 63
+            })
+        ));
+    }
+
+    #[test]
+    fn test_decompression_too_large() {
+        // A zlib stream that inflates past the entry's size
+        let archive_content = RawThorArchiveBuilder::new()
+            .file(
+                b"file.txt",
+                &zlib_compress(&[1u8; 256], Compression::default()),
+                64,
+            )
+            .build()
+            .unwrap();
+
+        let mut thor_archive = ThorArchive::from_bytes(archive_content.clone()).unwrap();
+        assert!(matches!(
+            thor_archive.read_file_content("file.txt"),
+            Err(GrufError::DecompressionTooLarge(64))
+        ));
+        let mut content = vec![];
+        assert!(matches!(
+            thor_archive.read_file_to_writer("file.txt", &mut content),
+            Err(GrufError::DecompressionTooLarge(64))
+        ));
+        assert!(content.len() <= 65);
+        // File table
+        let options = ThorOptions {
+            max_decompressed_table_size: 8,
+            ..Default::default()
+        };
+        assert!(matches!(
+            ThorArchive::with_options(Cursor::new(archive_content), &options),
+            Err(GrufError::DecompressionTooLarge(8))
+        ));
+    }
+
+    #[test]
+    fn test_file_entry_identity() {
+        let entry = ThorFileEntry {
+            size_compressed: 16,
+            size: 32,
+            relative_path: "data\\file.txt".to_string(),
+            raw_path: b"data\\file.txt".to_vec(),
+            is_removed: false,
+            flags: 0,
+            offset: 64,
+        };
+        let moved_entry = ThorFileEntry {
+            offset: 128,
+            ..entry.clone()
+        };
+        assert_eq!(entry, moved_entry);
+        assert!(entry.is_identical_to(&entry.clone()));
+        assert!(!entry.is_identical_to(&moved_entry));
+        let entries: std::collections::HashSet<ThorFileEntry> =
+            vec![entry, moved_entry].into_iter().collect();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[test]
+    fn test_read_file_content_shared() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ThorArchive<File>>();
+        assert_send_sync::<ThorArchive<BufReader<File>>>();
+
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("small.thor")).unwrap();
+        let expected_content: HashMap<String, Vec<u8>> = thor_archive
+            .present_entries()
+            .map(|e| e.relative_path.clone())
+            .collect::<Vec<String>>()
+            .into_iter()
+            .map(|path| {
+                let content = thor_archive.read_file_content(&path).unwrap();
+                (path, content)
+            })
+            .collect();
+        let thor_archive = std::sync::Arc::new(thor_archive);
+        let expected_content = std::sync::Arc::new(expected_content);
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let thor_archive = std::sync::Arc::clone(&thor_archive);
+                let expected_content = std::sync::Arc::clone(&expected_content);
+                std::thread::spawn(move || {
+                    for (path, content) in expected_content.iter() {
+                        assert_eq!(
+                            &thor_archive.read_file_content_shared(path).unwrap(),
+                            content
+                        );
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(matches!(
+            thor_archive.read_file_content_shared("missing.txt"),
+            Err(GrufError::EntryNotFound)
+        ));
+
+        // Truncated archive, 'client.exe' is stored at offset 52
+        let mut archive_content = fs::read(thor_dir_path.join("dir1.thor")).unwrap();
+        archive_content.truncate(52 + 1000);
+        let temp_dir = tempdir().unwrap();
+        let truncated_path = temp_dir.path().join("truncated.thor");
+        fs::write(&truncated_path, archive_content).unwrap();
+        let thor_archive = ThorArchive::open(&truncated_path).unwrap();
+        match thor_archive.read_file_content_shared("client.exe") {
+            Err(GrufError::TruncatedPayload {
+                relative_path,
+                expected: 20136,
+                available: 1000,
+            }) => assert_eq!(relative_path, "client.exe"),
+            r => panic!("unexpected result: {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_read_file_range() {
+        let content: Vec<u8> = (0..1024u32).map(|i| (i % 251) as u8).collect();
+        let mut archive_content = vec![];
+        {
+            let mut builder =
+                ThorArchiveBuilder::new(Cursor::new(&mut archive_content), false, None, false)
+                    .unwrap();
+            builder
+                .append_file_update("file.bin".to_string(), content.as_slice())
+                .unwrap();
+        }
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        assert_eq!(
+            thor_archive.read_file_range("file.bin", 0, 4).unwrap(),
+            &content[..4]
+        );
+        assert_eq!(
+            thor_archive.read_file_range("file.bin", 300, 100).unwrap(),
+            &content[300..400]
+        );
+        assert_eq!(
+            thor_archive.read_file_range("file.bin", 1000, 100).unwrap(),
+            &content[1000..]
+        );
+        assert!(thor_archive
+            .read_file_range("file.bin", 2000, 100)
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            thor_archive.read_file_range("missing.bin", 0, 4),
+            Err(GrufError::EntryNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_file_crc32() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("small.thor")).unwrap();
+        let file_paths: Vec<String> = thor_archive
+            .present_entries()
+            .map(|e| e.relative_path.clone())
+            .collect();
+        for file_path in file_paths {
+            let file_content = thor_archive.read_file_content(&file_path).unwrap();
+            assert_eq!(
+                thor_archive.file_crc32(&file_path).unwrap(),
+                crc32::checksum_ieee(&file_content)
+            );
+        }
+        assert!(matches!(
+            thor_archive.file_crc32("missing.txt"),
+            Err(GrufError::EntryNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_integrity_table() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("tiny.thor")).unwrap();
+        let integrity_table = thor_archive.read_integrity_table().unwrap().unwrap();
+        assert_eq!(integrity_table.len(), 1);
+        let (file_path, _) = integrity_table.iter().next().unwrap();
+        assert!(thor_archive.get_file_entry(file_path).is_some());
+        assert!(thor_archive.verify_against_integrity().unwrap().is_empty());
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("dir2.thor")).unwrap();
+        assert!(thor_archive.read_integrity_table().unwrap().is_none());
+        assert!(matches!(
+            thor_archive.verify_against_integrity(),
+            Err(GrufError::EntryNotFound)
+        ));
+
+        // Tampered archive
+        let mut archive_content = vec![];
+        {
+            let mut builder =
+                ThorArchiveBuilder::new(Cursor::new(&mut archive_content), false, None, true)
+                    .unwrap();
+            builder
+                .append_file_update("data\\a.txt".to_string(), &[1u8; 16][..])
+                .unwrap();
+            builder
+                .append_file_update("data\\b.txt".to_string(), &[2u8; 16][..])
+                .unwrap();
+        }
+        let mut integrity_table = ThorArchive::from_bytes(archive_content.clone())
+            .unwrap()
+            .read_integrity_table()
+            .unwrap()
+            .unwrap();
+        assert_eq!(integrity_table.len(), 2);
+        let mut tampered_content = vec![];
+        {
+            let mut builder =
+                ThorArchiveBuilder::new(Cursor::new(&mut tampered_content), false, None, false)
+                    .unwrap();
+            builder
+                .append_file_update("data\\a.txt".to_string(), &[1u8; 16][..])
+                .unwrap();
+            builder
+                .append_file_update("data\\b.txt".to_string(), &[3u8; 16][..])
+                .unwrap();
+            integrity_table.insert("data\\c.txt".to_string(), 0);
+            let integrity_data: String = integrity_table
+                .iter()
+                .map(|(path, hash)| format!("{}=0x{:08x}\r\n", path, hash))
+                .collect();
+            builder
+                .append_file_update(INTEGRITY_FILE_NAME.to_string(), integrity_data.as_bytes())
+                .unwrap();
+        }
+        let mut thor_archive = ThorArchive::from_bytes(tampered_content).unwrap();
+        assert_eq!(
+            thor_archive.verify_against_integrity().unwrap(),
+            vec!["data\\b.txt", "data\\c.txt"]
+        );
+        assert!(!thor_archive.is_valid().unwrap());
+    }
+
+    #[test]
+    fn test_compression_effectiveness() {
+        let temp_dir = tempdir().unwrap();
+        let thor_file_path = temp_dir.path().join("compression.thor");
+        {
+            let thor_file = File::create(&thor_file_path).unwrap();
+            let mut builder = ThorArchiveBuilder::new(thor_file, false, None, false).unwrap();
+            builder
+                .append_file_update("zeroes.bin".to_string(), &[0u8; 1024][..])
+                .unwrap();
+            builder
+                .append_file_update("tiny.bin".to_string(), &[42u8][..])
+                .unwrap();
+            builder
+                .append_file_update("empty.bin".to_string(), &[][..])
+                .unwrap();
+            builder.append_file_removal("removed.bin".to_string());
+        }
+        let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+        let report = thor_archive.compression_effectiveness();
+        assert_eq!(report.compressed_entries, 1);
+        assert_eq!(report.incompressible_entries, 1);
+        assert_eq!(report.total_size, 1025);
+        let compressed_size: usize = ["zeroes.bin", "tiny.bin"]
+            .iter()
+            .map(|path| thor_archive.get_entry_raw_data(path).unwrap().len())
+            .sum();
+        assert_eq!(report.total_size_compressed, compressed_size as u64);
+    }
+
+    #[test]
+    fn test_entries_in_table_order() {
+        let temp_dir = tempdir().unwrap();
+        let paths = [
+            "data\\b.txt",
+            "data\\a.txt",
+            "data\\c.txt",
+            "data\\removed.txt",
+        ];
+        let build_archive = |file_name: &str| {
+            let thor_file_path = temp_dir.path().join(file_name);
+            {
+                let thor_file = File::create(&thor_file_path).unwrap();
+                let mut builder = ThorArchiveBuilder::new(thor_file, false, None, false).unwrap();
+                for path in &paths[..3] {
+                    builder
+                        .append_file_update(path.to_string(), path.as_bytes())
+                        .unwrap();
+                }
+                builder.append_file_removal(paths[3].to_string());
+                // Updating an entry doesn't change its position
+                builder
+                    .append_file_update(paths[0].to_string(), &[0u8; 8][..])
+                    .unwrap();
+            }
+            ThorArchive::open(&thor_file_path).unwrap()
+        };
+        let thor_archive = build_archive("first.thor");
+        let ordered_paths: Vec<&str> = thor_archive
+            .entries_in_table_order()
+            .iter()
+            .map(|e| e.relative_path.as_str())
+            .collect();
+        assert_eq!(ordered_paths, paths);
+        assert!(thor_archive
+            .get_entries()
+            .map(|e| e.relative_path.as_str())
+            .eq(ordered_paths));
+        assert_eq!(
+            thor_archive.entries_in_table_order(),
+            build_archive("second.thor").entries_in_table_order()
+        );
+    }
+
+    #[test]
+    fn test_entry_at() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("small.thor")).unwrap();
+        let entries: Vec<ThorFileEntry> = thor_archive.get_entries().cloned().collect();
+        for (index, entry) in entries.iter().enumerate() {
+            assert!(thor_archive.entry_at(index).unwrap().is_identical_to(entry));
+            if entry.is_removed {
+                assert!(matches!(
+                    thor_archive.read_file_at(index),
+                    Err(GrufError::EntryRemoved)
+                ));
+            } else {
+                assert_eq!(
+                    thor_archive.read_file_at(index).unwrap(),
+                    thor_archive
+                        .read_file_content(&entry.relative_path)
+                        .unwrap()
+                );
+            }
+        }
+        assert!(thor_archive.entry_at(entries.len()).is_none());
+        assert!(matches!(
+            thor_archive.read_file_at(entries.len()),
+            Err(GrufError::EntryNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_aggregate_sizes() {
+        let mut archive_content = vec![];
+        {
+            let mut builder =
+                ThorArchiveBuilder::new(Cursor::new(&mut archive_content), false, None, false)
+                    .unwrap();
+            builder
+                .append_file_update("a.txt".to_string(), &[1u8; 64][..])
+                .unwrap();
+            builder
+                .append_file_update("b.txt".to_string(), &[2u8; 32][..])
+                .unwrap();
+            builder.append_file_removal("c.txt".to_string());
+        }
+        let thor_archive = ThorArchive::new(Cursor::new(archive_content)).unwrap();
+        assert_eq!(thor_archive.file_count(), 3);
+        assert_eq!(thor_archive.removed_count(), 1);
+        assert_eq!(thor_archive.present_count(), 2);
+        let removed_paths: Vec<&str> = thor_archive
+            .removed_entries()
+            .map(|e| e.relative_path.as_str())
+            .collect();
+        assert_eq!(removed_paths, vec!["c.txt"]);
+        let present_paths: Vec<&str> = thor_archive
+            .present_entries()
+            .map(|e| e.relative_path.as_str())
+            .collect();
+        assert_eq!(present_paths, vec!["a.txt", "b.txt"]);
+        let mut paths = vec![];
+        for entry in &thor_archive {
+            paths.push(entry.relative_path.as_str());
+        }
+        assert_eq!(paths, vec!["a.txt", "b.txt", "c.txt"]);
+        let (present_entries, removed_entries) = thor_archive.partition_entries();
+        assert_eq!(
+            present_entries,
+            thor_archive.present_entries().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            removed_entries,
+            thor_archive.removed_entries().collect::<Vec<_>>()
+        );
+        assert_eq!(thor_archive.total_decompressed_size(), 96);
+        let compressed_size: usize = ["a.txt", "b.txt"]
+            .iter()
+            .map(|path| thor_archive.get_file_entry(path).unwrap().size_compressed)
+            .sum();
+        assert_eq!(thor_archive.total_compressed_size(), compressed_size as u64);
+    }
+
+    #[test]
+    fn test_contains_file() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let thor_archive = ThorArchive::open(thor_dir_path.join("dir2.thor")).unwrap();
+        assert_eq!(thor_archive.len(), 4);
+        assert!(!thor_archive.is_empty());
+        assert!(thor_archive.contains_file("savedata\\OptionInfo.lua"));
+        assert!(!thor_archive.contains_file("savedata\\missing.lua"));
+
+        let mut archive_content = vec![];
+        {
+            let _builder =
+                ThorArchiveBuilder::new(Cursor::new(&mut archive_content), false, None, false)
+                    .unwrap();
+        }
+        let thor_archive = ThorArchive::new(Cursor::new(archive_content)).unwrap();
+        assert_eq!(thor_archive.len(), 0);
+        assert!(thor_archive.is_empty());
+    }
+
+    #[test]
+    fn test_manifest() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let thor_archive = ThorArchive::open(thor_dir_path.join("dir2.thor")).unwrap();
+        let manifest = serde_json::to_value(thor_archive.manifest()).unwrap();
+        assert_eq!(manifest["header"]["mode"], "MultipleFiles");
+        assert_eq!(manifest["header"]["use_grf_merging"], false);
+        let entries = manifest["entries"].as_array().unwrap();
+        assert_eq!(entries.len(), thor_archive.file_count());
+        for (entry, serialized_entry) in thor_archive.get_entries().zip(entries) {
+            assert_eq!(
+                serialized_entry["relative_path"],
+                entry.relative_path.as_str()
+            );
+            assert_eq!(serialized_entry["offset"], entry.offset);
+            assert_eq!(serialized_entry["size"], entry.size);
+            assert_eq!(serialized_entry["is_removed"], entry.is_removed);
+        }
+        assert!(manifest.get("entry_index").is_none());
+
+        let entries: Vec<ThorFileEntry> = thor_archive.get_entries().cloned().collect();
+        let container = thor_archive.into_container();
+        assert_eq!(container.entries, entries);
+        assert_eq!(serde_json::to_value(&container).unwrap(), manifest);
+    }
+
+    #[test]
+    fn test_lookup_with_forward_slashes() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("dir2.thor")).unwrap();
+        let entry = thor_archive
+            .get_file_entry("savedata/OptionInfo.lua")
+            .unwrap();
+        assert_eq!(entry.relative_path, "savedata\\OptionInfo.lua");
+        assert!(thor_archive.contains_file("savedata/OptionInfo.lua"));
+        assert_eq!(
+            thor_archive
+                .read_file_content("savedata/OptionInfo.lua")
+                .unwrap(),
+            thor_archive
+                .read_file_content("savedata\\OptionInfo.lua")
+                .unwrap()
+        );
+        assert!(thor_archive
+            .get_file_entry("savedata/optioninfo.lua")
+            .is_none());
+    }
+
+    #[test]
+    fn test_display() {
+        let mut entry = ThorFileEntry {
+            size_compressed: 16,
+            size: 32,
+            relative_path: "data\\file.txt".to_string(),
+            raw_path: b"data\\file.txt".to_vec(),
+            is_removed: false,
+            flags: 0,
+            offset: 64,
+        };
+        assert_eq!(entry.to_string(), "data\\file.txt (16/32 bytes)");
+        entry.is_removed = true;
+        assert_eq!(entry.to_string(), "DELETE data\\file.txt");
+        assert_eq!(ThorMode::SingleFile.to_string(), "SingleFile");
+        assert_eq!(ThorMode::MultipleFiles.to_string(), "MultipleFiles");
+        assert_eq!(ThorMode::Invalid.to_string(), "Invalid");
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let thor_data = fs::read(thor_dir_path.join("dir2.thor")).unwrap();
+        let mut thor_archive = ThorArchive::from_bytes(thor_data).unwrap();
+        assert_eq!(thor_archive.file_count(), 4);
+        assert_eq!(
+            thor_archive
+                .read_file_content("savedata\\UserKeys.lua")
+                .unwrap()
+                .len(),
+            20
+        );
+        assert!(matches!(
+            ThorArchive::from_bytes(b"not a THOR archive".to_vec()),
+            Err(GrufError::InvalidMagic { .. })
+        ));
+    }
+
+    #[test]
+    fn test_canonicalize_paths() {
+        let temp_dir = tempdir().unwrap();
+        let thor_file_path = temp_dir.path().join("collisions.thor");
+        let contents: HashMap<&str, Vec<u8>> = [
+            ("Data\\File.txt", vec![1u8; 16]),
+            ("DATA\\file.TXT", vec![2u8; 16]),
+            ("data\\other.txt", vec![3u8; 16]),
+        ]
+        .iter()
+        .cloned()
+        .collect();
+        {
+            let thor_file = File::create(&thor_file_path).unwrap();
+            let mut builder = ThorArchiveBuilder::new(thor_file, false, None, false).unwrap();
+            for (path, content) in &contents {
+                builder
+                    .append_file_update(path.to_string(), content.as_slice())
+                    .unwrap();
+            }
+        }
+        // Paths are kept as is by default
+        let thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+        assert_eq!(thor_archive.file_count(), 3);
+        assert!(thor_archive.path_collisions().is_empty());
+        // Canonicalized paths
+        let options = ThorOptions {
+            canonicalize_paths: true,
+            ..Default::default()
+        };
+        let mut thor_archive =
+            ThorArchive::with_options(File::open(&thor_file_path).unwrap(), &options).unwrap();
+        assert_eq!(thor_archive.file_count(), 2);
+        assert!(thor_archive.get_file_entry("data\\other.txt").is_some());
+        // Lookups are canonicalized as well
+        assert!(thor_archive.get_file_entry("Data/Other.TXT").is_some());
+        assert!(thor_archive.get_file_entry("DATA\\FILE.txt").is_some());
+        let collisions = thor_archive.path_collisions().to_vec();
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions[0].canonical_path, "data\\file.txt");
+        let mut original_paths = collisions[0].original_paths.clone();
+        // The last entry prevails
+        let prevailing_path = original_paths.last().unwrap().clone();
+        assert_eq!(
+            thor_archive.read_file_content("data\\file.txt").unwrap(),
+            contents[prevailing_path.as_str()]
+        );
+        original_paths.sort();
+        assert_eq!(original_paths, vec!["DATA\\file.TXT", "Data\\File.txt"]);
+    }
+
+    #[test]
+    fn test_read_file_content_with_dict() {
+        // Generated with Python's zlib.compressobj(zdict=DICTIONARY)
+        const DICTIONARY: &[u8] = b"data\\texture\\effect\\data\\texture\\effect\\data\\texture\\effect\\data\\texture\\effect\\";
+        const CONTENT: &[u8] = b"data\\texture\\effect\\smoke.bmp;data\\texture\\effect\\fire.bmp";
+        let compressed_content =
+            hex!("78bb2e4b20b1c326569c9b9f9daa97945b608d4d362db3082c0900b24e1733").to_vec();
+        // Craft an archive containing the entry
+        let archive_content = RawThorArchiveBuilder::new()
+            .file(b"data\\file.txt", &compressed_content, CONTENT.len() as u32)
+            .build()
+            .unwrap();
+
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        assert!(matches!(
+            thor_archive
+                .read_file_content("data\\file.txt")
+                .unwrap_err(),
+            GrufError::InvalidContent(_)
+        ));
+        assert_eq!(
+            thor_archive
+                .read_file_content_with_dict("data\\file.txt", DICTIONARY)
+                .unwrap(),
+            CONTENT
+        );
+        assert!(thor_archive
+            .read_file_content_with_dict("data\\file.txt", b"data\\")
+            .is_err());
+    }
+
+    #[test]
+    fn test_custom_decompressor() {
+        /// Decompressor for archives whose content and table are stored as is.
+        #[derive(Debug)]
+        struct StoredDecompressor;
+
+        impl Decompressor for StoredDecompressor {
+            fn decompress(&self, input: &[u8], _hint_size: usize) -> Result<Vec<u8>> {
+                Ok(input.to_vec())
+            }
+        }
+
+        // Craft an archive whose content and table aren't compressed
+        let contents: [(&str, &[u8]); 3] = [
+            ("data\\a.txt", b"stored content"),
+            ("data\\empty.txt", b""),
+            ("data\\b.txt", b"other stored content"),
+        ];
+        let mut builder = RawThorArchiveBuilder::new().table_compression(None);
+        for (path, content) in &contents {
+            builder = builder.file(path.as_bytes(), content, content.len() as u32);
+        }
+        let archive_content = builder.build().unwrap();
+
+        // The table isn't zlib data
+        assert!(matches!(
+            ThorArchive::from_bytes(archive_content.clone()),
+            Err(GrufError::TableDecompressionError { .. })
+        ));
+        let options = ThorOptions {
+            decompressor: Arc::new(StoredDecompressor),
+            ..Default::default()
+        };
+        let mut thor_archive =
+            ThorArchive::with_options(Cursor::new(archive_content.clone()), &options).unwrap();
+        assert_eq!(thor_archive.file_count(), 3);
+        let container =
+            parse_thor_patch_with_options(&mut Cursor::new(&archive_content), &options).unwrap();
+        for (path, content) in &contents {
+            assert_eq!(thor_archive.read_file_content(path).unwrap(), *content);
+            let mut read_content = vec![];
+            thor_archive
+                .open_file(path)
+                .unwrap()
+                .read_to_end(&mut read_content)
+                .unwrap();
+            assert_eq!(read_content, *content);
+            assert_eq!(
+                thor_archive.file_crc32(path).unwrap(),
+                crc32::checksum_ieee(content)
+            );
+            assert_eq!(
+                thor_archive.read_file_content_with_dict(path, b"").unwrap(),
+                *content
+            );
+            assert_eq!(
+                container
+                    .read_entry_content(&archive_content, path)
+                    .unwrap(),
+                *content
+            );
+        }
+        for reader in thor_archive.readers() {
+            let (path, mut reader) = reader.unwrap();
+            let mut read_content = vec![];
+            reader.read_to_end(&mut read_content).unwrap();
+            assert!(contents.contains(&(path.as_str(), read_content.as_slice())));
+        }
+
+        // Sizes are still checked
+        #[derive(Debug)]
+        struct TruncatingDecompressor;
+
+        impl Decompressor for TruncatingDecompressor {
+            fn decompress(&self, input: &[u8], _hint_size: usize) -> Result<Vec<u8>> {
+                Ok(input[..input.len() / 2].to_vec())
+            }
+        }
+        thor_archive.container.decompressor = SharedDecompressor(Arc::new(TruncatingDecompressor));
+        assert!(matches!(
+            thor_archive.read_file_content("data\\a.txt"),
+            Err(GrufError::SizeMismatch {
+                expected: 14,
+                actual: 7
+            })
+        ));
+        assert!(matches!(
+            thor_archive.read_file_to_writer("data\\a.txt", &mut io::sink()),
+            Err(GrufError::SizeMismatch {
+                expected: 14,
+                actual: 7
+            })
+        ));
+    }
+
+    #[test]
+    fn test_get_file_entry_owned() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("small.thor")).unwrap();
+        let entry = thor_archive
+            .get_file_entry_owned(INTEGRITY_FILE_NAME)
+            .unwrap();
+        let content = thor_archive
+            .read_file_content(&entry.relative_path)
+            .unwrap();
+        assert_eq!(content.len(), entry.size);
+        assert!(entry.is_identical_to(thor_archive.get_file_entry(INTEGRITY_FILE_NAME).unwrap()));
+        assert!(thor_archive.get_file_entry_owned("missing.txt").is_none());
+    }
+
+    #[test]
+    fn test_mixed_separators() {
+        let archive_content = build_thor_archive(
+            vec![
+                ("data\\a.txt", b"a".to_vec()),
+                ("data/b.txt", b"b".to_vec()),
+                ("data/c.txt", b"old".to_vec()),
+                ("data\\c.txt", b"new".to_vec()),
+            ],
+            &[],
+        )
+        .unwrap();
+        for &separator in &[PathSeparator::Backslash, PathSeparator::Slash] {
+            let options = ThorOptions {
+                canonical_separator: separator,
+                ..Default::default()
+            };
+            let mut thor_archive =
+                ThorArchive::with_options(Cursor::new(archive_content.clone()), &options).unwrap();
+            assert!(thor_archive.stats().mixed_separators);
+            assert_eq!(thor_archive.stats().duplicates_dropped, 1);
+            for (path, content) in &[
+                ("data\\a.txt", &b"a"[..]),
+                ("data/a.txt", &b"a"[..]),
+                ("data\\b.txt", &b"b"[..]),
+                ("data/b.txt", &b"b"[..]),
+                // `build_thor_archive` sorts entries by path, '/' first
+                ("data/c.txt", &b"new"[..]),
+                ("data\\c.txt", &b"new"[..]),
+            ] {
+                assert_eq!(thor_archive.read_file_content(path).unwrap(), *content);
             }
+            // Stored paths are left as is
+            assert_eq!(
+                thor_archive
+                    .get_file_entry("data\\b.txt")
+                    .unwrap()
+                    .relative_path,
+                "data/b.txt"
+            );
+        }
+        // Archives using either separator consistently
+        for path in &["data\\a.txt", "data/a.txt"] {
+            let thor_archive = ThorArchive::from_bytes(
+                build_thor_archive(vec![(*path, vec![]), ("root.txt", vec![])], &[]).unwrap(),
+            )
+            .unwrap();
+            assert!(!thor_archive.stats().mixed_separators);
         }
+    }
+
+    #[test]
+    fn test_entry_data_range() {
+        let archive_content = build_thor_archive(
+            vec![("data\\a.txt", vec![b'a'; 64]), ("data\\empty.txt", vec![])],
+            &["data\\removed.txt"],
+        )
+        .unwrap();
+        let mut thor_archive = ThorArchive::from_bytes(archive_content.clone()).unwrap();
+        let data_range = thor_archive.entry_data_range("data/a.txt").unwrap();
+        let entry = thor_archive.get_file_entry("data\\a.txt").unwrap();
+        assert_eq!(data_range.start, entry.offset);
+        assert_eq!(
+            &archive_content[data_range.start as usize..data_range.end as usize],
+            thor_archive
+                .get_entry_raw_data("data\\a.txt")
+                .unwrap()
+                .as_slice()
         );
-);
+        let data_range = thor_archive.entry_data_range("data\\empty.txt").unwrap();
+        assert_eq!(
+            data_range.end - data_range.start,
+            thor_archive
+                .get_file_entry("data\\empty.txt")
+                .unwrap()
+                .size_compressed as u64
+        );
+        assert_eq!(thor_archive.entry_data_range("data\\removed.txt"), None);
+        assert_eq!(thor_archive.entry_data_range("missing.txt"), None);
+    }
 
-named!(parse_multiple_files_entry<&[u8], ThorFileEntry>,
-    do_parse!(
-        relative_path_size: le_u8
-        >> relative_path: take_string_ansi!(relative_path_size)
-        >> flags: le_u8
-        >> offset: take_if_not_removed!(le_u32, flags)
-        >> size_compressed: take_if_not_removed!(le_i32, flags)
-        >> size: take_if_not_removed!(le_i32, flags)
-        >> (ThorFileEntry {
-            size_compressed: size_compressed as usize,
-            size: size as usize,
-            relative_path,
-            is_removed: is_file_removed(flags),
-            offset: offset as u64,
+    #[test]
+    fn test_transcode_to() {
+        let paths = [
+            "data\\a.txt",
+            "data\\b.txt",
+            "data\\dir\\",
+            "data\\removed.txt",
+        ];
+        let content: Vec<u8> = (0..16 * 1024).map(|i| (i % 251) as u8).collect();
+        let mut archive_content = vec![];
+        {
+            let mut builder =
+                ThorArchiveBuilder::new(Cursor::new(&mut archive_content), true, None, true)
+                    .unwrap()
+                    .with_compression(Compression::none());
+            builder
+                .append_file_update(paths[0].to_string(), content.as_slice())
+                .unwrap();
+            builder
+                .append_file_update(paths[1].to_string(), &b"b"[..])
+                .unwrap();
+            builder
+                .append_raw_file_update(paths[2].to_string(), &[], 0, 0)
+                .unwrap();
+            builder.append_file_removal(paths[3].to_string());
         }
-    )
-));
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        let mut transcoded_content = vec![];
+        let report = thor_archive
+            .transcode_to(Cursor::new(&mut transcoded_content), Compression::best())
+            .unwrap();
+        assert_eq!(report.transcoded_count, 3);
+        assert!(report.size_delta() < 0);
+        let mut transcoded_archive = ThorArchive::from_bytes(transcoded_content).unwrap();
+        assert_eq!(transcoded_archive.mode(), ThorMode::MultipleFiles);
+        assert_eq!(transcoded_archive.patch_kind(), PatchKind::DefaultGrf);
+        let transcoded_paths: Vec<&str> = transcoded_archive
+            .get_entries()
+            .map(|e| e.relative_path.as_str())
+            .collect();
+        assert_eq!(
+            transcoded_paths,
+            [&paths[..], &[INTEGRITY_FILE_NAME]].concat()
+        );
+        assert!(
+            transcoded_archive
+                .get_file_entry(paths[3])
+                .unwrap()
+                .is_removed
+        );
+        for path in &paths[..2] {
+            assert_eq!(
+                transcoded_archive.read_file_content(path).unwrap(),
+                thor_archive.read_file_content(path).unwrap()
+            );
+            assert_eq!(
+                transcoded_archive.file_crc32(path).unwrap(),
+                thor_archive.file_crc32(path).unwrap()
+            );
+        }
+        let a_entry = transcoded_archive.get_file_entry(paths[0]).unwrap();
+        assert!(
+            a_entry.size_compressed
+                < thor_archive
+                    .get_file_entry(paths[0])
+                    .unwrap()
+                    .size_compressed
+        );
+        assert_eq!(
+            transcoded_archive
+                .get_file_entry(paths[2])
+                .unwrap()
+                .size_compressed,
+            0
+        );
 
-named!(parse_multiple_files_entries<&[u8], HashMap<String, ThorFileEntry>>,
-    fold_many1!(parse_multiple_files_entry, HashMap::new(), |mut acc: HashMap<_, _>, item| {
-        acc.insert(item.relative_path.clone(), item);
-        acc
-    })
-);
+        // Single-file archives stay single-file
+        let mut single_file_content = vec![];
+        ThorBuilder::new()
+            .target_grf("data.grf")
+            .grf_merging(true)
+            .add("data\\a.txt", content.clone())
+            .build(Cursor::new(&mut single_file_content))
+            .unwrap();
+        let mut thor_archive = ThorArchive::from_bytes(single_file_content).unwrap();
+        let mut transcoded_content = vec![];
+        let report = thor_archive
+            .transcode_to(Cursor::new(&mut transcoded_content), Compression::none())
+            .unwrap();
+        assert_eq!(report.transcoded_count, 1);
+        assert!(report.size_delta() > 0);
+        let mut transcoded_archive = ThorArchive::from_bytes(transcoded_content).unwrap();
+        assert_eq!(transcoded_archive.mode(), ThorMode::SingleFile);
+        assert_eq!(
+            transcoded_archive.patch_kind(),
+            PatchKind::NamedGrf("data.grf".to_string())
+        );
+        assert_eq!(
+            transcoded_archive.read_file_content("data\\a.txt").unwrap(),
+            content
+        );
+    }
 
-pub fn parse_thor_patch<R: Seek + Read>(reader: &mut R) -> Result<ThorContainer> {
-    const HEADER_EXTENDED_MAX_SIZE: usize =
-        HEADER_MAX_SIZE + MULTIPLE_FILES_TABLE_DESC_SIZE + SINGLE_FILE_ENTRY_MAX_SIZE;
-    let mut thor_header_buf = Vec::with_capacity(HEADER_EXTENDED_MAX_SIZE);
-    let mut reader_chunk = reader.take(thor_header_buf.capacity() as u64);
-    reader_chunk.read_to_end(&mut thor_header_buf)?;
-    let (output, header) = parse_thor_header(&thor_header_buf)
-        .map_err(|_| GrufError::parsing_error("Failed to parse THOR header"))?;
-    match header.mode {
-        ThorMode::Invalid => Err(GrufError::parsing_error("Invalid THOR header mode")),
-        ThorMode::SingleFile => {
-            // Parse table
-            let (output, table) = parse_single_file_table(output)
-                .map_err(|_| GrufError::parsing_error("Failed to parse THOR file table"))?;
-            // Parse the single entry
-            let (output, mut entry) = parse_single_file_entry(output)
-                .map_err(|_| GrufError::parsing_error("Failed to parse THOR file entry"))?;
-            entry.offset = output.as_ptr() as u64 - thor_header_buf.as_ptr() as u64;
-            Ok(ThorContainer {
-                header,
-                table: ThorTable::SingleFile(table),
-                entries: [(entry.relative_path.clone(), entry)]
-                    .iter()
-                    .cloned()
-                    .collect(),
-            })
+    #[test]
+    fn test_repack_to() {
+        let archive_content = build_thor_archive(
+            vec![("data\\a.txt", vec![b'a'; 64]), ("data\\b.txt", vec![])],
+            &["data\\removed.txt"],
+        )
+        .unwrap();
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        for options in &[
+            RepackOptions::default(),
+            RepackOptions {
+                compression: Some(Compression::none()),
+                ..Default::default()
+            },
+        ] {
+            let mut repacked_content = vec![];
+            assert_eq!(
+                thor_archive
+                    .repack_to(Cursor::new(&mut repacked_content), options)
+                    .unwrap()
+                    .written_count,
+                2
+            );
+            let mut repacked_archive = ThorArchive::from_bytes(repacked_content).unwrap();
+            assert_eq!(repacked_archive.file_count(), 2);
+            assert_eq!(repacked_archive.removed_count(), 0);
+            for path in &["data\\a.txt", "data\\b.txt"] {
+                assert_eq!(
+                    repacked_archive.read_file_content(path).unwrap(),
+                    thor_archive.read_file_content(path).unwrap()
+                );
+            }
+            let a_entry = repacked_archive.get_file_entry("data\\a.txt").unwrap();
+            let size_compressed = thor_archive
+                .get_file_entry("data\\a.txt")
+                .unwrap()
+                .size_compressed;
+            if options.compression.is_some() {
+                assert!(a_entry.size_compressed > size_compressed);
+            } else {
+                assert_eq!(a_entry.size_compressed, size_compressed);
+            }
         }
-        ThorMode::MultipleFiles => {
-            let (output, table) = parse_multiple_files_table(output)
-                .map_err(|_| GrufError::parsing_error("Failed to parse THOR file table"))?;
-            let consumed_bytes = output.as_ptr() as u64 - thor_header_buf.as_ptr() as u64;
-            if table.file_table_offset < consumed_bytes {
-                return Err(GrufError::parsing_error("Invalid THOR file table offset"));
-            }
-            // Decompress the table with zlib
-            reader.seek(SeekFrom::Start(table.file_table_offset))?;
-            let mut compressed_table: Vec<u8> =
-                Vec::with_capacity(table.file_table_compressed_size);
-            let mut file_chunk = reader.take(compressed_table.capacity() as u64);
-            file_chunk.read_to_end(&mut compressed_table)?;
-            let mut decoder = ZlibDecoder::new(compressed_table.as_slice());
-            let mut decompressed_table = vec![];
-            let decompressed_size = decoder.read_to_end(&mut decompressed_table)?;
-            // Parse multiple entries
-            let entries = match decompressed_size {
-                0 => HashMap::new(), // No entries
-                _ => {
-                    let (_, entries) = parse_multiple_files_entries(decompressed_table.as_slice())
-                        .map_err(|_| {
-                            GrufError::parsing_error("Failed to parse THOR file entries")
-                        })?;
-                    entries
+
+        // Header properties and checksums are kept
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("small.thor")).unwrap();
+        let mut repacked_content = vec![];
+        let written_count = thor_archive
+            .repack_to(
+                Cursor::new(&mut repacked_content),
+                &RepackOptions::default(),
+            )
+            .unwrap()
+            .written_count;
+        let mut repacked_archive = ThorArchive::from_bytes(repacked_content).unwrap();
+        assert_eq!(
+            written_count,
+            thor_archive
+                .present_entries()
+                .filter(|e| !e.is_internal())
+                .count()
+        );
+        assert_eq!(repacked_archive.removed_count(), 0);
+        assert_eq!(repacked_archive.target_grf_name(), "data.grf");
+        assert_eq!(
+            repacked_archive.use_grf_merging(),
+            thor_archive.use_grf_merging()
+        );
+        assert!(repacked_archive.is_valid().unwrap());
+
+        // Identical content is stored once
+        let archive_content = build_thor_archive(
+            vec![
+                ("data\\a.txt", "same"),
+                ("data\\b.txt", "same"),
+                ("data\\c.txt", "other"),
+            ],
+            &[],
+        )
+        .unwrap();
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        let mut repacked_content = vec![];
+        let report = thor_archive
+            .repack_to(
+                Cursor::new(&mut repacked_content),
+                &RepackOptions {
+                    deduplicate: true,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            report,
+            RepackReport {
+                written_count: 3,
+                deduplicated_count: 1,
+            }
+        );
+        let mut repacked_archive = ThorArchive::from_bytes(repacked_content).unwrap();
+        for path in &["data\\a.txt", "data\\b.txt"] {
+            assert_eq!(repacked_archive.read_file_content(path).unwrap(), b"same");
+        }
+    }
+
+    #[test]
+    fn test_split() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let thor_file_path = thor_dir_path.join("small.thor");
+        let temp_dir = tempdir().unwrap();
+        let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+        let max_part_bytes = fs::metadata(&thor_file_path).unwrap().len() / 3;
+        let part_path = |i: usize| temp_dir.path().join(format!("part{}.thor", i));
+        let parts = thor_archive
+            .split(max_part_bytes, |i| Ok(File::create(part_path(i))?))
+            .unwrap();
+        let part_count = parts.len();
+        assert!(part_count > 1);
+        drop(parts);
+
+        let mut seen_entries = 0;
+        for i in 0..part_count {
+            let mut part = ThorArchive::open(part_path(i)).unwrap();
+            // Entries too big to fit in a part are stored alone
+            let part_size = fs::metadata(part_path(i)).unwrap().len();
+            assert!(
+                part_size <= max_part_bytes
+                    || part.get_entries().filter(|e| !e.is_internal()).count() == 1
+            );
+            assert_eq!(part.use_grf_merging(), thor_archive.use_grf_merging());
+            assert_eq!(part.target_grf_name(), thor_archive.target_grf_name());
+            assert!(part.is_valid().unwrap());
+            let part_entries: Vec<ThorFileEntry> = part
+                .get_entries()
+                .filter(|e| !e.is_internal())
+                .cloned()
+                .collect();
+            for entry in part_entries {
+                seen_entries += 1;
+                let original_entry = thor_archive.get_file_entry(&entry.relative_path).unwrap();
+                assert_eq!(entry.is_removed, original_entry.is_removed);
+                if !entry.is_removed {
+                    assert_eq!(
+                        part.read_file_content(&entry.relative_path).unwrap(),
+                        thor_archive
+                            .read_file_content(&entry.relative_path)
+                            .unwrap()
+                    );
                 }
-            };
-            Ok(ThorContainer {
-                header,
-                table: ThorTable::MultipleFiles(table),
-                entries,
-            })
+            }
         }
+        assert_eq!(
+            seen_entries,
+            thor_archive
+                .get_entries()
+                .filter(|e| !e.is_internal())
+                .count()
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::path::PathBuf;
+    #[test]
+    fn test_data_span() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        // Single file
+        let thor_archive = ThorArchive::open(thor_dir_path.join("dir1.thor")).unwrap();
+        assert_eq!(thor_archive.data_span(), 52..52 + 20136);
+        // Multiple files
+        let thor_archive = ThorArchive::open(thor_dir_path.join("dir2.thor")).unwrap();
+        let data_span = thor_archive.data_span();
+        for entry in thor_archive.get_entries() {
+            assert!(entry.offset >= data_span.start);
+            assert!(entry.offset + entry.size_compressed as u64 <= data_span.end);
+        }
+        assert!(thor_archive
+            .get_entries()
+            .any(|e| e.offset == data_span.start));
+        // No files
+        let thor_archive = ThorArchive::open(thor_dir_path.join("empty.thor")).unwrap();
+        assert!(thor_archive.data_span().is_empty());
+    }
 
     #[test]
-    fn test_patch_list_from_string() {
-        let plist_content = "//869 iteminfo_20170423.thor
-870 iteminfo_20170423_.thor
-871 sprites_20170427.thor
-872 sprites_20170429.thor
-623 2016-01_01.thor
-//873 rodex_20170501.thor
-//874 strings_20170501.thor
-875 rodex_20170501_.thor";
-        let expected_content: HashMap<usize, &str> = [
-            (623, "2016-01_01.thor"),
-            (870, "iteminfo_20170423_.thor"),
-            (871, "sprites_20170427.thor"),
-            (872, "sprites_20170429.thor"),
-            (875, "rodex_20170501_.thor"),
-        ]
-        .iter()
-        .cloned()
-        .collect();
-        //Empty patch list
-        let empty_thor_patch_list = patch_list_from_string("");
-        assert_eq!(empty_thor_patch_list.len(), 0);
-        // TODO(LinkZ): Ensure patch list is ordered by patch index
-        // Regular patch list
-        let thor_patch_list = patch_list_from_string(plist_content);
-        assert_eq!(thor_patch_list.len(), expected_content.len());
-        for patch_info in thor_patch_list {
-            assert!(expected_content.contains_key(&patch_info.index));
-            assert_eq!(patch_info.file_name, expected_content[&patch_info.index]);
+    fn test_rewrite_target_grf() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        for (archive_name, new_target_grf_name) in &[
+            ("small.thor", "mydata.grf"),
+            ("small.thor", "my.grf"),
+            ("small.thor", ""),
+            ("tiny.thor", "a_somewhat_longer_name.grf"),
+            ("tiny.thor", ""),
+            ("dir1.thor", "data.grf"),
+            ("prefixed-tiny.thor", "data.grf"),
+        ] {
+            let mut thor_archive = ThorArchive::open(thor_dir_path.join(archive_name)).unwrap();
+            let mut retargeted = Cursor::new(Vec::new());
+            thor_archive
+                .rewrite_target_grf(&mut retargeted, new_target_grf_name)
+                .unwrap();
+            retargeted.set_position(0);
+            let mut retargeted_archive = ThorArchive::new(retargeted).unwrap();
+            assert_eq!(
+                retargeted_archive.target_grf_name(),
+                new_target_grf_name.to_string()
+            );
+            assert_eq!(
+                retargeted_archive.use_grf_merging(),
+                thor_archive.use_grf_merging()
+            );
+            assert_eq!(retargeted_archive.file_count(), thor_archive.file_count());
+            let entries: Vec<ThorFileEntry> = thor_archive.get_entries().cloned().collect();
+            for entry in entries {
+                let retargeted_entry = retargeted_archive
+                    .get_file_entry(&entry.relative_path)
+                    .unwrap();
+                assert_eq!(retargeted_entry.is_removed, entry.is_removed);
+                if entry.is_removed {
+                    continue;
+                }
+                assert_eq!(
+                    retargeted_archive
+                        .read_file_content(&entry.relative_path)
+                        .unwrap(),
+                    thor_archive
+                        .read_file_content(&entry.relative_path)
+                        .unwrap()
+                );
+            }
         }
+        // Names that don't fit in the header's size byte
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("small.thor")).unwrap();
+        let mut retargeted = Cursor::new(Vec::new());
+        assert!(matches!(
+            thor_archive.rewrite_target_grf(&mut retargeted, &"a".repeat(256)),
+            Err(GrufError::SerializationError(_))
+        ));
+        assert!(retargeted.into_inner().is_empty());
+        // Same as `rewrite_target_grf`
+        let mut retargeted = Cursor::new(Vec::new());
+        thor_archive
+            .set_target_grf_in_place(&mut retargeted, "mydata.grf")
+            .unwrap();
+        retargeted.set_position(0);
+        let retargeted_archive = ThorArchive::new(retargeted).unwrap();
+        assert_eq!(retargeted_archive.target_grf_name(), "mydata.grf");
     }
 
     #[test]
-    fn test_open_empty_container() {
+    fn test_open_length_prefixed_container() {
         let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
-        let thor_file_path = thor_dir_path.join("empty.thor");
-        let thor_archive = ThorArchive::open(&thor_file_path).unwrap();
-        assert_eq!(thor_archive.file_count(), 0);
-        assert_eq!(thor_archive.target_grf_name(), "");
-        assert!(thor_archive.use_grf_merging());
+        for archive_name in &["tiny.thor", "dir1.thor"] {
+            let archive_path = thor_dir_path.join(archive_name);
+            let mut thor_archive = ThorArchive::open(&archive_path).unwrap();
+            assert_eq!(thor_archive.header_prefix(), None);
+            let archive_content = fs::read(&archive_path).unwrap();
+            let mut prefixed_content = (archive_content.len() as u32).to_le_bytes().to_vec();
+            prefixed_content.extend_from_slice(&archive_content);
+            let mut prefixed_archive = ThorArchive::new(Cursor::new(prefixed_content)).unwrap();
+            assert_eq!(
+                prefixed_archive.header_prefix(),
+                Some(archive_content.len() as u32)
+            );
+            assert_eq!(prefixed_archive.file_count(), thor_archive.file_count());
+            let entries: Vec<ThorFileEntry> = thor_archive.get_entries().cloned().collect();
+            for entry in entries.iter().filter(|e| !e.is_removed) {
+                assert_eq!(
+                    prefixed_archive
+                        .read_file_content(&entry.relative_path)
+                        .unwrap(),
+                    thor_archive
+                        .read_file_content(&entry.relative_path)
+                        .unwrap()
+                );
+            }
+        }
+        // Fixture
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("prefixed-tiny.thor")).unwrap();
+        assert_eq!(thor_archive.header_prefix(), Some(368));
+        assert!(thor_archive.is_valid().unwrap());
     }
 
     #[test]
-    fn test_open_thor_container() {
+    fn test_entries_with_absolute_offset() {
         let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
-        {
-            let thor_file_path = thor_dir_path.join("dir1.thor");
-            let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
-            assert_eq!(thor_archive.file_count(), 1);
-            assert_eq!(thor_archive.target_grf_name(), "");
-            assert!(!thor_archive.use_grf_merging());
-            assert!(matches!(
-                thor_archive.is_valid().unwrap_err(),
-                GrufError::EntryNotFound
-            ));
-            let entry = thor_archive.get_entries().next().unwrap();
-            assert_eq!(entry.offset, 52);
-            assert_eq!(entry.size, 22528);
-            assert_eq!(entry.size_compressed, 20136);
-            assert!(!entry.is_removed);
-            assert_eq!(entry.relative_path, "client.exe");
-            let _content = thor_archive.read_file_content("client.exe").unwrap();
+        for archive_name in &["dir1.thor", "tiny.thor", "prefixed-tiny.thor"] {
+            let archive_content = fs::read(thor_dir_path.join(archive_name)).unwrap();
+            let mut thor_archive = ThorArchive::new(Cursor::new(archive_content.clone())).unwrap();
+            let entries: Vec<(ThorFileEntry, u64)> = thor_archive
+                .entries_with_absolute_offset()
+                .map(|(e, offset)| (e.clone(), offset))
+                .collect();
+            assert!(!entries.is_empty());
+            for (entry, offset) in entries {
+                let start = offset as usize;
+                assert_eq!(
+                    &archive_content[start..start + entry.size_compressed],
+                    thor_archive
+                        .get_entry_raw_data(&entry.relative_path)
+                        .unwrap()
+                        .as_slice()
+                );
+            }
         }
-        {
-            let expected_content: HashMap<&str, usize> = [
-                ("ASPLnchr.exe", 248568),
-                ("savedata\\MiniPartyInfo.lua", 0),
-                ("savedata\\OptionInfo.lua", 2703),
-                ("savedata\\UserKeys.lua", 20),
-            ]
-            .iter()
-            .cloned()
-            .collect();
-            let check_dir2_thor_entries = |thor: &mut ThorArchive<File>| {
-                let file_entries: Vec<ThorFileEntry> = thor.get_entries().cloned().collect();
-                for file_entry in file_entries {
-                    let file_path: &str = &file_entry.relative_path[..];
-                    assert!(expected_content.contains_key(file_path));
-                    let expected_size = expected_content[file_path];
-                    assert_eq!(file_entry.size, expected_size);
-                    let _content = thor.read_file_content(file_path).unwrap();
-                }
-            };
-            let thor_file_path = thor_dir_path.join("dir2.thor");
-            let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
-            assert_eq!(thor_archive.file_count(), expected_content.len());
-            assert_eq!(thor_archive.target_grf_name(), "");
-            assert!(!thor_archive.use_grf_merging());
-            assert!(matches!(
-                thor_archive.is_valid().unwrap_err(),
-                GrufError::EntryNotFound
-            ));
-            check_dir2_thor_entries(&mut thor_archive);
+    }
+
+    /// Reader that keeps track of the number of bytes read from it.
+    struct CountingReader<R> {
+        inner: R,
+        bytes_read: Rc<RefCell<usize>>,
+    }
+
+    impl<R: Read> Read for CountingReader<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.inner.read(buf)?;
+            *self.bytes_read.borrow_mut() += n;
+            Ok(n)
         }
-        {
-            let expected_content: HashMap<&str, usize> = [
-                ("data.integrity", 63),
-                (
-                    "data\\texture\\\u{c0}\u{af}\u{c0}\u{fa}\u{c0}\u{ce}\u{c5}\u{cd}\u{c6}\u{e4}\u{c0}\u{cc}\u{bd}\u{ba}\\inventory\\icon_num.bmp",
-                    560,
-                ),
-            ]
-            .iter()
-            .cloned()
-            .collect();
-            let check_tiny_thor_entries = |thor: &mut ThorArchive<File>| {
-                let file_entries: Vec<ThorFileEntry> = thor.get_entries().cloned().collect();
-                for file_entry in file_entries {
-                    let file_path: &str = &file_entry.relative_path[..];
-                    assert!(expected_content.contains_key(file_path));
-                    let expected_size = expected_content[file_path];
-                    assert_eq!(file_entry.size, expected_size);
-                    let _content = thor.read_file_content(file_path).unwrap();
-                }
-            };
-            let thor_file_path = thor_dir_path.join("tiny.thor");
-            let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
-            assert_eq!(thor_archive.file_count(), expected_content.len());
-            assert_eq!(thor_archive.target_grf_name(), "");
-            assert!(thor_archive.use_grf_merging());
-            check_tiny_thor_entries(&mut thor_archive);
-            assert!(thor_archive.is_valid().unwrap());
+    }
+
+    impl<R: Seek> Seek for CountingReader<R> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
         }
+    }
+
+    #[test]
+    fn test_open_reads_header_and_table_only() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let thor_file_path = thor_dir_path.join("small.thor");
+        let bytes_read = Rc::new(RefCell::new(0));
+        let reader = CountingReader {
+            inner: File::open(&thor_file_path).unwrap(),
+            bytes_read: bytes_read.clone(),
+        };
+        let thor_archive = ThorArchive::new(reader).unwrap();
+        let table_size = match &thor_archive.container.table {
+            ThorTable::MultipleFiles(table) => table.file_table_compressed_size(),
+            ThorTable::SingleFile(_) => panic!("unexpected archive mode"),
+        };
+        // Entries' content is left on disk until it's read
+        assert!(*bytes_read.borrow() <= HEADER_EXTENDED_MAX_SIZE + table_size);
+        let archive_size = fs::metadata(&thor_file_path).unwrap().len() as usize;
+        assert!(*bytes_read.borrow() < archive_size);
+    }
+
+    #[test]
+    fn test_normalized_lookups() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let thor_archive = ThorArchive::open(thor_dir_path.join("dir2.thor")).unwrap();
+        let index = thor_archive.normalized_index();
+        assert_eq!(index.len(), thor_archive.file_count());
+        assert_eq!(
+            index.get("savedata/optioninfo.lua"),
+            Some(&"savedata\\OptionInfo.lua".to_string())
+        );
+        let entry = thor_archive
+            .get_file_entry_normalized("SaveData/OPTIONINFO.lua")
+            .unwrap();
+        assert_eq!(entry.relative_path, "savedata\\OptionInfo.lua");
+        assert!(thor_archive
+            .get_file_entry_ci("SAVEDATA\\optioninfo.LUA")
+            .is_some());
+        assert!(thor_archive
+            .get_file_entry_normalized("savedata/missing.lua")
+            .is_none());
+    }
+
+    #[test]
+    fn test_case_insensitive_lookups() {
+        let mut archive_content = vec![];
         {
-            let thor_file_path = thor_dir_path.join("small.thor");
-            let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
-            assert_eq!(thor_archive.file_count(), 16);
-            assert_eq!(thor_archive.target_grf_name(), "data.grf");
-            assert!(thor_archive.use_grf_merging());
-            assert!(thor_archive.is_valid().unwrap());
+            let mut builder =
+                ThorArchiveBuilder::new(Cursor::new(&mut archive_content), false, None, false)
+                    .unwrap();
+            builder
+                .append_file_update("DATA\\Texture\\몬스터\\X.BMP".to_string(), &[1u8; 8][..])
+                .unwrap();
+            builder
+                .append_file_update("data\\Ωmega.txt".to_string(), &[2u8; 8][..])
+                .unwrap();
+        }
+        let mut thor_archive = ThorArchive::new(Cursor::new(archive_content)).unwrap();
+        assert_eq!(
+            thor_archive
+                .read_file_content_ci("data\\texture\\몬스터\\x.bmp")
+                .unwrap(),
+            vec![1u8; 8]
+        );
+        // Only ASCII letters are case-folded
+        assert!(thor_archive.get_file_entry_ci("DATA\\Ωmega.txt").is_some());
+        assert!(thor_archive.get_file_entry_ci("data\\ωmega.txt").is_none());
+        assert!(matches!(
+            thor_archive.read_file_content_ci("data\\missing.txt"),
+            Err(GrufError::EntryNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_validate_manifest() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let thor_archive = ThorArchive::open(thor_dir_path.join("dir2.thor")).unwrap();
+        let report = thor_archive.validate_manifest(&["savedata/optioninfo.lua"], &["data.grf"]);
+        assert!(report.is_valid());
+        let report = thor_archive.validate_manifest(
+            &["SaveData/OptionInfo.lua", "missing.txt"],
+            &["SAVEDATA\\optioninfo.LUA"],
+        );
+        assert!(!report.is_valid());
+        assert_eq!(
+            report,
+            ManifestReport {
+                missing_required: vec!["missing.txt".to_string()],
+                present_forbidden: vec!["SAVEDATA\\optioninfo.LUA".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_targets_grf() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        // Named target GRF
+        let thor_archive = ThorArchive::open(thor_dir_path.join("small.thor")).unwrap();
+        assert!(thor_archive.targets_grf("data.grf"));
+        assert!(thor_archive.targets_grf("DATA.GRF"));
+        assert!(!thor_archive.targets_grf("mydata.grf"));
+        // Default target GRF
+        let thor_archive = ThorArchive::open(thor_dir_path.join("tiny.thor")).unwrap();
+        assert!(thor_archive.targets_grf("data.grf"));
+        assert!(thor_archive.targets_grf("mydata.grf"));
+        // Client directory
+        let thor_archive = ThorArchive::open(thor_dir_path.join("dir1.thor")).unwrap();
+        assert!(!thor_archive.targets_grf("data.grf"));
+    }
+
+    #[test]
+    fn test_patch_kind() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let thor_archive = ThorArchive::open(thor_dir_path.join("small.thor")).unwrap();
+        assert_eq!(
+            thor_archive.patch_kind(),
+            PatchKind::NamedGrf("data.grf".to_string())
+        );
+        let thor_archive = ThorArchive::open(thor_dir_path.join("tiny.thor")).unwrap();
+        assert_eq!(thor_archive.patch_kind(), PatchKind::DefaultGrf);
+        let thor_archive = ThorArchive::open(thor_dir_path.join("dir1.thor")).unwrap();
+        assert_eq!(thor_archive.patch_kind(), PatchKind::ClientDirectory);
+    }
+
+    #[test]
+    fn test_read_all_in_order() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let thor_file_path = thor_dir_path.join("small.thor");
+        let mut thor_archive = ThorArchive::open(&thor_file_path).unwrap();
+        let mut files_content = HashMap::new();
+        let mut last_offset = 0;
+        thor_archive
+            .read_all_in_order(|entry, content| {
+                assert!(entry.offset >= last_offset);
+                last_offset = entry.offset;
+                files_content.insert(entry.relative_path.clone(), content);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(files_content.len(), thor_archive.file_count());
+        for (file_path, content) in files_content {
+            assert_eq!(content, thor_archive.read_file_content(file_path).unwrap());
         }
     }
+
+    #[test]
+    fn test_max_entries() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let thor_file_path = thor_dir_path.join("dir2.thor");
+        let options = ThorOptions {
+            max_entries: 3,
+            ..Default::default()
+        };
+        let file = File::open(&thor_file_path).unwrap();
+        assert!(matches!(
+            ThorArchive::with_options(file, &options).unwrap_err(),
+            GrufError::TooManyEntries(3)
+        ));
+        let options = ThorOptions {
+            max_entries: 4,
+            ..Default::default()
+        };
+        let file = File::open(&thor_file_path).unwrap();
+        let thor_archive = ThorArchive::with_options(file, &options).unwrap();
+        assert_eq!(thor_archive.file_count(), 4);
+    }
+
+    #[test]
+    fn test_parse_bom_prefixed_path() {
+        let expected_path = "data\\\u{c720}\u{c800}.txt";
+        let encode_removed_entry = |encoded_path: Vec<u8>| {
+            let mut entry = vec![encoded_path.len() as u8];
+            entry.extend(encoded_path);
+            entry.push(1); // Removed file
+            entry
+        };
+        // UTF-8
+        let mut encoded_path = vec![0xEF, 0xBB, 0xBF];
+        encoded_path.extend(expected_path.as_bytes());
+        let (_, (entry, _)) = parse_multiple_files_entry(
+            &encode_removed_entry(encoded_path),
+            ThorOptions::default().path_decoding(),
+        )
+        .unwrap();
+        assert_eq!(entry.relative_path, expected_path);
+        assert!(entry.is_removed);
+        // UTF-16LE
+        let mut encoded_path = vec![0xFF, 0xFE];
+        encoded_path.extend(expected_path.encode_utf16().flat_map(|c| c.to_le_bytes()));
+        let (_, (entry, _)) = parse_multiple_files_entry(
+            &encode_removed_entry(encoded_path),
+            ThorOptions::default().path_decoding(),
+        )
+        .unwrap();
+        assert_eq!(entry.relative_path, expected_path);
+        // UTF-16BE
+        let mut encoded_path = vec![0xFE, 0xFF];
+        encoded_path.extend(expected_path.encode_utf16().flat_map(|c| c.to_be_bytes()));
+        let (_, (entry, _)) = parse_multiple_files_entry(
+            &encode_removed_entry(encoded_path),
+            ThorOptions::default().path_decoding(),
+        )
+        .unwrap();
+        assert_eq!(entry.relative_path, expected_path);
+        // No BOM
+        let encoded_path = b"data\\test.txt".to_vec();
+        let (_, (entry, _)) = parse_multiple_files_entry(
+            &encode_removed_entry(encoded_path),
+            ThorOptions::default().path_decoding(),
+        )
+        .unwrap();
+        assert_eq!(entry.relative_path, "data\\test.txt");
+    }
 }
@@ -0,0 +1,374 @@
+//! Helpers for testing code that handles THOR archives, without having to
+//! ship binary fixtures.
+
+use std::io::{Cursor, Seek, Write};
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::thor::builder::{write_multiple_files_table_desc, write_thor_header_prefix};
+use crate::thor::{
+    ThorArchiveBuilder, ThorMode, MULTIPLE_FILES_TABLE_DESC_SIZE, THOR_HEADER_FIXED_SIZE,
+};
+use crate::Result;
+
+/// Builds an in-memory THOR archive (in 'multiple files' mode, to be applied
+/// to the client's directory) containing the given files and removals.
+///
+/// Updates are written sorted by path so that the output doesn't depend on
+/// the iteration order of `updates`. The returned bytes can be parsed with
+/// `reader::parse_thor_patch` or `ThorArchive::from_bytes`.
+pub fn build_thor_archive<I, K, V>(updates: I, removals: &[&str]) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: Into<String>,
+    V: AsRef<[u8]>,
+{
+    build_sorted_thor_archive(updates, removals, false)
+}
+
+/// Same as `build_thor_archive`, but the archive also contains a
+/// 'data.integrity' file listing the files' checksums.
+pub fn build_thor_archive_with_checksums<I, K, V>(updates: I, removals: &[&str]) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: Into<String>,
+    V: AsRef<[u8]>,
+{
+    build_sorted_thor_archive(updates, removals, true)
+}
+
+fn build_sorted_thor_archive<I, K, V>(
+    updates: I,
+    removals: &[&str],
+    include_checksums: bool,
+) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: Into<String>,
+    V: AsRef<[u8]>,
+{
+    let mut updates: Vec<(String, V)> = updates
+        .into_iter()
+        .map(|(path, content)| (path.into(), content))
+        .collect();
+    updates.sort_by(|(path_a, _), (path_b, _)| path_a.cmp(path_b));
+    let mut archive_content = vec![];
+    {
+        let mut builder = ThorArchiveBuilder::new(
+            Cursor::new(&mut archive_content),
+            false,
+            None,
+            include_checksums,
+        )?;
+        for (path, content) in updates {
+            builder.append_file_update(path, content.as_ref())?;
+        }
+        for path in removals {
+            builder.append_file_removal(path.to_string());
+        }
+        builder.finish()?;
+    }
+    Ok(archive_content)
+}
+
+/// Writes an archive (in 'multiple files' mode, to be applied to the
+/// client's directory) containing `file_count` files of `file_size` bytes
+/// into `writer`, e.g., to measure the performance of large archives.
+///
+/// Files are filled with pseudo-random data that compresses moderately well,
+/// the output only depends on the arguments.
+pub fn write_generated_thor_archive<W: Write + Seek>(
+    writer: W,
+    file_count: usize,
+    file_size: usize,
+) -> Result<()> {
+    let mut builder = ThorArchiveBuilder::new(writer, false, None, false)?;
+    let mut state = 0x2545_f491_u32;
+    for i in 0..file_count {
+        let content: Vec<u8> = (0..file_size)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                b'a' + (state % 16) as u8
+            })
+            .collect();
+        builder.append_file_update(
+            format!("data\\generated\\{:02}\\{:05}.txt", i % 64, i),
+            content.as_slice(),
+        )?;
+    }
+    builder.finish()
+}
+
+/// Compresses `data` into a zlib stream, like entries' content is stored in
+/// THOR archives.
+pub fn zlib_compress(data: &[u8], level: Compression) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), level);
+    encoder.write_all(data).expect("writes to a Vec don't fail");
+    encoder.finish().expect("writes to a Vec don't fail")
+}
+
+enum RawTableEntry {
+    Removal(Vec<u8>),
+    File {
+        path: Vec<u8>,
+        payload: Vec<u8>,
+        size: u32,
+    },
+    External {
+        path: Vec<u8>,
+        offset: u32,
+        size_compressed: u32,
+        size: u32,
+    },
+    Raw(Vec<u8>),
+}
+
+/// Hand-assembles THOR archives in 'multiple files' mode, for tests that
+/// need content `ThorArchiveBuilder` wouldn't write (e.g., raw path bytes,
+/// invalid payloads, inconsistent sizes or a corrupted file table).
+///
+/// By default, the file table is zlib-compressed and stored after the
+/// entries' payloads, and the header announces as many files as entries
+/// were added.
+pub struct RawThorArchiveBuilder {
+    entries: Vec<RawTableEntry>,
+    file_count: Option<usize>,
+    table_compression: Option<Compression>,
+    table_offset: Option<u64>,
+    table_first: bool,
+}
+
+impl Default for RawThorArchiveBuilder {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            file_count: None,
+            table_compression: Some(Compression::default()),
+            table_offset: None,
+            table_first: false,
+        }
+    }
+}
+
+impl RawThorArchiveBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the removal of `path`.
+    pub fn removal(mut self, path: &[u8]) -> Self {
+        self.entries.push(RawTableEntry::Removal(path.to_vec()));
+        self
+    }
+
+    /// Adds a file whose `payload` is stored as is in the archive and whose
+    /// decompressed size is recorded as `size`.
+    pub fn file(mut self, path: &[u8], payload: &[u8], size: u32) -> Self {
+        self.entries.push(RawTableEntry::File {
+            path: path.to_vec(),
+            payload: payload.to_vec(),
+            size,
+        });
+        self
+    }
+
+    /// Adds a file whose content is expected at `offset`, without storing
+    /// any payload.
+    pub fn external_file(
+        mut self,
+        path: &[u8],
+        offset: u32,
+        size_compressed: u32,
+        size: u32,
+    ) -> Self {
+        self.entries.push(RawTableEntry::External {
+            path: path.to_vec(),
+            offset,
+            size_compressed,
+            size,
+        });
+        self
+    }
+
+    /// Appends `bytes` to the file table as is, e.g., to write a truncated
+    /// entry. Counts as one entry.
+    pub fn raw_entry(mut self, bytes: &[u8]) -> Self {
+        self.entries.push(RawTableEntry::Raw(bytes.to_vec()));
+        self
+    }
+
+    /// Overrides the file count announced by the header.
+    pub fn file_count(mut self, file_count: usize) -> Self {
+        self.file_count = Some(file_count);
+        self
+    }
+
+    /// Compresses the file table with `level` (`None` stores its content
+    /// without any zlib stream).
+    pub fn table_compression(mut self, level: Option<Compression>) -> Self {
+        self.table_compression = level;
+        self
+    }
+
+    /// Overrides the table offset stored in the table descriptor, without
+    /// moving the table.
+    pub fn table_offset(mut self, table_offset: u64) -> Self {
+        self.table_offset = Some(table_offset);
+        self
+    }
+
+    /// Stores the file table before the entries' payloads. The table's size
+    /// mustn't depend on the payloads' offsets (e.g., it must be compressed
+    /// with `Compression::none()`).
+    pub fn table_first(mut self) -> Self {
+        self.table_first = true;
+        self
+    }
+
+    /// Returns the archive's content.
+    pub fn build(self) -> Result<Vec<u8>> {
+        let header_size = (THOR_HEADER_FIXED_SIZE + MULTIPLE_FILES_TABLE_DESC_SIZE) as u64;
+        let data_offset = if self.table_first {
+            header_size + self.serialize_table(header_size).0.len() as u64
+        } else {
+            header_size
+        };
+        let (table, data) = self.serialize_table(data_offset);
+        let table_offset = if self.table_first {
+            assert_eq!(
+                header_size + table.len() as u64,
+                data_offset,
+                "the table's size depends on the payloads' offsets"
+            );
+            header_size
+        } else {
+            data_offset + data.len() as u64
+        };
+
+        let mut archive_content = vec![];
+        write_thor_header_prefix(
+            &mut archive_content,
+            false,
+            self.file_count.unwrap_or(self.entries.len()),
+            ThorMode::MultipleFiles,
+            "",
+        )?;
+        write_multiple_files_table_desc(
+            &mut archive_content,
+            table.len(),
+            self.table_offset.unwrap_or(table_offset),
+        )?;
+        if self.table_first {
+            archive_content.extend_from_slice(&table);
+            archive_content.extend_from_slice(&data);
+        } else {
+            archive_content.extend_from_slice(&data);
+            archive_content.extend_from_slice(&table);
+        }
+        Ok(archive_content)
+    }
+
+    /// Returns the file table (compressed as requested) and the payloads,
+    /// stored from `data_offset`.
+    fn serialize_table(&self, data_offset: u64) -> (Vec<u8>, Vec<u8>) {
+        let mut table = vec![];
+        let mut data = vec![];
+        for entry in &self.entries {
+            match entry {
+                RawTableEntry::Removal(path) => {
+                    table.push(path.len() as u8);
+                    table.extend_from_slice(path);
+                    table.push(1);
+                }
+                RawTableEntry::File {
+                    path,
+                    payload,
+                    size,
+                } => {
+                    let offset = (data_offset + data.len() as u64) as u32;
+                    push_file_entry(&mut table, path, [offset, payload.len() as u32, *size]);
+                    data.extend_from_slice(payload);
+                }
+                RawTableEntry::External {
+                    path,
+                    offset,
+                    size_compressed,
+                    size,
+                } => push_file_entry(&mut table, path, [*offset, *size_compressed, *size]),
+                RawTableEntry::Raw(bytes) => table.extend_from_slice(bytes),
+            }
+        }
+        let table = match self.table_compression {
+            Some(level) => zlib_compress(&table, level),
+            None => table,
+        };
+        (table, data)
+    }
+}
+
+/// Writes a present entry: its offset, compressed size and size.
+fn push_file_entry(table: &mut Vec<u8>, path: &[u8], fields: [u32; 3]) {
+    table.push(path.len() as u8);
+    table.extend_from_slice(path);
+    table.push(0);
+    for field in &fields {
+        table.extend_from_slice(&field.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thor::reader::parse_thor_patch;
+    use crate::thor::ThorArchive;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_write_generated_thor_archive() {
+        let mut archive_content = Cursor::new(vec![]);
+        write_generated_thor_archive(&mut archive_content, 3, 100).unwrap();
+        let archive_content = archive_content.into_inner();
+        let mut other_content = Cursor::new(vec![]);
+        write_generated_thor_archive(&mut other_content, 3, 100).unwrap();
+        assert_eq!(other_content.into_inner(), archive_content);
+
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        assert_eq!(thor_archive.file_count(), 3);
+        let content = thor_archive
+            .read_file_content("data\\generated\\02\\00002.txt")
+            .unwrap();
+        assert_eq!(content.len(), 100);
+    }
+
+    #[test]
+    fn test_build_thor_archive() {
+        let mut updates = HashMap::new();
+        updates.insert("data\\b.txt", b"b".to_vec());
+        updates.insert("data\\a.txt", b"a".to_vec());
+        let archive_content = build_thor_archive(updates.clone(), &["data\\c.txt"]).unwrap();
+        assert_eq!(
+            build_thor_archive(updates, &["data\\c.txt"]).unwrap(),
+            archive_content
+        );
+
+        let container = parse_thor_patch(&mut Cursor::new(&archive_content)).unwrap();
+        let paths: Vec<&str> = container
+            .entries()
+            .iter()
+            .map(|e| e.relative_path.as_str())
+            .collect();
+        assert_eq!(paths, vec!["data\\a.txt", "data\\b.txt", "data\\c.txt"]);
+        let mut thor_archive = ThorArchive::from_bytes(archive_content).unwrap();
+        assert!(!thor_archive.use_grf_merging());
+        assert_eq!(thor_archive.read_file_content("data\\a.txt").unwrap(), b"a");
+        assert!(
+            thor_archive
+                .get_file_entry("data\\c.txt")
+                .unwrap()
+                .is_removed
+        );
+    }
+}
@@ -6,6 +6,7 @@ mod ui;
 
 use log::LevelFilter;
 use std::env;
+use std::io;
 use std::path::PathBuf;
 
 use anyhow::{anyhow, Context, Result};
@@ -15,7 +16,8 @@ use tinyfiledialogs as tfd;
 use tokio::runtime;
 
 use patcher::{
-    patcher_thread_routine, retrieve_patcher_configuration, PatcherCommand, PatcherConfiguration,
+    dry_run_patch, patcher_thread_routine, retrieve_patcher_configuration, PatcherCommand,
+    PatcherConfiguration,
 };
 use ui::{UiController, WebViewUserData};
 
@@ -30,6 +32,10 @@ struct Opt {
     /// Sets a custom working directory
     #[structopt(short, long, parse(from_os_str))]
     working_directory: Option<PathBuf>,
+    /// Prints the changes the given patch would make to the game client and
+    /// exits, without modifying anything
+    #[structopt(long, parse(from_os_str))]
+    dry_run: Option<PathBuf>,
 }
 
 fn main() -> Result<()> {
@@ -59,6 +65,11 @@ fn main() -> Result<()> {
         Ok(v) => v,
     };
 
+    if let Some(patch_file_path) = cli_args.dry_run {
+        return dry_run_patch(&patch_file_path, &config, io::stdout())
+            .with_context(|| format!("Failed to plan patch '{}'", patch_file_path.display()));
+    }
+
     // Create a channel to allow the webview's thread to communicate with the patching thread
     let (tx, rx) = flume::bounded(32);
     let window_title = config.window.title.clone();
@@ -54,14 +54,16 @@ pub struct PatchServerInfo {
 
 #[derive(Deserialize, Clone)]
 pub struct ClientConfiguration {
-    pub default_grf_name: String, // GRF file to patch by default
+    pub root_directory: Option<String>, // Directory containing the game client's files
+    pub default_grf_name: String,       // GRF file to patch by default
 }
 
 #[derive(Deserialize, Clone)]
 pub struct PatchingConfiguration {
-    pub in_place: bool,        // In-place GRF patching
-    pub check_integrity: bool, // Check THOR archives' integrity
-    pub create_grf: bool,      // Create new GRFs if they don't exist
+    pub in_place: bool,                      // In-place GRF patching
+    pub check_integrity: bool,               // Check THOR archives' integrity
+    pub create_grf: bool,                    // Create new GRFs if they don't exist
+    pub metadata_names: Option<Vec<String>>, // Patches' metadata files, which are never applied
 }
 
 pub fn retrieve_patcher_configuration(
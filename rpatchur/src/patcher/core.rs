@@ -1,7 +1,8 @@
 use std::env;
+use std::io::{Read, Seek, Write};
 use std::path::{Path, PathBuf};
 use std::sync::{
-    atomic::{AtomicUsize, Ordering},
+    atomic::{AtomicBool, AtomicUsize, Ordering},
     Arc,
 };
 use std::time::{Duration, Instant};
@@ -10,7 +11,7 @@ use advisory_lock::{AdvisoryFileLock, FileLockMode};
 use anyhow::{anyhow, Context, Result};
 use futures::executor::block_on;
 use futures::stream::{StreamExt, TryStreamExt};
-use gruf::thor::{self, ThorArchive, ThorPatchInfo, ThorPatchList};
+use gruf::thor::{self, PatchKind, ThorArchive, ThorPatchInfo, ThorPatchList};
 use gruf::GrufError;
 use tokio::fs::File;
 use tokio::io::{AsyncSeekExt, AsyncWriteExt};
@@ -21,7 +22,10 @@ use super::cancellation::{
     process_incoming_commands, wait_for_cancellation, InterruptibleFnError, InterruptibleFnResult,
 };
 use super::config::PatchServerInfo;
-use super::patching::{apply_patch_to_disk, apply_patch_to_grf, GrfPatchingMethod};
+use super::patching::{
+    apply_patch_to_disk, apply_patch_to_grf, plan_patch_to_disk, plan_patch_to_grf,
+    write_patch_plan, ExtractProgress, GrfPatchingMethod,
+};
 use super::{get_patcher_name, PatcherCommand, PatcherConfiguration};
 use crate::ui::{PatchingStatus, UiController};
 
@@ -123,15 +127,15 @@ fn apply_single_patch(
                 ui_controller.set_patch_in_progress(false);
             });
 
-            let current_working_dir =
-                env::current_dir().with_context(|| "Failed to resolve current working directory");
-            match current_working_dir {
+            let client_root = resolve_client_root(config)
+                .with_context(|| "Failed to resolve the client's root directory");
+            match client_root {
                 Err(err) => {
                     log::error!("{:#}", err);
                     ui_controller
                         .dispatch_patching_status(PatchingStatus::Error(format!("{:#}", err)));
                 }
-                Ok(current_working_dir) => {
+                Ok(client_root) => {
                     let patch_file_name = patch_file_path
                         .as_ref()
                         .file_name()
@@ -140,7 +144,9 @@ fn apply_single_patch(
                         .unwrap_or_default()
                         .to_string();
                     log::info!("Applying patch '{}'", patch_file_name);
-                    let res = apply_patch(patch_file_path, config, current_working_dir);
+                    let cancel_flag = Arc::new(AtomicBool::new(false));
+                    let res =
+                        apply_patch(patch_file_path, config, client_root, &cancel_flag, |_| {});
                     match res {
                         Err(err) => {
                             log::error!("{:#}", err);
@@ -559,7 +565,11 @@ async fn download_patch_to_file<CB: FnMut(u64, u64)>(
 /// Parses and applies a list of patches to GRFs and/or to the game client's
 /// files.
 ///
-/// This function is interruptible.
+/// The progress of the patch being applied is reported to the UI along with
+/// the overall progress.
+///
+/// This function is interruptible. Cancellation is only checked between
+/// patches, so that a patch is never partially applied.
 async fn apply_patches(
     pending_patch_queue: Vec<PendingPatch>,
     config: &PatcherConfiguration,
@@ -567,14 +577,19 @@ async fn apply_patches(
     ui_controller: &UiController,
     patching_thread_rx: &mut flume::Receiver<PatcherCommand>,
 ) -> InterruptibleFnResult<()> {
-    let current_working_dir = env::current_dir().map_err(|e| {
+    let client_root = resolve_client_root(config).map_err(|e| {
         InterruptibleFnError::Err(format!(
-            "Failed to resolve current working directory: {}.",
+            "Failed to resolve the client's root directory: {}.",
             e
         ))
     })?;
     let patch_count = pending_patch_queue.len();
-    ui_controller.dispatch_patching_status(PatchingStatus::InstallationInProgress(0, patch_count));
+    let cancel_flag = Arc::new(AtomicBool::new(false));
+    ui_controller.dispatch_patching_status(PatchingStatus::InstallationInProgress(
+        0,
+        patch_count,
+        ExtractProgress::default(),
+    ));
     for (patch_number, pending_patch) in pending_patch_queue.into_iter().enumerate() {
         // Cancel the patching process if we've been asked to or if the other
         // end of the channel has been disconnected
@@ -582,7 +597,42 @@ async fn apply_patches(
 
         let patch_name = pending_patch.info.file_name;
         log::info!("Processing {}", patch_name);
-        apply_patch(pending_patch.local_file_path, config, &current_working_dir).map_err(|e| {
+        // Only dispatch the patch's progress when its percentage changes, to
+        // avoid flooding the UI
+        let mut last_percentage = None;
+        let mut interruption = None;
+        let dispatch_patch_progress = |progress: ExtractProgress| {
+            // Check for cancellation after each entry, the patch stops before
+            // the next one
+            if interruption.is_none() {
+                if let Err(e) = process_incoming_commands(patching_thread_rx) {
+                    interruption = Some(e);
+                    cancel_flag.store(true, Ordering::SeqCst);
+                }
+            }
+            let percentage = (100 * progress.processed_entries)
+                .checked_div(progress.total_entries)
+                .unwrap_or(100);
+            if last_percentage != Some(percentage) {
+                last_percentage = Some(percentage);
+                ui_controller.dispatch_patching_status(PatchingStatus::InstallationInProgress(
+                    patch_number,
+                    patch_count,
+                    progress,
+                ));
+            }
+        };
+        let result = apply_patch(
+            pending_patch.local_file_path,
+            config,
+            &client_root,
+            &cancel_flag,
+            dispatch_patch_progress,
+        );
+        if let Some(e) = interruption {
+            return Err(e);
+        }
+        result.map_err(|e| {
             InterruptibleFnError::Err(format!("Failed to apply patch '{}': {}.", patch_name, e))
         })?;
         // Update the cache file with the last successful patch's index
@@ -600,44 +650,117 @@ async fn apply_patches(
         ui_controller.dispatch_patching_status(PatchingStatus::InstallationInProgress(
             1 + patch_number,
             patch_count,
+            ExtractProgress::default(),
         ));
     }
     Ok(())
 }
 
+/// Resolves the directory containing the game client's files.
+///
+/// Relative paths given in the configuration are resolved from the current
+/// working directory, which is also used when no root directory is configured.
+fn resolve_client_root(config: &PatcherConfiguration) -> Result<PathBuf> {
+    let current_working_dir = env::current_dir()?;
+    Ok(match &config.client.root_directory {
+        Some(root_directory) => current_working_dir.join(root_directory),
+        None => current_working_dir,
+    })
+}
+
+/// Returns the paths of patches' metadata files, which are never applied to
+/// the game client.
+///
+/// Defaults to `thor::RESERVED_METADATA_NAMES` when no names are configured.
+fn resolve_metadata_names(config: &PatcherConfiguration) -> Vec<String> {
+    match &config.patching.metadata_names {
+        Some(metadata_names) => metadata_names.clone(),
+        None => thor::RESERVED_METADATA_NAMES
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+    }
+}
+
+/// Applies a THOR patch to the game client located in `client_root`.
+///
+/// GRF files targeted by the patch are resolved relative to `client_root`.
+/// `progress_callback` is called each time an entry of the patch has been
+/// processed, the patch is aborted before the next entry once `cancel_flag` is
+/// set.
 fn apply_patch(
     thor_archive_path: impl AsRef<Path>,
     config: &PatcherConfiguration,
-    current_working_dir: impl AsRef<Path>,
+    client_root: impl AsRef<Path>,
+    cancel_flag: &Arc<AtomicBool>,
+    progress_callback: impl FnMut(ExtractProgress),
 ) -> Result<()> {
     let mut thor_archive = ThorArchive::open(thor_archive_path.as_ref())?;
-    if thor_archive.use_grf_merging() {
-        // Patch GRF file
-        let target_grf_name = {
-            if thor_archive.target_grf_name().is_empty() {
-                config.client.default_grf_name.clone()
-            } else {
-                thor_archive.target_grf_name()
-            }
-        };
-        log::trace!("Target GRF: {:?}", target_grf_name);
-        let grf_patching_method = match config.patching.in_place {
-            true => GrfPatchingMethod::InPlace,
-            false => GrfPatchingMethod::OutOfPlace,
-        };
-        let target_grf_path = current_working_dir.as_ref().join(&target_grf_name);
-        apply_patch_to_grf(
-            grf_patching_method,
-            config.patching.create_grf,
-            target_grf_path,
-            &mut thor_archive,
-        )
-    } else {
+    let metadata_names = resolve_metadata_names(config);
+    match resolve_target_grf_path(&thor_archive, config, client_root.as_ref()) {
+        Some(target_grf_path) => {
+            // Patch GRF file
+            let grf_patching_method = match config.patching.in_place {
+                true => GrfPatchingMethod::InPlace,
+                false => GrfPatchingMethod::OutOfPlace,
+            };
+            apply_patch_to_grf(
+                grf_patching_method,
+                config.patching.create_grf,
+                target_grf_path,
+                &mut thor_archive,
+                &metadata_names,
+                cancel_flag,
+                progress_callback,
+            )
+        }
         // Patch root directory
-        apply_patch_to_disk(current_working_dir, &mut thor_archive)
+        None => apply_patch_to_disk(
+            client_root,
+            &mut thor_archive,
+            &metadata_names,
+            cancel_flag,
+            progress_callback,
+        ),
     }
 }
 
+/// Writes the changes a THOR patch would make to the game client to `writer`,
+/// without modifying anything.
+pub fn dry_run_patch(
+    thor_archive_path: impl AsRef<Path>,
+    config: &PatcherConfiguration,
+    writer: impl Write,
+) -> Result<()> {
+    let client_root = resolve_client_root(config)?;
+    let thor_archive = ThorArchive::open(thor_archive_path.as_ref())?;
+    let metadata_names = resolve_metadata_names(config);
+    let plan = match resolve_target_grf_path(&thor_archive, config, &client_root) {
+        Some(target_grf_path) => {
+            plan_patch_to_grf(target_grf_path, &thor_archive, &metadata_names)?
+        }
+        None => plan_patch_to_disk(client_root, &thor_archive, &metadata_names)?,
+    };
+    write_patch_plan(&plan, writer)
+}
+
+/// Returns the path of the GRF file targeted by a THOR patch, relative to
+/// `client_root`, or `None` if the patch must be applied to the client's
+/// directory.
+fn resolve_target_grf_path<R: Read + Seek>(
+    thor_archive: &ThorArchive<R>,
+    config: &PatcherConfiguration,
+    client_root: impl AsRef<Path>,
+) -> Option<PathBuf> {
+    let target_grf_name = match thor_archive.patch_kind() {
+        PatchKind::ClientDirectory => return None,
+        PatchKind::DefaultGrf => config.client.default_grf_name.clone(),
+        PatchKind::NamedGrf(target_grf_name) => target_grf_name,
+    };
+    log::trace!("Target GRF: {:?}", target_grf_name);
+    Some(client_root.as_ref().join(&target_grf_name))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
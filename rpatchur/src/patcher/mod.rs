@@ -9,7 +9,8 @@ use std::ffi::OsString;
 use std::path::PathBuf;
 
 pub use self::config::{retrieve_patcher_configuration, PatcherConfiguration};
-pub use self::core::patcher_thread_routine;
+pub use self::core::{dry_run_patch, patcher_thread_routine};
+pub use self::patching::ExtractProgress;
 use anyhow::{Context, Result};
 
 pub enum PatcherCommand {
@@ -1,11 +1,14 @@
 use std::collections::HashMap;
 use std::fs;
-use std::io::{Read, Seek};
-use std::path::{Path, PathBuf};
+use std::io::{Read, Seek, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use anyhow::Result;
-use gruf::grf::{GrfArchive, GrfArchiveBuilder};
-use gruf::thor::{ThorArchive, ThorFileEntry};
+use gruf::grf::{merge_into_grf_with_options, GrfApplyOptions, GrfArchive, GrfArchiveBuilder};
+use gruf::thor::{safe_join, ExtractOptions, ThorArchive, ThorFileEntry};
+use gruf::GrufError;
 
 /// Indicates the method that should be used when patching GRF files.
 pub enum GrfPatchingMethod {
@@ -26,6 +29,21 @@ enum DataTransformation {
     // DecompressZlib,
 }
 
+/// Progress of the application of a single patch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExtractProgress {
+    pub processed_entries: usize,
+    pub total_entries: usize,
+}
+
+/// Change that applying a patch would make to a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlannedChange {
+    Added { size: usize },
+    Modified { old_size: usize, new_size: usize },
+    Removed { size: usize },
+}
+
 struct MergeEntry {
     pub source: MergeEntrySource,
     pub source_offset: u64,
@@ -34,11 +52,18 @@ struct MergeEntry {
 }
 
 /// Patches a GRF file with a THOR archive/patch.
+///
+/// Files named after one of `metadata_names` (compared case-insensitively)
+/// are never merged into the GRF. `cancel_flag` is checked before each entry,
+/// patching fails with `GrufError::Cancelled` once it's set.
 pub fn apply_patch_to_grf<R: Read + Seek>(
     patching_method: GrfPatchingMethod,
     create_if_needed: bool,
     grf_file_path: impl AsRef<Path>,
     thor_archive: &mut ThorArchive<R>,
+    metadata_names: &[String],
+    cancel_flag: &Arc<AtomicBool>,
+    progress_callback: impl FnMut(ExtractProgress),
 ) -> Result<()> {
     if !grf_file_path.as_ref().exists() && create_if_needed {
         // Create a new GRF file if needed
@@ -46,8 +71,20 @@ pub fn apply_patch_to_grf<R: Read + Seek>(
         GrfArchiveBuilder::create(new_grf, 2, 0)?;
     }
     match patching_method {
-        GrfPatchingMethod::InPlace => apply_patch_to_grf_ip(grf_file_path, thor_archive),
-        GrfPatchingMethod::OutOfPlace => apply_patch_to_grf_oop(grf_file_path, thor_archive),
+        GrfPatchingMethod::InPlace => apply_patch_to_grf_ip(
+            grf_file_path,
+            thor_archive,
+            metadata_names,
+            cancel_flag,
+            progress_callback,
+        ),
+        GrfPatchingMethod::OutOfPlace => apply_patch_to_grf_oop(
+            grf_file_path,
+            thor_archive,
+            metadata_names,
+            cancel_flag,
+            progress_callback,
+        ),
     }
 }
 
@@ -58,31 +95,40 @@ pub fn apply_patch_to_grf<R: Read + Seek>(
 fn apply_patch_to_grf_ip<R: Read + Seek>(
     grf_file_path: impl AsRef<Path>,
     thor_archive: &mut ThorArchive<R>,
+    metadata_names: &[String],
+    cancel_flag: &Arc<AtomicBool>,
+    mut progress_callback: impl FnMut(ExtractProgress),
 ) -> Result<()> {
-    let mut builder = GrfArchiveBuilder::open(grf_file_path)?;
-    let mut thor_entries: Vec<ThorFileEntry> = thor_archive
-        .get_entries()
-        .filter(|e| !e.is_internal())
-        .cloned()
-        .collect();
-    thor_entries.sort_unstable_by(|a, b| a.offset.cmp(&b.offset));
-    for entry in thor_entries {
-        if entry.is_removed {
-            let _ = builder.remove_file(&entry.relative_path);
-        } else {
-            builder.import_raw_entry_from_thor(thor_archive, entry.relative_path)?;
-        }
-    }
-    Ok(())
+    let options = GrfApplyOptions {
+        metadata_names: metadata_names.to_vec(),
+        cancel_flag: Some(cancel_flag.clone()),
+        ..Default::default()
+    };
+    Ok(merge_into_grf_with_options(
+        grf_file_path,
+        thor_archive,
+        &options,
+        |processed_entries, total_entries| {
+            progress_callback(ExtractProgress {
+                processed_entries,
+                total_entries,
+            })
+        },
+    )?)
 }
 
 /// Patches a GRF in an out-of-place manner.
 ///
-/// This is safer and produces output of smaller size but slower.
+/// This is safer and produces output of smaller size but slower. The original
+/// GRF is restored in case of error (e.g., if patching is cancelled).
 fn apply_patch_to_grf_oop<R: Read + Seek>(
     grf_file_path: impl AsRef<Path>,
     thor_archive: &mut ThorArchive<R>,
+    metadata_names: &[String],
+    cancel_flag: &Arc<AtomicBool>,
+    mut progress_callback: impl FnMut(ExtractProgress),
 ) -> Result<()> {
+    let options = skip_metadata_options(metadata_names);
     // Rename file to back it up
     let mut backup_file_path = grf_file_path.as_ref().to_path_buf();
     backup_file_path.set_extension("grf.bak");
@@ -110,7 +156,7 @@ fn apply_patch_to_grf_oop<R: Read + Seek>(
     }
     // Add files from the patch
     for entry in thor_archive.get_entries() {
-        if entry.is_removed || entry.is_internal() {
+        if entry.is_removed || options.is_skipped(entry) {
             continue;
         }
         merge_entries.insert(
@@ -124,10 +170,12 @@ fn apply_patch_to_grf_oop<R: Read + Seek>(
         );
     }
 
-    {
-        let grf_file = fs::File::create(grf_file_path)?;
+    let result = (|| -> Result<()> {
+        let grf_file = fs::File::create(grf_file_path.as_ref())?;
         let mut builder = GrfArchiveBuilder::create(grf_file, 2, 0)?;
-        for (relative_path, entry) in merge_entries {
+        let total_entries = merge_entries.len();
+        for (entry_number, (relative_path, entry)) in merge_entries.into_iter().enumerate() {
+            check_cancelled(cancel_flag)?;
             match entry.source {
                 MergeEntrySource::GrfArchive => {
                     builder.import_raw_entry_from_grf(&mut grf_archive, relative_path)?;
@@ -136,30 +184,58 @@ fn apply_patch_to_grf_oop<R: Read + Seek>(
                     builder.import_raw_entry_from_thor(thor_archive, relative_path)?;
                 }
             }
+            progress_callback(ExtractProgress {
+                processed_entries: 1 + entry_number,
+                total_entries,
+            });
         }
+        Ok(builder.finish()?)
+    })();
+    drop(grf_archive);
+    if let Err(e) = result {
+        // Put the original GRF back in place of the partially built one
+        fs::rename(&backup_file_path, grf_file_path.as_ref())?;
+        return Err(e);
     }
     // Remove backup file once the patched GRF has been built
     Ok(fs::remove_file(backup_file_path)?)
 }
 
-/// Patches files located in the game client's directory with a THOR
-/// archive/patch.
+/// Patches files located in the game client's directory (`client_root`) with
+/// a THOR archive/patch.
+///
+/// Files named after one of `metadata_names` (compared case-insensitively)
+/// are never written to the client's directory. `cancel_flag` is checked
+/// before each entry, patching fails with `GrufError::Cancelled` once it's
+/// set (files patched so far are kept).
 pub fn apply_patch_to_disk<R: Read + Seek>(
-    root_directory: impl AsRef<Path>,
+    client_root: impl AsRef<Path>,
     thor_archive: &mut ThorArchive<R>,
+    metadata_names: &[String],
+    cancel_flag: &Arc<AtomicBool>,
+    mut progress_callback: impl FnMut(ExtractProgress),
 ) -> Result<()> {
+    let options = skip_metadata_options(metadata_names);
+    // Fail before modifying anything if the patch cannot fit on the disk
+    thor_archive.has_enough_space_with_options(client_root.as_ref(), 0, &options)?;
     // TODO(LinkZ): Save original files before updating/removing them in order
     // to be able to restore them in case of failure
     // TODO(LinkZ): Make async?
     let mut file_entries: Vec<ThorFileEntry> = thor_archive
         .get_entries()
-        .filter(|e| !e.is_internal())
+        .filter(|e| !options.is_skipped(e))
         .cloned()
         .collect();
     file_entries.sort_unstable_by(|a, b| a.offset.cmp(&b.offset));
-    for entry in file_entries {
-        let dest_path = join_windows_relative_path(root_directory.as_ref(), &entry.relative_path);
-        if entry.is_removed {
+    let total_entries = file_entries.len();
+    for (entry_number, entry) in file_entries.into_iter().enumerate() {
+        check_cancelled(cancel_flag)?;
+        // Rejects paths that would escape the client's directory
+        let dest_path = safe_join(client_root.as_ref(), &entry.relative_path)?;
+        if entry.is_removed && entry.is_directory() {
+            // Only remove empty directories and ignore errors
+            let _ignore = fs::remove_dir(dest_path);
+        } else if entry.is_removed {
             // Try to remove file and ignore errors (file might not exist)
             let _ignore = fs::remove_file(dest_path);
         } else {
@@ -170,23 +246,143 @@ pub fn apply_patch_to_disk<R: Read + Seek>(
             // Extract file
             thor_archive.extract_file(&entry.relative_path, &dest_path)?;
         }
+        progress_callback(ExtractProgress {
+            processed_entries: 1 + entry_number,
+            total_entries,
+        });
     }
     Ok(())
 }
 
-/// Utility function used to join path-like segments the same way it's done in
-/// the GRF file format (Windows style).
-fn join_windows_relative_path(path: &Path, windows_relative_path: &str) -> PathBuf {
-    let mut result = PathBuf::from(path);
-    for component in windows_relative_path.split('\\') {
-        result.push(component);
+/// Computes the changes that patching the GRF file located at `grf_file_path`
+/// with a THOR archive would make, without modifying anything.
+///
+/// A missing GRF file is considered empty. Entries are sorted by path.
+pub fn plan_patch_to_grf<R: Read + Seek>(
+    grf_file_path: impl AsRef<Path>,
+    thor_archive: &ThorArchive<R>,
+    metadata_names: &[String],
+) -> Result<Vec<(String, PlannedChange)>> {
+    let grf_archive = if grf_file_path.as_ref().exists() {
+        Some(GrfArchive::open(grf_file_path)?)
+    } else {
+        None
+    };
+    let current_size = |relative_path: &str| {
+        grf_archive
+            .as_ref()
+            .and_then(|grf| grf.get_file_entry(relative_path))
+            .map(|e| e.size)
+    };
+    Ok(plan_patch(thor_archive, metadata_names, current_size))
+}
+
+/// Computes the changes that patching files located in the game client's
+/// directory (`client_root`) with a THOR archive would make, without
+/// modifying anything.
+///
+/// Entries are sorted by path.
+pub fn plan_patch_to_disk<R: Read + Seek>(
+    client_root: impl AsRef<Path>,
+    thor_archive: &ThorArchive<R>,
+    metadata_names: &[String],
+) -> Result<Vec<(String, PlannedChange)>> {
+    let current_size = |relative_path: &str| {
+        let file_path = safe_join(client_root.as_ref(), relative_path).ok()?;
+        fs::metadata(file_path)
+            .ok()
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len() as usize)
+    };
+    Ok(plan_patch(thor_archive, metadata_names, current_size))
+}
+
+fn plan_patch<R, F>(
+    thor_archive: &ThorArchive<R>,
+    metadata_names: &[String],
+    current_size: F,
+) -> Vec<(String, PlannedChange)>
+where
+    R: Read + Seek,
+    F: Fn(&str) -> Option<usize>,
+{
+    let options = skip_metadata_options(metadata_names);
+    let mut plan: Vec<(String, PlannedChange)> = thor_archive
+        .get_entries()
+        .filter(|e| !options.is_skipped(e))
+        .filter_map(|entry| {
+            let change = match (entry.is_removed, current_size(&entry.relative_path)) {
+                // Removing a file that doesn't exist doesn't change anything
+                (true, None) => return None,
+                (true, Some(size)) => PlannedChange::Removed { size },
+                (false, None) => PlannedChange::Added { size: entry.size },
+                (false, Some(old_size)) => PlannedChange::Modified {
+                    old_size,
+                    new_size: entry.size,
+                },
+            };
+            Some((entry.relative_path.clone(), change))
+        })
+        .collect();
+    plan.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    plan
+}
+
+fn check_cancelled(cancel_flag: &AtomicBool) -> Result<()> {
+    if cancel_flag.load(Ordering::SeqCst) {
+        return Err(GrufError::Cancelled.into());
     }
-    result
+    Ok(())
+}
+
+/// Options that leave the files named after one of `metadata_names` out of
+/// the files patched.
+fn skip_metadata_options(metadata_names: &[String]) -> ExtractOptions {
+    ExtractOptions {
+        skip_integrity_file: true,
+        metadata_names: metadata_names.to_vec(),
+        ..Default::default()
+    }
+}
+
+/// Writes a human-readable description of `plan` to `writer`.
+pub fn write_patch_plan<W: Write>(plan: &[(String, PlannedChange)], mut writer: W) -> Result<()> {
+    let (mut added, mut modified, mut removed) = (0, 0, 0);
+    for (relative_path, change) in plan {
+        match change {
+            PlannedChange::Added { size } => {
+                added += 1;
+                writeln!(writer, "+ {} ({} bytes)", relative_path, size)?;
+            }
+            PlannedChange::Modified { old_size, new_size } => {
+                modified += 1;
+                writeln!(
+                    writer,
+                    "~ {} ({} -> {} bytes)",
+                    relative_path, old_size, new_size
+                )?;
+            }
+            PlannedChange::Removed { size } => {
+                removed += 1;
+                writeln!(writer, "- {} ({} bytes)", relative_path, size)?;
+            }
+        }
+    }
+    writeln!(
+        writer,
+        "{} file(s) changed: {} added, {} modified, {} removed",
+        plan.len(),
+        added,
+        modified,
+        removed
+    )?;
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::path::PathBuf;
     use tempfile::tempdir;
     use walkdir::WalkDir;
 
@@ -214,13 +410,140 @@ mod tests {
             assert!(!expected_file_path.exists());
             assert_eq!(0, count_files(temp_dir.path()));
 
-            apply_patch_to_disk(temp_dir.path(), &mut thor_archive).unwrap();
+            let mut last_progress = ExtractProgress::default();
+            apply_patch_to_disk(
+                temp_dir.path(),
+                &mut thor_archive,
+                &default_metadata_names(),
+                &Arc::new(AtomicBool::new(false)),
+                |progress| {
+                    assert_eq!(
+                        progress.processed_entries,
+                        last_progress.processed_entries + 1
+                    );
+                    last_progress = progress;
+                },
+            )
+            .unwrap();
 
             // After patching
             assert!(expected_file_path.exists());
             assert_eq!(nb_of_added_files, count_files(temp_dir.path()));
+            assert_eq!(last_progress.processed_entries, nb_of_added_files);
+            assert_eq!(last_progress.total_entries, nb_of_added_files);
             // TODO(LinkZ): Check content
         }
+        // Client directories that don't exist yet (e.g., fresh installs)
+        let client_root = temp_dir.path().join("new").join("client");
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("tiny.thor")).unwrap();
+        apply_patch_to_disk(
+            &client_root,
+            &mut thor_archive,
+            &default_metadata_names(),
+            &Arc::new(AtomicBool::new(false)),
+            |_| {},
+        )
+        .unwrap();
+        assert!(client_root.exists());
+    }
+
+    #[test]
+    fn test_apply_patch_to_disk_cancelled() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let temp_dir = tempdir().unwrap();
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("small.thor")).unwrap();
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+        let mut processed_entries = 0;
+        let result = apply_patch_to_disk(
+            temp_dir.path(),
+            &mut thor_archive,
+            &default_metadata_names(),
+            &cancel_flag,
+            |progress| {
+                processed_entries = progress.processed_entries;
+                cancel_flag.store(true, Ordering::SeqCst);
+            },
+        );
+        // The patch stops right after the first entry
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<GrufError>(),
+            Some(GrufError::Cancelled)
+        ));
+        assert_eq!(processed_entries, 1);
+    }
+
+    #[test]
+    fn test_plan_patch_to_disk() {
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let temp_dir = tempdir().unwrap();
+        let mut thor_archive = ThorArchive::open(&thor_dir_path.join("small.thor")).unwrap();
+        let plan =
+            plan_patch_to_disk(temp_dir.path(), &thor_archive, &default_metadata_names()).unwrap();
+        let nb_of_added_files = thor_archive.file_count() - 1;
+        assert_eq!(plan.len(), nb_of_added_files);
+        assert!(plan
+            .iter()
+            .all(|(_, change)| matches!(change, PlannedChange::Added { .. })));
+        // Planning doesn't modify anything
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+        // Files named after one of the configured metadata names are skipped
+        let mut metadata_names = default_metadata_names();
+        metadata_names.push(plan[0].0.to_uppercase());
+        let custom_plan =
+            plan_patch_to_disk(temp_dir.path(), &thor_archive, &metadata_names).unwrap();
+        assert_eq!(custom_plan, plan[1..].to_vec());
+
+        apply_patch_to_disk(
+            temp_dir.path(),
+            &mut thor_archive,
+            &default_metadata_names(),
+            &Arc::new(AtomicBool::new(false)),
+            |_| {},
+        )
+        .unwrap();
+        let plan =
+            plan_patch_to_disk(temp_dir.path(), &thor_archive, &default_metadata_names()).unwrap();
+        assert_eq!(plan.len(), nb_of_added_files);
+        for (relative_path, change) in &plan {
+            let entry = thor_archive.get_file_entry(relative_path).unwrap();
+            assert_eq!(
+                *change,
+                PlannedChange::Modified {
+                    old_size: entry.size,
+                    new_size: entry.size
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_write_patch_plan() {
+        let plan = vec![
+            (
+                "data\\added.txt".to_string(),
+                PlannedChange::Added { size: 3 },
+            ),
+            (
+                "data\\modified.txt".to_string(),
+                PlannedChange::Modified {
+                    old_size: 1,
+                    new_size: 2,
+                },
+            ),
+            (
+                "data\\removed.txt".to_string(),
+                PlannedChange::Removed { size: 4 },
+            ),
+        ];
+        let mut output = vec![];
+        write_patch_plan(&plan, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "+ data\\added.txt (3 bytes)\n\
+             ~ data\\modified.txt (1 -> 2 bytes)\n\
+             - data\\removed.txt (4 bytes)\n\
+             3 file(s) changed: 1 added, 1 modified, 1 removed\n"
+        );
     }
 
     #[test]
@@ -246,6 +569,9 @@ mod tests {
                 false,
                 &grf_archive_path,
                 &mut thor_archive,
+                &default_metadata_names(),
+                &Arc::new(AtomicBool::new(false)),
+                |_| {},
             )
             .unwrap();
 
@@ -272,6 +598,9 @@ mod tests {
                 true,
                 &grf_archive_path,
                 &mut thor_archive,
+                &default_metadata_names(),
+                &Arc::new(AtomicBool::new(false)),
+                |_| {},
             )
             .unwrap();
 
@@ -307,6 +636,9 @@ mod tests {
                 false,
                 &grf_archive_path,
                 &mut thor_archive,
+                &default_metadata_names(),
+                &Arc::new(AtomicBool::new(false)),
+                |_| {},
             )
             .unwrap();
 
@@ -333,6 +665,9 @@ mod tests {
                 true,
                 &grf_archive_path,
                 &mut thor_archive,
+                &default_metadata_names(),
+                &Arc::new(AtomicBool::new(false)),
+                |_| {},
             )
             .unwrap();
 
@@ -345,6 +680,38 @@ mod tests {
         assert!(patch_maintained_integrity(&thor_archive_path, &grf_archive_path).unwrap());
     }
 
+    #[test]
+    fn test_apply_patch_to_grf_oop_cancelled() {
+        let grf_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/grf");
+        let thor_dir_path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("resources/tests/thor");
+        let temp_dir = tempdir().unwrap();
+        let grf_archive_path = temp_dir.path().join("small.grf");
+        fs::copy(grf_dir_path.join("200-small.grf"), &grf_archive_path).unwrap();
+        let original_content = fs::read(&grf_archive_path).unwrap();
+
+        let mut thor_archive = ThorArchive::open(thor_dir_path.join("small.thor")).unwrap();
+        let result = apply_patch_to_grf(
+            GrfPatchingMethod::OutOfPlace,
+            false,
+            &grf_archive_path,
+            &mut thor_archive,
+            &default_metadata_names(),
+            &Arc::new(AtomicBool::new(true)),
+            |_| {},
+        );
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<GrufError>(),
+            Some(GrufError::Cancelled)
+        ));
+        // The original GRF is restored
+        assert_eq!(fs::read(&grf_archive_path).unwrap(), original_content);
+        assert!(!grf_archive_path.with_extension("grf.bak").exists());
+    }
+
+    fn default_metadata_names() -> Vec<String> {
+        ExtractOptions::default().metadata_names
+    }
+
     fn patch_maintained_integrity(
         thor_file_path: &PathBuf,
         grf_file_path: &PathBuf,
@@ -352,8 +719,9 @@ mod tests {
         let mut thor_archive = ThorArchive::open(&thor_file_path)?;
         let mut grf_archive = GrfArchive::open(&grf_file_path)?;
         let thor_entries: Vec<ThorFileEntry> = thor_archive.get_entries().cloned().collect();
+        let options = skip_metadata_options(&default_metadata_names());
         for file_entry in thor_entries {
-            if file_entry.is_internal() || file_entry.is_removed {
+            if options.is_skipped(&file_entry) || file_entry.is_removed {
                 continue;
             }
             let expected_content = thor_archive.read_file_content(&file_entry.relative_path)?;
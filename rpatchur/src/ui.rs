@@ -1,7 +1,7 @@
 use std::fs;
 use std::path::PathBuf;
 
-use crate::patcher::{get_patcher_name, PatcherCommand, PatcherConfiguration};
+use crate::patcher::{get_patcher_name, ExtractProgress, PatcherCommand, PatcherConfiguration};
 use crate::process::start_executable;
 use serde::Deserialize;
 use serde_json::Value;
@@ -35,9 +35,11 @@ impl UiController {
                         nb_downloaded, nb_total, bytes_per_sec
                     ))
                 }
-                PatchingStatus::InstallationInProgress(nb_installed, nb_total) => webview.eval(
-                    &format!("patchingStatusInstalling({}, {})", nb_installed, nb_total),
-                ),
+                PatchingStatus::InstallationInProgress(nb_installed, nb_total, progress) => webview
+                    .eval(&format!(
+                        "patchingStatusInstalling({}, {}, {}, {})",
+                        nb_installed, nb_total, progress.processed_entries, progress.total_entries
+                    )),
                 PatchingStatus::ManualPatchApplied(name) => {
                     webview.eval(&format!("patchingStatusPatchApplied(\"{}\")", name))
                 }
@@ -64,10 +66,10 @@ impl UiController {
 /// Used to indicate the current status of the patching process.
 pub enum PatchingStatus {
     Ready,
-    Error(String),                         // Error message
+    Error(String),                                         // Error message
     DownloadInProgress(usize, usize, u64), // Downloaded files, Total number, Bytes per second
-    InstallationInProgress(usize, usize),  // Installed patches, Total number
-    ManualPatchApplied(String),            // Patch file name
+    InstallationInProgress(usize, usize, ExtractProgress), // Installed patches, Total number, Current patch
+    ManualPatchApplied(String),                            // Patch file name
 }
 
 pub struct WebViewUserData {